@@ -1,5 +1,6 @@
 pub mod always_success;
 pub mod next_epoch_ext;
 pub mod overall;
+pub mod script_cost_model;
 pub mod secp_2in2out;
 pub mod util;
@@ -0,0 +1,345 @@
+use crate::benchmarks::util::{create_2out_transaction, create_secp_tx, secp_cell, secp_data_cell};
+use ckb_crypto::secp::Privkey;
+use ckb_hash::{blake2b_256, new_blake2b};
+use ckb_script::cost_model::InstructionClass;
+use ckb_script::{ScriptGroupType, ScriptProfile, TransactionScriptsVerifier};
+use ckb_store::data_loader_wrapper::DataLoaderWrapper;
+use ckb_system_scripts::BUNDLED_CELL;
+use ckb_test_chain_utils::{always_success_cell, MockStore};
+use ckb_types::{
+    bytes::Bytes,
+    core::{
+        capacity_bytes,
+        cell::{CellMeta, CellMetaBuilder, ResolvedTransaction},
+        Capacity, Cycle, ScriptHashType, TransactionBuilder, TransactionView,
+    },
+    h256,
+    packed::{Byte32, CellDep, CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use criterion::{criterion_group, Criterion, Throughput};
+use std::time::Duration;
+
+const MAX_CYCLES: Cycle = 100_000_000;
+
+// Runs real system scripts under `TransactionScriptsVerifier` and reports the
+// wall-clock time per consumed cycle, for whole script groups and broken down
+// by instruction class and by syscall, to check the cost model against the
+// actual CPU time.
+struct Case {
+    name: &'static str,
+    rtx: ResolvedTransaction,
+    script_group_type: ScriptGroupType,
+    script_hash: Byte32,
+}
+
+fn cell_meta(output: CellOutput, data: Bytes, out_point: OutPoint) -> CellMeta {
+    CellMetaBuilder::from_cell_output(output, data)
+        .out_point(out_point)
+        .build()
+}
+
+fn secp_case(name: &'static str, inputs: usize) -> Case {
+    let secp_tx = create_secp_tx();
+    let cell_deps: Vec<CellDep> = (0..2)
+        .map(|index| {
+            CellDep::new_builder()
+                .out_point(OutPoint::new(secp_tx.hash(), index))
+                .build()
+        })
+        .collect();
+    let resolved_cell_deps = (0..2)
+        .map(|index| {
+            let (output, data) = secp_tx.output_with_data(index).expect("secp cell");
+            cell_meta(output, data, OutPoint::new(secp_tx.hash(), index as u32))
+        })
+        .collect();
+
+    let (_, _, secp_script) = secp_cell();
+    let parent = parent_transaction(inputs, secp_script.clone());
+    let transaction = create_2out_transaction(parent.output_pts(), secp_script.clone(), cell_deps);
+
+    Case {
+        name,
+        rtx: ResolvedTransaction {
+            transaction,
+            resolved_cell_deps,
+            resolved_inputs: resolved_outputs(&parent),
+            resolved_dep_groups: vec![],
+        },
+        script_group_type: ScriptGroupType::Lock,
+        script_hash: secp_script.calc_script_hash(),
+    }
+}
+
+const MULTISIG_CELL: &str = "specs/cells/secp256k1_blake160_multisig_all";
+
+// The keys of the 2-of-3 multisig vectors
+const MULTISIG_PRIVKEYS: [H256; 3] = [
+    h256!("0x7d0b1cfe1fd4c2d2ad3e7fca8d5ec44bcbc1fa26f5c5e7dc37c18e2f4c3b2a91"),
+    h256!("0x2a5cd2ff9f1e1c0c3cb8a0f1e6a5c2d3b9f6c1e0a7d4b3c2e1f0a9b8c7d6e5f4"),
+    h256!("0x0e8f5f8b4a1cd23b7f5c6a9e2d4b1c3e5f7a9b2c4d6e8f0a1b3c5d7e9f2a4b6c"),
+];
+const MULTISIG_THRESHOLD: u8 = 2;
+
+// Unlocks a 2-of-3 multisig lock signed by the first two keys. The lock args are
+// the blake160 of the multisig script `0 | require_first_n | threshold | pubkeys_cnt |
+// blake160(pubkey)...`, and the witness of every input is the multisig script
+// followed by the signatures over the transaction hash, the message the sighash
+// lock signs too.
+fn multisig_case(name: &'static str, inputs: usize) -> Case {
+    let multisig_data: Bytes = BUNDLED_CELL.get(MULTISIG_CELL).expect("load multisig")[..].into();
+    let (secp_data_cell, secp_data) = secp_data_cell();
+    let deps_tx = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::null(), 0))
+        .output(secp_data_cell.clone())
+        .output_data(secp_data.pack())
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::bytes(multisig_data.len()).unwrap().pack())
+                .build(),
+        )
+        .output_data(multisig_data.pack())
+        .build();
+
+    let privkeys: Vec<Privkey> = MULTISIG_PRIVKEYS.iter().cloned().map(Into::into).collect();
+    let mut multisig_script = vec![0, 0, MULTISIG_THRESHOLD, privkeys.len() as u8];
+    for privkey in &privkeys {
+        let pubkey = privkey.pubkey().expect("multisig pubkey");
+        multisig_script.extend_from_slice(&blake2b_256(&pubkey.serialize())[..20]);
+    }
+    let multisig_lock = Script::new_builder()
+        .code_hash(CellOutput::calc_data_hash(&multisig_data))
+        .hash_type(ScriptHashType::Data.pack())
+        .args(Bytes::from(&blake2b_256(&multisig_script)[..20]).pack())
+        .build();
+
+    let parent = parent_transaction(inputs, multisig_lock.clone());
+    let raw = TransactionBuilder::default()
+        .inputs(
+            parent
+                .output_pts()
+                .into_iter()
+                .map(|out_point| CellInput::new(out_point, 0)),
+        )
+        .output(
+            CellOutput::new_builder()
+                .capacity(capacity_bytes!(50_000).pack())
+                .lock(multisig_lock.clone())
+                .build(),
+        )
+        .output_data(Bytes::new().pack())
+        .cell_deps(
+            (0..2)
+                .map(|index| {
+                    CellDep::new_builder()
+                        .out_point(OutPoint::new(deps_tx.hash(), index))
+                        .build()
+                })
+                .collect::<Vec<_>>(),
+        )
+        .build();
+
+    let mut message = [0u8; 32];
+    let mut blake2b = new_blake2b();
+    blake2b.update(&raw.hash().raw_data());
+    blake2b.finalize(&mut message);
+    let message = H256::from(message);
+    let mut witness = multisig_script;
+    for privkey in privkeys.iter().take(MULTISIG_THRESHOLD as usize) {
+        let signature = privkey.sign_recoverable(&message).expect("sign tx");
+        witness.extend_from_slice(&signature.serialize());
+    }
+    let witness = Bytes::from(witness);
+    let transaction = raw
+        .as_advanced_builder()
+        .witnesses(vec![witness.pack(); inputs])
+        .build();
+
+    Case {
+        name,
+        rtx: ResolvedTransaction {
+            transaction,
+            resolved_cell_deps: resolved_outputs(&deps_tx),
+            resolved_inputs: resolved_outputs(&parent),
+            resolved_dep_groups: vec![],
+        },
+        script_group_type: ScriptGroupType::Lock,
+        script_hash: multisig_lock.calc_script_hash(),
+    }
+}
+
+// Deposits into the DAO, which runs the DAO type script over the deposit
+// output.
+fn dao_deposit_case() -> Case {
+    let dao_data: Bytes = BUNDLED_CELL.get("specs/cells/dao").expect("load dao")[..].into();
+    let (always_success_cell, always_success_data, always_success_script) = always_success_cell();
+    let deps_tx = TransactionBuilder::default()
+        .output(always_success_cell.clone())
+        .output_data(always_success_data.pack())
+        .output(
+            CellOutput::new_builder()
+                .capacity(Capacity::bytes(dao_data.len()).unwrap().pack())
+                .build(),
+        )
+        .output_data(dao_data.pack())
+        .build();
+    let dao_script = Script::new_builder()
+        .code_hash(CellOutput::calc_data_hash(&dao_data))
+        .hash_type(ScriptHashType::Data.pack())
+        .build();
+
+    let parent = parent_transaction(1, always_success_script.clone());
+    let transaction = TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::new(parent.hash(), 0), 0))
+        .output(
+            CellOutput::new_builder()
+                .capacity(capacity_bytes!(50_000).pack())
+                .lock(always_success_script.clone())
+                .type_(Some(dao_script.clone()).pack())
+                .build(),
+        )
+        .output_data(Bytes::from(vec![0; 8]).pack())
+        .cell_deps(
+            (0..2)
+                .map(|index| {
+                    CellDep::new_builder()
+                        .out_point(OutPoint::new(deps_tx.hash(), index))
+                        .build()
+                })
+                .collect::<Vec<_>>(),
+        )
+        .build();
+
+    Case {
+        name: "dao_deposit",
+        rtx: ResolvedTransaction {
+            transaction,
+            resolved_cell_deps: resolved_outputs(&deps_tx),
+            resolved_inputs: resolved_outputs(&parent),
+            resolved_dep_groups: vec![],
+        },
+        script_group_type: ScriptGroupType::Type,
+        script_hash: dao_script.calc_script_hash(),
+    }
+}
+
+fn parent_transaction(outputs: usize, lock: Script) -> TransactionView {
+    let output = CellOutput::new_builder()
+        .capacity(capacity_bytes!(50_000).pack())
+        .lock(lock)
+        .build();
+    TransactionBuilder::default()
+        .input(CellInput::new(OutPoint::null(), 0))
+        .outputs(vec![output; outputs])
+        .outputs_data(vec![Bytes::new().pack(); outputs])
+        .build()
+}
+
+fn resolved_outputs(tx: &TransactionView) -> Vec<CellMeta> {
+    tx.outputs_with_data_iter()
+        .enumerate()
+        .map(|(index, (output, data))| {
+            cell_meta(output, data, OutPoint::new(tx.hash(), index as u32))
+        })
+        .collect()
+}
+
+fn corpus() -> Vec<Case> {
+    let mut cases = vec![
+        secp_case("secp_sighash_1_input", 1),
+        secp_case("secp_sighash_2_inputs", 2),
+        dao_deposit_case(),
+    ];
+    // The multisig lock is only bundled by the later system scripts
+    if BUNDLED_CELL.get(MULTISIG_CELL).is_ok() {
+        cases.push(multisig_case("secp_multisig_2_of_3_1_input", 1));
+        cases.push(multisig_case("secp_multisig_2_of_3_2_inputs", 2));
+    } else {
+        eprintln!(
+            "script_cost_model: skip the multisig cases, {} is not bundled",
+            MULTISIG_CELL
+        );
+    }
+    cases
+}
+
+// Time spent in the profiled entries, without the estimated overhead of
+// timing every instruction.
+fn measured(elapsed: Duration, count: u64, timer_overhead: Duration) -> Duration {
+    let overhead = (timer_overhead.as_nanos() as u64).saturating_mul(count);
+    Duration::from_nanos((elapsed.as_nanos() as u64).saturating_sub(overhead))
+}
+
+fn bench(c: &mut Criterion) {
+    let store = MockStore::default();
+    let data_loader = DataLoaderWrapper::new(store.store());
+    let timer_overhead = ScriptProfile::timer_overhead();
+
+    for case in corpus() {
+        let verifier = TransactionScriptsVerifier::new(&case.rtx, &data_loader);
+        let profile_single = || {
+            verifier
+                .profile_single(&case.script_group_type, &case.script_hash, MAX_CYCLES)
+                .expect("verify script group OK")
+        };
+        let profile = profile_single();
+
+        // Throughput is reported in cycles, so criterion shows cycles per second.
+        let mut group = c.benchmark_group(format!("script_cost_model/{}", case.name));
+        group.throughput(Throughput::Elements(profile.cycles));
+        group.bench_function("verify", |b| {
+            b.iter(|| {
+                verifier
+                    .verify_single(&case.script_group_type, &case.script_hash, MAX_CYCLES)
+                    .expect("verify script group OK")
+            })
+        });
+        group.finish();
+
+        let mut group = c.benchmark_group(format!("script_cost_model/{}/opcode", case.name));
+        for class in InstructionClass::all() {
+            let entry = match profile.instructions.get(class) {
+                Some(entry) => *entry,
+                None => continue,
+            };
+            group.throughput(Throughput::Elements(entry.cycles));
+            group.bench_function(class.as_str(), |b| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| {
+                            let entry = profile_single().instructions[class];
+                            measured(entry.elapsed, entry.count, timer_overhead)
+                        })
+                        .sum()
+                })
+            });
+        }
+        group.finish();
+
+        let mut group = c.benchmark_group(format!("script_cost_model/{}/syscall", case.name));
+        let mut numbers: Vec<u64> = profile.syscalls.keys().cloned().collect();
+        numbers.sort();
+        for number in numbers {
+            group.throughput(Throughput::Elements(profile.syscalls[&number].cycles));
+            group.bench_function(number.to_string(), |b| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| {
+                            let entry = profile_single().syscalls[&number];
+                            measured(entry.elapsed, entry.count, timer_overhead)
+                        })
+                        .sum()
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(
+    name = script_cost_model;
+    config = Criterion::default().sample_size(10);
+    targets = bench
+);
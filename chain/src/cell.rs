@@ -19,6 +19,7 @@ pub fn attach_block_cell(
                 o.get_mut().set_dead(cell.index().unpack());
                 if o.get().all_dead() {
                     txn.delete_cell_set(&cell.tx_hash())?;
+                    txn.insert_spent_transaction(block.number(), &block.hash(), &cell.tx_hash())?;
                     o.remove_entry();
                 } else {
                     txn.update_cell_set(&cell.tx_hash(), &o.get().pack())?;
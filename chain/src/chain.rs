@@ -29,10 +29,12 @@ use std::sync::Arc;
 use std::{cmp, thread};
 
 type ProcessBlockRequest = Request<(Arc<BlockView>, Switch), Result<bool, Error>>;
+type TruncateRequest = Request<Byte32, Result<(), Error>>;
 
 #[derive(Clone)]
 pub struct ChainController {
     process_block_sender: Sender<ProcessBlockRequest>,
+    truncate_sender: Sender<TruncateRequest>,
     stop: StopHandler<()>,
 }
 
@@ -58,10 +60,22 @@ impl ChainController {
                 .into())
         })
     }
+
+    /// Rolls the main chain back to the block, which becomes the tip.
+    ///
+    /// The detached blocks are kept as fork blocks, the same as a fork switch.
+    pub fn truncate(&self, target_tip_hash: Byte32) -> Result<(), Error> {
+        Request::call(&self.truncate_sender, target_tip_hash).unwrap_or_else(|| {
+            Err(InternalErrorKind::System
+                .reason("Chain service has gone")
+                .into())
+        })
+    }
 }
 
 struct ChainReceivers {
     process_block_receiver: Receiver<ProcessBlockRequest>,
+    truncate_receiver: Receiver<TruncateRequest>,
 }
 
 #[derive(Debug, Default)]
@@ -174,6 +188,7 @@ impl ChainService {
             crossbeam_channel::bounded::<()>(SIGNAL_CHANNEL_SIZE);
         let (process_block_sender, process_block_receiver) =
             crossbeam_channel::bounded(DEFAULT_CHANNEL_SIZE);
+        let (truncate_sender, truncate_receiver) = crossbeam_channel::bounded(SIGNAL_CHANNEL_SIZE);
 
         // Mainly for test: give a empty thread_name
         let mut thread_builder = thread::Builder::new();
@@ -183,7 +198,9 @@ impl ChainService {
 
         let receivers = ChainReceivers {
             process_block_receiver,
+            truncate_receiver,
         };
+        self.shared.spawn_pruning();
         let thread = thread_builder
             .spawn(move || loop {
                 select! {
@@ -198,6 +215,15 @@ impl ChainService {
                             error!("process_block_receiver closed");
                             break;
                         },
+                    },
+                    recv(receivers.truncate_receiver) -> msg => match msg {
+                        Ok(Request { responder, arguments: target_tip_hash }) => {
+                            let _ = responder.send(self.truncate(&target_tip_hash));
+                        },
+                        _ => {
+                            error!("truncate_receiver closed");
+                            break;
+                        },
                     }
                 }
             })
@@ -206,6 +232,7 @@ impl ChainService {
 
         ChainController {
             process_block_sender,
+            truncate_sender,
             stop,
         }
     }
@@ -240,6 +267,8 @@ impl ChainService {
     }
 
     fn insert_block(&mut self, block: Arc<BlockView>, switch: Switch) -> Result<bool, Error> {
+        // the pruning catch up writes the same keys, see `ChainDB::write_lock`
+        let write_guard = self.shared.store().write_lock();
         let db_txn = self.shared.store().begin_transaction();
         let txn_snapshot = db_txn.get_snapshot();
         let _snapshot_tip_hash = db_txn.get_update_for_tip_hash(&txn_snapshot);
@@ -332,6 +361,7 @@ impl ChainService {
             self.reconcile_main_chain(&db_txn, &mut fork, switch, &mut cell_set)?;
 
             db_txn.insert_tip_header(&block.header())?;
+            db_txn.prune(&block.header())?;
             if new_epoch || fork.has_detached() {
                 db_txn.insert_current_epoch_ext(&epoch)?;
             }
@@ -340,6 +370,7 @@ impl ChainService {
             db_txn.insert_block_ext(&block.header().hash(), &ext)?;
         }
         db_txn.commit()?;
+        drop(write_guard);
 
         if new_best_block {
             let tip_header = block.header().to_owned();
@@ -408,6 +439,114 @@ impl ChainService {
         Ok(true)
     }
 
+    // Detaches the main chain blocks after the target, in the same way as the
+    // fork switch in `insert_block`, then makes the target the tip.
+    pub(crate) fn truncate(&mut self, target_tip_hash: &Byte32) -> Result<(), Error> {
+        let write_guard = self.shared.store().write_lock();
+        let db_txn = self.shared.store().begin_transaction();
+        let txn_snapshot = db_txn.get_snapshot();
+        let _snapshot_tip_hash = db_txn.get_update_for_tip_hash(&txn_snapshot);
+
+        let target_tip_header = match txn_snapshot.get_block_header(target_tip_hash) {
+            Some(header)
+                if txn_snapshot.get_block_hash(header.number()).as_ref()
+                    == Some(target_tip_hash) =>
+            {
+                header
+            }
+            _ => {
+                return Err(InternalErrorKind::System
+                    .reason(format!(
+                        "block {} is not in the main chain",
+                        target_tip_hash
+                    ))
+                    .into());
+            }
+        };
+        // The transactions spent by the pruned blocks are deleted, so their
+        // cells can not be restored
+        if let Some(pruned_number) = txn_snapshot.get_pruned_number() {
+            if target_tip_header.number() + 1 < pruned_number {
+                return Err(InternalErrorKind::System
+                    .reason(format!(
+                        "can not roll back to block {}, the blocks before {} are pruned",
+                        target_tip_header.number(),
+                        pruned_number
+                    ))
+                    .into());
+            }
+        }
+
+        let shared_snapshot = Arc::clone(&self.shared.snapshot());
+        let mut cell_set = shared_snapshot.cell_set().clone();
+        let origin_proposals = shared_snapshot.proposals();
+        let current_tip_number = shared_snapshot.tip_header().number();
+
+        let mut fork = ForkChanges::default();
+        for number in target_tip_header.number() + 1..=current_tip_number {
+            let block = txn_snapshot
+                .get_block_hash(number)
+                .and_then(|hash| txn_snapshot.get_block(&hash))
+                .expect("main chain blocks stored before truncate");
+            fork.detached_blocks.push_back(block);
+        }
+
+        let epoch = txn_snapshot
+            .get_block_epoch(target_tip_hash)
+            .expect("target tip epoch stored before truncate");
+        let total_difficulty = txn_snapshot
+            .get_block_ext(target_tip_hash)
+            .expect("target tip ext stored before truncate")
+            .total_difficulty;
+
+        self.rollback(&fork, &db_txn, &mut cell_set)?;
+        db_txn.insert_tip_header(&target_tip_header)?;
+        db_txn.insert_current_epoch_ext(&epoch)?;
+        db_txn.commit()?;
+        drop(write_guard);
+
+        info!(
+            "truncate to block: {}, hash: {}, detached {} blocks",
+            target_tip_header.number(),
+            target_tip_header.hash(),
+            fork.detached_blocks().len()
+        );
+
+        self.update_proposal_table(&fork);
+        let (detached_proposal_id, new_proposals) = self
+            .proposal_table
+            .finalize(origin_proposals, target_tip_header.number());
+        fork.detached_proposal_id = detached_proposal_id;
+
+        let new_snapshot = self.shared.new_snapshot(
+            target_tip_header,
+            total_difficulty,
+            epoch,
+            cell_set,
+            new_proposals,
+        );
+        self.shared.store_snapshot(Arc::clone(&new_snapshot));
+
+        if let Err(e) = self.shared.tx_pool_controller().update_tx_pool_for_reorg(
+            fork.detached_blocks().clone(),
+            fork.attached_blocks().clone(),
+            fork.detached_proposal_id().clone(),
+            new_snapshot,
+        ) {
+            error!("notify update_tx_pool_for_reorg error {}", e);
+        }
+        for detached_block in fork.detached_blocks() {
+            if let Err(e) = self
+                .shared
+                .tx_pool_controller()
+                .notify_new_uncle(detached_block.as_uncle())
+            {
+                error!("notify new_uncle error {}", e);
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn update_proposal_table(&mut self, fork: &ForkChanges) {
         for blk in fork.detached_blocks() {
             self.proposal_table.remove(blk.header().number());
@@ -566,7 +705,15 @@ impl ChainService {
             attach_block_cell(txn, b, cell_set)?;
         }
 
-        let verify_context = VerifyContext::new(txn, self.shared.consensus());
+        let verification_config = self.shared.verification_config();
+        let trace_script = verification_config
+            .trace_script_hash
+            .as_ref()
+            .map(|hash| (hash.pack(), verification_config.trace_dir.as_path()));
+        let verify_context = VerifyContext::new(txn, self.shared.consensus())
+            .parallel_script_groups(verification_config.parallel_script_groups)
+            .thread_pool(self.shared.block_verify_pool())
+            .trace_script(trace_script);
         let future_executor = self.shared.tx_pool_controller().executor();
 
         let mut found_error = None;
@@ -0,0 +1,177 @@
+//! Rebuilds the cell set, the transaction infos, the uncles, the main chain
+//! index and the epoch indexes from the stored headers and bodies.
+use crate::cell::attach_block_cell;
+use ckb_chain_spec::consensus::Consensus;
+use ckb_error::{Error, InternalErrorKind};
+use ckb_store::{
+    ChainDB, ChainStore, StoreTransaction, COLUMN_CELL_SET, COLUMN_INDEX, COLUMN_TRANSACTION_INFO,
+    COLUMN_UNCLES,
+};
+use ckb_types::{
+    core::{BlockNumber, BlockView, EpochExt, TransactionMeta},
+    packed::Byte32,
+    prelude::*,
+};
+use im::hashmap::HashMap as HamtMap;
+
+const DEFAULT_BATCH_SIZE: BlockNumber = 1000;
+const CLEAR_BATCH_SIZE: usize = 10_000;
+
+/// The reindex attaches the main chain blocks in order, the same as the chain
+/// service, committing every `batch_size` blocks with the number of the next
+/// block, so it resumes from there if it is interrupted.
+///
+/// The block epoch indexes of the fork blocks are kept, the chain service
+/// needs them to attach the blocks building on them.
+pub struct Reindexer<'a> {
+    store: &'a ChainDB,
+    consensus: &'a Consensus,
+    batch_size: BlockNumber,
+}
+
+impl<'a> Reindexer<'a> {
+    pub fn new(store: &'a ChainDB, consensus: &'a Consensus) -> Self {
+        Reindexer {
+            store,
+            consensus,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    pub fn batch_size(mut self, batch_size: BlockNumber) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Whether an interrupted reindex is pending
+    pub fn is_resuming(&self) -> bool {
+        self.store.get_reindex_number().is_some()
+    }
+
+    /// Reindexes the main chain, calls `progress` with the number of the
+    /// reindexed blocks and the number of all the blocks after each batch.
+    pub fn reindex<F>(&self, mut progress: F) -> Result<(), Error>
+    where
+        F: FnMut(BlockNumber, BlockNumber),
+    {
+        if self.store.get_pruned_number().is_some() {
+            return Err(InternalErrorKind::Database
+                .reason("can not reindex a pruned database, whose old blocks are deleted")
+                .into());
+        }
+        let main_chain = self.main_chain()?;
+        let total = main_chain.len() as BlockNumber;
+
+        let start = match self.store.get_reindex_number() {
+            Some(number) if number > 0 => number,
+            _ => {
+                let txn = self.store.begin_transaction();
+                txn.insert_reindex_number(0)?;
+                txn.commit()?;
+                for col in [
+                    COLUMN_CELL_SET,
+                    COLUMN_TRANSACTION_INFO,
+                    COLUMN_UNCLES,
+                    COLUMN_INDEX,
+                ]
+                .iter()
+                {
+                    self.store.clear_column(*col, CLEAR_BATCH_SIZE)?;
+                }
+                0
+            }
+        };
+        let mut cell_set = self.load_cell_set()?;
+        progress(start, total);
+
+        let mut number = start;
+        while number < total {
+            let end = (number + self.batch_size).min(total);
+            let txn = self.store.begin_transaction();
+            for hash in &main_chain[number as usize..end as usize] {
+                let block = txn.get_block(hash).ok_or_else(|| {
+                    InternalErrorKind::Database.reason(format!("block {} is missing", hash))
+                })?;
+                self.attach_block(&txn, &block, &mut cell_set)?;
+            }
+            if end == total {
+                let tip_epoch = txn
+                    .get_block_epoch(&main_chain[main_chain.len() - 1])
+                    .expect("tip epoch is reindexed");
+                txn.insert_current_epoch_ext(&tip_epoch)?;
+                txn.delete_reindex_number()?;
+            } else {
+                txn.insert_reindex_number(end)?;
+            }
+            txn.commit()?;
+            number = end;
+            progress(number, total);
+        }
+        Ok(())
+    }
+
+    // The same as `ChainService::insert_block` and `reconcile_main_chain`,
+    // without the verification and the block exts, which are kept.
+    fn attach_block(
+        &self,
+        txn: &StoreTransaction,
+        block: &BlockView,
+        cell_set: &mut HamtMap<Byte32, TransactionMeta>,
+    ) -> Result<(), Error> {
+        let epoch = self.block_epoch(txn, block)?;
+        txn.insert_block_epoch_index(&block.hash(), &epoch.last_block_hash_in_previous_epoch())?;
+        txn.insert_epoch_ext(&epoch.last_block_hash_in_previous_epoch(), &epoch)?;
+        txn.attach_block(block)?;
+        attach_block_cell(txn, block, cell_set)
+    }
+
+    fn block_epoch(&self, txn: &StoreTransaction, block: &BlockView) -> Result<EpochExt, Error> {
+        if block.is_genesis() {
+            return Ok(self.consensus.genesis_epoch_ext().to_owned());
+        }
+        let parent_hash = block.parent_hash();
+        let parent_header = txn.get_block_header(&parent_hash);
+        let parent_epoch = txn.get_block_epoch(&parent_hash);
+        match (parent_header, parent_epoch) {
+            (Some(parent_header), Some(parent_epoch)) => Ok(txn
+                .next_epoch_ext(self.consensus, &parent_epoch, &parent_header)
+                .unwrap_or(parent_epoch)),
+            _ => Err(InternalErrorKind::Database
+                .reason(format!(
+                    "the parent epoch of block {} is missing",
+                    block.hash()
+                ))
+                .into()),
+        }
+    }
+
+    // Collects the main chain hashes from the genesis to the tip by the parent
+    // hashes, the main chain index may be broken.
+    fn main_chain(&self) -> Result<Vec<Byte32>, Error> {
+        let tip = self
+            .store
+            .get_tip_header()
+            .ok_or_else(|| InternalErrorKind::Database.reason("the tip header is missing"))?;
+        let mut hashes = Vec::with_capacity(tip.number() as usize + 1);
+        let mut header = tip;
+        while header.number() > 0 {
+            let parent_hash = header.parent_hash();
+            hashes.push(header.hash());
+            header = self.store.get_block_header(&parent_hash).ok_or_else(|| {
+                InternalErrorKind::Database.reason(format!("header {} is missing", parent_hash))
+            })?;
+        }
+        hashes.push(header.hash());
+        hashes.reverse();
+        Ok(hashes)
+    }
+
+    fn load_cell_set(&self) -> Result<HamtMap<Byte32, TransactionMeta>, Error> {
+        let mut cell_set = HamtMap::new();
+        self.store.traverse_cell_set(|tx_hash, tx_meta| {
+            cell_set.insert(tx_hash, tx_meta.unpack());
+            Ok(())
+        })?;
+        Ok(cell_set)
+    }
+}
@@ -13,10 +13,17 @@ bitflags! {
         const DISABLE_REWARD            = 0b00010000;
         const DISABLE_TXS               = 0b00100000;
         const DISABLE_NON_CONTEXTUAL    = 0b01000000;
+        const DISABLE_SCRIPT            = 0b10000000;
         const DISABLE_ALL               = Self::DISABLE_EPOCH.bits | Self::DISABLE_UNCLES.bits |
                                     Self::DISABLE_TWO_PHASE_COMMIT.bits | Self::DISABLE_DAOHEADER.bits |
                                     Self::DISABLE_REWARD.bits | Self::DISABLE_TXS.bits |
                                     Self::DISABLE_NON_CONTEXTUAL.bits;
+        // Trusted blocks, e.g. before an assume valid block, skip the script
+        // execution and the contextual checks which only reject blocks. The
+        // checks deriving the chain state (epoch, dao, reward and cells) are
+        // still done.
+        const ASSUME_VALID              = Self::DISABLE_SCRIPT.bits | Self::DISABLE_UNCLES.bits |
+                                    Self::DISABLE_TWO_PHASE_COMMIT.bits;
     }
 }
 
@@ -49,4 +56,7 @@ impl VerificationSwitch for Switch {
     fn disable_txs(&self) -> bool {
         self.contains(Switch::DISABLE_TXS)
     }
+    fn disable_script(&self) -> bool {
+        self.contains(Switch::DISABLE_SCRIPT)
+    }
 }
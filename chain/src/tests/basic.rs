@@ -1,7 +1,7 @@
 use crate::tests::util::{
     create_always_success_tx, create_cellbase, create_multi_outputs_transaction,
-    create_transaction, create_transaction_with_out_point, dao_data, start_chain, MockChain,
-    MockStore,
+    create_transaction, create_transaction_with_out_point, dao_data, start_chain,
+    start_chain_with_verification_config, MockChain, MockStore,
 };
 use crate::{chain::ChainController, switch::Switch};
 use ckb_chain_spec::consensus::{Consensus, ConsensusBuilder};
@@ -9,6 +9,7 @@ use ckb_dao_utils::genesis_dao_data;
 use ckb_error::assert_error_eq;
 use ckb_shared::shared::Shared;
 use ckb_store::ChainStore;
+use ckb_test_chain_utils::always_success_cell;
 use ckb_types::core::error::OutPointError;
 use ckb_types::prelude::*;
 use ckb_types::{
@@ -16,13 +17,15 @@ use ckb_types::{
     core::{
         capacity_bytes,
         cell::{CellMeta, CellProvider, CellStatus},
-        BlockBuilder, BlockView, Capacity, EpochExt, HeaderView, TransactionBuilder,
-        TransactionInfo,
+        BlockBuilder, BlockView, Capacity, EpochExt, EpochNumberWithFraction, HeaderView,
+        ScriptHashType, TransactionBuilder, TransactionInfo, TransactionView,
     },
-    packed::{CellInput, CellOutputBuilder, OutPoint, Script},
-    utilities::difficulty_to_compact,
+    h256,
+    packed::{CellInput, CellOutput, CellOutputBuilder, OutPoint, Script},
+    utilities::{difficulty_to_compact, DIFF_TWO},
     U256,
 };
+use ckb_verification::{BlockError, BlockErrorKind, BlockTransactionsError, VerificationConfig};
 use std::sync::Arc;
 
 #[test]
@@ -209,6 +212,256 @@ fn test_transaction_spend_in_same_block() {
     );
 }
 
+#[test]
+fn test_parallel_block_verification() {
+    let verification_config = VerificationConfig {
+        parallel_script_groups: true,
+        block_verify_threads: 2,
+        ..Default::default()
+    };
+    let (chain_controller, shared, parent) =
+        start_chain_with_verification_config(None, verification_config);
+    let mock_store = MockStore::new(&parent, shared.store());
+    let mut chain = MockChain::new(parent.clone(), shared.consensus());
+    chain.gen_empty_block(&mock_store);
+
+    let last_cell_base = &chain.tip().transactions()[0];
+    let tx1 = create_multi_outputs_transaction(&last_cell_base, vec![0], 2, vec![1]);
+    let tx2 = create_multi_outputs_transaction(&tx1, vec![0], 2, vec![2]);
+    let txs = vec![tx1.clone(), tx2.clone()];
+
+    chain.gen_block_with_proposal_txs(txs.clone(), &mock_store);
+    chain.gen_empty_block(&mock_store);
+    chain.gen_block_with_commit_txs(txs, &mock_store, false);
+
+    for block in chain.blocks() {
+        chain_controller
+            .internal_process_block(Arc::new(block.clone()), Switch::DISABLE_EPOCH)
+            .expect("process block ok");
+    }
+
+    let tip_hash = shared.snapshot().tip_header().hash();
+    assert_eq!(
+        shared.store().get_block_ext(&tip_hash).unwrap().verified,
+        Some(true)
+    );
+    assert_eq!(
+        shared.snapshot().cell(&OutPoint::new(tx1.hash(), 0), false),
+        CellStatus::Dead
+    );
+    assert!(shared
+        .snapshot()
+        .cell(&OutPoint::new(tx2.hash(), 0), false)
+        .is_live());
+}
+
+#[test]
+fn test_trace_script_in_block_verification() {
+    let trace_dir = tempfile::tempdir().unwrap();
+    let (_, _, always_success_script) = always_success_cell();
+    let always_success_hash = always_success_script.calc_script_hash();
+    let verification_config = VerificationConfig {
+        trace_script_hash: Some(always_success_hash.unpack()),
+        trace_dir: trace_dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let (chain_controller, shared, parent) =
+        start_chain_with_verification_config(None, verification_config);
+    let mock_store = MockStore::new(&parent, shared.store());
+    let mut chain = MockChain::new(parent.clone(), shared.consensus());
+    chain.gen_empty_block(&mock_store);
+
+    let last_cell_base = &chain.tip().transactions()[0];
+    let tx = create_multi_outputs_transaction(&last_cell_base, vec![0], 2, vec![1]);
+    chain.gen_block_with_proposal_txs(vec![tx.clone()], &mock_store);
+    chain.gen_empty_block(&mock_store);
+    chain.gen_block_with_commit_txs(vec![tx.clone()], &mock_store, false);
+
+    for block in chain.blocks() {
+        chain_controller
+            .internal_process_block(Arc::new(block.clone()), Switch::DISABLE_EPOCH)
+            .expect("process block ok");
+    }
+
+    let trace_path = trace_dir.path().join(format!(
+        "{:x}-lock-{:x}.jsonl",
+        tx.hash(),
+        always_success_hash
+    ));
+    let trace = std::fs::read_to_string(trace_path).expect("trace file");
+    assert!(trace.lines().count() > 0);
+    // the cellbases have no inputs, so no other transaction is traced
+    assert_eq!(std::fs::read_dir(trace_dir.path()).unwrap().count(), 1);
+}
+
+// Locks the outputs of the transaction by a script which can't be found
+fn lock_by_missing_script(tx: TransactionView) -> TransactionView {
+    let missing_lock = Script::new_builder()
+        .code_hash(h256!("0x1").pack())
+        .hash_type(ScriptHashType::Data.pack())
+        .build();
+    let outputs: Vec<CellOutput> = tx
+        .outputs()
+        .into_iter()
+        .map(|output| output.as_builder().lock(missing_lock.clone()).build())
+        .collect();
+    tx.as_advanced_builder().set_outputs(outputs).build()
+}
+
+#[test]
+fn test_parallel_block_verification_reports_first_invalid_tx() {
+    let verification_config = VerificationConfig {
+        parallel_script_groups: true,
+        block_verify_threads: 4,
+        ..Default::default()
+    };
+    let (chain_controller, shared, parent) =
+        start_chain_with_verification_config(None, verification_config);
+    let mock_store = MockStore::new(&parent, shared.store());
+    let mut chain = MockChain::new(parent.clone(), shared.consensus());
+    chain.gen_empty_block(&mock_store);
+
+    // all the transactions spending the outputs of tx1 fail in the script verification
+    let last_cell_base = &chain.tip().transactions()[0];
+    let tx1 = lock_by_missing_script(create_multi_outputs_transaction(
+        &last_cell_base,
+        vec![0],
+        4,
+        vec![1],
+    ));
+    let mut txs = vec![tx1.clone()];
+    for index in 0..4 {
+        txs.push(create_multi_outputs_transaction(
+            &tx1,
+            vec![index],
+            1,
+            vec![index as u8 + 2],
+        ));
+    }
+
+    chain.gen_block_with_proposal_txs(txs.clone(), &mock_store);
+    chain.gen_empty_block(&mock_store);
+    chain.gen_block_with_commit_txs(txs, &mock_store, false);
+    let (last, blocks) = chain.blocks().split_last().unwrap();
+
+    for block in blocks {
+        chain_controller
+            .internal_process_block(Arc::new(block.clone()), Switch::DISABLE_EPOCH)
+            .expect("process block ok");
+    }
+    let error = chain_controller
+        .internal_process_block(Arc::new(last.clone()), Switch::DISABLE_EPOCH)
+        .unwrap_err();
+    let error = error
+        .downcast_ref::<BlockError>()
+        .and_then(BlockError::downcast_ref::<BlockTransactionsError>)
+        .expect("block transactions error");
+    // the cellbase and tx1 are valid
+    assert_eq!(error.index, 2);
+}
+
+#[test]
+fn test_parallel_block_verification_exceeds_max_block_cycles() {
+    let tx = create_always_success_tx();
+    let dao = genesis_dao_data(vec![&tx]).unwrap();
+    let genesis_block = BlockBuilder::default()
+        .dao(dao)
+        .compact_target(DIFF_TWO.pack())
+        .transaction(tx)
+        .build();
+    // the always success lock takes 537 cycles, only one transaction fits
+    let consensus = ConsensusBuilder::default()
+        .cellbase_maturity(EpochNumberWithFraction::new(0, 0, 1))
+        .genesis_block(genesis_block)
+        .max_block_cycles(1000)
+        .build();
+    let verification_config = VerificationConfig {
+        parallel_script_groups: true,
+        block_verify_threads: 4,
+        ..Default::default()
+    };
+    let (chain_controller, shared, parent) =
+        start_chain_with_verification_config(Some(consensus), verification_config);
+    let mock_store = MockStore::new(&parent, shared.store());
+    let mut chain = MockChain::new(parent.clone(), shared.consensus());
+    chain.gen_empty_block(&mock_store);
+
+    // tx4 is invalid, but the cycles are exceeded by tx2 before it
+    let last_cell_base = &chain.tip().transactions()[0];
+    let tx1 = create_multi_outputs_transaction(&last_cell_base, vec![0], 2, vec![1]);
+    let tx2 = create_multi_outputs_transaction(&tx1, vec![0], 1, vec![2]);
+    let tx3 = lock_by_missing_script(create_multi_outputs_transaction(&tx1, vec![1], 1, vec![3]));
+    let tx4 = create_multi_outputs_transaction(&tx3, vec![0], 1, vec![4]);
+    let txs = vec![tx1, tx2, tx3, tx4];
+
+    chain.gen_block_with_proposal_txs(txs.clone(), &mock_store);
+    chain.gen_empty_block(&mock_store);
+    chain.gen_block_with_commit_txs(txs, &mock_store, false);
+    let (last, blocks) = chain.blocks().split_last().unwrap();
+
+    for block in blocks {
+        chain_controller
+            .internal_process_block(Arc::new(block.clone()), Switch::DISABLE_EPOCH)
+            .expect("process block ok");
+    }
+    assert_error_eq!(
+        BlockErrorKind::ExceededMaximumCycles,
+        chain_controller
+            .internal_process_block(Arc::new(last.clone()), Switch::DISABLE_EPOCH)
+            .unwrap_err(),
+    );
+}
+
+#[test]
+fn test_assume_valid_skips_script() {
+    let (chain_controller, shared, parent) = start_chain(None);
+    let mock_store = MockStore::new(&parent, shared.store());
+    let mut chain = MockChain::new(parent.clone(), shared.consensus());
+    chain.gen_empty_block(&mock_store);
+
+    // the outputs of tx1 are locked by a missing script, spending them fails
+    // in the script verification
+    let last_cell_base = &chain.tip().transactions()[0];
+    let tx1 = create_multi_outputs_transaction(&last_cell_base, vec![0], 2, vec![1]);
+    let missing_lock = Script::new_builder()
+        .code_hash(h256!("0x1").pack())
+        .hash_type(ScriptHashType::Data.pack())
+        .build();
+    let outputs: Vec<CellOutput> = tx1
+        .outputs()
+        .into_iter()
+        .map(|output| output.as_builder().lock(missing_lock.clone()).build())
+        .collect();
+    let tx1 = tx1.as_advanced_builder().set_outputs(outputs).build();
+    let tx2 = create_multi_outputs_transaction(&tx1, vec![0], 2, vec![2]);
+    let txs = vec![tx1, tx2];
+
+    chain.gen_block_with_proposal_txs(txs.clone(), &mock_store);
+    chain.gen_empty_block(&mock_store);
+    chain.gen_block_with_commit_txs(txs, &mock_store, false);
+    let (last, blocks) = chain.blocks().split_last().unwrap();
+
+    for block in blocks {
+        chain_controller
+            .internal_process_block(Arc::new(block.clone()), Switch::DISABLE_EPOCH)
+            .expect("process block ok");
+    }
+    assert!(chain_controller
+        .internal_process_block(Arc::new(last.clone()), Switch::DISABLE_EPOCH)
+        .is_err());
+
+    let (chain_controller, shared, _) = start_chain(None);
+    for block in chain.blocks() {
+        chain_controller
+            .internal_process_block(
+                Arc::new(block.clone()),
+                Switch::ASSUME_VALID | Switch::DISABLE_EPOCH,
+            )
+            .expect("process block ok");
+    }
+    assert_eq!(shared.snapshot().tip_header().hash(), last.hash());
+}
+
 #[test]
 fn test_transaction_conflict_in_same_block() {
     let (chain_controller, shared, parent) = start_chain(None);
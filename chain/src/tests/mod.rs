@@ -2,5 +2,7 @@ mod basic;
 mod block_assembler;
 mod delay_verify;
 mod find_fork;
+mod reindex;
 mod reward;
+mod truncate;
 mod util;
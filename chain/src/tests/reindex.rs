@@ -0,0 +1,97 @@
+use crate::reindex::Reindexer;
+use crate::switch::Switch;
+use crate::tests::util::{create_transaction, start_chain, MockChain, MockStore};
+use ckb_store::{ChainStore, COLUMN_CELL_SET, COLUMN_INDEX, COLUMN_TRANSACTION_INFO};
+use ckb_types::prelude::*;
+use std::sync::Arc;
+
+#[test]
+fn reindex_rebuilds_cell_set_and_indexes() {
+    let (chain_controller, shared, parent) = start_chain(None);
+    let mock_store = MockStore::new(&parent, shared.store());
+    let mut chain = MockChain::new(parent.clone(), shared.consensus());
+    for _ in 0..10 {
+        chain.gen_empty_block(&mock_store);
+    }
+    // Each transaction spends the cellbase of the first block or the output
+    // of the previous one, so the replay deletes the spent transactions
+    let mut spent_hash = chain.blocks()[0].transactions()[0].hash();
+    for i in 0..10 {
+        let tx = create_transaction(&spent_hash, i);
+        spent_hash = tx.hash();
+        chain.gen_block_with_commit_txs(vec![tx], &mock_store, false);
+    }
+    for block in chain.blocks() {
+        chain_controller
+            .internal_process_block(Arc::new(block.clone()), Switch::DISABLE_ALL)
+            .expect("process block ok");
+    }
+
+    let store = shared.store();
+    let snapshot = |store: &ckb_store::ChainDB| {
+        chain
+            .blocks()
+            .iter()
+            .map(|block| {
+                let txs = block
+                    .transactions()
+                    .iter()
+                    .map(|tx| {
+                        (
+                            store.get_transaction_info(&tx.hash()),
+                            store.get_tx_meta(&tx.hash()),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                (
+                    store.get_block_hash(block.number()),
+                    store.get_block_number(&block.hash()),
+                    txs,
+                    store.get_block_epoch(&block.hash()),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let expected = snapshot(store);
+    let expected_epoch = store.get_current_epoch_ext();
+    let first_spent = chain.blocks()[0].transactions()[0].hash();
+    assert!(store.get_tx_meta(&first_spent).is_none());
+    assert!(store.get_tx_meta(&spent_hash).is_some());
+
+    store.clear_column(COLUMN_INDEX, 4).unwrap();
+    store.clear_column(COLUMN_CELL_SET, 4).unwrap();
+    store.clear_column(COLUMN_TRANSACTION_INFO, 4).unwrap();
+    assert_ne!(snapshot(store), expected);
+
+    let reindexer = Reindexer::new(store, shared.consensus()).batch_size(6);
+    assert!(!reindexer.is_resuming());
+    let mut reports = Vec::new();
+    reindexer
+        .reindex(|reindexed, total| reports.push((reindexed, total)))
+        .expect("reindex");
+    assert_eq!(
+        reports,
+        vec![(0, 21), (6, 21), (12, 21), (18, 21), (21, 21)]
+    );
+    assert_eq!(snapshot(store), expected);
+    assert_eq!(store.get_current_epoch_ext(), expected_epoch);
+    assert!(store.get_tx_meta(&first_spent).is_none());
+    assert!(store.get_tx_meta(&spent_hash).is_some());
+
+    // An interrupted reindex resumes from the recorded number
+    let txn = store.begin_transaction();
+    txn.insert_reindex_number(12).unwrap();
+    txn.commit().unwrap();
+    assert!(reindexer.is_resuming());
+    reports.clear();
+    reindexer
+        .reindex(|reindexed, total| reports.push((reindexed, total)))
+        .expect("reindex");
+    assert_eq!(reports, vec![(12, 21), (18, 21), (21, 21)]);
+    assert!(!reindexer.is_resuming());
+    assert_eq!(snapshot(store), expected);
+    assert_eq!(
+        store.get_tip_header().map(|tip| tip.hash()),
+        Some(chain.tip_header().hash())
+    );
+}
@@ -0,0 +1,58 @@
+use crate::tests::util::{start_chain, MockChain, MockStore};
+use ckb_store::ChainStore;
+use ckb_types::prelude::*;
+use std::sync::Arc;
+
+#[test]
+fn truncate_to_main_chain_block() {
+    let (chain_controller, shared, parent) = start_chain(None);
+    let mock_store = MockStore::new(&parent, shared.store());
+    let mut chain = MockChain::new(parent.clone(), shared.consensus());
+    for _ in 0..10 {
+        chain.gen_empty_block(&mock_store);
+    }
+    for block in chain.blocks() {
+        chain_controller
+            .process_block(Arc::new(block.clone()))
+            .expect("process block ok");
+    }
+
+    let target = chain.blocks()[4].clone();
+    let detached = chain.blocks()[5].clone();
+    chain_controller
+        .truncate(target.hash())
+        .expect("truncate ok");
+
+    let snapshot = shared.snapshot();
+    assert_eq!(snapshot.tip_header(), &target.header());
+    assert_eq!(
+        shared.store().get_tip_header().map(|tip| tip.hash()),
+        Some(target.hash())
+    );
+    assert_eq!(
+        shared.store().get_current_epoch_ext(),
+        shared.store().get_block_epoch(&target.hash())
+    );
+    assert!(shared.store().get_block_hash(detached.number()).is_none());
+    let target_cellbase = target.transactions()[0].hash();
+    let detached_cellbase = detached.transactions()[0].hash();
+    assert!(snapshot.cell_set().contains_key(&target_cellbase));
+    assert!(!snapshot.cell_set().contains_key(&detached_cellbase));
+    assert!(shared
+        .store()
+        .get_transaction_info(&detached_cellbase)
+        .is_none());
+    // The detached blocks are kept as fork blocks
+    assert!(shared.store().get_block(&detached.hash()).is_some());
+
+    // The detached blocks are not in the main chain any more
+    assert!(chain_controller.truncate(detached.hash()).is_err());
+
+    let mut fork = MockChain::new(target.header(), shared.consensus());
+    fork.gen_empty_block_with_nonce(100, &mock_store);
+    let new_tip = fork.tip().clone();
+    assert!(chain_controller
+        .process_block(Arc::new(new_tip.clone()))
+        .expect("process block ok"));
+    assert_eq!(shared.snapshot().tip_header(), &new_tip.header());
+}
@@ -20,6 +20,7 @@ use ckb_types::{
     utilities::{difficulty_to_compact, DIFF_TWO},
     U256,
 };
+use ckb_verification::VerificationConfig;
 use std::collections::HashSet;
 
 const MIN_CAP: Capacity = capacity_bytes!(60);
@@ -41,7 +42,14 @@ pub(crate) fn create_always_success_out_point() -> OutPoint {
 }
 
 pub(crate) fn start_chain(consensus: Option<Consensus>) -> (ChainController, Shared, HeaderView) {
-    let builder = SharedBuilder::default();
+    start_chain_with_verification_config(consensus, VerificationConfig::default())
+}
+
+pub(crate) fn start_chain_with_verification_config(
+    consensus: Option<Consensus>,
+    verification_config: VerificationConfig,
+) -> (ChainController, Shared, HeaderView) {
+    let builder = SharedBuilder::default().verification_config(verification_config);
     let consensus = consensus.unwrap_or_else(|| {
         let tx = create_always_success_tx();
         let dao = genesis_dao_data(vec![&tx]).unwrap();
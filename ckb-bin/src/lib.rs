@@ -20,6 +20,9 @@ pub fn run_app(version: Version) -> Result<(), ExitCode> {
                 (cli::CMD_SECP256K1_LOCK, Some(sub_matches)) => {
                     subcommand::cli::secp256k1_lock(sub_matches)
                 }
+                (cli::CMD_DECODE_CAPTURE, Some(sub_matches)) => {
+                    subcommand::cli::decode_capture(sub_matches)
+                }
                 (cli::CMD_HASHES, Some(sub_matches)) => {
                     subcommand::cli::hashes(Setup::root_dir_from_matches(&matches)?, sub_matches)
                 }
@@ -42,6 +45,20 @@ pub fn run_app(version: Version) -> Result<(), ExitCode> {
         (cli::CMD_IMPORT, Some(matches)) => subcommand::import(setup.import(&matches)?),
         (cli::CMD_STATS, Some(matches)) => subcommand::stats(setup.stats(&matches)?),
         (cli::CMD_RESET_DATA, Some(matches)) => subcommand::reset_data(setup.reset_data(&matches)?),
+        (cli::CMD_MIGRATE, _) => subcommand::migrate(setup.migrate()?),
+        (cli::CMD_BACKUP, Some(matches)) => subcommand::backup(setup.backup(&matches)?),
+        (cli::CMD_RESTORE, Some(matches)) => subcommand::restore(setup.restore(&matches)?),
+        (cli::CMD_DB, Some(matches)) => match matches.subcommand() {
+            (cli::CMD_STATS, _) => subcommand::db::stats(setup.db_stats()?),
+            (cli::CMD_CHECK, Some(sub_matches)) => {
+                subcommand::db::check(setup.db_check(&sub_matches)?)
+            }
+            (cli::CMD_REINDEX, _) => subcommand::db::reindex(setup.db_reindex()?),
+            (cli::CMD_ROLLBACK, Some(sub_matches)) => {
+                subcommand::db::rollback(setup.db_rollback(&sub_matches)?)
+            }
+            _ => unreachable!(),
+        },
         _ => unreachable!(),
     }
 }
@@ -0,0 +1,49 @@
+use ckb_app_config::{BackupArgs, ExitCode};
+use ckb_db::{DBConfig, RocksDB};
+use ckb_rpc::{BACKUP_DB_DIR, BACKUP_INDEXER_DB_DIR};
+use std::fs;
+use std::path::Path;
+
+pub fn backup(args: BackupArgs) -> Result<(), ExitCode> {
+    if args.target.exists() {
+        eprintln!("Backup error: {:?} already exists", args.target);
+        return Err(ExitCode::Failure);
+    }
+    fs::create_dir_all(&args.target).map_err(|err| {
+        eprintln!("Backup error: {}", err);
+        ExitCode::IO
+    })?;
+
+    // Same order as the `create_backup` RPC, the indexer first.
+    backup_db(
+        "indexer database",
+        &args.config.indexer.db,
+        ckb_indexer::COLUMNS,
+        &args.target.join(BACKUP_INDEXER_DB_DIR),
+    )?;
+    backup_db(
+        "database",
+        &args.config.db,
+        ckb_store::COLUMNS,
+        &args.target.join(BACKUP_DB_DIR),
+    )?;
+    println!("Backed up to {:?}", args.target);
+    Ok(())
+}
+
+fn backup_db(name: &str, config: &DBConfig, columns: u32, target: &Path) -> Result<(), ExitCode> {
+    if !config.path.exists() {
+        println!("Skip the {}, {:?} does not exist", name, config.path);
+        return Ok(());
+    }
+    RocksDB::open_with_error(config, columns)
+        .and_then(|db| db.create_checkpoint(target))
+        .map_err(|err| {
+            eprintln!(
+                "Back up the {} error: {}\n\
+                 If the node is running, use the `create_backup` RPC instead",
+                name, err
+            );
+            ExitCode::Failure
+        })
+}
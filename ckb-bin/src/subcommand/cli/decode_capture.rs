@@ -0,0 +1,274 @@
+use ckb_app_config::{cli, ExitCode};
+use ckb_jsonrpc_types::{
+    Block, Header, JsonBytes, ProposalShortId, Transaction, Uint32, Uint64, UncleBlock,
+};
+use ckb_network::{
+    capture::{CaptureReader, CapturedMessage, Direction},
+    ProtocolId,
+};
+use ckb_sync::NetworkProtocol;
+use ckb_types::{packed, prelude::*, H256};
+use clap::ArgMatches;
+use serde_derive::Serialize;
+
+#[derive(Serialize)]
+struct CapturedRecord {
+    timestamp: u64,
+    direction: &'static str,
+    protocol_id: usize,
+    // Empty when the message is sent by a core protocol, such as ping
+    session_ids: Vec<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<Message>,
+    // The raw payload when the message can't be decoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<JsonBytes>,
+}
+
+#[derive(Serialize)]
+struct MerkleProof {
+    indices: Vec<Uint32>,
+    lemmas: Vec<H256>,
+}
+
+#[derive(Serialize)]
+struct IndexTransaction {
+    index: Uint32,
+    transaction: Transaction,
+}
+
+#[derive(Serialize)]
+struct RelayTransaction {
+    cycles: Uint64,
+    transaction: Transaction,
+}
+
+/// The JSON form of the sync and relay messages, see the molecule schema
+#[derive(Serialize)]
+#[serde(tag = "type")]
+enum Message {
+    GetHeaders {
+        hash_stop: H256,
+        block_locator_hashes: Vec<H256>,
+    },
+    SendHeaders {
+        headers: Vec<Header>,
+    },
+    GetBlocks {
+        block_hashes: Vec<H256>,
+    },
+    SendBlock {
+        block: Block,
+    },
+    SetFilter {
+        hash_seed: Uint32,
+        filter: JsonBytes,
+        num_hashes: u8,
+    },
+    AddFilter {
+        filter: JsonBytes,
+    },
+    ClearFilter,
+    FilteredBlock {
+        header: Header,
+        transactions: Vec<Transaction>,
+        proof: MerkleProof,
+    },
+    InIBD,
+    CompactBlock {
+        header: Header,
+        short_ids: Vec<ProposalShortId>,
+        prefilled_transactions: Vec<IndexTransaction>,
+        uncles: Vec<H256>,
+        proposals: Vec<ProposalShortId>,
+    },
+    RelayTransactions {
+        transactions: Vec<RelayTransaction>,
+    },
+    RelayTransactionHashes {
+        tx_hashes: Vec<H256>,
+    },
+    GetRelayTransactions {
+        tx_hashes: Vec<H256>,
+    },
+    GetBlockTransactions {
+        block_hash: H256,
+        indexes: Vec<Uint32>,
+        uncle_indexes: Vec<Uint32>,
+    },
+    BlockTransactions {
+        block_hash: H256,
+        transactions: Vec<Transaction>,
+        uncles: Vec<UncleBlock>,
+    },
+    GetBlockProposal {
+        block_hash: H256,
+        proposals: Vec<ProposalShortId>,
+    },
+    BlockProposal {
+        transactions: Vec<Transaction>,
+    },
+}
+
+fn hashes(hashes: packed::Byte32Vec) -> Vec<H256> {
+    hashes
+        .into_iter()
+        .map(|hash| Unpack::<H256>::unpack(&hash))
+        .collect()
+}
+
+fn indexes(indexes: packed::Uint32Vec) -> Vec<Uint32> {
+    indexes
+        .into_iter()
+        .map(|index| Unpack::<u32>::unpack(&index).into())
+        .collect()
+}
+
+fn proposals(proposals: packed::ProposalShortIdVec) -> Vec<ProposalShortId> {
+    proposals.into_iter().map(Into::into).collect()
+}
+
+fn transactions(transactions: packed::TransactionVec) -> Vec<Transaction> {
+    transactions.into_iter().map(Into::into).collect()
+}
+
+fn decode_sync_message(data: &[u8]) -> Option<Message> {
+    let message = match packed::SyncMessage::from_slice(data).ok()?.to_enum() {
+        packed::SyncMessageUnion::NotSet => return None,
+        packed::SyncMessageUnion::GetHeaders(message) => Message::GetHeaders {
+            hash_stop: message.hash_stop().unpack(),
+            block_locator_hashes: hashes(message.block_locator_hashes()),
+        },
+        packed::SyncMessageUnion::SendHeaders(message) => Message::SendHeaders {
+            headers: message.headers().into_iter().map(Into::into).collect(),
+        },
+        packed::SyncMessageUnion::GetBlocks(message) => Message::GetBlocks {
+            block_hashes: hashes(message.block_hashes()),
+        },
+        packed::SyncMessageUnion::SendBlock(message) => Message::SendBlock {
+            block: message.block().into(),
+        },
+        packed::SyncMessageUnion::SetFilter(message) => Message::SetFilter {
+            hash_seed: Unpack::<u32>::unpack(&message.hash_seed()).into(),
+            filter: message.filter().into(),
+            num_hashes: message.num_hashes(),
+        },
+        packed::SyncMessageUnion::AddFilter(message) => Message::AddFilter {
+            filter: message.filter().into(),
+        },
+        packed::SyncMessageUnion::ClearFilter(_) => Message::ClearFilter,
+        packed::SyncMessageUnion::FilteredBlock(message) => Message::FilteredBlock {
+            header: message.header().into(),
+            transactions: transactions(message.transactions()),
+            proof: MerkleProof {
+                indices: indexes(message.proof().indices()),
+                lemmas: hashes(message.proof().lemmas()),
+            },
+        },
+        packed::SyncMessageUnion::InIBD(_) => Message::InIBD,
+    };
+    Some(message)
+}
+
+fn decode_relay_message(data: &[u8]) -> Option<Message> {
+    let message = match packed::RelayMessage::from_slice(data).ok()?.to_enum() {
+        packed::RelayMessageUnion::NotSet => return None,
+        packed::RelayMessageUnion::CompactBlock(message) => Message::CompactBlock {
+            header: message.header().into(),
+            short_ids: proposals(message.short_ids()),
+            prefilled_transactions: message
+                .prefilled_transactions()
+                .into_iter()
+                .map(|prefilled| IndexTransaction {
+                    index: Unpack::<u32>::unpack(&prefilled.index()).into(),
+                    transaction: prefilled.transaction().into(),
+                })
+                .collect(),
+            uncles: hashes(message.uncles()),
+            proposals: proposals(message.proposals()),
+        },
+        packed::RelayMessageUnion::RelayTransactions(message) => Message::RelayTransactions {
+            transactions: message
+                .transactions()
+                .into_iter()
+                .map(|relay| RelayTransaction {
+                    cycles: Unpack::<u64>::unpack(&relay.cycles()).into(),
+                    transaction: relay.transaction().into(),
+                })
+                .collect(),
+        },
+        packed::RelayMessageUnion::RelayTransactionHashes(message) => {
+            Message::RelayTransactionHashes {
+                tx_hashes: hashes(message.tx_hashes()),
+            }
+        }
+        packed::RelayMessageUnion::GetRelayTransactions(message) => Message::GetRelayTransactions {
+            tx_hashes: hashes(message.tx_hashes()),
+        },
+        packed::RelayMessageUnion::GetBlockTransactions(message) => Message::GetBlockTransactions {
+            block_hash: message.block_hash().unpack(),
+            indexes: indexes(message.indexes()),
+            uncle_indexes: indexes(message.uncle_indexes()),
+        },
+        packed::RelayMessageUnion::BlockTransactions(message) => Message::BlockTransactions {
+            block_hash: message.block_hash().unpack(),
+            transactions: transactions(message.transactions()),
+            uncles: message.uncles().into_iter().map(Into::into).collect(),
+        },
+        packed::RelayMessageUnion::GetBlockProposal(message) => Message::GetBlockProposal {
+            block_hash: message.block_hash().unpack(),
+            proposals: proposals(message.proposals()),
+        },
+        packed::RelayMessageUnion::BlockProposal(message) => Message::BlockProposal {
+            transactions: transactions(message.transactions()),
+        },
+    };
+    Some(message)
+}
+
+impl From<CapturedMessage> for CapturedRecord {
+    fn from(captured: CapturedMessage) -> Self {
+        let sync_protocol: ProtocolId = NetworkProtocol::SYNC.into();
+        let relay_protocol: ProtocolId = NetworkProtocol::RELAY.into();
+        let message = if captured.protocol_id == sync_protocol {
+            decode_sync_message(&captured.data)
+        } else if captured.protocol_id == relay_protocol {
+            decode_relay_message(&captured.data)
+        } else {
+            None
+        };
+        let data = if message.is_none() {
+            Some(JsonBytes::from_vec(captured.data.to_vec()))
+        } else {
+            None
+        };
+        CapturedRecord {
+            timestamp: captured.timestamp,
+            direction: match captured.direction {
+                Direction::Inbound => "inbound",
+                Direction::Outbound => "outbound",
+            },
+            protocol_id: captured.protocol_id.value(),
+            session_ids: captured
+                .session_ids
+                .iter()
+                .map(|session_id| session_id.value())
+                .collect(),
+            message,
+            data,
+        }
+    }
+}
+
+pub fn decode_capture<'m>(matches: &ArgMatches<'m>) -> Result<(), ExitCode> {
+    let path = matches.value_of(cli::ARG_FILE).unwrap();
+    for captured in CaptureReader::open(path)? {
+        let record = CapturedRecord::from(captured?);
+        let json = serde_json::to_string(&record).map_err(|err| {
+            eprintln!("Failed to encode the captured message: {}", err);
+            ExitCode::Failure
+        })?;
+        println!("{}", json);
+    }
+    Ok(())
+}
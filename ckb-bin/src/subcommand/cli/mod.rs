@@ -1,8 +1,10 @@
 mod blake;
+mod decode_capture;
 mod hashes;
 mod secp256k1_lock;
 
 pub use blake::{blake160, blake256};
+pub use decode_capture::decode_capture;
 pub use hashes::hashes;
 pub use secp256k1_lock::secp256k1_lock;
 
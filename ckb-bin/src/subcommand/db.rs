@@ -0,0 +1,219 @@
+use ckb_app_config::{
+    DBCheckArgs, DBReindexArgs, DBRollbackArgs, DBStatsArgs, ExitCode, RollbackTarget,
+};
+use ckb_chain::{chain::ChainService, reindex::Reindexer};
+use ckb_db::{Col, DBConfig, RocksDB};
+use ckb_indexer::DefaultIndexerStore;
+use ckb_shared::shared::SharedBuilder;
+use ckb_store::{ChainDB, ChainStore};
+use ckb_types::prelude::*;
+use std::fmt::Display;
+
+pub fn stats(args: DBStatsArgs) -> Result<(), ExitCode> {
+    print_db_stats("database", &args.config.db, &ckb_store::COLUMN_NAMES)?;
+    print_db_stats(
+        "indexer database",
+        &args.config.indexer.db,
+        &ckb_indexer::COLUMN_NAMES,
+    )
+}
+
+fn print_db_stats(name: &str, config: &DBConfig, columns: &[(Col, &str)]) -> Result<(), ExitCode> {
+    if !config.path.exists() {
+        println!("Skip the {}, {:?} does not exist", name, config.path);
+        return Ok(());
+    }
+    let cols: Vec<_> = columns.iter().map(|(col, _)| *col).collect();
+    let stats = RocksDB::open_with_error(config, cols.len() as u32)
+        .and_then(|db| db.stats(&cols))
+        .map_err(|err| {
+            eprintln!(
+                "Inspect the {} error: {}\n\
+                 If the node is running, use the `get_db_stats` RPC instead",
+                name, err
+            );
+            ExitCode::Failure
+        })?;
+
+    println!("The {} {:?}", name, config.path);
+    println!(
+        "{:<24}{:>14}{:>16}{:>8}{:>16}{:>12}{:>20}",
+        "column", "keys", "live data", "SSTs", "SST size", "mem tables", "pending compaction"
+    );
+    for ((_, column_name), column) in columns.iter().zip(stats.columns.iter()) {
+        let pending = if column.compaction_pending {
+            column.pending_compaction_bytes.to_string()
+        } else {
+            "-".to_owned()
+        };
+        println!(
+            "{:<24}{:>14}{:>16}{:>8}{:>16}{:>12}{:>20}",
+            column_name,
+            column.estimated_keys,
+            column.estimated_live_data_size,
+            column.sst_files,
+            column.sst_files_size,
+            column.mem_tables_size,
+            pending
+        );
+    }
+    println!("running compactions: {}", stats.running_compactions);
+    match stats.block_cache_hit_rate() {
+        Some(rate) => println!(
+            "block cache hits: {}, misses: {}, hit rate: {:.2}%",
+            stats.block_cache_hits.unwrap_or_default(),
+            stats.block_cache_misses.unwrap_or_default(),
+            rate * 100.0
+        ),
+        None => println!("block cache hits: n/a, enable `db.statistics` to collect them"),
+    }
+    println!();
+    Ok(())
+}
+
+pub fn check(args: DBCheckArgs) -> Result<(), ExitCode> {
+    let config = &args.config;
+    if !config.db.path.exists() {
+        eprintln!("The database {:?} does not exist", config.db.path);
+        return Err(ExitCode::Failure);
+    }
+    let db = RocksDB::open_with_error(&config.db, ckb_store::COLUMNS)
+        .map_err(|err| open_error("database", err))?;
+    let store = ChainDB::new(db, config.store);
+
+    println!("Checking the database {:?}", config.db.path);
+    let report = store.check();
+    for inconsistency in report.inconsistencies.iter() {
+        let fixable = if inconsistency.is_fixable() {
+            " (fixable)"
+        } else {
+            ""
+        };
+        println!("  {}{}", inconsistency, fixable);
+    }
+    println!(
+        "Checked {} blocks, found {} inconsistencies",
+        report.checked_blocks,
+        report.inconsistencies.len()
+    );
+    if !report.cell_set_checked {
+        println!("The cell set is not checked, the database is pruned, has invalid blocks or an unreadable cell set");
+    }
+    let mut unfixed = report.inconsistencies.len();
+    if args.fix && !report.is_consistent() {
+        let fixed = store.fix(&report.inconsistencies).map_err(|err| {
+            eprintln!("Fix the database error: {}", err);
+            ExitCode::Failure
+        })?;
+        println!("Fixed {} inconsistencies", fixed);
+        unfixed -= fixed;
+    }
+
+    if config.indexer.db.path.exists() {
+        let indexer_db = RocksDB::open_with_error(&config.indexer.db, ckb_indexer::COLUMNS)
+            .map_err(|err| open_error("indexer database", err))?;
+        println!("Checking the indexer database {:?}", config.indexer.db.path);
+        let inconsistencies = ckb_indexer::check_consistency(&indexer_db, &store);
+        for inconsistency in inconsistencies.iter() {
+            println!("  {}", inconsistency);
+        }
+        println!("Found {} inconsistencies", inconsistencies.len());
+        if args.fix && !inconsistencies.is_empty() {
+            let reindexed = ckb_indexer::fix_inconsistencies(&indexer_db, &store, &inconsistencies);
+            println!(
+                "Reset {} lock hashes, they are indexed again from the genesis when the node starts",
+                reindexed
+            );
+        } else {
+            unfixed += inconsistencies.len();
+        }
+    }
+
+    if unfixed > 0 {
+        if !args.fix {
+            println!("Run with `--fix` to fix the fixable inconsistencies");
+        }
+        Err(ExitCode::Failure)
+    } else {
+        Ok(())
+    }
+}
+
+pub fn reindex(args: DBReindexArgs) -> Result<(), ExitCode> {
+    let config = &args.config;
+    if !config.db.path.exists() {
+        eprintln!("The database {:?} does not exist", config.db.path);
+        return Err(ExitCode::Failure);
+    }
+    let db = RocksDB::open_with_error(&config.db, ckb_store::COLUMNS)
+        .map_err(|err| open_error("database", err))?;
+    let store = ChainDB::new(db, config.store);
+
+    let reindexer = Reindexer::new(&store, &args.consensus);
+    if reindexer.is_resuming() {
+        println!("Resuming the interrupted reindex of {:?}", config.db.path);
+    } else {
+        println!("Reindexing {:?}", config.db.path);
+    }
+    reindexer
+        .reindex(|reindexed, total| println!("Reindexed {}/{} blocks", reindexed, total))
+        .map_err(|err| {
+            eprintln!("Reindex error: {}\nRun the reindex again to resume it", err);
+            ExitCode::Failure
+        })?;
+    println!("Done");
+    Ok(())
+}
+
+pub fn rollback(args: DBRollbackArgs) -> Result<(), ExitCode> {
+    let (shared, table) = SharedBuilder::with_db_config(&args.config.db)
+        .consensus(args.consensus)
+        .store_config(args.config.store)
+        .build()
+        .map_err(|err| open_error("database", err))?;
+    let target_tip_hash = match args.target {
+        RollbackTarget::Number(number) => shared.store().get_block_hash(number),
+        RollbackTarget::Hash(hash) => Some(hash.pack()),
+    }
+    .ok_or_else(|| {
+        eprintln!("The block is not in the main chain");
+        ExitCode::Failure
+    })?;
+    let tip_number = shared.snapshot().tip_header().number();
+
+    // The indexer database only exists when the indexer was enabled
+    let indexer_store = if args.config.indexer.db.path.exists() {
+        Some(DefaultIndexerStore::new(
+            &args.config.indexer,
+            shared.clone(),
+        ))
+    } else {
+        None
+    };
+
+    let chain_service = ChainService::new(shared.clone(), table);
+    let chain_controller = chain_service.start::<&str>(Some("RollbackChainService"));
+    chain_controller.truncate(target_tip_hash).map_err(|err| {
+        eprintln!("Rollback error: {}", err);
+        ExitCode::Failure
+    })?;
+    if let Some(indexer_store) = indexer_store {
+        indexer_store.detach_forks();
+    }
+    let tip_header = shared.snapshot().tip_header().to_owned();
+    println!(
+        "Rolled back {} blocks, the tip is {} {}",
+        tip_number - tip_header.number(),
+        tip_header.number(),
+        tip_header.hash()
+    );
+    Ok(())
+}
+
+fn open_error<E: Display>(name: &str, err: E) -> ExitCode {
+    eprintln!(
+        "Open the {} error: {}\nThe node must be stopped first",
+        name, err
+    );
+    ExitCode::Failure
+}
@@ -11,6 +11,11 @@ pub fn export(args: ExportArgs) -> Result<(), ExitCode> {
             ExitCode::Failure
         })?;
     Export::new(shared, args.format, args.target)
+        .compress(args.compress)
+        .from(args.from)
+        .to(args.to)
+        .append(args.append)
+        .chunk_size(args.chunk_size)
         .execute()
         .map_err(|err| {
             eprintln!("Export error: {:?}", err);
@@ -2,6 +2,7 @@ use ckb_app_config::{ExitCode, ImportArgs};
 use ckb_chain::chain::ChainService;
 use ckb_instrument::Import;
 use ckb_shared::shared::SharedBuilder;
+use ckb_types::prelude::*;
 
 pub fn import(args: ImportArgs) -> Result<(), ExitCode> {
     let (shared, table) = SharedBuilder::with_db_config(&args.config.db)
@@ -15,7 +16,8 @@ pub fn import(args: ImportArgs) -> Result<(), ExitCode> {
     let chain_service = ChainService::new(shared.clone(), table);
     let chain_controller = chain_service.start::<&str>(Some("ImportChainService"));
 
-    Import::new(chain_controller, args.format, args.source)
+    Import::new(chain_controller, shared, args.format, args.source)
+        .assume_valid_target(args.assume_valid_target.map(|hash| hash.pack()))
         .execute()
         .map_err(|err| {
             eprintln!("Import error: {:?}", err);
@@ -0,0 +1,35 @@
+use ckb_app_config::{ExitCode, MigrateArgs};
+use ckb_db::{DBConfig, Migrations, RocksDB};
+
+pub fn migrate(args: MigrateArgs) -> Result<(), ExitCode> {
+    migrate_db(
+        "database",
+        &args.config.db,
+        ckb_store::COLUMNS,
+        &ckb_store::migrations(),
+    )?;
+    migrate_db(
+        "indexer database",
+        &args.config.indexer.db,
+        ckb_indexer::COLUMNS,
+        &ckb_indexer::migrations(),
+    )
+}
+
+fn migrate_db(
+    name: &str,
+    config: &DBConfig,
+    columns: u32,
+    migrations: &Migrations,
+) -> Result<(), ExitCode> {
+    if !config.path.exists() {
+        println!("Skip the {}, {:?} does not exist", name, config.path);
+        return Ok(());
+    }
+    RocksDB::migrate(config, columns, migrations).map_err(|err| {
+        eprintln!("Migrate the {} error: {}", name, err);
+        ExitCode::Failure
+    })?;
+    println!("The {} is up to date", name);
+    Ok(())
+}
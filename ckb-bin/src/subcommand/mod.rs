@@ -1,18 +1,25 @@
+mod backup;
 pub mod cli;
+pub mod db;
 mod export;
 mod import;
 mod init;
+mod migrate;
 mod miner;
 mod prof;
 mod reset_data;
+mod restore;
 mod run;
 mod stats;
 
+pub use self::backup::backup;
 pub use self::export::export;
 pub use self::import::import;
 pub use self::init::init;
+pub use self::migrate::migrate;
 pub use self::miner::miner;
 pub use self::prof::profile;
 pub use self::reset_data::reset_data;
+pub use self::restore::restore;
 pub use self::run::run;
 pub use self::stats::stats;
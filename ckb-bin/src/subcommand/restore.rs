@@ -0,0 +1,158 @@
+use ckb_app_config::{ExitCode, RestoreArgs};
+use ckb_db::{DBConfig, RocksDB};
+use ckb_rpc::{BACKUP_DB_DIR, BACKUP_INDEXER_DB_DIR};
+use ckb_store::ChainDB;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub fn restore(args: RestoreArgs) -> Result<(), ExitCode> {
+    let targets = [
+        (
+            args.source.join(BACKUP_DB_DIR),
+            args.config.db.path.as_path(),
+        ),
+        (
+            args.source.join(BACKUP_INDEXER_DB_DIR),
+            args.config.indexer.db.path.as_path(),
+        ),
+    ];
+    if !targets[0].0.exists() {
+        eprintln!("Restore error: {:?} is not a backup", args.source);
+        return Err(ExitCode::Failure);
+    }
+    if !args.force {
+        if let Some((_, existing)) = targets.iter().find(|(_, to)| !is_empty_dir(to)) {
+            eprintln!(
+                "Restore error: {:?} already exists, use --force to replace it",
+                existing
+            );
+            return Err(ExitCode::Failure);
+        }
+    }
+
+    // Both databases are copied aside and verified as a pair before the
+    // existing ones are replaced.
+    let restoring: Vec<_> = targets
+        .iter()
+        .filter(|(from, _)| from.exists())
+        .map(|(from, to)| (from, to.with_extension("restoring"), *to))
+        .collect();
+    let copied = restoring.iter().try_for_each(|(from, tmp, to)| {
+        println!("Restoring {:?} to {:?}", from, to);
+        restore_dir(from, tmp)
+    });
+    let result = copied
+        .map_err(|err| {
+            eprintln!("Restore error: {}", err);
+            ExitCode::IO
+        })
+        .and_then(|_| {
+            // A backup without the indexer database keeps the existing one,
+            // which must not index the blocks newer than the backup
+            let existing_indexer_db = args.config.indexer.db.path.as_path();
+            match restoring.get(1) {
+                Some((_, tmp, _)) => check_pair(&args, &restoring[0].1, Some(tmp.as_path())),
+                None if !is_empty_dir(existing_indexer_db) => {
+                    check_pair(&args, &restoring[0].1, Some(existing_indexer_db)).map_err(
+                        |exit_code| {
+                            eprintln!(
+                                "The backup has no indexer database, remove {:?} to restore it",
+                                existing_indexer_db
+                            );
+                            exit_code
+                        },
+                    )
+                }
+                None => Ok(()),
+            }
+        });
+    if let Err(exit_code) = result {
+        for (_, tmp, _) in restoring.iter() {
+            let _ = fs::remove_dir_all(tmp);
+        }
+        return Err(exit_code);
+    }
+
+    for (_, tmp, to) in restoring.iter() {
+        replace_dir(tmp, to).map_err(|err| {
+            eprintln!("Restore error: {}", err);
+            ExitCode::IO
+        })?;
+    }
+    Ok(())
+}
+
+// Rejects an indexer database indexing blocks which the chain database does
+// not have, such as one from another backup.
+fn check_pair(args: &RestoreArgs, db: &Path, indexer_db: Option<&Path>) -> Result<(), ExitCode> {
+    let indexer_db = match indexer_db {
+        Some(indexer_db) => indexer_db,
+        None => return Ok(()),
+    };
+    let open = |config: &DBConfig, path: &Path, columns: u32| {
+        let config = DBConfig {
+            path: path.to_path_buf(),
+            ..config.clone()
+        };
+        RocksDB::open_with_error(&config, columns).map_err(|err| {
+            eprintln!("Restore error: open {:?} error: {}", path, err);
+            ExitCode::Failure
+        })
+    };
+    let store = ChainDB::new(
+        open(&args.config.db, db, ckb_store::COLUMNS)?,
+        args.config.store,
+    );
+    let indexer = open(&args.config.indexer.db, indexer_db, ckb_indexer::COLUMNS)?;
+    let inconsistencies = ckb_indexer::check_indexed_blocks(&indexer, &store);
+    if inconsistencies.is_empty() {
+        return Ok(());
+    }
+    eprintln!(
+        "Restore error: the indexer database {:?} does not match the database in the backup",
+        indexer_db
+    );
+    for inconsistency in inconsistencies {
+        eprintln!("  {}", inconsistency);
+    }
+    Err(ExitCode::Failure)
+}
+
+// The config loader creates the database directories, so an empty one is
+// treated as missing. A path which can't be read is not empty, it may hold a
+// database.
+fn is_empty_dir(path: &Path) -> bool {
+    match fs::read_dir(path) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(err) => err.kind() == io::ErrorKind::NotFound,
+    }
+}
+
+fn restore_dir(from: &Path, to: &Path) -> io::Result<()> {
+    if to.exists() {
+        fs::remove_dir_all(to)?;
+    }
+    copy_dir(from, to)
+}
+
+fn replace_dir(from: &Path, to: &Path) -> io::Result<()> {
+    if to.exists() {
+        fs::remove_dir_all(to)?;
+    }
+    fs::rename(from, to)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            copy_dir(&path, &to.join(entry.file_name()))?;
+        } else {
+            fs::copy(&path, to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
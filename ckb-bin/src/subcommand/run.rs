@@ -4,12 +4,15 @@ use ckb_build_info::Version;
 use ckb_chain::chain::ChainService;
 use ckb_jsonrpc_types::ScriptHashType;
 use ckb_logger::info_target;
-use ckb_network::{CKBProtocol, NetworkService, NetworkState};
+use ckb_network::{CKBProtocol, MessageNames, NetworkService, NetworkState};
 use ckb_network_alert::alert_relayer::AlertRelayer;
 use ckb_resource::Resource;
-use ckb_rpc::{RpcServer, ServiceBuilder};
+use ckb_rpc::{Module, RpcServer, ServiceBuilder, BACKUPS_DIR};
 use ckb_shared::shared::{Shared, SharedBuilder};
-use ckb_sync::{NetTimeProtocol, NetworkProtocol, Relayer, SyncSharedState, Synchronizer};
+use ckb_sync::{
+    NetTimeProtocol, NetworkProtocol, Relayer, SyncSharedState, Synchronizer, RELAY_MESSAGE_NAMES,
+    SYNC_MESSAGE_NAMES,
+};
 use ckb_types::prelude::*;
 use ckb_verification::{BlockVerifier, Verifier};
 use std::sync::Arc;
@@ -21,11 +24,17 @@ pub fn run(args: RunArgs, version: Version) -> Result<(), ExitCode> {
 
     let block_assembler_config = sanitize_block_assembler_config(&args)?;
     let miner_enable = block_assembler_config.is_some();
+    let pruned = args.config.store.prune_depth.is_some();
+    if pruned && args.config.rpc.modules.contains(&Module::Indexer) {
+        eprintln!("Run error: the Indexer module cannot index the pruned blocks");
+        return Err(ExitCode::Config);
+    }
 
     let (shared, table) = SharedBuilder::with_db_config(&args.config.db)
         .consensus(args.consensus)
         .tx_pool_config(args.config.tx_pool)
         .store_config(args.config.store)
+        .verification_config(args.config.verification)
         .block_assembler_config(block_assembler_config)
         .build()
         .map_err(|err| {
@@ -46,7 +55,9 @@ pub fn run(args: RunArgs, version: Version) -> Result<(), ExitCode> {
 
     let sync_shared_state = Arc::new(SyncSharedState::new(shared.clone()));
     let network_state = Arc::new(
-        NetworkState::from_config(args.config.network).expect("Init network state failed"),
+        NetworkState::from_config(args.config.network)
+            .expect("Init network state failed")
+            .pruned(pruned),
     );
     let synchronizer = Synchronizer::new(chain_controller.clone(), Arc::clone(&sync_shared_state));
 
@@ -71,28 +82,38 @@ pub fn run(args: RunArgs, version: Version) -> Result<(), ExitCode> {
             &["1".to_string()][..],
             move || Box::new(synchronizer_clone.clone()),
             Arc::clone(&network_state),
-        ),
+        )
+        .message_names(SYNC_MESSAGE_NAMES),
         CKBProtocol::new(
             "rel".to_string(),
             NetworkProtocol::RELAY.into(),
             &["1".to_string()][..],
             move || Box::new(relayer.clone()),
             Arc::clone(&network_state),
-        ),
+        )
+        .message_names(RELAY_MESSAGE_NAMES),
         CKBProtocol::new(
             "tim".to_string(),
             NetworkProtocol::TIME.into(),
             &["1".to_string()][..],
             move || Box::new(net_timer.clone()),
             Arc::clone(&network_state),
-        ),
+        )
+        .message_names(MessageNames {
+            names: &["Time"],
+            index: |_| Some(0),
+        }),
         CKBProtocol::new(
             "alt".to_string(),
             NetworkProtocol::ALERT.into(),
             &["1".to_string()][..],
             move || Box::new(alert_relayer.clone()),
             Arc::clone(&network_state),
-        ),
+        )
+        .message_names(MessageNames {
+            names: &["Alert"],
+            index: |_| Some(0),
+        }),
     ];
     let network_controller = NetworkService::new(
         Arc::clone(&network_state),
@@ -115,13 +136,10 @@ pub fn run(args: RunArgs, version: Version) -> Result<(), ExitCode> {
         .enable_net(network_controller.clone())
         .enable_stats(shared.clone(), synchronizer, Arc::clone(&alert_notifier))
         .enable_experiment(shared.clone())
-        .enable_integration_test(
-            shared.clone(),
-            network_controller.clone(),
-            chain_controller.clone(),
-        )
-        .enable_alert(alert_verifier, alert_notifier, network_controller)
-        .enable_indexer(&args.config.indexer, shared.clone());
+        .enable_alert(alert_verifier, alert_notifier, network_controller.clone())
+        .enable_indexer(&args.config.indexer, shared.clone())
+        .enable_integration_test(shared.clone(), network_controller, chain_controller.clone())
+        .enable_admin(shared.clone(), args.config.data_dir.join(BACKUPS_DIR));
     let io_handler = builder.build();
 
     let rpc_server = RpcServer::new(args.config.rpc, io_handler);
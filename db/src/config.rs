@@ -7,4 +7,8 @@ pub struct DBConfig {
     #[serde(default)]
     pub path: PathBuf,
     pub options: Option<HashMap<String, String>>,
+    // Collect the RocksDB statistics, such as the block cache hits, which
+    // costs a little performance
+    #[serde(default)]
+    pub statistics: bool,
 }
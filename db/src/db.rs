@@ -1,12 +1,18 @@
+use crate::migration::Migrations;
 use crate::snapshot::RocksDBSnapshot;
+use crate::stats::DBStatistics;
 use crate::transaction::RocksDBTransaction;
 use crate::{internal_error, Col, DBConfig, Result};
 use ckb_logger::{info, warn};
+use libc::c_char;
 use rocksdb::ops::{Get, GetColumnFamilys, GetPinnedCF, IterateCF, OpenCF, Put, SetOptions};
 use rocksdb::{
-    ffi, ColumnFamily, DBPinnableSlice, IteratorMode, OptimisticTransactionDB,
+    ffi, ffi_util, ColumnFamily, DBPinnableSlice, IteratorMode, OptimisticTransactionDB,
     OptimisticTransactionOptions, Options, WriteOptions,
 };
+use std::ffi::CString;
+use std::path::Path;
+use std::ptr;
 use std::sync::Arc;
 
 // If any data format in database was changed, we have to update this constant manually.
@@ -14,10 +20,11 @@ use std::sync::Arc;
 //      - If the data can be migrated manually: update "x.y1.z" to "x.y2.0".
 //      - If the data can not be migrated: update "x1.y.z" to "x2.0.0".
 pub(crate) const VERSION_KEY: &str = "db-version";
-pub(crate) const VERSION_VALUE: &str = "0.2100.0";
+pub(crate) const VERSION_VALUE: &str = "0.2100.1";
 
 pub struct RocksDB {
     pub(crate) inner: Arc<OptimisticTransactionDB>,
+    pub(crate) statistics: Option<DBStatistics>,
 }
 
 impl RocksDB {
@@ -26,10 +33,26 @@ impl RocksDB {
         columns: u32,
         ver_key: &str,
         ver_val: &str,
+    ) -> Result<Self> {
+        Self::open_and_migrate(config, columns, ver_key, ver_val, &Migrations::new(), false)
+    }
+
+    // Opens the database and migrates its data to the version `ver_val`, the
+    // migrations to a new minor version only run if `manual` is set.
+    fn open_and_migrate(
+        config: &DBConfig,
+        columns: u32,
+        ver_key: &str,
+        ver_val: &str,
+        migrations: &Migrations,
+        manual: bool,
     ) -> Result<Self> {
         let mut opts = Options::default();
         opts.create_if_missing(false);
         opts.create_missing_column_families(true);
+        if config.statistics {
+            opts.enable_statistics();
+        }
 
         let cfnames: Vec<_> = (0..columns).map(|c| c.to_string()).collect();
         let cf_options: Vec<&str> = cfnames.iter().map(|n| n as &str).collect();
@@ -97,27 +120,41 @@ impl RocksDB {
             internal_error(format!("required database version is malformed: {}", err))
         })?;
         if required_version.major != version.major
-            || required_version.minor != version.minor
-            || required_version.patch < version.patch
+            || required_version < version
+            || (required_version.minor != version.minor
+                && !migrations.has_pending(&version, &required_version))
         {
             return Err(internal_error(format!(
                 "the database version is not matched, require {} but it's {}",
                 required_version, version
             )));
-        } else if required_version.patch > version.patch {
+        }
+
+        let statistics = if config.statistics {
+            Some(DBStatistics::new(opts))
+        } else {
+            None
+        };
+        let rocksdb = RocksDB {
+            inner: Arc::new(db),
+            statistics,
+        };
+        if required_version > version {
+            if required_version.minor != version.minor && !manual {
+                return Err(internal_error(format!(
+                    "the database version {} requires a migration to {}, please run `ckb migrate`",
+                    version, required_version
+                )));
+            }
             warn!(
                 "Migrating the data from {} to {} ...",
-                required_version, version
+                version, required_version
             );
-            // Do data migration here.
-            db.put(ver_key, ver_val).map_err(|err| {
-                internal_error(format!("Failed to update database version: {}", err))
-            })?;
+            migrations.migrate(&rocksdb, ver_key, &version, &required_version)?;
+            rocksdb.put_version(ver_key, ver_val)?;
         }
 
-        Ok(RocksDB {
-            inner: Arc::new(db),
-        })
+        Ok(rocksdb)
     }
 
     // TODO Change `panic(...)` to `Result<...>`
@@ -130,6 +167,40 @@ impl RocksDB {
         Self::open_with_check(config, columns, VERSION_KEY, VERSION_VALUE)
     }
 
+    /// Opens the database, running the migrations to a new patch version.
+    pub fn open_with_migrations(
+        config: &DBConfig,
+        columns: u32,
+        migrations: &Migrations,
+    ) -> Result<Self> {
+        Self::open_and_migrate(
+            config,
+            columns,
+            VERSION_KEY,
+            VERSION_VALUE,
+            migrations,
+            false,
+        )
+    }
+
+    /// Opens the database, running all the pending migrations.
+    pub fn migrate(config: &DBConfig, columns: u32, migrations: &Migrations) -> Result<Self> {
+        Self::open_and_migrate(
+            config,
+            columns,
+            VERSION_KEY,
+            VERSION_VALUE,
+            migrations,
+            true,
+        )
+    }
+
+    pub(crate) fn put_version(&self, ver_key: &str, ver_val: &str) -> Result<()> {
+        self.inner
+            .put(ver_key, ver_val)
+            .map_err(|err| internal_error(format!("Failed to update database version: {}", err)))
+    }
+
     pub fn open_tmp(columns: u32) -> Self {
         let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
         let config = DBConfig {
@@ -172,6 +243,40 @@ impl RocksDB {
         }
     }
 
+    /// Creates a checkpoint in `path`, which must not exist.
+    ///
+    /// A checkpoint is a consistent copy of the database which can be opened
+    /// as is, the table files are hard linked when `path` is on the same
+    /// filesystem and copied otherwise.
+    pub fn create_checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path
+            .as_ref()
+            .to_str()
+            .and_then(|path| CString::new(path).ok())
+            .ok_or_else(|| internal_error("invalid checkpoint path"))?;
+        unsafe {
+            let mut err: *mut c_char = ptr::null_mut();
+            let checkpoint =
+                ffi::rocksdb_checkpoint_object_create(self.inner.base_db_ptr(), &mut err);
+            if !err.is_null() {
+                return Err(internal_error(format!(
+                    "failed to create the checkpoint: {}",
+                    ffi_util::error_message(err)
+                )));
+            }
+            // Always flush the memtables, so the checkpoint needs no log files.
+            ffi::rocksdb_checkpoint_create(checkpoint, path.as_ptr(), 0, &mut err);
+            ffi::rocksdb_checkpoint_object_destroy(checkpoint);
+            if !err.is_null() {
+                return Err(internal_error(format!(
+                    "failed to create the checkpoint: {}",
+                    ffi_util::error_message(err)
+                )));
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_snapshot(&self) -> RocksDBSnapshot {
         unsafe {
             let snapshot = ffi::rocksdb_create_snapshot(self.inner.base_db_ptr());
@@ -189,6 +294,7 @@ pub(crate) fn cf_handle(db: &OptimisticTransactionDB, col: Col) -> Result<&Colum
 mod tests {
     use super::{DBConfig, Result, RocksDB, VERSION_KEY, VERSION_VALUE};
     use crate::internal_error;
+    use crate::migration::{Migration, MigrationProgress, Migrations};
     use ckb_error::assert_error_eq;
     use std::collections::HashMap;
     use tempfile;
@@ -225,6 +331,7 @@ mod tests {
                 opts.insert("disable_auto_compactions".to_owned(), "true".to_owned());
                 opts
             }),
+            ..Default::default()
         };
         RocksDB::open(&config, 2); // no panic
     }
@@ -243,6 +350,7 @@ mod tests {
                 opts.insert("letsrock".to_owned(), "true".to_owned());
                 opts
             }),
+            ..Default::default()
         };
         RocksDB::open(&config, 2); // panic
     }
@@ -330,4 +438,99 @@ mod tests {
         let _ = RocksDB::open_with_check(&config, 1, VERSION_KEY, VERSION_VALUE).unwrap();
         let _ = RocksDB::open_with_check(&config, 1, VERSION_KEY, VERSION_VALUE).unwrap();
     }
+
+    struct PutMigration(&'static str);
+
+    impl Migration for PutMigration {
+        fn version(&self) -> &str {
+            self.0
+        }
+
+        fn migrate(&self, db: &RocksDB, progress: &mut MigrationProgress) -> Result<()> {
+            progress.set_total(1);
+            let txn = db.transaction();
+            txn.put("0", self.0.as_bytes(), &[])?;
+            txn.commit()?;
+            progress.inc(1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_migrations() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("test_migrations")
+            .tempdir()
+            .unwrap();
+        let config = DBConfig {
+            path: tmp_dir.as_ref().to_path_buf(),
+            ..Default::default()
+        };
+        let mut migrations = Migrations::new();
+        migrations.add_migration(Box::new(PutMigration("0.1.1")));
+        migrations.add_migration(Box::new(PutMigration("0.2.0")));
+        migrations.add_migration(Box::new(PutMigration("0.2.1")));
+        migrations.add_migration(Box::new(PutMigration("0.3.0")));
+        let open = |version, manual| {
+            RocksDB::open_and_migrate(&config, 1, VERSION_KEY, version, &migrations, manual)
+        };
+        let migrated =
+            |db: &RocksDB, version: &str| db.get_pinned("0", version.as_bytes()).unwrap().is_some();
+
+        drop(open("0.1.0", false).unwrap());
+        // patch versions are migrated at startup
+        let db = open("0.1.1", false).unwrap();
+        assert!(migrated(&db, "0.1.1"));
+        drop(db);
+
+        // minor versions require `ckb migrate`
+        assert_error_eq!(
+            open("0.2.1", false).err().unwrap(),
+            internal_error(
+                "the database version 0.1.1 requires a migration to 0.2.1, please run `ckb migrate`"
+            ),
+        );
+        let db = open("0.2.1", true).unwrap();
+        assert!(migrated(&db, "0.2.0"));
+        assert!(migrated(&db, "0.2.1"));
+        assert!(!migrated(&db, "0.3.0"));
+        drop(db);
+        let _ = open("0.2.1", false).unwrap();
+    }
+
+    #[test]
+    fn create_checkpoint() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("create_checkpoint")
+            .tempdir()
+            .unwrap();
+        let config = DBConfig {
+            path: tmp_dir.path().join("db"),
+            ..Default::default()
+        };
+        let db = RocksDB::open(&config, 2);
+        let txn = db.transaction();
+        txn.put("0", &[0], &[0, 0]).unwrap();
+        txn.put("1", &[1], &[1, 1]).unwrap();
+        txn.commit().unwrap();
+
+        let path = tmp_dir.path().join("checkpoint");
+        db.create_checkpoint(&path).unwrap();
+        assert!(db.create_checkpoint(&path).is_err());
+
+        let txn = db.transaction();
+        txn.put("0", &[2], &[2, 2]).unwrap();
+        txn.commit().unwrap();
+
+        let config = DBConfig {
+            path,
+            ..Default::default()
+        };
+        let checkpoint = RocksDB::open(&config, 2);
+        assert_eq!(
+            checkpoint.get_pinned("1", &[1]).unwrap().unwrap().as_ref(),
+            &[1, 1]
+        );
+        assert!(checkpoint.get_pinned("0", &[2]).unwrap().is_none());
+    }
 }
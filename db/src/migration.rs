@@ -0,0 +1,121 @@
+//! Migrations of the data in a database.
+//!
+//! The database stores its data format version under `VERSION_KEY`. When a
+//! database with an older version is opened, the registered migrations newer
+//! than the stored version run in version order, and the stored version is
+//! updated after each one, so an interrupted migration resumes from the first
+//! migration which has not finished.
+//!
+//! Migrations to a new patch version run when the database is opened, the
+//! ones to a new minor version only run with `ckb migrate`.
+use crate::{Result, RocksDB};
+use ckb_logger::info;
+use semver::Version;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::time::{Duration, Instant};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+pub trait Migration {
+    /// The database version after this migration.
+    fn version(&self) -> &str;
+
+    /// Migrates the data.
+    ///
+    /// An interrupted migration runs again from the start, so it has to skip
+    /// the data it has already migrated.
+    fn migrate(&self, db: &RocksDB, progress: &mut MigrationProgress) -> Result<()>;
+}
+
+/// Reports the progress of a migration in the log.
+pub struct MigrationProgress {
+    version: String,
+    total: u64,
+    done: u64,
+    reported_at: Instant,
+}
+
+impl MigrationProgress {
+    fn new(version: &str) -> Self {
+        MigrationProgress {
+            version: version.to_owned(),
+            total: 0,
+            done: 0,
+            reported_at: Instant::now(),
+        }
+    }
+
+    /// Sets the total amount of work, in any unit the migration likes.
+    pub fn set_total(&mut self, total: u64) {
+        self.total = total;
+    }
+
+    pub fn inc(&mut self, delta: u64) {
+        self.done += delta;
+        if self.reported_at.elapsed() >= PROGRESS_INTERVAL {
+            self.reported_at = Instant::now();
+            if self.total > 0 {
+                info!(
+                    "Migrating to {}: {}/{} ({}%)",
+                    self.version,
+                    self.done,
+                    self.total,
+                    self.done * 100 / self.total
+                );
+            } else {
+                info!("Migrating to {}: {}", self.version, self.done);
+            }
+        }
+    }
+}
+
+/// The registry of the migrations of a database.
+#[derive(Default)]
+pub struct Migrations {
+    migrations: BTreeMap<Version, Box<dyn Migration>>,
+}
+
+impl Migrations {
+    pub fn new() -> Self {
+        Migrations::default()
+    }
+
+    pub fn add_migration(&mut self, migration: Box<dyn Migration>) {
+        let version = Version::parse(migration.version())
+            .unwrap_or_else(|err| panic!("malformed migration version: {}", err));
+        self.migrations.insert(version, migration);
+    }
+
+    // The migrations after the version `from` up to `to`, in version order.
+    fn pending(
+        &self,
+        from: &Version,
+        to: &Version,
+    ) -> impl Iterator<Item = (&Version, &dyn Migration)> {
+        self.migrations
+            .range((Bound::Excluded(from.clone()), Bound::Included(to.clone())))
+            .map(|(version, migration)| (version, migration.as_ref()))
+    }
+
+    pub(crate) fn has_pending(&self, from: &Version, to: &Version) -> bool {
+        self.pending(from, to).next().is_some()
+    }
+
+    pub(crate) fn migrate(
+        &self,
+        db: &RocksDB,
+        ver_key: &str,
+        from: &Version,
+        to: &Version,
+    ) -> Result<()> {
+        for (version, migration) in self.pending(from, to) {
+            info!("Migrating the data to {} ...", version);
+            let mut progress = MigrationProgress::new(migration.version());
+            migration.migrate(db, &mut progress)?;
+            db.put_version(ver_key, migration.version())?;
+            info!("Migrated the data to {}", version);
+        }
+        Ok(())
+    }
+}
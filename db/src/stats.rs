@@ -0,0 +1,188 @@
+//! Statistics of a database, read from the RocksDB properties and, when
+//! `DBConfig::statistics` is enabled, the RocksDB statistics.
+use crate::db::cf_handle;
+use crate::{internal_error, Col, Result, RocksDB};
+use libc::c_void;
+use rocksdb::{ffi, Handle, Options};
+use std::ffi::{CStr, CString};
+
+// The default number of levels of a column
+const MAX_LEVELS: usize = 7;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ColumnStats {
+    pub column: Col,
+    pub estimated_keys: u64,
+    pub estimated_live_data_size: u64,
+    pub sst_files: u64,
+    pub sst_files_size: u64,
+    pub mem_tables_size: u64,
+    pub block_cache_usage: u64,
+    pub compaction_pending: bool,
+    pub pending_compaction_bytes: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DBStats {
+    pub columns: Vec<ColumnStats>,
+    pub running_compactions: u64,
+    /// None unless `DBConfig::statistics` is enabled
+    pub block_cache_hits: Option<u64>,
+    /// None unless `DBConfig::statistics` is enabled
+    pub block_cache_misses: Option<u64>,
+}
+
+impl DBStats {
+    pub fn block_cache_hit_rate(&self) -> Option<f64> {
+        match (self.block_cache_hits, self.block_cache_misses) {
+            (Some(hits), Some(misses)) if hits + misses > 0 => {
+                Some(hits as f64 / (hits + misses) as f64)
+            }
+            _ => None,
+        }
+    }
+}
+
+// The options the database is opened with, the statistics enabled in them
+// are shared with the database.
+pub(crate) struct DBStatistics(Options);
+
+// The RocksDB statistics are thread safe, and the options are only used to
+// read them.
+unsafe impl Send for DBStatistics {}
+unsafe impl Sync for DBStatistics {}
+
+impl DBStatistics {
+    pub(crate) fn new(options: Options) -> Self {
+        DBStatistics(options)
+    }
+
+    // Reads a ticker, the statistics have a line `<name> COUNT : <count>` for
+    // each ticker.
+    fn ticker(&self, name: &str) -> Option<u64> {
+        let statistics = self.0.get_statistics()?;
+        statistics.lines().find_map(|line| {
+            let mut parts = line.splitn(2, " COUNT : ");
+            if parts.next()? == name {
+                parts.next()?.trim().parse().ok()
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl RocksDB {
+    /// Returns the value of a RocksDB property of the column, see
+    /// `rocksdb/db.h` for the properties.
+    pub fn property_value(&self, col: Col, name: &str) -> Result<Option<String>> {
+        let cf = cf_handle(&self.inner, col)?;
+        let name = CString::new(name).map_err(internal_error)?;
+        unsafe {
+            let value = ffi::rocksdb_property_value_cf(
+                self.inner.base_db_ptr(),
+                cf.handle(),
+                name.as_ptr(),
+            );
+            if value.is_null() {
+                return Ok(None);
+            }
+            let ret = CStr::from_ptr(value).to_string_lossy().into_owned();
+            libc::free(value as *mut c_void);
+            Ok(Some(ret))
+        }
+    }
+
+    pub fn property_int_value(&self, col: Col, name: &str) -> Result<Option<u64>> {
+        self.property_value(col, name)?
+            .map(|value| {
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|err| internal_error(format!("malformed property {}: {}", name, err)))
+            })
+            .transpose()
+    }
+
+    pub fn column_stats(&self, col: Col) -> Result<ColumnStats> {
+        let int_value = |name: &str| {
+            self.property_int_value(col, name)
+                .map(Option::unwrap_or_default)
+        };
+        let mut sst_files = 0;
+        for level in 0..MAX_LEVELS {
+            let name = format!("rocksdb.num-files-at-level{}", level);
+            match self.property_int_value(col, &name)? {
+                Some(files) => sst_files += files,
+                None => break,
+            }
+        }
+        Ok(ColumnStats {
+            column: col,
+            estimated_keys: int_value("rocksdb.estimate-num-keys")?,
+            estimated_live_data_size: int_value("rocksdb.estimate-live-data-size")?,
+            sst_files,
+            sst_files_size: int_value("rocksdb.total-sst-files-size")?,
+            mem_tables_size: int_value("rocksdb.cur-size-all-mem-tables")?,
+            block_cache_usage: int_value("rocksdb.block-cache-usage")?,
+            compaction_pending: int_value("rocksdb.compaction-pending")? > 0,
+            pending_compaction_bytes: int_value("rocksdb.estimate-pending-compaction-bytes")?,
+        })
+    }
+
+    pub fn stats(&self, columns: &[Col]) -> Result<DBStats> {
+        let columns = columns
+            .iter()
+            .map(|col| self.column_stats(*col))
+            .collect::<Result<Vec<_>>>()?;
+        let running_compactions = match columns.first() {
+            Some(stats) => self
+                .property_int_value(stats.column, "rocksdb.num-running-compactions")?
+                .unwrap_or_default(),
+            None => 0,
+        };
+        let ticker = |name: &str| {
+            self.statistics
+                .as_ref()
+                .and_then(|statistics| statistics.ticker(name))
+        };
+        Ok(DBStats {
+            columns,
+            running_compactions,
+            block_cache_hits: ticker("rocksdb.block.cache.hit"),
+            block_cache_misses: ticker("rocksdb.block.cache.miss"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DBConfig, RocksDB};
+
+    #[test]
+    fn column_stats() {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("column_stats")
+            .tempdir()
+            .unwrap();
+        let config = DBConfig {
+            path: tmp_dir.path().to_path_buf(),
+            statistics: true,
+            ..Default::default()
+        };
+        let db = RocksDB::open(&config, 2);
+        let txn = db.transaction();
+        for i in 0..10u8 {
+            txn.put("0", &[i], &[i; 100]).unwrap();
+        }
+        txn.commit().unwrap();
+
+        let stats = db.stats(&["0", "1"]).unwrap();
+        assert_eq!(stats.columns.len(), 2);
+        assert_eq!(stats.columns[0].column, "0");
+        assert_eq!(stats.columns[0].estimated_keys, 10);
+        assert_eq!(stats.columns[1].estimated_keys, 0);
+        assert!(stats.block_cache_hits.is_some());
+        assert!(db.property_value("2", "rocksdb.estimate-num-keys").is_err());
+    }
+}
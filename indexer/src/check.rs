@@ -0,0 +1,200 @@
+//! Verifies that the indexer agrees with the chain store.
+use crate::store::{
+    delete_lock_hash, IndexerStoreTransaction, COLUMN_LOCK_HASH_INDEX_STATE,
+    COLUMN_LOCK_HASH_LIVE_CELL, COLUMN_OUT_POINT_LOCK_HASH,
+};
+use crate::types::{LockHashIndex, LockHashIndexState};
+use ckb_db::{Direction, RocksDB};
+use ckb_store::{ChainDB, ChainStore};
+use ckb_types::{
+    core::BlockNumber,
+    packed::{self, Byte32, OutPoint},
+    prelude::*,
+};
+use std::collections::HashSet;
+use std::fmt;
+
+/// An inconsistency between the indexer and the chain, found by `check_consistency`
+#[derive(Clone, Debug, PartialEq)]
+pub enum IndexerInconsistency {
+    /// The lock hash is indexed to a block which the chain store does not
+    /// have, the indexer can not detach it
+    UnknownIndexedBlock {
+        lock_hash: Byte32,
+        block_number: BlockNumber,
+        block_hash: Byte32,
+    },
+    /// A live cell of the lock hash is not created in the main chain, or is
+    /// dead in the chain while the lock hash is indexed to the tip
+    UnknownLiveCell {
+        lock_hash: Byte32,
+        out_point: OutPoint,
+    },
+    /// A live cell of the lock hash misses its out point entry
+    MissingOutPoint {
+        lock_hash: Byte32,
+        out_point: OutPoint,
+    },
+}
+
+impl IndexerInconsistency {
+    pub fn lock_hash(&self) -> &Byte32 {
+        match self {
+            IndexerInconsistency::UnknownIndexedBlock { lock_hash, .. }
+            | IndexerInconsistency::UnknownLiveCell { lock_hash, .. }
+            | IndexerInconsistency::MissingOutPoint { lock_hash, .. } => lock_hash,
+        }
+    }
+}
+
+impl fmt::Display for IndexerInconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IndexerInconsistency::UnknownIndexedBlock {
+                lock_hash,
+                block_number,
+                block_hash,
+            } => write!(
+                f,
+                "lock hash {} is indexed to the unknown block {} {}",
+                lock_hash, block_number, block_hash
+            ),
+            IndexerInconsistency::UnknownLiveCell {
+                lock_hash,
+                out_point,
+            } => write!(
+                f,
+                "lock hash {} has the live cell {} which is not live in the chain",
+                lock_hash, out_point
+            ),
+            IndexerInconsistency::MissingOutPoint {
+                lock_hash,
+                out_point,
+            } => write!(
+                f,
+                "lock hash {} has the live cell {} without its out point entry",
+                lock_hash, out_point
+            ),
+        }
+    }
+}
+
+/// Verifies the index states and the live cells of the indexer database
+/// against the chain store.
+pub fn check_consistency(db: &RocksDB, store: &ChainDB) -> Vec<IndexerInconsistency> {
+    let tip_hash = store.get_tip_header().map(|tip| tip.hash());
+    let mut inconsistencies = Vec::new();
+    for (lock_hash, index_state) in index_states(db) {
+        if !store.block_exists(&index_state.block_hash) {
+            inconsistencies.push(IndexerInconsistency::UnknownIndexedBlock {
+                lock_hash,
+                block_number: index_state.block_number,
+                block_hash: index_state.block_hash,
+            });
+            continue;
+        }
+        // The indexer detaches the fork blocks itself
+        if store.get_block_number(&index_state.block_hash) != Some(index_state.block_number) {
+            continue;
+        }
+        let indexed_to_tip = tip_hash.as_ref() == Some(&index_state.block_hash);
+        let live_cells = db
+            .iter(
+                COLUMN_LOCK_HASH_LIVE_CELL,
+                lock_hash.as_slice(),
+                Direction::Forward,
+            )
+            .expect("indexer db iter should be ok")
+            .take_while(|(key, _)| key.starts_with(lock_hash.as_slice()))
+            .map(|(key, _)| {
+                LockHashIndex::from_packed(packed::LockHashIndexReader::from_slice(&key).unwrap())
+            });
+        for lock_hash_index in live_cells {
+            let out_point = lock_hash_index.out_point;
+            let tx_hash = out_point.tx_hash();
+            let index: u32 = out_point.index().unpack();
+            let created = store
+                .get_transaction_info(&tx_hash)
+                .map(|info| info.block_number == lock_hash_index.block_number)
+                .unwrap_or(false);
+            let live = !indexed_to_tip
+                || store
+                    .get_tx_meta(&tx_hash)
+                    .and_then(|meta| meta.is_dead(index as usize))
+                    == Some(false);
+            if !created || !live {
+                inconsistencies.push(IndexerInconsistency::UnknownLiveCell {
+                    lock_hash: lock_hash.clone(),
+                    out_point,
+                });
+            } else if db
+                .get_pinned(COLUMN_OUT_POINT_LOCK_HASH, out_point.as_slice())
+                .expect("indexer db read should be ok")
+                .is_none()
+            {
+                inconsistencies.push(IndexerInconsistency::MissingOutPoint {
+                    lock_hash: lock_hash.clone(),
+                    out_point,
+                });
+            }
+        }
+    }
+    inconsistencies
+}
+
+/// Only verifies that the chain store has the blocks the lock hashes are
+/// indexed to, so the indexer can work with the chain store.
+pub fn check_indexed_blocks(db: &RocksDB, store: &ChainDB) -> Vec<IndexerInconsistency> {
+    index_states(db)
+        .into_iter()
+        .filter(|(_, index_state)| !store.block_exists(&index_state.block_hash))
+        .map(
+            |(lock_hash, index_state)| IndexerInconsistency::UnknownIndexedBlock {
+                lock_hash,
+                block_number: index_state.block_number,
+                block_hash: index_state.block_hash,
+            },
+        )
+        .collect()
+}
+
+fn index_states(db: &RocksDB) -> Vec<(Byte32, LockHashIndexState)> {
+    db.iter(COLUMN_LOCK_HASH_INDEX_STATE, &[], Direction::Forward)
+        .expect("indexer db iter should be ok")
+        .map(|(key, value)| {
+            (
+                Byte32::from_slice(&key).expect("db safe access"),
+                LockHashIndexState::from_packed(
+                    packed::LockHashIndexStateReader::from_slice(&value)
+                        .expect("verify LockHashIndexState in storage should be ok"),
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Fixes the inconsistencies by indexing their lock hashes again from the
+/// genesis, returns the number of the reindexed lock hashes.
+pub fn fix_inconsistencies(
+    db: &RocksDB,
+    store: &ChainDB,
+    inconsistencies: &[IndexerInconsistency],
+) -> usize {
+    let lock_hashes: HashSet<_> = inconsistencies
+        .iter()
+        .map(IndexerInconsistency::lock_hash)
+        .collect();
+    let genesis = LockHashIndexState {
+        block_number: 0,
+        block_hash: store.get_block_hash(0).expect("genesis exists"),
+    };
+    let txn = IndexerStoreTransaction {
+        txn: db.transaction(),
+    };
+    for lock_hash in lock_hashes.iter() {
+        delete_lock_hash(db, &txn, lock_hash);
+        txn.insert_lock_hash_index_state(lock_hash, &genesis);
+    }
+    txn.commit();
+    lock_hashes.len()
+}
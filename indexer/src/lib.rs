@@ -1,5 +1,11 @@
+mod check;
+mod migrations;
 mod store;
 mod types;
 
-pub use store::{DefaultIndexerStore, IndexerStore};
+pub use check::{
+    check_consistency, check_indexed_blocks, fix_inconsistencies, IndexerInconsistency,
+};
+pub use migrations::migrations;
+pub use store::{DefaultIndexerStore, IndexerStore, COLUMNS, COLUMN_NAMES};
 pub use types::{CellTransaction, IndexerConfig, LiveCell, TransactionPoint};
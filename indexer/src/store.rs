@@ -1,10 +1,11 @@
+use crate::migrations::migrations;
 use crate::types::{
     CellTransaction, IndexerConfig, LiveCell, LockHashCellOutput, LockHashIndex,
     LockHashIndexState, TransactionPoint,
 };
-use ckb_db::{db::RocksDB, Col, DBIterator, Direction, RocksDBTransaction};
+use ckb_db::{db::RocksDB, Col, DBIterator, DBStats, Direction, RocksDBTransaction};
 use ckb_logger::{debug, error, trace};
-use ckb_shared::shared::Shared;
+use ckb_shared::{shared::Shared, Snapshot};
 use ckb_store::ChainStore;
 use ckb_types::{
     core::{self, BlockNumber},
@@ -13,11 +14,12 @@ use ckb_types::{
 };
 use ckb_util::Mutex;
 use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-const COLUMNS: u32 = 4;
+pub const COLUMNS: u32 = 4;
 
 /// +---------------------------------+---------------+--------------------------+
 /// |             Column              |      Key      |          Value           |
@@ -28,10 +30,18 @@ const COLUMNS: u32 = 4;
 /// | COLUMN_OUT_POINT_LOCK_HASH      | OutPoint      | LockHashCellOutput       |
 /// +---------------------------------+---------------+--------------------------+
 
-const COLUMN_LOCK_HASH_INDEX_STATE: Col = "0";
-const COLUMN_LOCK_HASH_LIVE_CELL: Col = "1";
-const COLUMN_LOCK_HASH_TRANSACTION: Col = "2";
-const COLUMN_OUT_POINT_LOCK_HASH: Col = "3";
+pub(crate) const COLUMN_LOCK_HASH_INDEX_STATE: Col = "0";
+pub(crate) const COLUMN_LOCK_HASH_LIVE_CELL: Col = "1";
+pub(crate) const COLUMN_LOCK_HASH_TRANSACTION: Col = "2";
+pub(crate) const COLUMN_OUT_POINT_LOCK_HASH: Col = "3";
+
+/// The names of the columns, shown in the database statistics
+pub const COLUMN_NAMES: [(Col, &str); COLUMNS as usize] = [
+    (COLUMN_LOCK_HASH_INDEX_STATE, "lock_hash_index_state"),
+    (COLUMN_LOCK_HASH_LIVE_CELL, "lock_hash_live_cell"),
+    (COLUMN_LOCK_HASH_TRANSACTION, "lock_hash_transaction"),
+    (COLUMN_OUT_POINT_LOCK_HASH, "out_point_lock_hash"),
+];
 
 pub trait IndexerStore: Sync + Send {
     fn get_live_cells(
@@ -52,6 +62,8 @@ pub trait IndexerStore: Sync + Send {
 
     fn get_lock_hash_index_states(&self) -> HashMap<Byte32, LockHashIndexState>;
 
+    /// Indexes the lock hash from the block after `index_from`, it is clamped
+    /// to `min_index_from` and to the tip.
     fn insert_lock_hash(
         &self,
         lock_hash: &Byte32,
@@ -59,6 +71,10 @@ pub trait IndexerStore: Sync + Send {
     ) -> LockHashIndexState;
 
     fn remove_lock_hash(&self, lock_hash: &Byte32);
+
+    /// The lowest block a lock hash can be indexed from, the blocks after it
+    /// are not pruned.
+    fn min_index_from(&self) -> BlockNumber;
 }
 
 #[derive(Clone)]
@@ -165,7 +181,10 @@ impl IndexerStore for DefaultIndexerStore {
         let index_state = {
             let snapshot = self.shared.snapshot();
             let tip_number = snapshot.tip_header().number();
-            let block_number = index_from.unwrap_or_else(|| tip_number).min(tip_number);
+            let block_number = index_from
+                .unwrap_or_else(|| tip_number)
+                .max(min_index_from(&snapshot))
+                .min(tip_number);
             LockHashIndexState {
                 block_number,
                 block_hash: snapshot.get_block_hash(block_number).expect("block exists"),
@@ -182,50 +201,80 @@ impl IndexerStore for DefaultIndexerStore {
     fn remove_lock_hash(&self, lock_hash: &Byte32) {
         let sync_lock = self.sync_lock.lock();
         self.commit_txn(|txn| {
-            let iter = self
-                .db
-                .iter(
-                    COLUMN_LOCK_HASH_LIVE_CELL,
-                    lock_hash.as_slice(),
-                    Direction::Forward,
-                )
-                .expect("indexer db iter should be ok");
-
-            iter.take_while(|(key, _)| key.starts_with(lock_hash.as_slice()))
-                .for_each(|(key, _)| {
-                    let lock_hash_index = LockHashIndex::from_packed(
-                        packed::LockHashIndexReader::from_slice(&key).unwrap(),
-                    );
-                    txn.delete_lock_hash_live_cell(&lock_hash_index);
-                    txn.delete_cell_out_point_lock_hash(&lock_hash_index.out_point);
-                });
+            delete_lock_hash(&self.db, txn, lock_hash);
+        });
+        drop(sync_lock);
+    }
 
-            let iter = self
-                .db
-                .iter(
-                    COLUMN_LOCK_HASH_TRANSACTION,
-                    lock_hash.as_slice(),
-                    Direction::Forward,
-                )
-                .expect("indexer db iter should be ok");
-
-            iter.take_while(|(key, _)| key.starts_with(lock_hash.as_slice()))
-                .for_each(|(key, _)| {
-                    let lock_hash_index = LockHashIndex::from_packed(
-                        packed::LockHashIndexReader::from_slice(&key).unwrap(),
-                    );
-                    txn.delete_lock_hash_transaction(&lock_hash_index);
-                });
+    fn min_index_from(&self) -> BlockNumber {
+        min_index_from(&self.shared.snapshot())
+    }
+}
+
+// The genesis block is never pruned, so a lock hash is indexed from it unless
+// the pruning has removed the blocks after it
+fn min_index_from(snapshot: &Snapshot) -> BlockNumber {
+    snapshot
+        .get_pruned_number()
+        .map(|pruned_number| pruned_number.saturating_sub(1))
+        .unwrap_or(0)
+}
 
-            txn.delete_lock_hash_index_state(&lock_hash);
+// Deletes the live cells, the transactions and the index state of the lock hash
+pub(crate) fn delete_lock_hash(db: &RocksDB, txn: &IndexerStoreTransaction, lock_hash: &Byte32) {
+    let iter = db
+        .iter(
+            COLUMN_LOCK_HASH_LIVE_CELL,
+            lock_hash.as_slice(),
+            Direction::Forward,
+        )
+        .expect("indexer db iter should be ok");
+
+    iter.take_while(|(key, _)| key.starts_with(lock_hash.as_slice()))
+        .for_each(|(key, _)| {
+            let lock_hash_index =
+                LockHashIndex::from_packed(packed::LockHashIndexReader::from_slice(&key).unwrap());
+            txn.delete_lock_hash_live_cell(&lock_hash_index);
+            txn.delete_cell_out_point_lock_hash(&lock_hash_index.out_point);
         });
-        drop(sync_lock);
+
+    let iter = db
+        .iter(
+            COLUMN_LOCK_HASH_TRANSACTION,
+            lock_hash.as_slice(),
+            Direction::Forward,
+        )
+        .expect("indexer db iter should be ok");
+
+    iter.take_while(|(key, _)| key.starts_with(lock_hash.as_slice()))
+        .for_each(|(key, _)| {
+            let lock_hash_index =
+                LockHashIndex::from_packed(packed::LockHashIndexReader::from_slice(&key).unwrap());
+            txn.delete_lock_hash_transaction(&lock_hash_index);
+        });
+
+    txn.delete_lock_hash_index_state(lock_hash);
+}
+
+// The blocks from the indexed block back to the main chain, in the detach
+// order, None if any of them is pruned or missing
+fn fork_blocks(snapshot: &Snapshot, block_hash: &Byte32) -> Option<Vec<core::BlockView>> {
+    let mut blocks = vec![snapshot.get_block(block_hash)?];
+    loop {
+        let block = blocks.last().expect("at least the indexed block");
+        let parent_hash = block.data().header().raw().parent_hash();
+        if snapshot.get_block_hash(block.header().number() - 1) == Some(parent_hash.clone()) {
+            return Some(blocks);
+        }
+        let parent = snapshot.get_block(&parent_hash)?;
+        blocks.push(parent);
     }
 }
 
 impl DefaultIndexerStore {
     pub fn new(config: &IndexerConfig, shared: Shared) -> Self {
-        let db = RocksDB::open(&config.db, COLUMNS);
+        let db = RocksDB::open_with_migrations(&config.db, COLUMNS, &migrations())
+            .unwrap_or_else(|err| panic!("{}", err));
         DefaultIndexerStore {
             db: Arc::new(db),
             shared,
@@ -235,6 +284,26 @@ impl DefaultIndexerStore {
         }
     }
 
+    /// Creates a consistent copy of the indexer database in `path`, then
+    /// calls `then` before the indexer syncs with the chain again.
+    ///
+    /// A copy of the chain database made in `then` has all the blocks
+    /// indexed in the copy of the indexer database.
+    pub fn create_checkpoint<F>(&self, path: &Path, then: F) -> ckb_db::Result<()>
+    where
+        F: FnOnce() -> ckb_db::Result<()>,
+    {
+        let _sync_lock = self.sync_lock.lock();
+        self.db.create_checkpoint(path)?;
+        then()
+    }
+
+    /// Returns the statistics of the columns, in the order of `COLUMN_NAMES`.
+    pub fn stats(&self) -> ckb_db::Result<DBStats> {
+        let columns: Vec<_> = COLUMN_NAMES.iter().map(|(col, _)| *col).collect();
+        self.db.stats(&columns)
+    }
+
     pub fn start<S: ToString>(self, thread_name: Option<S>) {
         let mut thread_builder = thread::Builder::new();
         if let Some(name) = thread_name {
@@ -260,49 +329,42 @@ impl DefaultIndexerStore {
         txn.commit();
     }
 
+    /// Detaches the indexed blocks which are not in the main chain any more,
+    /// such as the blocks removed by the chain truncation, so the queries
+    /// don't return their cells.
+    pub fn detach_forks(&self) {
+        let _sync_lock = self.sync_lock.lock();
+        let snapshot = self.shared.snapshot();
+        self.detach_fork_blocks(&snapshot);
+    }
+
     pub fn sync_index_states(&self) {
         let sync_lock = self.sync_lock.lock();
         debug!("Start sync index states with chain store");
-        let mut lock_hash_index_states = self.get_lock_hash_index_states();
-        if lock_hash_index_states.is_empty() {
+        if self.get_lock_hash_index_states().is_empty() {
             return;
         }
         let snapshot = self.shared.snapshot();
-        // retains the lock hashes on fork chain and detach blocks
-        lock_hash_index_states.retain(|_, index_state| {
-            snapshot.get_block_number(&index_state.block_hash.clone())
-                != Some(index_state.block_number)
-        });
-        lock_hash_index_states
-            .iter()
-            .for_each(|(lock_hash, index_state)| {
-                let mut index_lock_hashes = HashSet::new();
-                index_lock_hashes.insert(lock_hash.to_owned());
-
-                let mut block = snapshot
-                    .get_block(&index_state.block_hash.clone())
-                    .expect("block exists");
-                // detach blocks until reach a block on main chain
-                self.commit_txn(|txn| {
-                    self.detach_block(txn, &index_lock_hashes, &block);
-                    while snapshot.get_block_hash(block.header().number() - 1)
-                        != Some(block.data().header().raw().parent_hash())
-                    {
-                        block = snapshot
-                            .get_block(&block.data().header().raw().parent_hash())
-                            .expect("block exists");
-                        self.detach_block(txn, &index_lock_hashes, &block);
-                    }
-                    let index_state = LockHashIndexState {
-                        block_number: block.header().number() - 1,
-                        block_hash: block.header().parent_hash().to_owned(),
-                    };
-                    txn.insert_lock_hash_index_state(lock_hash, &index_state);
-                });
-            });
+        self.detach_fork_blocks(&snapshot);
 
         // attach blocks until reach tip or txn limit
         let mut lock_hash_index_states = self.get_lock_hash_index_states();
+        // The lock hashes indexed up to a pruned block can't catch up, they
+        // have to be indexed again from a later block
+        let min_index_from = min_index_from(&snapshot);
+        lock_hash_index_states.retain(|lock_hash, index_state| {
+            let stale = index_state.block_number < min_index_from;
+            if stale {
+                error!(
+                    "Indexer can not sync lock hash {} from block {}, the blocks after it are pruned",
+                    lock_hash, index_state.block_number
+                );
+            }
+            !stale
+        });
+        if lock_hash_index_states.is_empty() {
+            return;
+        }
 
         let min_block_number: BlockNumber = lock_hash_index_states
             .values()
@@ -319,29 +381,36 @@ impl DefaultIndexerStore {
 
         let tip_number = snapshot.tip_header().number();
         self.commit_txn(|txn| {
-            (start_number..=tip_number)
-                .take(self.batch_size)
-                .for_each(|block_number| {
-                    let index_lock_hashes = lock_hash_index_states
-                        .iter()
-                        .filter(|(_, index_state)| index_state.block_number <= block_number)
-                        .map(|(lock_hash, _)| lock_hash)
-                        .cloned()
-                        .collect();
-                    let block = snapshot
-                        .get_block_hash(block_number)
-                        .as_ref()
-                        .and_then(|hash| snapshot.get_block(hash))
-                        .expect("block exists");
-                    self.attach_block(txn, &index_lock_hashes, &block);
-                    let index_state = LockHashIndexState {
-                        block_number,
-                        block_hash: block.hash(),
-                    };
-                    index_lock_hashes.into_iter().for_each(|lock_hash| {
-                        lock_hash_index_states.insert(lock_hash, index_state.clone());
-                    })
-                });
+            for block_number in (start_number..=tip_number).take(self.batch_size) {
+                let index_lock_hashes = lock_hash_index_states
+                    .iter()
+                    .filter(|(_, index_state)| index_state.block_number <= block_number)
+                    .map(|(lock_hash, _)| lock_hash)
+                    .cloned()
+                    .collect();
+                let block = match snapshot
+                    .get_block_hash(block_number)
+                    .as_ref()
+                    .and_then(|hash| snapshot.get_block(hash))
+                {
+                    Some(block) => block,
+                    None => {
+                        error!(
+                            "Indexer can not attach block {}, it is pruned or missing",
+                            block_number
+                        );
+                        break;
+                    }
+                };
+                self.attach_block(txn, &index_lock_hashes, &block);
+                let index_state = LockHashIndexState {
+                    block_number,
+                    block_hash: block.hash(),
+                };
+                index_lock_hashes.into_iter().for_each(|lock_hash| {
+                    lock_hash_index_states.insert(lock_hash, index_state.clone());
+                })
+            }
 
             lock_hash_index_states
                 .iter()
@@ -354,6 +423,45 @@ impl DefaultIndexerStore {
         debug!("End sync index states with chain store");
     }
 
+    // The caller holds the sync lock
+    fn detach_fork_blocks(&self, snapshot: &Snapshot) {
+        let mut lock_hash_index_states = self.get_lock_hash_index_states();
+        // retains the lock hashes on fork chain and detach blocks
+        lock_hash_index_states.retain(|_, index_state| {
+            snapshot.get_block_number(&index_state.block_hash.clone())
+                != Some(index_state.block_number)
+        });
+        lock_hash_index_states
+            .iter()
+            .for_each(|(lock_hash, index_state)| {
+                let mut index_lock_hashes = HashSet::new();
+                index_lock_hashes.insert(lock_hash.to_owned());
+
+                let blocks = match fork_blocks(snapshot, &index_state.block_hash) {
+                    Some(blocks) => blocks,
+                    None => {
+                        error!(
+                            "Indexer can not detach the blocks of lock hash {} after block {}, \
+                             they are pruned or missing",
+                            lock_hash, index_state.block_hash
+                        );
+                        return;
+                    }
+                };
+                self.commit_txn(|txn| {
+                    for block in &blocks {
+                        self.detach_block(txn, &index_lock_hashes, block);
+                    }
+                    let fork_point = blocks.last().expect("at least the indexed block");
+                    let index_state = LockHashIndexState {
+                        block_number: fork_point.header().number() - 1,
+                        block_hash: fork_point.header().parent_hash().to_owned(),
+                    };
+                    txn.insert_lock_hash_index_state(lock_hash, &index_state);
+                });
+            });
+    }
+
     fn detach_block(
         &self,
         txn: &IndexerStoreTransaction,
@@ -454,7 +562,7 @@ impl DefaultIndexerStore {
     }
 }
 
-struct IndexerStoreTransaction {
+pub(crate) struct IndexerStoreTransaction {
     pub txn: RocksDBTransaction,
 }
 
@@ -497,7 +605,11 @@ impl IndexerStoreTransaction {
         }
     }
 
-    fn insert_lock_hash_index_state(&self, lock_hash: &Byte32, index_state: &LockHashIndexState) {
+    pub(crate) fn insert_lock_hash_index_state(
+        &self,
+        lock_hash: &Byte32,
+        index_state: &LockHashIndexState,
+    ) {
         let value = index_state.pack();
         self.txn
             .put(
@@ -597,7 +709,7 @@ impl IndexerStoreTransaction {
             })
     }
 
-    fn commit(self) {
+    pub(crate) fn commit(self) {
         // only log the error, indexer store commit failure should not causing the thread to panic entirely.
         if let Err(err) = self.txn.commit() {
             error!("indexer db failed to commit txn, error: {:?}", err)
@@ -615,6 +727,7 @@ mod tests {
     use ckb_chain_spec::consensus::Consensus;
     use ckb_resource::CODE_HASH_DAO;
     use ckb_shared::shared::{Shared, SharedBuilder};
+    use ckb_store::{StoreConfig, MIN_PRUNE_DEPTH};
     use ckb_types::{
         core::{
             capacity_bytes, BlockBuilder, Capacity, HeaderBuilder, ScriptHashType,
@@ -643,6 +756,69 @@ mod tests {
         )
     }
 
+    #[test]
+    fn index_lock_hash_on_pruned_store() {
+        let store_config = StoreConfig {
+            prune_depth: Some(MIN_PRUNE_DEPTH),
+            ..Default::default()
+        };
+        let (shared, table) = SharedBuilder::default()
+            .consensus(Consensus::default())
+            .store_config(store_config)
+            .build()
+            .unwrap();
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("index_lock_hash_on_pruned_store")
+            .tempdir()
+            .unwrap();
+        let mut config = IndexerConfig::default();
+        config.db.path = tmp_dir.as_ref().to_path_buf();
+        let chain = ChainService::new(shared.clone(), table).start::<&str>(None);
+        let store = DefaultIndexerStore::new(&config, shared.clone());
+
+        let mut parent = shared.consensus().genesis_block().header();
+        for _ in 0..MIN_PRUNE_DEPTH + 10 {
+            let block = BlockBuilder::default()
+                .header(
+                    HeaderBuilder::default()
+                        .compact_target(DIFF_TWO.pack())
+                        .number((parent.number() + 1).pack())
+                        .parent_hash(parent.hash())
+                        .build(),
+                )
+                .build();
+            chain
+                .internal_process_block(Arc::new(block.clone()), Switch::DISABLE_ALL)
+                .unwrap();
+            parent = block.header();
+        }
+        while shared.store().is_pruning_in_background() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let min_index_from = store.min_index_from();
+        assert!(min_index_from > 0);
+
+        // A lock hash indexed up to a pruned block stops syncing
+        let stale_lock_hash = CODE_HASH_DAO.pack();
+        let stale_state = LockHashIndexState {
+            block_number: 1,
+            block_hash: shared.snapshot().get_block_hash(1).unwrap(),
+        };
+        store.commit_txn(|txn| {
+            txn.insert_lock_hash_index_state(&stale_lock_hash, &stale_state);
+        });
+        // A lock hash indexed from a pruned block starts after them
+        let state = store.insert_lock_hash(&Byte32::zero(), Some(0));
+        assert_eq!(min_index_from, state.block_number);
+
+        for _ in 0..=(MIN_PRUNE_DEPTH + 10) / store.batch_size as u64 {
+            store.sync_index_states();
+        }
+        let states = store.get_lock_hash_index_states();
+        assert_eq!(stale_state.block_hash, states[&stale_lock_hash].block_hash);
+        assert_eq!(parent.number(), states[&Byte32::zero()].block_number);
+    }
+
     #[test]
     fn lock_hash_index() {
         let (store, _, _) = setup("lock_hash_index");
@@ -1342,4 +1518,56 @@ mod tests {
         let cell_transactions = store.get_transactions(&script1.calc_script_hash(), 0, 100, false);
         assert_eq!(0, cell_transactions.len());
     }
+
+    #[test]
+    fn detach_truncated_blocks() {
+        let (store, chain, shared) = setup("detach_truncated_blocks");
+        let script1 = ScriptBuilder::default()
+            .code_hash(CODE_HASH_DAO.pack())
+            .hash_type(ScriptHashType::Data.pack())
+            .build();
+        store.insert_lock_hash(&script1.calc_script_hash(), None);
+
+        let tx11 = TransactionBuilder::default()
+            .output(
+                CellOutputBuilder::default()
+                    .capacity(capacity_bytes!(1000).pack())
+                    .lock(script1.clone())
+                    .build(),
+            )
+            .output_data(Default::default())
+            .build();
+
+        let block1 = BlockBuilder::default()
+            .transaction(tx11)
+            .header(
+                HeaderBuilder::default()
+                    .compact_target(DIFF_TWO.pack())
+                    .number(1.pack())
+                    .parent_hash(shared.genesis_hash())
+                    .build(),
+            )
+            .build();
+
+        chain
+            .internal_process_block(Arc::new(block1), Switch::DISABLE_ALL)
+            .unwrap();
+        store.sync_index_states();
+        let cells = store.get_live_cells(&script1.calc_script_hash(), 0, 100, false);
+        assert_eq!(1, cells.len());
+
+        chain.truncate(shared.genesis_hash()).unwrap();
+        store.detach_forks();
+        let cells = store.get_live_cells(&script1.calc_script_hash(), 0, 100, false);
+        assert_eq!(0, cells.len());
+        let cell_transactions = store.get_transactions(&script1.calc_script_hash(), 0, 100, false);
+        assert_eq!(0, cell_transactions.len());
+        assert_eq!(
+            Some(0),
+            store
+                .get_lock_hash_index_states()
+                .get(&script1.calc_script_hash())
+                .map(|index_state| index_state.block_number)
+        );
+    }
 }
@@ -0,0 +1,136 @@
+//! Anchors are the outbound peers we were connected to when the node shut down.
+//!
+//! They are written to a file in the network directory and dialed first on the
+//! next start, so an attacker cannot take over all the outbound connections of a
+//! restarted node by polluting the peer store with its own addresses. The
+//! block-relay-only anchors are dialed as block-relay-only connections again.
+use crate::errors::{ConfigError, Error, PeerStoreError};
+use crate::peer_store::types::MultiaddrExt;
+use crate::{Peer, PeerId};
+use ckb_logger::{debug, warn};
+use p2p::multiaddr::{Multiaddr, Protocol};
+use serde_derive::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::path::Path;
+
+pub(crate) const MAX_ANCHORS: usize = 2;
+
+/// An outbound peer to reconnect to on the next start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Anchor {
+    pub(crate) peer_id: PeerId,
+    pub(crate) addr: Multiaddr,
+    pub(crate) block_relay_only: bool,
+}
+
+// The anchor in the file, the address includes the peer id
+#[derive(Serialize, Deserialize)]
+struct AnchorRecord {
+    addr: String,
+    #[serde(default)]
+    block_relay_only: bool,
+}
+
+/// Chooses the anchors among the connected peers.
+///
+/// Only outbound peers are chosen, the block-relay-only ones come first, then
+/// the ones which delivered blocks to us, then the ones connected for the
+/// longest time.
+pub(crate) fn select_anchors<'a, I: Iterator<Item = &'a Peer>>(peers: I) -> Vec<Anchor> {
+    let mut candidates: Vec<&Peer> = peers
+        .filter(|peer| peer.is_outbound() && !peer.is_feeler)
+        .collect();
+    candidates.sort_by(|a, b| {
+        b.is_block_relay_only
+            .cmp(&a.is_block_relay_only)
+            .then(
+                b.last_block_time
+                    .is_some()
+                    .cmp(&a.last_block_time.is_some()),
+            )
+            .then(a.connected_time.cmp(&b.connected_time))
+    });
+    candidates
+        .into_iter()
+        .take(MAX_ANCHORS)
+        .map(|peer| Anchor {
+            peer_id: peer.peer_id.clone(),
+            addr: peer.connected_addr.exclude_p2p(),
+            block_relay_only: peer.is_block_relay_only,
+        })
+        .collect()
+}
+
+pub(crate) fn dump_anchors<P: AsRef<Path>>(path: P, anchors: &[Anchor]) -> Result<(), Error> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let records = anchors
+        .iter()
+        .map(|anchor| {
+            Ok(AnchorRecord {
+                addr: anchor.addr.attach_p2p(&anchor.peer_id)?.to_string(),
+                block_relay_only: anchor.block_relay_only,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    // write to a temp file then rename, so a crash never leaves a truncated file
+    let tmp_path = path.with_extension("tmp");
+    serde_json::to_writer(
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)?,
+        &records,
+    )
+    .map_err(PeerStoreError::Serde)?;
+    fs::rename(&tmp_path, path)?;
+    debug!("dump {} anchors to {:?}", records.len(), path);
+    Ok(())
+}
+
+/// Loads the anchors then removes the file.
+///
+/// The file is removed so the node won't reconnect to the same anchors forever
+/// if one of them makes it crash.
+pub(crate) fn load_anchors<P: AsRef<Path>>(path: P) -> Result<Vec<Anchor>, Error> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let records: Result<Vec<AnchorRecord>, _> = serde_json::from_reader(File::open(path)?);
+    if let Err(err) = fs::remove_file(path) {
+        warn!("Failed to remove anchors file {:?}: {}", path, err);
+    }
+    let anchors = records
+        .map_err(PeerStoreError::Serde)?
+        .iter()
+        .filter_map(|record| match parse_anchor(&record.addr) {
+            Ok((peer_id, addr)) => Some(Anchor {
+                peer_id,
+                addr,
+                block_relay_only: record.block_relay_only,
+            }),
+            Err(err) => {
+                warn!("Ignore invalid anchor {}: {}", record.addr, err);
+                None
+            }
+        })
+        .take(MAX_ANCHORS)
+        .collect();
+    Ok(anchors)
+}
+
+fn parse_anchor(addr: &str) -> Result<(PeerId, Multiaddr), Error> {
+    let mut addr: Multiaddr = addr.parse().map_err(|_| ConfigError::BadAddress)?;
+    match addr.pop() {
+        Some(Protocol::P2p(key)) => {
+            let peer_id =
+                PeerId::from_bytes(key.into_bytes()).map_err(|_| ConfigError::BadAddress)?;
+            Ok((peer_id, addr))
+        }
+        _ => Err(ConfigError::BadAddress.into()),
+    }
+}
@@ -4,9 +4,27 @@ use crate::Score;
 /// we maintain a score to each peer
 /// report peer bahaviour will affects peer's score
 ///
-/// Currently this feature is disabled, maybe someday we will add it back or totally remove it.
+/// The score decays toward the default score over time, the peer is
+/// disconnected and banned once its score drops below the ban score, or at
+/// once for the fatal behaviours.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Behaviour {
+    /// The peer sent a block which fails the verification
+    InvalidBlock,
+    /// The peer sent a compact block or block transactions which can not be
+    /// reconstructed into a valid block
+    InvalidCompactBlock,
+    /// The peer sent data we never requested
+    UnrequestedData,
+    /// The peer sent a message which can not be decoded
+    MalformedMessage,
+    /// The peer did not respond in time
+    Timeout,
+    /// The peer delivered a new block which we accepted
+    UsefulBlock,
+    /// The peer relayed a new transaction which we accepted, it only protects
+    /// the peer from the inbound eviction
+    UsefulTransaction,
     #[cfg(test)]
     TestGood,
     #[cfg(test)]
@@ -15,13 +33,29 @@ pub enum Behaviour {
 
 impl Behaviour {
     pub fn score(self) -> Score {
-        #[allow(unreachable_patterns)]
         match self {
+            Behaviour::InvalidBlock => -100,
+            Behaviour::InvalidCompactBlock => -60,
+            Behaviour::UnrequestedData => -10,
+            Behaviour::MalformedMessage => -100,
+            Behaviour::Timeout => -20,
+            Behaviour::UsefulBlock => 2,
+            Behaviour::UsefulTransaction => 0,
             #[cfg(test)]
             Behaviour::TestGood => 10,
             #[cfg(test)]
             Behaviour::TestBad => -10,
-            _ => 0,
+        }
+    }
+
+    /// Whether the peer is banned whatever its score is, a good history
+    /// does not excuse sending consensus-invalid or malformed data
+    pub fn is_fatal(self) -> bool {
+        match self {
+            Behaviour::InvalidBlock
+            | Behaviour::InvalidCompactBlock
+            | Behaviour::MalformedMessage => true,
+            _ => false,
         }
     }
 }
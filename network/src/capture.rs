@@ -0,0 +1,251 @@
+use crate::{config::CaptureConfig, ProtocolId, MAX_FRAME_LENGTH};
+use p2p::{bytes::Bytes, SessionId};
+use std::fs::{create_dir_all, rename, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The file being written, the rotated files are suffixed with `.1`, `.2`...
+/// and the larger suffix holds the older messages
+pub const CAPTURE_FILE_NAME: &str = "messages.capture";
+
+// timestamp (8) + direction (1) + protocol id (8) + session count (4)
+const RECORD_HEADER_SIZE: usize = 21;
+const SESSION_ID_SIZE: usize = 8;
+const DATA_LENGTH_SIZE: usize = 4;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// A protocol message recorded in the capture files
+///
+/// Each record is encoded in little endian as
+/// `timestamp (u64) | direction (u8) | protocol id (u64) | session count (u32) |
+/// session ids (u64 each) | length (u32) | data`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CapturedMessage {
+    /// Unix time in milliseconds
+    pub timestamp: u64,
+    pub direction: Direction,
+    pub protocol_id: ProtocolId,
+    /// The sessions the message is received from or broadcast to, it is empty
+    /// when the message is sent by a core protocol handler, whose session is unknown
+    pub session_ids: Vec<SessionId>,
+    /// The raw (uncompressed) payload
+    pub data: Bytes,
+}
+
+impl CapturedMessage {
+    fn encoded_len(&self) -> usize {
+        RECORD_HEADER_SIZE
+            + self.session_ids.len() * SESSION_ID_SIZE
+            + DATA_LENGTH_SIZE
+            + self.data.len()
+    }
+
+    // The records over the limits can't be read back
+    fn check_len(&self) -> io::Result<()> {
+        check_len(self.session_ids.len() * SESSION_ID_SIZE)?;
+        check_len(self.data.len())
+    }
+
+    pub(crate) fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut header = Vec::with_capacity(
+            RECORD_HEADER_SIZE + self.session_ids.len() * SESSION_ID_SIZE + DATA_LENGTH_SIZE,
+        );
+        header.extend_from_slice(&self.timestamp.to_le_bytes());
+        header.push(match self.direction {
+            Direction::Inbound => 0,
+            Direction::Outbound => 1,
+        });
+        header.extend_from_slice(&(self.protocol_id.value() as u64).to_le_bytes());
+        header.extend_from_slice(&(self.session_ids.len() as u32).to_le_bytes());
+        for session_id in &self.session_ids {
+            header.extend_from_slice(&(session_id.value() as u64).to_le_bytes());
+        }
+        header.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        writer.write_all(&header)?;
+        writer.write_all(&self.data)
+    }
+
+    /// Read the next record, returns `None` at the end of the reader
+    pub(crate) fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        if !read_or_eof(reader, &mut header)? {
+            return Ok(None);
+        }
+        let mut u64_bytes = [0u8; 8];
+        let mut u32_bytes = [0u8; 4];
+        u64_bytes.copy_from_slice(&header[0..8]);
+        let timestamp = u64::from_le_bytes(u64_bytes);
+        let direction = match header[8] {
+            0 => Direction::Inbound,
+            1 => Direction::Outbound,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid captured message direction",
+                ))
+            }
+        };
+        u64_bytes.copy_from_slice(&header[9..17]);
+        let protocol_id = (u64::from_le_bytes(u64_bytes) as usize).into();
+        u32_bytes.copy_from_slice(&header[17..21]);
+        let session_ids_len = u32::from_le_bytes(u32_bytes) as usize * SESSION_ID_SIZE;
+        check_len(session_ids_len)?;
+        let session_ids: Vec<SessionId> = read_len(reader, session_ids_len)?
+            .chunks(SESSION_ID_SIZE)
+            .map(|chunk| {
+                u64_bytes.copy_from_slice(chunk);
+                (u64::from_le_bytes(u64_bytes) as usize).into()
+            })
+            .collect();
+        reader.read_exact(&mut u32_bytes)?;
+        let data_len = u32::from_le_bytes(u32_bytes) as usize;
+        check_len(data_len)?;
+        let data = read_len(reader, data_len)?;
+        Ok(Some(CapturedMessage {
+            timestamp,
+            direction,
+            protocol_id,
+            session_ids,
+            data: data.into(),
+        }))
+    }
+}
+
+// The length of a field is bounded by the frame length, so a corrupted length
+// doesn't allocate gigabytes
+fn check_len(len: usize) -> io::Result<()> {
+    if len > MAX_FRAME_LENGTH {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("captured message field too large: {} bytes", len),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+// The buffer grows as the bytes are read rather than being allocated upfront
+fn read_len<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+// Fill the buffer, returns false if the reader ends before any byte is read
+fn read_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(true)
+}
+
+/// Appends the captured messages to `CAPTURE_FILE_NAME` in the dir, the file is
+/// rotated once it exceeds `max_file_size` and at most `max_files` files are kept
+pub struct CaptureWriter {
+    dir: PathBuf,
+    max_file_size: u64,
+    max_files: usize,
+    file: BufWriter<File>,
+    file_size: u64,
+}
+
+impl CaptureWriter {
+    pub fn open<P: AsRef<Path>>(dir: P, config: &CaptureConfig) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        create_dir_all(&dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(CAPTURE_FILE_NAME))?;
+        let file_size = file.metadata()?.len();
+        Ok(CaptureWriter {
+            dir,
+            max_file_size: config.max_file_size,
+            max_files: config.max_files,
+            file: BufWriter::new(file),
+            file_size,
+        })
+    }
+
+    fn file_path(&self, index: usize) -> PathBuf {
+        if index == 0 {
+            self.dir.join(CAPTURE_FILE_NAME)
+        } else {
+            self.dir.join(format!("{}.{}", CAPTURE_FILE_NAME, index))
+        }
+    }
+
+    pub fn write(&mut self, message: &CapturedMessage) -> io::Result<()> {
+        message.check_len()?;
+        if self.file_size > 0 && self.file_size + message.encoded_len() as u64 > self.max_file_size
+        {
+            self.rotate()?;
+        }
+        message.write_to(&mut self.file)?;
+        self.file_size += message.encoded_len() as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    // Shift the files by one suffix, the oldest one is overwritten
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for index in (1..self.max_files).rev() {
+            let from = self.file_path(index - 1);
+            if from.exists() {
+                rename(from, self.file_path(index))?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(self.file_path(0))?;
+        self.file = BufWriter::new(file);
+        self.file_size = 0;
+        Ok(())
+    }
+}
+
+/// Iterates the messages in a capture file
+pub struct CaptureReader<R> {
+    reader: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(CaptureReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(reader: R) -> Self {
+        CaptureReader { reader }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CapturedMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        CapturedMessage::read_from(&mut self.reader).transpose()
+    }
+}
@@ -13,6 +13,7 @@ use serde_derive::{Deserialize, Serialize};
 use std::fs;
 use std::io::Read;
 use std::io::Write;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
@@ -21,6 +22,9 @@ pub struct NetworkConfig {
     pub whitelist_only: bool,
     pub max_peers: u32,
     pub max_outbound_peers: u32,
+    // Extra outbound connections which only relay blocks, no transactions and no addresses
+    #[serde(default)]
+    pub block_relay_only_peers: u32,
     #[serde(default)]
     pub path: PathBuf,
     #[serde(default)]
@@ -43,6 +47,58 @@ pub struct NetworkConfig {
     pub bootnode_mode: bool,
     // Max send buffer size
     pub max_send_buffer: Option<usize>,
+    // Global upload rate limit in bytes per second
+    pub max_upload_rate: Option<u64>,
+    // Global download rate limit in bytes per second
+    pub max_download_rate: Option<u64>,
+    // Upload rate limit of each peer in bytes per second
+    pub max_peer_upload_rate: Option<u64>,
+    // Download rate limit of each peer in bytes per second
+    pub max_peer_download_rate: Option<u64>,
+    // Upload cap in bytes per day, the historical blocks are no longer served once reached
+    pub max_upload_per_day: Option<u64>,
+    // Route the outbound connections through a SOCKS5 proxy
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    // Record the messages of all the protocols to rotating files in the capture dir
+    #[serde(default)]
+    pub capture: Option<CaptureConfig>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    // SOCKS5 proxy address, e.g. "127.0.0.1:1080"
+    pub socks5: SocketAddr,
+    // Disable the inbound listening and the address self-advertisement
+    #[serde(default)]
+    pub disable_listen: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CaptureConfig {
+    // Rotate the capture file once it reaches this size in bytes
+    #[serde(default = "default_capture_max_file_size")]
+    pub max_file_size: u64,
+    // Keep at most this many capture files, the oldest one is removed on rotation
+    #[serde(default = "default_capture_max_files")]
+    pub max_files: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        CaptureConfig {
+            max_file_size: default_capture_max_file_size(),
+            max_files: default_capture_max_files(),
+        }
+    }
+}
+
+fn default_capture_max_file_size() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_capture_max_files() -> usize {
+    8
 }
 
 fn generate_random_key() -> [u8; 32] {
@@ -68,6 +124,18 @@ impl NetworkConfig {
         path
     }
 
+    pub fn anchors_path(&self) -> PathBuf {
+        let mut path = self.path.clone();
+        path.push("anchors.json");
+        path
+    }
+
+    pub fn capture_path(&self) -> PathBuf {
+        let mut path = self.path.clone();
+        path.push("capture");
+        path
+    }
+
     pub fn create_dir_if_not_exists(&self) -> Result<(), Error> {
         if !self.path.exists() {
             fs::create_dir(&self.path)?;
@@ -83,6 +151,14 @@ impl NetworkConfig {
         self.max_outbound_peers
     }
 
+    /// Inbound listening and address self-advertisement are disabled behind the proxy
+    pub fn listen_disabled(&self) -> bool {
+        self.proxy
+            .as_ref()
+            .map(|proxy| proxy.disable_listen)
+            .unwrap_or(false)
+    }
+
     pub fn max_send_buffer(&self) -> usize {
         self.max_send_buffer.unwrap_or(DEFAULT_SEND_BUFFER)
     }
@@ -21,9 +21,8 @@ pub enum Error {
 
 #[derive(Debug)]
 pub enum PeerStoreError {
-    /// indicate the peer store is full
-    EvictionFailed,
     Serde(serde_json::Error),
+    DB(ckb_error::Error),
 }
 
 #[derive(Debug)]
@@ -1,4 +1,6 @@
+mod anchors;
 mod behaviour;
+pub mod capture;
 mod compress;
 mod config;
 pub mod errors;
@@ -8,20 +10,24 @@ mod peer;
 pub mod peer_registry;
 pub mod peer_store;
 mod protocols;
+mod proxy;
 mod services;
+mod throttle;
+mod traffic;
 
 #[cfg(test)]
 mod tests;
 
 pub use crate::{
     behaviour::Behaviour,
-    config::NetworkConfig,
+    config::{CaptureConfig, NetworkConfig, ProxyConfig},
     errors::Error,
     network::{NetworkController, NetworkService, NetworkState},
     peer::{Peer, PeerIdentifyInfo},
     peer_registry::PeerRegistry,
     peer_store::{types::MultiaddrExt, Score},
     protocols::{CKBProtocol, CKBProtocolContext, CKBProtocolHandler, PeerIndex},
+    traffic::{MessageCount, MessageNames, PeerTraffic, ProtocolTraffic, Traffic},
 };
 pub use p2p::{
     multiaddr,
@@ -1,3 +1,6 @@
+use crate::anchors::load_anchors;
+use crate::capture::{CaptureWriter, CapturedMessage, Direction};
+use crate::compress::compress;
 use crate::errors::Error;
 use crate::peer_registry::{ConnectionStatus, PeerRegistry};
 use crate::peer_store::{
@@ -11,10 +14,15 @@ use crate::protocols::{
     identify::IdentifyCallback,
     ping::PingService,
 };
+use crate::proxy::Socks5Proxy;
 use crate::services::{
     dns_seeding::DnsSeedingService, dump_peer_store::DumpPeerStoreService,
     outbound_peer::OutboundPeerService,
 };
+use crate::throttle::Bandwidth;
+use crate::traffic::{
+    MessageNames, ProtocolCounters, ProtocolTraffic, TrafficCodec, TrafficHandler,
+};
 use crate::Peer;
 use crate::{
     Behaviour, CKBProtocol, NetworkConfig, ProtocolId, ProtocolVersion, PublicKey, ServiceControl,
@@ -39,9 +47,9 @@ use p2p::{
         DialProtocol, ProtocolEvent, ProtocolHandle, Service, ServiceError, ServiceEvent,
         TargetSession,
     },
-    traits::ServiceHandle,
-    utils::extract_peer_id,
-    SessionId,
+    traits::{ServiceHandle, ServiceProtocol},
+    utils::{extract_peer_id, multiaddr_to_socketaddr, socketaddr_to_multiaddr},
+    ProtocolMeta, SessionId,
 };
 use p2p_identify::IdentifyProtocol;
 use p2p_ping::PingHandler;
@@ -49,6 +57,7 @@ use std::boxed::Box;
 use std::cmp::max;
 use std::collections::{HashMap, HashSet};
 use std::io;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
@@ -90,6 +99,19 @@ pub struct NetworkState {
     local_peer_id: PeerId,
     bootnodes: Vec<(PeerId, Multiaddr)>,
     pub(crate) config: NetworkConfig,
+    /// The node has pruned the old block bodies, advertised in identify
+    pub(crate) pruned: bool,
+    /// Traffic of the protocols summed over all the peers, registered before
+    /// the service starts
+    traffic: RwLock<HashMap<ProtocolId, Arc<ProtocolCounters>>>,
+    /// Upload and download limits of the protocols
+    bandwidth: Mutex<Bandwidth>,
+    /// The SOCKS5 proxy of the outbound connections
+    proxy: Option<Socks5Proxy>,
+    /// Maps the local bridge address to the dialed address
+    proxied_addrs: RwLock<HashMap<SocketAddr, Multiaddr>>,
+    /// Records the messages of all the protocols when the capture is enabled
+    capture: Option<Mutex<CaptureWriter>>,
 }
 
 impl NetworkState {
@@ -118,8 +140,23 @@ impl NetworkState {
             whitelist_peers,
         );
 
+        let bandwidth = Mutex::new(Bandwidth::from_config(&config));
+        let proxy = config
+            .proxy
+            .as_ref()
+            .map(|proxy| Socks5Proxy::new(proxy.socks5));
+        let capture = config
+            .capture
+            .as_ref()
+            .map(|capture| CaptureWriter::open(config.capture_path(), capture).map(Mutex::new))
+            .transpose()?;
+
         Ok(NetworkState {
             peer_store,
+            bandwidth,
+            proxy,
+            capture,
+            proxied_addrs: RwLock::new(HashMap::default()),
             config,
             bootnodes,
             peer_registry: RwLock::new(peer_registry),
@@ -131,19 +168,224 @@ impl NetworkState {
             local_private_key: local_private_key.clone(),
             local_peer_id: local_private_key.to_public_key().peer_id(),
             protocol_ids: RwLock::new(HashSet::default()),
+            pruned: false,
+            traffic: RwLock::new(HashMap::default()),
         })
     }
 
+    /// Advertises to the peers that the node cannot serve the old blocks.
+    pub fn pruned(mut self, pruned: bool) -> Self {
+        self.pruned = pruned;
+        self
+    }
+
+    pub(crate) fn register_traffic(
+        &self,
+        proto_id: ProtocolId,
+        name: String,
+        message_names: Option<MessageNames>,
+    ) {
+        self.traffic.write().insert(
+            proto_id,
+            Arc::new(ProtocolCounters::new(name, message_names)),
+        );
+    }
+
+    fn protocol_counters(&self, proto_id: ProtocolId) -> Option<Arc<ProtocolCounters>> {
+        self.traffic.read().get(&proto_id).cloned()
+    }
+
+    /// Counts a message received from the session, `bytes` is the size on the wire.
+    ///
+    /// Returns how long to hold the message to meet the download limits.
+    pub(crate) fn record_received(
+        &self,
+        proto_id: ProtocolId,
+        session_id: SessionId,
+        bytes: usize,
+        raw_data: &[u8],
+    ) -> Option<Duration> {
+        if self.capture.is_some() {
+            self.capture_message(
+                Direction::Inbound,
+                proto_id,
+                &[session_id],
+                &Bytes::from(raw_data),
+            );
+        }
+        let delay = {
+            let now = Instant::now();
+            let mut bandwidth = self.bandwidth.lock();
+            bandwidth.record_received(session_id, bytes, now);
+            bandwidth.download_delay(session_id, now)
+        };
+        if let Some(protocol) = self.protocol_counters(proto_id) {
+            let index = protocol.message_index(raw_data);
+            protocol.record_received(bytes, raw_data.len(), index);
+            if let Some(peer_traffic) = self.with_peer_registry(|reg| {
+                reg.get_peer(session_id)
+                    .map(|peer| Arc::clone(&peer.traffic))
+            }) {
+                peer_traffic.counters(proto_id, &protocol).record_received(
+                    bytes,
+                    raw_data.len(),
+                    index,
+                );
+            }
+        }
+        delay
+    }
+
+    /// Counts a message sent by the handler of a core protocol, whose session
+    /// is unknown, see `TrafficCodec`
+    pub(crate) fn record_sent_by_handler(&self, proto_id: ProtocolId, data: &[u8]) {
+        if self.capture.is_some() {
+            self.capture_message(Direction::Outbound, proto_id, &[], &Bytes::from(data));
+        }
+        if let Some(protocol) = self.protocol_counters(proto_id) {
+            let index = protocol.message_index(data);
+            protocol.record_sent(data.len(), data.len(), index, 1);
+        }
+    }
+
+    // A broadcast message is recorded once with all the target sessions
+    fn capture_message(
+        &self,
+        direction: Direction,
+        proto_id: ProtocolId,
+        session_ids: &[SessionId],
+        raw_data: &Bytes,
+    ) {
+        if let Some(ref capture) = self.capture {
+            let message = CapturedMessage {
+                timestamp: faketime::unix_time_as_millis(),
+                direction,
+                protocol_id: proto_id,
+                session_ids: session_ids.to_vec(),
+                data: raw_data.clone(),
+            };
+            if let Err(err) = capture.lock().write(&message) {
+                warn!("Failed to capture message: {}", err);
+            }
+        }
+    }
+
+    /// Flushes the captured messages to the capture file
+    pub(crate) fn flush_capture(&self) {
+        if let Some(ref capture) = self.capture {
+            if let Err(err) = capture.lock().flush() {
+                warn!("Failed to flush the capture file: {}", err);
+            }
+        }
+    }
+
+    /// Compresses the message and counts it as sent to each target session.
+    ///
+    /// Returns the compressed message and how long to hold it to meet the
+    /// upload limits.
+    pub(crate) fn compress_to_send(
+        &self,
+        proto_id: ProtocolId,
+        target: &TargetSession,
+        raw_data: Bytes,
+    ) -> (Bytes, Option<Duration>) {
+        let data = compress(raw_data.clone());
+        // The sessions closed in the meantime are not counted
+        let (session_ids, peers_traffic): (Vec<_>, Vec<_>) = self.with_peer_registry(|reg| {
+            let session_ids = match target {
+                TargetSession::Single(session_id) => vec![*session_id],
+                TargetSession::Multi(session_ids) => session_ids.clone(),
+                TargetSession::All => reg
+                    .peers()
+                    .values()
+                    .filter(|peer| peer.protocols.contains_key(&proto_id))
+                    .map(|peer| peer.session_id)
+                    .collect(),
+            };
+            session_ids
+                .into_iter()
+                .filter_map(|session_id| {
+                    reg.get_peer(session_id)
+                        .map(|peer| (session_id, Arc::clone(&peer.traffic)))
+                })
+                .unzip()
+        });
+        self.capture_message(Direction::Outbound, proto_id, &session_ids, &raw_data);
+        let delay = {
+            let now = Instant::now();
+            let mut bandwidth = self.bandwidth.lock();
+            for session_id in &session_ids {
+                bandwidth.record_sent(*session_id, data.len(), now);
+            }
+            bandwidth.upload_delay(&session_ids, now)
+        };
+        let protocol = match self.protocol_counters(proto_id) {
+            Some(protocol) => protocol,
+            None => return (data, delay),
+        };
+        let index = protocol.message_index(&raw_data);
+        protocol.record_sent(data.len(), raw_data.len(), index, session_ids.len());
+        for peer_traffic in peers_traffic {
+            peer_traffic.counters(proto_id, &protocol).record_sent(
+                data.len(),
+                raw_data.len(),
+                index,
+                1,
+            );
+        }
+        (data, delay)
+    }
+
+    /// Whether the upload rate limit is exceeded, globally or for the session
+    pub(crate) fn upload_paused(&self, session_id: Option<SessionId>) -> bool {
+        self.bandwidth
+            .lock()
+            .upload_paused(session_id, Instant::now())
+    }
+
+    /// Whether the download rate limit is exceeded, globally or for the session
+    pub(crate) fn download_paused(&self, session_id: SessionId) -> bool {
+        self.bandwidth
+            .lock()
+            .download_paused(session_id, Instant::now())
+    }
+
+    /// Whether the daily upload cap is reached
+    pub(crate) fn upload_cap_reached(&self) -> bool {
+        self.bandwidth.lock().upload_cap_reached(Instant::now())
+    }
+
+    /// Traffic of the protocols summed over all the peers, ordered by protocol id
+    pub fn protocols_traffic(&self) -> Vec<(ProtocolId, ProtocolTraffic)> {
+        let mut protocols = self
+            .traffic
+            .read()
+            .iter()
+            .map(|(proto_id, protocol)| (*proto_id, protocol.traffic()))
+            .collect::<Vec<_>>();
+        protocols.sort_by_key(|(proto_id, _)| *proto_id);
+        protocols
+    }
+
     pub(crate) fn report_session(
         &self,
         p2p_control: &ServiceControl,
         session_id: SessionId,
         behaviour: Behaviour,
     ) {
-        if let Some(peer_id) = self.with_peer_registry(|reg| {
-            reg.get_peer(session_id)
+        if let Some(peer_id) = self.with_peer_registry_mut(|reg| {
+            reg.get_peer_mut(session_id)
                 .filter(|peer| !peer.is_whitelist)
-                .map(|peer| peer.peer_id.clone())
+                .map(|peer| {
+                    match behaviour {
+                        Behaviour::UsefulBlock => peer.last_block_time = Some(Instant::now()),
+                        Behaviour::UsefulTransaction => {
+                            peer.last_transaction_time = Some(Instant::now())
+                        }
+                        _ => {}
+                    }
+                    peer.peer_id.clone()
+                })
         }) {
             self.report_peer(p2p_control, &peer_id, behaviour);
         } else {
@@ -232,6 +474,19 @@ impl NetworkState {
         self.with_peer_registry(|registry| registry.get_key_by_peer_id(peer_id))
     }
 
+    /// The remote address of the session, the dialed address if it is proxied
+    pub(crate) fn remote_addr(&self, addr: &Multiaddr) -> Multiaddr {
+        multiaddr_to_socketaddr(addr)
+            .and_then(|socket_addr| self.proxied_addrs.read().get(&socket_addr).cloned())
+            .unwrap_or_else(|| addr.clone())
+    }
+
+    pub(crate) fn remove_proxied_addr(&self, addr: &Multiaddr) {
+        if let Some(socket_addr) = multiaddr_to_socketaddr(addr) {
+            self.proxied_addrs.write().remove(&socket_addr);
+        }
+    }
+
     pub(crate) fn accept_peer(
         &self,
         session_context: &SessionContext,
@@ -248,12 +503,15 @@ impl NetworkState {
         let accept_peer_result = {
             self.peer_registry.write().accept_peer(
                 peer_id.clone(),
-                session_context.address.clone(),
+                self.remote_addr(&session_context.address),
                 session_context.id,
                 session_context.ty,
                 &mut peer_store,
             )
         };
+        if accept_peer_result.is_ok() {
+            self.bandwidth.lock().add_peer(session_context.id);
+        }
         accept_peer_result.map_err(Into::into)
     }
 
@@ -405,9 +663,11 @@ impl NetworkState {
         self.dialing_addrs.write().remove(peer_id);
     }
 
-    pub(crate) fn dial_failed(&self, peer_id: PeerId) {
+    /// `addr` is the dialed address, not the proxy bridge
+    pub(crate) fn dial_failed(&self, peer_id: PeerId, addr: &Multiaddr) {
         self.with_peer_registry_mut(|reg| {
             reg.remove_feeler(&peer_id);
+            reg.remove_block_relay_only_dial(addr);
         });
         self.dialing_addrs.write().remove(&peer_id);
     }
@@ -429,9 +689,32 @@ impl NetworkState {
             )));
         }
 
-        let addr = addr.attach_p2p(peer_id)?;
-        debug!("dialing {} with {:?}", addr, target);
-        p2p_control.dial(addr, target)?;
+        let (addr, bridge) = match self.proxy {
+            Some(ref proxy) => {
+                let bridge = proxy.bridge(&addr)?;
+                let bridge_addr = bridge.local_addr();
+                debug!("dialing {} through the proxy bridge {}", addr, bridge_addr);
+                self.proxied_addrs
+                    .write()
+                    .insert(bridge_addr, addr.exclude_p2p());
+                (socketaddr_to_multiaddr(bridge_addr), Some(bridge))
+            }
+            None => (addr, None),
+        };
+        let dialed = addr.attach_p2p(peer_id).and_then(|addr| {
+            debug!("dialing {} with {:?}", addr, target);
+            p2p_control.dial(addr, target).map_err(Into::into)
+        });
+        // an unused bridge is dropped, which closes its listener
+        if let Some(bridge) = bridge {
+            let bridge_addr = bridge.local_addr();
+            if let Err(err) = dialed.and_then(|_| bridge.start()) {
+                self.proxied_addrs.write().remove(&bridge_addr);
+                return Err(err);
+            }
+        } else {
+            dialed?;
+        }
         self.dialing_addrs
             .write()
             .insert(peer_id.to_owned(), Instant::now());
@@ -451,6 +734,29 @@ impl NetworkState {
         }
     }
 
+    /// Dial just identify protocol, the connection only relays blocks once established
+    pub fn dial_block_relay_only(
+        &self,
+        p2p_control: &ServiceControl,
+        peer_id: &PeerId,
+        addr: Multiaddr,
+    ) {
+        // marked before dialing, the session may open before `dial_inner` returns
+        let marked = self.with_peer_registry_mut(|reg| reg.add_block_relay_only_dial(&addr));
+        if let Err(err) = self.dial_inner(
+            p2p_control,
+            peer_id,
+            addr.clone(),
+            DialProtocol::Single(IDENTIFY_PROTOCOL_ID.into()),
+            false,
+        ) {
+            debug!("dial_block_relay_only error: {}", err);
+            if marked {
+                self.with_peer_registry_mut(|reg| reg.remove_block_relay_only_dial(&addr));
+            }
+        }
+    }
+
     /// Dial just feeler protocol
     pub fn dial_feeler(&self, p2p_control: &ServiceControl, peer_id: &PeerId, addr: Multiaddr) {
         if let Err(err) = self.dial_inner(
@@ -486,6 +792,9 @@ impl NetworkState {
     }
 
     pub fn add_observed_addrs(&self, iter: impl Iterator<Item = Multiaddr>) {
+        if self.config.listen_disabled() {
+            return;
+        }
         let mut public_addrs = self.public_addrs.write();
         let mut pending_observed_addrs = self.pending_observed_addrs.write();
         for addr in iter {
@@ -553,7 +862,9 @@ impl ServiceHandle for EventHandler {
                 debug!("DialerError({}) {}", address, error);
                 if error == &P2pError::ConnectSelf {
                     debug!("dial observed address success: {:?}", address);
-                    let addr = address
+                    let addr = self
+                        .network_state
+                        .remote_addr(address)
                         .iter()
                         .filter(|proto| match proto {
                             multiaddr::Protocol::P2p(_) => false,
@@ -563,7 +874,9 @@ impl ServiceHandle for EventHandler {
                     self.network_state.vote_listened_addr(addr, 1);
                 }
                 let peer_id = extract_peer_id(address).expect("Secio must enabled");
-                self.network_state.dial_failed(peer_id);
+                let remote_addr = self.network_state.remote_addr(address);
+                self.network_state.remove_proxied_addr(address);
+                self.network_state.dial_failed(peer_id, &remote_addr);
             }
             ServiceError::ProtocolError {
                 id,
@@ -699,6 +1012,12 @@ impl ServiceHandle for EventHandler {
                     .disconnecting_sessions
                     .write()
                     .remove(&session_context.id);
+                self.network_state
+                    .bandwidth
+                    .lock()
+                    .remove_peer(session_context.id);
+                self.network_state
+                    .remove_proxied_addr(&session_context.address);
                 let peer_exists = self
                     .network_state
                     .peer_registry
@@ -819,38 +1138,35 @@ impl NetworkService {
         let ping_interval = Duration::from_secs(config.ping_interval_secs);
         let ping_timeout = Duration::from_secs(config.ping_timeout_secs);
 
-        let ping_meta = MetaBuilder::default()
-            .id(PING_PROTOCOL_ID.into())
-            .service_handle(move || {
-                ProtocolHandle::Both(Box::new(PingHandler::new(
-                    ping_interval,
-                    ping_timeout,
-                    ping_sender.clone(),
-                )))
-            })
-            .build();
+        let ping_meta = traffic_meta(&network_state, PING_PROTOCOL_ID, "/p2p/ping", move || {
+            Box::new(PingHandler::new(
+                ping_interval,
+                ping_timeout,
+                ping_sender.clone(),
+            ))
+        });
 
         // Discovery protocol
         let (disc_sender, disc_receiver) = mpsc::unbounded();
-        let disc_meta = MetaBuilder::default()
-            .id(DISCOVERY_PROTOCOL_ID.into())
-            .service_handle(move || {
-                ProtocolHandle::Both(Box::new(
-                    DiscoveryProtocol::new(disc_sender.clone())
-                        .global_ip_only(!config.discovery_local_address),
-                ))
-            })
-            .build();
+        let global_ip_only = !config.discovery_local_address;
+        let disc_meta = traffic_meta(
+            &network_state,
+            DISCOVERY_PROTOCOL_ID,
+            "/p2p/discovery",
+            move || {
+                Box::new(DiscoveryProtocol::new(disc_sender.clone()).global_ip_only(global_ip_only))
+            },
+        );
 
         // Identify protocol
         let identify_callback =
             IdentifyCallback::new(Arc::clone(&network_state), name, client_version);
-        let identify_meta = MetaBuilder::default()
-            .id(IDENTIFY_PROTOCOL_ID.into())
-            .service_handle(move || {
-                ProtocolHandle::Both(Box::new(IdentifyProtocol::new(identify_callback.clone())))
-            })
-            .build();
+        let identify_meta = traffic_meta(
+            &network_state,
+            IDENTIFY_PROTOCOL_ID,
+            "/p2p/identify",
+            move || Box::new(IdentifyProtocol::new(identify_callback.clone())),
+        );
 
         // Feeler protocol
         // TODO: versions
@@ -890,7 +1206,7 @@ impl NetworkService {
         };
         let p2p_service = service_builder
             .key_pair(network_state.local_private_key.clone())
-            .upnp(config.upnp)
+            .upnp(config.upnp && !config.listen_disabled())
             .forever(true)
             .build(event_handler);
 
@@ -939,8 +1255,16 @@ impl NetworkService {
         thread_name: Option<S>,
     ) -> Result<NetworkController, Error> {
         let config = &self.network_state.config;
+        if config.listen_disabled() {
+            info!("Inbound listening is disabled behind the proxy");
+        }
+        let listen_addresses = if config.listen_disabled() {
+            &[][..]
+        } else {
+            &config.listen_addresses[..]
+        };
         // listen local addresses
-        for addr in &config.listen_addresses {
+        for addr in listen_addresses {
             match self.p2p_service.listen(addr.to_owned()) {
                 Ok(listen_address) => {
                     info!(
@@ -970,6 +1294,31 @@ impl NetworkService {
                 .dial_identify(self.p2p_service.control(), &peer_id, addr);
         }
 
+        // dial anchors before the addrs picked from peer_store
+        let anchors = load_anchors(config.anchors_path()).unwrap_or_else(|err| {
+            warn!("Load anchors error: {}", err);
+            Vec::new()
+        });
+        for anchor in &anchors {
+            debug!(
+                "dial anchor {:?} {:?}, block relay only: {}",
+                anchor.peer_id, anchor.addr, anchor.block_relay_only
+            );
+            if anchor.block_relay_only {
+                self.network_state.dial_block_relay_only(
+                    self.p2p_service.control(),
+                    &anchor.peer_id,
+                    anchor.addr.to_owned(),
+                );
+            } else {
+                self.network_state.dial_identify(
+                    self.p2p_service.control(),
+                    &anchor.peer_id,
+                    anchor.addr.to_owned(),
+                );
+            }
+        }
+
         // get bootnodes
         // try get addrs from peer_store, if peer_store have no enough addrs then use bootnodes
         let bootnodes = self.network_state.with_peer_store_mut(|peer_store| {
@@ -977,6 +1326,7 @@ impl NetworkService {
             let mut addrs: Vec<_> = peer_store
                 .fetch_addrs_to_attempt(count)
                 .into_iter()
+                .filter(|paddr| !anchors.iter().any(|anchor| anchor.peer_id == paddr.peer_id))
                 .map(|paddr| (paddr.peer_id, paddr.addr))
                 .collect();
             addrs.extend(
@@ -1140,6 +1490,11 @@ impl NetworkController {
             .collect()
     }
 
+    /// Traffic of the protocols summed over all the peers
+    pub fn protocols_traffic(&self) -> Vec<(ProtocolId, ProtocolTraffic)> {
+        self.network_state.protocols_traffic()
+    }
+
     fn try_broadcast(
         &self,
         quick: bool,
@@ -1147,6 +1502,7 @@ impl NetworkController {
         proto_id: ProtocolId,
         data: Bytes,
     ) -> Result<(), P2pError> {
+        let data = self.network_state.compress_to_send(proto_id, &target, data);
         let now = Instant::now();
         loop {
             let result = if quick {
@@ -1205,6 +1561,34 @@ impl Drop for NetworkController {
 }
 
 // Send a optional message before disconnect a peer
+// Builds a core protocol whose traffic is counted, such as ping, discovery and
+// identify, the name is only shown in the traffic statistics
+fn traffic_meta<F>(
+    network_state: &Arc<NetworkState>,
+    id: usize,
+    name: &str,
+    handle: F,
+) -> ProtocolMeta
+where
+    F: Fn() -> Box<dyn ServiceProtocol + Send + 'static> + Send + 'static,
+{
+    let proto_id: ProtocolId = id.into();
+    network_state.register_traffic(proto_id, name.to_string(), None);
+    let codec_state = Arc::clone(network_state);
+    let handler_state = Arc::clone(network_state);
+    MetaBuilder::default()
+        .id(proto_id)
+        .codec(move || Box::new(TrafficCodec::new(proto_id, Arc::clone(&codec_state))))
+        .service_handle(move || {
+            ProtocolHandle::Both(Box::new(TrafficHandler {
+                proto_id,
+                network_state: Arc::clone(&handler_state),
+                inner: handle(),
+            }))
+        })
+        .build()
+}
+
 pub(crate) fn disconnect_with_message(
     control: &ServiceControl,
     peer_index: SessionId,
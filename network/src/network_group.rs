@@ -1,8 +1,8 @@
 use crate::peer_store::types::MultiaddrExt;
 use p2p::multiaddr::Multiaddr;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Hash, Eq, PartialEq, Debug, Clone)]
 pub enum Group {
     NoGroup,
     LocalNetwork,
@@ -17,14 +17,10 @@ pub trait NetworkGroup {
 impl NetworkGroup for Multiaddr {
     fn network_group(&self) -> Group {
         if let Ok(ip_addr) = self.extract_ip_addr().map(|ip_port| ip_port.ip) {
-            if ip_addr.is_loopback() {
+            // The non-global addresses are all in the local network group
+            if !is_global(&ip_addr) {
                 return Group::LocalNetwork;
             }
-            // TODO uncomment after ip feature stable
-            // if !ip_addr.is_global() {
-            //     // Global NetworkGroup
-            //     return Group::GlobalNetwork
-            // }
 
             // IPv4 NetworkGroup
             if let IpAddr::V4(ipv4) = ip_addr {
@@ -45,3 +41,48 @@ impl NetworkGroup for Multiaddr {
         Group::NoGroup
     }
 }
+
+/// Whether the address is globally routable, `IpAddr::is_global` is not stable yet
+pub fn is_global(ip_addr: &IpAddr) -> bool {
+    match ip_addr {
+        IpAddr::V4(ipv4) => is_global_ipv4(ipv4),
+        IpAddr::V6(ipv6) => match ipv6.to_ipv4() {
+            // IPv4-mapped and IPv4-compatible addresses, but `::` and `::1` are not
+            Some(ipv4) if !ipv6.is_unspecified() && !ipv6.is_loopback() => is_global_ipv4(&ipv4),
+            _ => is_global_ipv6(ipv6),
+        },
+    }
+}
+
+fn is_global_ipv4(ipv4: &Ipv4Addr) -> bool {
+    let octets = ipv4.octets();
+    !(ipv4.is_unspecified()
+        || ipv4.is_private()
+        || ipv4.is_loopback()
+        || ipv4.is_link_local()
+        || ipv4.is_broadcast()
+        || ipv4.is_documentation()
+        // 0.0.0.0/8 "this network"
+        || octets[0] == 0
+        // 100.64.0.0/10 shared address space
+        || (octets[0] == 100 && (octets[1] & 0b1100_0000) == 0b0100_0000)
+        // 192.0.0.0/24 IETF protocol assignments
+        || (octets[0] == 192 && octets[1] == 0 && octets[2] == 0)
+        // 198.18.0.0/15 benchmarking
+        || (octets[0] == 198 && (octets[1] & 0xfe) == 18)
+        // 240.0.0.0/4 reserved, and multicast 224.0.0.0/4
+        || octets[0] >= 224)
+}
+
+fn is_global_ipv6(ipv6: &Ipv6Addr) -> bool {
+    let segments = ipv6.segments();
+    !(ipv6.is_unspecified()
+        || ipv6.is_loopback()
+        || ipv6.is_multicast()
+        // fc00::/7 unique local
+        || (segments[0] & 0xfe00) == 0xfc00
+        // fe80::/10 link local
+        || (segments[0] & 0xffc0) == 0xfe80
+        // 2001:db8::/32 documentation
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
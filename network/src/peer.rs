@@ -1,12 +1,16 @@
 use crate::network_group::{Group, NetworkGroup};
+use crate::traffic::PeerTraffic;
 use crate::{multiaddr::Multiaddr, ProtocolId, ProtocolVersion, SessionType};
 use p2p::{secio::PeerId, SessionId};
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 #[derive(Clone, Debug)]
 pub struct PeerIdentifyInfo {
     pub client_version: String,
+    /// The peer only serves the recent blocks
+    pub pruned: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -18,13 +22,21 @@ pub struct Peer {
     pub identify_info: Option<PeerIdentifyInfo>,
     pub last_ping_time: Option<Instant>,
     pub last_message_time: Option<Instant>,
+    /// The last time the peer delivered a new block which we accepted
+    pub last_block_time: Option<Instant>,
+    /// The last time the peer relayed a new transaction which we accepted
+    pub last_transaction_time: Option<Instant>,
     pub ping: Option<Duration>,
     pub is_feeler: bool,
+    /// The outbound peer only relays blocks, no transactions and no addresses
+    pub is_block_relay_only: bool,
     pub connected_time: Instant,
     pub session_id: SessionId,
     pub session_type: SessionType,
     pub protocols: HashMap<ProtocolId, ProtocolVersion>,
     pub is_whitelist: bool,
+    /// Traffic of the protocols exchanged with this peer
+    pub traffic: Arc<PeerTraffic>,
 }
 
 impl Peer {
@@ -42,13 +54,17 @@ impl Peer {
             ping: None,
             last_ping_time: None,
             last_message_time: None,
+            last_block_time: None,
+            last_transaction_time: None,
             connected_time: Instant::now(),
             is_feeler: false,
+            is_block_relay_only: false,
             peer_id,
             session_id,
             session_type,
             protocols: HashMap::with_capacity_and_hasher(1, Default::default()),
             is_whitelist,
+            traffic: Arc::new(PeerTraffic::default()),
         }
     }
 
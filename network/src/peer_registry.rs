@@ -1,16 +1,20 @@
-use crate::peer_store::PeerStore;
+use crate::peer_store::{types::MultiaddrExt, PeerStore};
 use crate::{
     errors::{Error, PeerError},
     Peer, PeerId, SessionType,
 };
 use ckb_logger::debug;
 use p2p::{multiaddr::Multiaddr, SessionId};
-use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::random;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::iter::FromIterator;
 
 pub(crate) const EVICTION_PROTECT_PEERS: usize = 8;
+pub(crate) const EVICTION_PROTECT_NETWORK_GROUPS: usize = 4;
+pub(crate) const EVICTION_PROTECT_BLOCK_PEERS: usize = 4;
+pub(crate) const EVICTION_PROTECT_TRANSACTION_PEERS: usize = 4;
 
 pub struct PeerRegistry {
     peers: HashMap<SessionId, Peer>,
@@ -22,13 +26,20 @@ pub struct PeerRegistry {
     whitelist_only: bool,
     whitelist_peers: HashSet<PeerId>,
     feeler_peers: HashSet<PeerId>,
+    // The addresses dialed for the block-relay-only connections, a connection
+    // to the same peer from another dial is not block-relay-only
+    block_relay_only_dials: HashSet<Multiaddr>,
+    // Secret key to choose the protected network groups in the inbound eviction
+    eviction_key: u64,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct ConnectionStatus {
     pub total: u32,
     pub non_whitelist_inbound: u32,
+    /// The block-relay-only outbound peers are not counted in `non_whitelist_outbound`
     pub non_whitelist_outbound: u32,
+    pub block_relay_only_outbound: u32,
     pub max_inbound: u32,
     pub max_outbound: u32,
 }
@@ -55,6 +66,8 @@ impl PeerRegistry {
             peers: HashMap::with_capacity_and_hasher(20, Default::default()),
             whitelist_peers: whitelist_peers_set,
             feeler_peers: HashSet::default(),
+            block_relay_only_dials: HashSet::default(),
+            eviction_key: random(),
             max_inbound,
             max_outbound,
             whitelist_only,
@@ -69,6 +82,10 @@ impl PeerRegistry {
         session_type: SessionType,
         peer_store: &mut PeerStore,
     ) -> Result<Option<Peer>, Error> {
+        let is_block_relay_only = session_type.is_outbound()
+            && self
+                .block_relay_only_dials
+                .remove(&remote_addr.exclude_p2p());
         if self.peers.contains_key(&session_id) {
             return Err(PeerError::SessionExists(session_id).into());
         }
@@ -97,12 +114,16 @@ impl PeerRegistry {
                         return Err(PeerError::ReachMaxInboundLimit.into());
                     }
                 }
-            } else if connection_status.non_whitelist_outbound >= self.max_outbound {
+            } else if !is_block_relay_only
+                && connection_status.non_whitelist_outbound >= self.max_outbound
+            {
+                // the block-relay-only peers are limited by the outbound peer service
                 return Err(PeerError::ReachMaxOutboundLimit.into());
             }
         }
         peer_store.add_connected_peer(peer_id.clone(), remote_addr.clone(), session_type)?;
-        let peer = Peer::new(session_id, session_type, peer_id, remote_addr, is_whitelist);
+        let mut peer = Peer::new(session_id, session_type, peer_id, remote_addr, is_whitelist);
+        peer.is_block_relay_only = is_block_relay_only;
         self.peers.insert(session_id, peer);
         Ok(evicted_peer)
     }
@@ -116,6 +137,17 @@ impl PeerRegistry {
                 .collect::<Vec<_>>()
         };
         // Protect peers based on characteristics that an attacker hard to simulate or manipulate
+        // Protect peers from the network groups chosen by the secret key, an attacker
+        // can not predict which groups are protected
+        sort_then_drop(
+            &mut candidate_peers,
+            EVICTION_PROTECT_NETWORK_GROUPS,
+            |peer1, peer2| {
+                self.keyed_network_group(peer1)
+                    .cmp(&self.keyed_network_group(peer2))
+            },
+        );
+
         // Protect peers which has the lowest ping
         sort_then_drop(
             &mut candidate_peers,
@@ -123,32 +155,34 @@ impl PeerRegistry {
             |peer1, peer2| {
                 let peer1_ping = peer1
                     .ping
-                    .map(|p| p.as_secs())
-                    .unwrap_or_else(|| std::u64::MAX);
+                    .map(|p| p.as_millis())
+                    .unwrap_or_else(|| std::u128::MAX);
                 let peer2_ping = peer2
                     .ping
-                    .map(|p| p.as_secs())
-                    .unwrap_or_else(|| std::u64::MAX);
+                    .map(|p| p.as_millis())
+                    .unwrap_or_else(|| std::u128::MAX);
                 peer2_ping.cmp(&peer1_ping)
             },
         );
 
-        // Protect peers which most recently sent messages
+        // Protect peers which most recently delivered new transactions
         sort_then_drop(
             &mut candidate_peers,
-            EVICTION_PROTECT_PEERS,
+            EVICTION_PROTECT_TRANSACTION_PEERS,
             |peer1, peer2| {
-                let peer1_last_message = peer1
-                    .last_message_time
-                    .map(|t| t.elapsed().as_secs())
-                    .unwrap_or_else(|| std::u64::MAX);
-                let peer2_last_message = peer2
-                    .last_message_time
-                    .map(|t| t.elapsed().as_secs())
-                    .unwrap_or_else(|| std::u64::MAX);
-                peer2_last_message.cmp(&peer1_last_message)
+                peer1
+                    .last_transaction_time
+                    .cmp(&peer2.last_transaction_time)
             },
         );
+
+        // Protect peers which most recently delivered new blocks
+        sort_then_drop(
+            &mut candidate_peers,
+            EVICTION_PROTECT_BLOCK_PEERS,
+            |peer1, peer2| peer1.last_block_time.cmp(&peer2.last_block_time),
+        );
+
         // Protect half peers which have the longest connection time
         let protect_peers = candidate_peers.len() >> 1;
         sort_then_drop(&mut candidate_peers, protect_peers, |peer1, peer2| {
@@ -156,7 +190,7 @@ impl PeerRegistry {
         });
 
         // Group peers by network group
-        let evict_group = candidate_peers
+        let groups = candidate_peers
             .into_iter()
             .fold(HashMap::new(), |mut groups, peer| {
                 groups
@@ -164,18 +198,37 @@ impl PeerRegistry {
                     .or_insert_with(Vec::new)
                     .push(peer);
                 groups
-            })
+            });
+
+        // Evict the youngest peer from the most represented group, the group which
+        // has the youngest peer wins the tie
+        let youngest = |group: &Vec<&Peer>| {
+            group
+                .iter()
+                .map(|peer| peer.connected_time)
+                .max()
+                .expect("group is not empty")
+        };
+        groups
             .values()
-            .max_by_key(|group| group.len())
-            .cloned()
-            .unwrap_or_else(Vec::new);
-
-        // randomly evict a peer
-        let mut rng = thread_rng();
-        evict_group.choose(&mut rng).map(|peer| {
-            debug!("evict inbound peer {:?}", peer.peer_id);
-            peer.session_id
-        })
+            .max_by(|group1, group2| {
+                group1
+                    .len()
+                    .cmp(&group2.len())
+                    .then_with(|| youngest(group1).cmp(&youngest(group2)))
+            })
+            .and_then(|group| group.iter().max_by_key(|peer| peer.connected_time))
+            .map(|peer| {
+                debug!("evict inbound peer {:?}", peer.peer_id);
+                peer.session_id
+            })
+    }
+
+    fn keyed_network_group(&self, peer: &Peer) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.eviction_key.hash(&mut hasher);
+        peer.network_group().hash(&mut hasher);
+        hasher.finish()
     }
 
     pub fn add_feeler(&mut self, peer_id: PeerId) {
@@ -190,6 +243,19 @@ impl PeerRegistry {
         self.feeler_peers.contains(peer_id)
     }
 
+    /// Returns false if the address is already being dialed for a block-relay-only connection
+    pub fn add_block_relay_only_dial(&mut self, addr: &Multiaddr) -> bool {
+        self.block_relay_only_dials.insert(addr.exclude_p2p())
+    }
+
+    pub fn remove_block_relay_only_dial(&mut self, addr: &Multiaddr) {
+        self.block_relay_only_dials.remove(&addr.exclude_p2p());
+    }
+
+    pub fn block_relay_only_dials_count(&self) -> usize {
+        self.block_relay_only_dials.len()
+    }
+
     pub fn get_peer(&self, session_id: SessionId) -> Option<&Peer> {
         self.peers.get(&session_id)
     }
@@ -229,8 +295,11 @@ impl PeerRegistry {
         let total = self.peers.len() as u32;
         let mut non_whitelist_inbound: u32 = 0;
         let mut non_whitelist_outbound: u32 = 0;
+        let mut block_relay_only_outbound: u32 = 0;
         for peer in self.peers.values().filter(|peer| !peer.is_whitelist) {
-            if peer.is_outbound() {
+            if peer.is_block_relay_only {
+                block_relay_only_outbound += 1;
+            } else if peer.is_outbound() {
                 non_whitelist_outbound += 1;
             } else {
                 non_whitelist_inbound += 1;
@@ -240,6 +309,7 @@ impl PeerRegistry {
             total,
             non_whitelist_inbound,
             non_whitelist_outbound,
+            block_relay_only_outbound,
             max_inbound: self.max_inbound,
             max_outbound: self.max_outbound,
         }
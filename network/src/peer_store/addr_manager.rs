@@ -1,43 +1,167 @@
-use crate::peer_store::types::{AddrInfo, IpPort};
-use rand::Rng;
+use crate::network_group::{Group, NetworkGroup};
+use crate::peer_store::types::{AddrInfo, Bucket, IpPort};
+use p2p::multiaddr::Multiaddr;
+use rand::{random, Rng};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+/// The new table holds the addresses we have never connected to
+pub(crate) const NEW_BUCKETS_COUNT: usize = 256;
+/// The tried table holds the addresses we have connected to
+pub(crate) const TRIED_BUCKETS_COUNT: usize = 64;
+pub(crate) const BUCKET_SIZE: usize = 64;
+/// The addresses told by one source network group only go into this many new buckets
+pub(crate) const NEW_BUCKETS_PER_SOURCE_GROUP: u64 = 16;
+/// The addresses in one network group only go into this many tried buckets
+pub(crate) const TRIED_BUCKETS_PER_GROUP: u64 = 4;
 
-#[derive(Default)]
 pub struct AddrManager {
     next_id: u64,
     addr_to_id: HashMap<IpPort, u64>,
     id_to_info: HashMap<u64, AddrInfo>,
     random_ids: Vec<u64>,
+    buckets: HashMap<Bucket, HashSet<u64>>,
+    // Secret key to place the addresses in buckets, so an attacker can't choose
+    // addresses which fill a bucket
+    bucket_key: u64,
+    // Addresses added, changed or removed since the last `take_dirty`
+    dirty: HashSet<IpPort>,
+}
+
+impl Default for AddrManager {
+    fn default() -> Self {
+        Self::new(random())
+    }
 }
 
 impl AddrManager {
-    pub fn add(&mut self, mut addr_info: AddrInfo) {
+    pub fn new(bucket_key: u64) -> Self {
+        AddrManager {
+            next_id: 0,
+            addr_to_id: HashMap::default(),
+            id_to_info: HashMap::default(),
+            random_ids: Vec::new(),
+            buckets: HashMap::default(),
+            bucket_key,
+            dirty: HashSet::default(),
+        }
+    }
+
+    pub fn add(&mut self, addr_info: AddrInfo) {
+        self.add_with_source(addr_info, None)
+    }
+
+    /// Add an address told by the source, the addresses from one source network
+    /// group can only fill a few buckets of the new table.
+    ///
+    /// The address is dropped when its bucket is full of good addresses.
+    pub fn add_with_source(&mut self, mut addr_info: AddrInfo, source: Option<&Multiaddr>) {
         let id = self.next_id;
         let key = addr_info.ip_port();
-        if let Some(exists_last_connected_at_ms) =
-            self.get(&key).map(|addr| addr.last_connected_at_ms)
-        {
-            // replace exists addr if has later last_connected_at_ms
+        if let Some(exists) = self.get(&key).map(|addr| {
+            (
+                addr.last_connected_at_ms,
+                addr.score,
+                addr.score_updated_at_ms,
+            )
+        }) {
+            let (exists_last_connected_at_ms, score, score_updated_at_ms) = exists;
+            // replace exists addr if has later last_connected_at_ms, the score
+            // belongs to the address and survives the reconnections
             if addr_info.last_connected_at_ms > exists_last_connected_at_ms {
+                addr_info.score = score;
+                addr_info.score_updated_at_ms = score_updated_at_ms;
                 self.remove(&key);
             } else {
                 return;
             }
         }
+        let bucket = self.bucket_of(&addr_info, source);
+        let now_ms = faketime::unix_time_as_millis();
+        if !self.make_room(bucket, now_ms) {
+            return;
+        }
         self.addr_to_id.insert(key, id);
         addr_info.random_id_pos = self.random_ids.len();
+        addr_info.bucket = Some(bucket);
         self.id_to_info.insert(id, addr_info);
         self.random_ids.push(id);
+        self.buckets.entry(bucket).or_default().insert(id);
+        self.dirty.insert(key);
         self.next_id += 1;
     }
 
+    fn bucket_of(&self, addr_info: &AddrInfo, source: Option<&Multiaddr>) -> Bucket {
+        if addr_info.last_connected_at_ms > 0 {
+            return self.tried_bucket(addr_info);
+        }
+        match (source, addr_info.bucket) {
+            (Some(source), _) => self.new_bucket(addr_info, &source.network_group()),
+            // keep the bucket restored from the database
+            (None, Some(Bucket::New(index))) if index < NEW_BUCKETS_COUNT => Bucket::New(index),
+            (None, _) => self.new_bucket(addr_info, &addr_info.addr.network_group()),
+        }
+    }
+
+    fn keyed_hash<T: Hash>(&self, value: T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.bucket_key.hash(&mut hasher);
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn new_bucket(&self, addr_info: &AddrInfo, source_group: &Group) -> Bucket {
+        let slot = self.keyed_hash((addr_info.addr.network_group(), source_group))
+            % NEW_BUCKETS_PER_SOURCE_GROUP;
+        Bucket::New((self.keyed_hash((source_group, slot)) % NEW_BUCKETS_COUNT as u64) as usize)
+    }
+
+    fn tried_bucket(&self, addr_info: &AddrInfo) -> Bucket {
+        let slot = self.keyed_hash(addr_info.ip_port()) % TRIED_BUCKETS_PER_GROUP;
+        Bucket::Tried(
+            (self.keyed_hash((addr_info.addr.network_group(), slot)) % TRIED_BUCKETS_COUNT as u64)
+                as usize,
+        )
+    }
+
+    /// Evict an address from the full bucket, return false if nothing can be evicted.
+    ///
+    /// The new table only evicts the terrible addresses, so the addresses we
+    /// know can't be flushed out by an attacker. The tried table evicts the
+    /// address which has not been connected for the longest time.
+    fn make_room(&mut self, bucket: Bucket, now_ms: u64) -> bool {
+        let victim = match self.buckets.get(&bucket) {
+            Some(ids) if ids.len() >= BUCKET_SIZE => {
+                let mut infos = ids.iter().map(|id| &self.id_to_info[id]);
+                let victim = match bucket {
+                    Bucket::New(_) => infos.find(|info| info.is_terrible(now_ms)),
+                    Bucket::Tried(_) => infos.min_by_key(|info| info.last_connected_at_ms),
+                };
+                victim.map(AddrInfo::ip_port)
+            }
+            _ => return true,
+        };
+        match victim {
+            Some(key) => {
+                self.remove(&key);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// randomly return addrs that worth to try or connect.
+    ///
+    /// The addrs are taken from the tried table and the new table in turn.
     pub fn fetch_random<F>(&mut self, count: usize, filter: F) -> Vec<AddrInfo>
     where
         F: Fn(&AddrInfo) -> bool,
     {
         let mut duplicate_ips = HashSet::new();
-        let mut addr_infos = Vec::with_capacity(count);
+        let mut tried_addrs = Vec::with_capacity(count);
+        let mut new_addrs = Vec::with_capacity(count);
         let mut rng = rand::thread_rng();
         let now_ms = faketime::unix_time_as_millis();
         for i in 0..self.random_ids.len() {
@@ -53,12 +177,30 @@ impl AddrManager {
                 addr_info.ip_port.ip.is_unspecified() || addr_info.ip_port.ip.is_loopback();
             if (is_test_ip || is_unique_ip) && !addr_info.is_terrible(now_ms) && filter(&addr_info)
             {
-                addr_infos.push(addr_info);
+                let is_tried = addr_info.bucket.map(Bucket::is_tried).unwrap_or(false);
+                if is_tried && tried_addrs.len() < count {
+                    tried_addrs.push(addr_info);
+                } else if !is_tried && new_addrs.len() < count {
+                    new_addrs.push(addr_info);
+                }
             }
-            if addr_infos.len() == count {
+            if tried_addrs.len() == count && new_addrs.len() == count {
                 break;
             }
         }
+        let mut addr_infos = Vec::with_capacity(count);
+        let mut tried_addrs = tried_addrs.into_iter();
+        let mut new_addrs = new_addrs.into_iter();
+        while addr_infos.len() < count {
+            match (tried_addrs.next(), new_addrs.next()) {
+                (None, None) => break,
+                (tried_addr, new_addr) => {
+                    addr_infos.extend(tried_addr);
+                    addr_infos.extend(new_addr);
+                }
+            }
+        }
+        addr_infos.truncate(count);
         addr_infos
     }
 
@@ -76,7 +218,14 @@ impl AddrManager {
             // swap with last index, then remove the last index
             self.swap_random_id(random_id_pos, self.random_ids.len() - 1);
             self.random_ids.pop();
-            self.id_to_info.remove(&id)
+            self.dirty.insert(*addr);
+            let addr_info = self.id_to_info.remove(&id);
+            if let Some(bucket) = addr_info.as_ref().and_then(|info| info.bucket) {
+                if let Some(ids) = self.buckets.get_mut(&bucket) {
+                    ids.remove(&id);
+                }
+            }
+            addr_info
         } else {
             None
         }
@@ -90,12 +239,23 @@ impl AddrManager {
 
     pub fn get_mut(&mut self, addr: &IpPort) -> Option<&mut AddrInfo> {
         if let Some(id) = self.addr_to_id.get(addr) {
+            self.dirty.insert(*addr);
             self.id_to_info.get_mut(&id)
         } else {
             None
         }
     }
 
+    /// The addrs added, changed or removed since the last `take_dirty`
+    pub(crate) fn dirty(&self) -> &HashSet<IpPort> {
+        &self.dirty
+    }
+
+    /// Take the addrs added, changed or removed since the last call
+    pub(crate) fn take_dirty(&mut self) -> HashSet<IpPort> {
+        mem::replace(&mut self.dirty, HashSet::default())
+    }
+
     /// swap random_id i and j,
     /// this function keep random_id_pos in consistency
     fn swap_random_id(&mut self, i: usize, j: usize) {
@@ -2,7 +2,8 @@ use crate::peer_store::types::{ip_to_network, BannedAddr, MultiaddrExt};
 use crate::peer_store::Multiaddr;
 use faketime::unix_time_as_millis;
 use ipnetwork::IpNetwork;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 use std::net::IpAddr;
 
 const CLEAR_EXPIRES_PERIOD: usize = 1024;
@@ -10,6 +11,8 @@ const CLEAR_EXPIRES_PERIOD: usize = 1024;
 pub struct BanList {
     inner: HashMap<IpNetwork, BannedAddr>,
     insert_count: usize,
+    // Networks banned or unbanned since the last `take_dirty`
+    dirty: HashSet<IpNetwork>,
 }
 
 impl Default for BanList {
@@ -23,10 +26,12 @@ impl BanList {
         BanList {
             inner: HashMap::default(),
             insert_count: 0,
+            dirty: HashSet::default(),
         }
     }
 
     pub fn ban(&mut self, banned_addr: BannedAddr) {
+        self.dirty.insert(banned_addr.address);
         self.inner.insert(banned_addr.address, banned_addr);
         let (insert_count, _) = self.insert_count.overflowing_add(1);
         self.insert_count = insert_count;
@@ -36,6 +41,7 @@ impl BanList {
     }
 
     pub fn unban_network(&mut self, ip_network: &IpNetwork) {
+        self.dirty.insert(*ip_network);
         self.inner.remove(&ip_network);
     }
 
@@ -69,9 +75,29 @@ impl BanList {
         self.inner.values().map(ToOwned::to_owned).collect()
     }
 
+    pub fn get_banned_addr(&self, ip_network: &IpNetwork) -> Option<&BannedAddr> {
+        self.inner.get(ip_network)
+    }
+
+    /// The networks banned or unbanned since the last `take_dirty`
+    pub(crate) fn dirty(&self) -> &HashSet<IpNetwork> {
+        &self.dirty
+    }
+
+    /// Take the networks banned or unbanned since the last call
+    pub(crate) fn take_dirty(&mut self) -> HashSet<IpNetwork> {
+        mem::replace(&mut self.dirty, HashSet::default())
+    }
+
     fn clear_expires(&mut self) {
         let now = unix_time_as_millis();
-        self.inner
-            .retain(|_, banned_addr| banned_addr.ban_until.gt(&now));
+        let dirty = &mut self.dirty;
+        self.inner.retain(|ip_network, banned_addr| {
+            let retain = banned_addr.ban_until.gt(&now);
+            if !retain {
+                dirty.insert(*ip_network);
+            }
+            retain
+        });
     }
 }
@@ -9,9 +9,8 @@ pub use crate::SessionType;
 pub(crate) use crate::{Behaviour, PeerId};
 use p2p::multiaddr::Multiaddr;
 pub use peer_store_impl::PeerStore;
+use std::cmp;
 
-/// peer store evict peers after reach this limitation
-pub(crate) const ADDR_COUNT_LIMIT: usize = 16384;
 /// Consider we never seen a peer if peer's last_connected_at beyond this timeout
 const ADDR_TIMEOUT_MS: u64 = 7 * 24 * 3600 * 1000;
 const ADDR_MAX_RETRIES: u32 = 3;
@@ -23,20 +22,44 @@ pub type Score = i32;
 #[derive(Copy, Clone, Debug)]
 pub struct PeerScoreConfig {
     pub default_score: Score,
+    pub max_score: Score,
     pub ban_score: Score,
     pub ban_timeout_ms: u64,
+    /// The difference between a score and the default score halves in this time
+    pub score_half_life_ms: u64,
 }
 
 impl Default for PeerScoreConfig {
     fn default() -> Self {
         PeerScoreConfig {
             default_score: 100,
+            max_score: 200,
             ban_score: 40,
             ban_timeout_ms: 24 * 3600 * 1000, // 1 day
+            score_half_life_ms: 3600 * 1000,  // 1 hour
         }
     }
 }
 
+impl PeerScoreConfig {
+    /// Decays the score toward the default score over the elapsed time
+    pub fn decay(&self, score: Score, elapsed_ms: u64) -> Score {
+        let deviation = f64::from(score.saturating_sub(self.default_score));
+        let half_lives = elapsed_ms as f64 / self.score_half_life_ms.max(1) as f64;
+        self.default_score
+            .saturating_add((deviation * 0.5f64.powf(half_lives)).round() as Score)
+    }
+
+    /// Applies the behaviour to the score last updated `elapsed_ms` ago
+    pub fn update(&self, score: Score, elapsed_ms: u64, behaviour: Behaviour) -> Score {
+        cmp::min(
+            self.decay(score, elapsed_ms)
+                .saturating_add(behaviour.score()),
+            self.max_score,
+        )
+    }
+}
+
 /// Peer Status
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Status {
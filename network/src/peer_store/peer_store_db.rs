@@ -3,109 +3,252 @@ use crate::{
     peer_store::{
         addr_manager::AddrManager,
         ban_list::BanList,
-        types::{AddrInfo, BannedAddr},
+        types::{AddrInfo, BannedAddr, IpPort},
         PeerStore,
     },
 };
-use ckb_logger::debug;
-use std::fs::{copy, create_dir_all, remove_file, rename, File, OpenOptions};
-use std::io::{Read, Write};
+use ckb_db::{Col, DBConfig, RocksDB};
+use ckb_logger::{debug, info, warn};
+use ipnetwork::IpNetwork;
+use rand::random;
+use serde::de::DeserializeOwned;
+use std::fs::{create_dir_all, remove_dir_all, remove_file, File};
+use std::net::SocketAddr;
 use std::path::Path;
 
+// The JSON files dumped by the old versions, they are imported into the database
 const DEFAULT_ADDR_MANAGER_DB: &str = "addr_manager.db";
 const DEFAULT_BAN_LIST_DB: &str = "ban_list.db";
+const DB_DIR: &str = "db";
 
-impl AddrManager {
-    pub fn load<R: Read>(r: R) -> Result<Self, Error> {
-        let addrs: Vec<AddrInfo> = serde_json::from_reader(r).map_err(PeerStoreError::Serde)?;
-        let mut addr_manager = AddrManager::default();
-        addrs.into_iter().for_each(|addr| addr_manager.add(addr));
-        Ok(addr_manager)
-    }
+const COLUMNS: u32 = 3;
+const COLUMN_ADDR: Col = "0";
+const COLUMN_BANNED_ADDR: Col = "1";
+const COLUMN_META: Col = "2";
+const META_BUCKET_KEY: &[u8] = b"bucket_key";
 
-    pub fn dump<W: Write>(&self, w: W) -> Result<(), Error> {
-        let addrs: Vec<_> = self.addrs_iter().collect();
-        debug!("dump {} addrs", addrs.len());
-        serde_json::to_writer(w, &addrs).map_err(|err| PeerStoreError::Serde(err).into())
-    }
+/// Persists the peer store, only the changed entries are written on each dump
+pub(crate) struct PeerStoreDB {
+    db: RocksDB,
 }
 
-impl BanList {
-    pub fn load<R: Read>(r: R) -> Result<Self, Error> {
-        let banned_addrs: Vec<BannedAddr> =
-            serde_json::from_reader(r).map_err(PeerStoreError::Serde)?;
-        let mut ban_list = BanList::default();
-        banned_addrs
-            .into_iter()
-            .for_each(|banned_addr| ban_list.ban(banned_addr));
-        Ok(ban_list)
-    }
+fn addr_key(ip_port: &IpPort) -> Vec<u8> {
+    SocketAddr::new(ip_port.ip, ip_port.port)
+        .to_string()
+        .into_bytes()
+}
 
-    pub fn dump<W: Write>(&self, w: W) -> Result<(), Error> {
-        let banned_addrs = self.get_banned_addrs();
-        debug!("dump {} banned addrs", banned_addrs.len());
-        serde_json::to_writer(w, &banned_addrs).map_err(|err| PeerStoreError::Serde(err).into())
-    }
+fn banned_addr_key(ip_network: &IpNetwork) -> Vec<u8> {
+    ip_network.to_string().into_bytes()
 }
 
-impl PeerStore {
-    pub fn load_from_dir<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        let addr_manager_path = path.as_ref().join(DEFAULT_ADDR_MANAGER_DB);
-        let ban_list_path = path.as_ref().join(DEFAULT_BAN_LIST_DB);
+// `RocksDB` repairs the corrupted database on open, these errors are left
+// when the repair fails or the version record is broken
+fn is_corrupted(err: &ckb_error::Error) -> bool {
+    let err = err.to_string();
+    err.contains("Corruption:")
+        || err.contains("repair")
+        || err.contains("version info about database is lost")
+        || err.contains("database version is malformed")
+}
 
-        let addr_manager = if addr_manager_path.exists() {
-            AddrManager::load(File::open(addr_manager_path)?)?
-        } else {
-            debug!("Failed to load addr manager from {:?}", addr_manager_path);
-            AddrManager::default()
+impl PeerStoreDB {
+    pub(crate) fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let config = DBConfig {
+            path: path.as_ref().to_path_buf(),
+            ..Default::default()
         };
-
-        let ban_list = if ban_list_path.exists() {
-            BanList::load(File::open(ban_list_path)?)?
-        } else {
-            debug!("Failed to load ban list from {:?}", ban_list_path);
-            BanList::default()
+        let db = match RocksDB::open_with_error(&config, COLUMNS) {
+            Ok(db) => db,
+            Err(ref err) if is_corrupted(err) => {
+                // the peer store can be rebuilt from the network, start over
+                // rather than refuse to start
+                warn!(
+                    "Failed to open peer store db {:?}: {}, recreate it",
+                    config.path, err
+                );
+                remove_dir_all(&config.path)?;
+                RocksDB::open_with_error(&config, COLUMNS).map_err(PeerStoreError::DB)?
+            }
+            // the lock held by another instance and the IO errors are not
+            // fixed by wiping the ban list
+            Err(err) => return Err(PeerStoreError::DB(err).into()),
         };
+        Ok(PeerStoreDB { db })
+    }
 
-        Ok(PeerStore::new(addr_manager, ban_list))
+    fn load_values<T: DeserializeOwned>(&self, col: Col) -> Result<Vec<T>, Error> {
+        let mut values = Vec::new();
+        self.db
+            .traverse(col, |_key, value| {
+                values.push(value.to_vec());
+                Ok(())
+            })
+            .map_err(PeerStoreError::DB)?;
+        Ok(values
+            .iter()
+            .filter_map(|value| match serde_json::from_slice(value) {
+                Ok(value) => Some(value),
+                Err(err) => {
+                    warn!("Ignore malformed peer store entry: {}", err);
+                    None
+                }
+            })
+            .collect())
     }
 
-    pub fn dump_to_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
-        // create dir
-        create_dir_all(&path)?;
-        // dump file to temp dir
-        let tmp_dir = tempfile::tempdir()?;
-        let tmp_addr_manager = tmp_dir.path().join(DEFAULT_ADDR_MANAGER_DB);
-        let tmp_ban_list = tmp_dir.path().join(DEFAULT_BAN_LIST_DB);
-        self.addr_manager().dump(
-            OpenOptions::new()
-                .write(true)
-                .create(true)
-                .append(false)
-                .open(&tmp_addr_manager)?,
-        )?;
-        move_file(
-            tmp_addr_manager,
-            path.as_ref().join(DEFAULT_ADDR_MANAGER_DB),
-        )?;
-        self.ban_list().dump(
-            OpenOptions::new()
-                .write(true)
-                .create(true)
-                .append(false)
-                .open(&tmp_ban_list)?,
-        )?;
-        move_file(tmp_ban_list, path.as_ref().join(DEFAULT_BAN_LIST_DB))?;
+    fn load_bucket_key(&self) -> Result<u64, Error> {
+        if let Some(value) = self
+            .db
+            .get_pinned(COLUMN_META, META_BUCKET_KEY)
+            .map_err(PeerStoreError::DB)?
+        {
+            if value.len() == 8 {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&value);
+                return Ok(u64::from_le_bytes(bytes));
+            }
+        }
+        let bucket_key = random::<u64>();
+        let txn = self.db.transaction();
+        txn.put(COLUMN_META, META_BUCKET_KEY, &bucket_key.to_le_bytes())
+            .map_err(PeerStoreError::DB)?;
+        txn.commit().map_err(PeerStoreError::DB)?;
+        Ok(bucket_key)
+    }
+
+    pub(crate) fn load_addr_manager(&self) -> Result<AddrManager, Error> {
+        let addrs: Vec<AddrInfo> = self.load_values(COLUMN_ADDR)?;
+        let mut addr_manager = AddrManager::new(self.load_bucket_key()?);
+        let keys: Vec<_> = addrs.iter().map(AddrInfo::ip_port).collect();
+        addrs.into_iter().for_each(|addr| addr_manager.add(addr));
+        addr_manager.take_dirty();
+        // delete the addrs which don't fit in the buckets anymore
+        let txn = self.db.transaction();
+        for key in keys.iter().filter(|key| addr_manager.get(key).is_none()) {
+            txn.delete(COLUMN_ADDR, &addr_key(key))
+                .map_err(PeerStoreError::DB)?;
+        }
+        txn.commit().map_err(PeerStoreError::DB)?;
+        debug!("load {} addrs", addr_manager.count());
+        Ok(addr_manager)
+    }
+
+    pub(crate) fn load_ban_list(&self) -> Result<BanList, Error> {
+        let banned_addrs: Vec<BannedAddr> = self.load_values(COLUMN_BANNED_ADDR)?;
+        let now_ms = faketime::unix_time_as_millis();
+        let mut ban_list = BanList::default();
+        let txn = self.db.transaction();
+        for banned_addr in banned_addrs {
+            if banned_addr.ban_until > now_ms {
+                ban_list.ban(banned_addr);
+            } else {
+                txn.delete(COLUMN_BANNED_ADDR, &banned_addr_key(&banned_addr.address))
+                    .map_err(PeerStoreError::DB)?;
+            }
+        }
+        txn.commit().map_err(PeerStoreError::DB)?;
+        ban_list.take_dirty();
+        Ok(ban_list)
+    }
+
+    /// Import the JSON files dumped by the old versions, the files are removed
+    /// once imported
+    pub(crate) fn import_json_files<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        let addr_manager_path = dir.as_ref().join(DEFAULT_ADDR_MANAGER_DB);
+        if addr_manager_path.exists() {
+            let addrs: Vec<AddrInfo> = serde_json::from_reader(File::open(&addr_manager_path)?)
+                .map_err(PeerStoreError::Serde)?;
+            let txn = self.db.transaction();
+            for addr in &addrs {
+                let value = serde_json::to_vec(addr).map_err(PeerStoreError::Serde)?;
+                txn.put(COLUMN_ADDR, &addr_key(&addr.ip_port), &value)
+                    .map_err(PeerStoreError::DB)?;
+            }
+            txn.commit().map_err(PeerStoreError::DB)?;
+            remove_file(&addr_manager_path)?;
+            info!(
+                "Imported {} addrs from {:?}",
+                addrs.len(),
+                addr_manager_path
+            );
+        }
+
+        let ban_list_path = dir.as_ref().join(DEFAULT_BAN_LIST_DB);
+        if ban_list_path.exists() {
+            let banned_addrs: Vec<BannedAddr> =
+                serde_json::from_reader(File::open(&ban_list_path)?)
+                    .map_err(PeerStoreError::Serde)?;
+            let txn = self.db.transaction();
+            for banned_addr in &banned_addrs {
+                let value = serde_json::to_vec(banned_addr).map_err(PeerStoreError::Serde)?;
+                txn.put(
+                    COLUMN_BANNED_ADDR,
+                    &banned_addr_key(&banned_addr.address),
+                    &value,
+                )
+                .map_err(PeerStoreError::DB)?;
+            }
+            txn.commit().map_err(PeerStoreError::DB)?;
+            remove_file(&ban_list_path)?;
+            info!(
+                "Imported {} banned addrs from {:?}",
+                banned_addrs.len(),
+                ban_list_path
+            );
+        }
+        Ok(())
+    }
+
+    /// Write the addrs and the banned addrs changed since the last dump in one
+    /// transaction, the changes are kept for the next dump if it fails
+    pub(crate) fn dump(
+        &self,
+        addr_manager: &mut AddrManager,
+        ban_list: &mut BanList,
+    ) -> Result<(), Error> {
+        let txn = self.db.transaction();
+        for ip_port in addr_manager.dirty() {
+            let result = match addr_manager.get(ip_port) {
+                Some(addr) => {
+                    let value = serde_json::to_vec(addr).map_err(PeerStoreError::Serde)?;
+                    txn.put(COLUMN_ADDR, &addr_key(ip_port), &value)
+                }
+                None => txn.delete(COLUMN_ADDR, &addr_key(ip_port)),
+            };
+            result.map_err(PeerStoreError::DB)?;
+        }
+        for ip_network in ban_list.dirty() {
+            let result = match ban_list.get_banned_addr(ip_network) {
+                Some(banned_addr) => {
+                    let value = serde_json::to_vec(banned_addr).map_err(PeerStoreError::Serde)?;
+                    txn.put(COLUMN_BANNED_ADDR, &banned_addr_key(ip_network), &value)
+                }
+                None => txn.delete(COLUMN_BANNED_ADDR, &banned_addr_key(ip_network)),
+            };
+            result.map_err(PeerStoreError::DB)?;
+        }
+        txn.commit().map_err(PeerStoreError::DB)?;
+        let addrs = addr_manager.take_dirty();
+        let banned_addrs = ban_list.take_dirty();
+        debug!(
+            "dump {} changed addrs and {} changed banned addrs",
+            addrs.len(),
+            banned_addrs.len()
+        );
         Ok(())
     }
 }
 
-/// This function use `copy` then `remove_file` as a fallback when `rename` failed,
-/// this maybe happen when src and dst on different file systems.
-fn move_file<P: AsRef<Path>>(src: P, dst: P) -> Result<(), Error> {
-    if rename(&src, &dst).is_err() {
-        copy(&src, &dst)?;
-        remove_file(&src)?;
+impl PeerStore {
+    /// Open the peer store database in the dir, the JSON files dumped by the
+    /// old versions are imported
+    pub fn load_from_dir<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        create_dir_all(&path)?;
+        let db = PeerStoreDB::open(path.as_ref().join(DB_DIR))?;
+        db.import_json_files(&path)?;
+        let addr_manager = db.load_addr_manager()?;
+        let ban_list = db.load_ban_list()?;
+        Ok(PeerStore::with_db(addr_manager, ban_list, db))
     }
-    Ok(())
 }
@@ -1,12 +1,11 @@
 use crate::{
-    errors::{PeerStoreError, Result},
-    network_group::{Group, NetworkGroup},
+    errors::Result,
     peer_store::{
         addr_manager::AddrManager,
         ban_list::BanList,
+        peer_store_db::PeerStoreDB,
         types::{ip_to_network, AddrInfo, BannedAddr, MultiaddrExt, PeerInfo},
-        Behaviour, Multiaddr, PeerScoreConfig, ReportResult, Status, ADDR_COUNT_LIMIT,
-        ADDR_TIMEOUT_MS,
+        Behaviour, Multiaddr, PeerScoreConfig, ReportResult, Status, ADDR_TIMEOUT_MS,
     },
     PeerId, SessionType,
 };
@@ -20,6 +19,7 @@ pub struct PeerStore {
     ban_list: RefCell<BanList>,
     peers: RefCell<HashMap<PeerId, PeerInfo>>,
     score_config: PeerScoreConfig,
+    db: Option<PeerStoreDB>,
 }
 
 impl PeerStore {
@@ -29,6 +29,22 @@ impl PeerStore {
             ban_list: RefCell::new(ban_list),
             peers: Default::default(),
             score_config: Default::default(),
+            db: None,
+        }
+    }
+
+    pub(crate) fn with_db(addr_manager: AddrManager, ban_list: BanList, db: PeerStoreDB) -> Self {
+        PeerStore {
+            db: Some(db),
+            ..PeerStore::new(addr_manager, ban_list)
+        }
+    }
+
+    /// Write the changes since the last dump to the database
+    pub fn dump(&mut self) -> Result<()> {
+        match self.db {
+            Some(ref db) => db.dump(&mut self.addr_manager, self.ban_list.get_mut()),
+            None => Ok(()),
         }
     }
 
@@ -49,7 +65,13 @@ impl PeerStore {
                 peer.session_type = session_type;
             }
             Entry::Vacant(entry) => {
-                let peer = PeerInfo::new(peer_id.to_owned(), addr.clone(), session_type, now_ms);
+                let peer = PeerInfo::new(
+                    peer_id.to_owned(),
+                    addr.clone(),
+                    session_type,
+                    now_ms,
+                    self.score_config.default_score,
+                );
                 entry.insert(peer);
             }
         }
@@ -69,15 +91,37 @@ impl PeerStore {
     /// Add discovered peer addresses
     /// this method will assume peer and addr is untrust since we have not connected to it.
     pub fn add_addr(&mut self, peer_id: PeerId, addr: Multiaddr) -> Result<()> {
-        self.check_purge()?;
+        self.add_addr_with_source(peer_id, addr, None)
+    }
+
+    /// Add peer addresses told by the source peer, the network group of the
+    /// source decides the buckets the addresses go into
+    pub fn add_addr_from(
+        &mut self,
+        peer_id: PeerId,
+        addr: Multiaddr,
+        source: &Multiaddr,
+    ) -> Result<()> {
+        self.add_addr_with_source(peer_id, addr, Some(source))
+    }
+
+    fn add_addr_with_source(
+        &mut self,
+        peer_id: PeerId,
+        addr: Multiaddr,
+        source: Option<&Multiaddr>,
+    ) -> Result<()> {
         let score = self.score_config.default_score;
-        self.addr_manager.add(AddrInfo::new(
-            peer_id,
-            addr.extract_ip_addr()?,
-            addr.exclude_p2p(),
-            0,
-            score,
-        ));
+        self.addr_manager.add_with_source(
+            AddrInfo::new(
+                peer_id,
+                addr.extract_ip_addr()?,
+                addr.exclude_p2p(),
+                0,
+                score,
+            ),
+            source,
+        );
         Ok(())
     }
 
@@ -89,24 +133,42 @@ impl PeerStore {
         &mut self.addr_manager
     }
 
-    /// Report peer behaviours
+    /// Report peer behaviours, bans the peer address when its score drops
+    /// below the ban score or the behaviour is fatal
     pub fn report(&mut self, peer_id: &PeerId, behaviour: Behaviour) -> Result<ReportResult> {
-        if let Some(peer) = {
-            let peers = self.peers.borrow();
-            peers.get(peer_id).map(ToOwned::to_owned)
-        } {
+        let now_ms = faketime::unix_time_as_millis();
+        let score_config = self.score_config;
+        let (score, connected_addr) = {
+            let peer = match self.peers.get_mut().get_mut(peer_id) {
+                Some(peer) => peer,
+                None => return Ok(ReportResult::Ok),
+            };
             let key = peer.connected_addr.extract_ip_addr()?;
-            let mut peer_addr = self.addr_manager.get_mut(&key).expect("peer addr exists");
-            let score = peer_addr.score.saturating_add(behaviour.score());
-            peer_addr.score = score;
-            if score < self.score_config.ban_score {
-                self.ban_addr(
-                    &peer.connected_addr,
-                    self.score_config.ban_timeout_ms,
-                    format!("report behaviour {:?}", behaviour),
-                )?;
-                return Ok(ReportResult::Banned);
-            }
+            // The addresses in the addr manager keep their scores across the
+            // connections, the others only for the connection
+            let score = match self.addr_manager.get_mut(&key) {
+                Some(peer_addr) => {
+                    let elapsed_ms = now_ms.saturating_sub(peer_addr.score_updated_at_ms);
+                    peer_addr.score = score_config.update(peer_addr.score, elapsed_ms, behaviour);
+                    peer_addr.score_updated_at_ms = now_ms;
+                    peer_addr.score
+                }
+                None => {
+                    let elapsed_ms = now_ms.saturating_sub(peer.score_updated_at_ms);
+                    peer.score = score_config.update(peer.score, elapsed_ms, behaviour);
+                    peer.score_updated_at_ms = now_ms;
+                    peer.score
+                }
+            };
+            (score, peer.connected_addr.clone())
+        };
+        if behaviour.is_fatal() || score < score_config.ban_score {
+            self.ban_addr(
+                &connected_addr,
+                score_config.ban_timeout_ms,
+                format!("report behaviour {:?}, score {}", behaviour, score),
+            )?;
+            return Ok(ReportResult::Banned);
         }
         Ok(ReportResult::Ok)
     }
@@ -206,43 +268,4 @@ impl PeerStore {
     pub fn mut_ban_list(&mut self) -> &mut BanList {
         self.ban_list.get_mut()
     }
-
-    /// check and try delete addrs if reach limit
-    /// return Err if peer_store is full and can't be purge
-    fn check_purge(&mut self) -> Result<()> {
-        if self.addr_manager.count() < ADDR_COUNT_LIMIT {
-            return Ok(());
-        }
-        let now_ms = faketime::unix_time_as_millis();
-        let candidate_peers: Vec<_> = {
-            // find candidate peers by network group
-            let mut peers_by_network_group: HashMap<Group, Vec<_>> = HashMap::default();
-            for addr in self.addr_manager.addrs_iter() {
-                let network_group = addr.addr.network_group();
-                peers_by_network_group
-                    .entry(network_group)
-                    .or_default()
-                    .push(addr);
-            }
-            let ban_score = self.score_config.ban_score;
-            // find the largest network group
-            peers_by_network_group
-                .values()
-                .max_by_key(|peers| peers.len())
-                .expect("largest network group")
-                .iter()
-                .filter(move |addr| addr.is_terrible(now_ms) || addr.score <= ban_score)
-                .map(|addr| addr.ip_port())
-                .collect()
-        };
-
-        if candidate_peers.is_empty() {
-            return Err(PeerStoreError::EvictionFailed.into());
-        }
-
-        for key in candidate_peers {
-            self.addr_manager.remove(&key);
-        }
-        Ok(())
-    }
 }
@@ -22,6 +22,9 @@ pub struct PeerInfo {
     pub connected_addr: Multiaddr,
     pub session_type: SessionType,
     pub last_connected_at_ms: u64,
+    /// The score of the connection, for the peers not in the addr manager
+    pub score: Score,
+    pub score_updated_at_ms: u64,
 }
 
 impl PeerInfo {
@@ -30,12 +33,31 @@ impl PeerInfo {
         connected_addr: Multiaddr,
         session_type: SessionType,
         last_connected_at_ms: u64,
+        score: Score,
     ) -> Self {
         PeerInfo {
             peer_id,
             connected_addr,
             session_type,
             last_connected_at_ms,
+            score,
+            score_updated_at_ms: last_connected_at_ms,
+        }
+    }
+}
+
+/// The bucket of an address in the new table or the tried table of `AddrManager`
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Bucket {
+    New(usize),
+    Tried(usize),
+}
+
+impl Bucket {
+    pub fn is_tried(self) -> bool {
+        match self {
+            Bucket::Tried(_) => true,
+            Bucket::New(_) => false,
         }
     }
 }
@@ -47,10 +69,15 @@ pub struct AddrInfo {
     pub ip_port: IpPort,
     pub addr: Multiaddr,
     pub score: Score,
+    #[serde(default)]
+    pub score_updated_at_ms: u64,
     pub last_connected_at_ms: u64,
     pub last_tried_at_ms: u64,
     pub attempts_count: u32,
     pub random_id_pos: usize,
+    /// Assigned by `AddrManager`, the addresses imported from the old JSON files have none
+    #[serde(default)]
+    pub bucket: Option<Bucket>,
 }
 
 impl AddrInfo {
@@ -66,10 +93,12 @@ impl AddrInfo {
             ip_port,
             addr,
             score,
+            score_updated_at_ms: 0,
             last_connected_at_ms,
             last_tried_at_ms: 0,
             attempts_count: 0,
             random_id_pos: 0,
+            bucket: None,
         }
     }
 
@@ -201,12 +201,22 @@ impl DiscoveryService {
             }
             DiscoveryEvent::AddNewAddrs { session_id, addrs } => {
                 if let Some(_peer_id) = self.sessions.get(&session_id) {
-                    // TODO: wait for peer store update
+                    // the addresses are bucketed by the network group of the announcing peer
+                    let source = self.network_state.with_peer_registry(|reg| {
+                        reg.get_peer(session_id)
+                            .map(|peer| peer.connected_addr.clone())
+                    });
                     for addr in addrs.into_iter().filter(|addr| self.is_valid_addr(addr)) {
                         trace!("Add discovered address:{:?}", addr);
                         if let Some(peer_id) = extract_peer_id(&addr) {
                             self.network_state.with_peer_store_mut(|peer_store| {
-                                if let Err(err) = peer_store.add_addr(peer_id.clone(), addr) {
+                                let result = match source {
+                                    Some(ref source) => {
+                                        peer_store.add_addr_from(peer_id.clone(), addr, source)
+                                    }
+                                    None => peer_store.add_addr(peer_id.clone(), addr),
+                                };
+                                if let Err(err) = result {
                                     debug!(
                                         "Failed to add discoved address to peer_store {:?} {:?}",
                                         err, peer_id
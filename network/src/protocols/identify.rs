@@ -1,5 +1,8 @@
 // use crate::peer_store::Behaviour;
-use crate::{network::FEELER_PROTOCOL_ID, NetworkState, PeerIdentifyInfo};
+use crate::{
+    network::{DISCOVERY_PROTOCOL_ID, FEELER_PROTOCOL_ID},
+    NetworkState, PeerIdentifyInfo,
+};
 use ckb_logger::{debug, trace};
 use ckb_types::{bytes::Bytes, packed, prelude::*};
 use p2p::{
@@ -26,7 +29,11 @@ impl IdentifyCallback {
         name: String,
         client_version: String,
     ) -> IdentifyCallback {
-        let flags = Flags(Flag::FullNode as u64);
+        let flags = if network_state.pruned {
+            Flags(Flag::FullNode as u64 | Flag::Pruned as u64)
+        } else {
+            Flags(Flag::FullNode as u64)
+        };
 
         IdentifyCallback {
             network_state,
@@ -35,6 +42,10 @@ impl IdentifyCallback {
     }
 
     fn listen_addrs(&self) -> Vec<Multiaddr> {
+        // The node is not reachable behind the proxy
+        if self.network_state.config.listen_disabled() {
+            return Vec::new();
+        }
         let mut addrs = self.network_state.public_addrs(MAX_RETURN_LISTEN_ADDRS * 2);
         addrs.sort_by(|a, b| a.1.cmp(&b.1));
         addrs
@@ -63,19 +74,29 @@ impl Callback for IdentifyCallback {
                         if let Some(peer) = registry.get_peer_mut(context.session.id) {
                             peer.identify_info = Some(PeerIdentifyInfo {
                                 client_version: version,
+                                pruned: flags.contains(Flag::Pruned.into()),
                             })
                         }
                     });
                 };
 
                 if context.session.ty.is_outbound() {
-                    if flags.contains(self.identify.flags) {
+                    if flags.contains(Flag::FullNode.into()) {
                         registry_client_version(client_version);
 
-                        // The remote end can support all local protocols.
-                        let protos = self
-                            .network_state
-                            .get_protocol_ids(|id| id != FEELER_PROTOCOL_ID.into());
+                        // The remote end can support all local protocols, but
+                        // the block-relay-only connections don't gossip addresses.
+                        let is_block_relay_only =
+                            self.network_state.with_peer_registry(|registry| {
+                                registry
+                                    .get_peer(context.session.id)
+                                    .map(|peer| peer.is_block_relay_only)
+                                    .unwrap_or(false)
+                            });
+                        let protos = self.network_state.get_protocol_ids(|id| {
+                            id != FEELER_PROTOCOL_ID.into()
+                                && !(is_block_relay_only && id == DISCOVERY_PROTOCOL_ID.into())
+                        });
 
                         let _ = context
                             .open_protocols(context.session.id, TargetProtocol::Multi(protos));
@@ -102,17 +123,24 @@ impl Callback for IdentifyCallback {
             peer_id,
             addrs,
         );
-        self.network_state.with_peer_registry_mut(|reg| {
+        let connected_addr = self.network_state.with_peer_registry_mut(|reg| {
             if let Some(peer) = reg
                 .get_key_by_peer_id(peer_id)
                 .and_then(|session_id| reg.get_peer_mut(session_id))
             {
                 peer.listened_addrs = addrs.clone();
+                Some(peer.connected_addr.clone())
+            } else {
+                None
             }
         });
         self.network_state.with_peer_store_mut(|peer_store| {
             for addr in addrs {
-                if let Err(err) = peer_store.add_addr(peer_id.clone(), addr) {
+                let result = match connected_addr {
+                    Some(ref source) => peer_store.add_addr_from(peer_id.clone(), addr, source),
+                    None => peer_store.add_addr(peer_id.clone(), addr),
+                };
+                if let Err(err) = result {
                     debug!("Failed to add addrs to peer_store {:?} {:?}", err, peer_id);
                 }
             }
@@ -222,6 +250,8 @@ impl Identify {
 enum Flag {
     /// Support all protocol
     FullNode = 0x1,
+    /// Only serve the recent blocks, the old block bodies are pruned
+    Pruned = 0x2,
 }
 
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
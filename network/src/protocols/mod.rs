@@ -6,27 +6,35 @@ pub(crate) mod ping;
 #[cfg(test)]
 mod test;
 
-use ckb_logger::trace;
+use ckb_logger::{debug, trace};
 use futures::{try_ready, Future, Poll};
 use p2p::{
     builder::MetaBuilder,
     bytes::Bytes,
     context::{ProtocolContext, ProtocolContextMutRef},
+    error::Error as P2pError,
     service::{ProtocolHandle, ProtocolMeta, ServiceControl, TargetSession},
     traits::ServiceProtocol,
     ProtocolId, SessionId,
 };
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::codec::length_delimited;
+use tokio::timer::Delay;
 
 pub type PeerIndex = SessionId;
 pub type BoxedFutureTask = Box<dyn Future<Item = (), Error = ()> + 'static + Send>;
 
+// The messages held by the download limits are checked at this interval
+const HELD_MESSAGES_INTERVAL: Duration = Duration::from_millis(100);
+const HELD_MESSAGES_TOKEN: u64 = std::u64::MAX - 1;
+// A peer holding more messages is sending much faster than the download limits
+const MAX_HELD_MESSAGES: usize = 256;
+
 use crate::{
-    compress::{compress, decompress},
-    network::disconnect_with_message,
-    Behaviour, Error, NetworkState, Peer, PeerRegistry, ProtocolVersion, MAX_FRAME_LENGTH,
+    compress::decompress, network::disconnect_with_message, traffic::MessageNames, Behaviour,
+    Error, NetworkState, Peer, PeerRegistry, ProtocolVersion, MAX_FRAME_LENGTH,
 };
 
 pub trait CKBProtocolContext: Send {
@@ -57,7 +65,13 @@ pub trait CKBProtocolContext: Send {
     fn connected_peers(&self) -> Vec<PeerIndex>;
     fn report_peer(&self, peer_index: PeerIndex, behaviour: Behaviour);
     fn ban_peer(&self, peer_index: PeerIndex, duration: Duration);
+    // The send buffer of the session is full or the upload rate limit is exceeded,
+    // the messages sent over the upload limits are held until they are met again
     fn send_paused(&self) -> bool;
+    // The download rate limit is exceeded, globally or for the peer
+    fn download_paused(&self, peer_index: PeerIndex) -> bool;
+    // The daily upload cap is reached, the historical blocks should not be served
+    fn upload_cap_reached(&self) -> bool;
     // Other methods
     fn protocol_id(&self) -> ProtocolId;
 }
@@ -96,6 +110,8 @@ pub struct CKBProtocol {
     supported_versions: Vec<ProtocolVersion>,
     handler: Box<dyn Fn() -> Box<dyn CKBProtocolHandler + Send + 'static> + Send + 'static>,
     network_state: Arc<NetworkState>,
+    // names the messages in the traffic statistics
+    message_names: Option<MessageNames>,
 }
 
 impl CKBProtocol {
@@ -110,6 +126,7 @@ impl CKBProtocol {
             id,
             network_state,
             handler: Box::new(handler),
+            message_names: None,
             protocol_name: format!("/ckb/{}/", protocol_name).to_string(),
             supported_versions: {
                 let mut versions: Vec<_> = versions.to_vec();
@@ -119,6 +136,12 @@ impl CKBProtocol {
         }
     }
 
+    /// Names the messages of this protocol in the traffic statistics
+    pub fn message_names(mut self, message_names: MessageNames) -> Self {
+        self.message_names = Some(message_names);
+        self
+    }
+
     pub fn id(&self) -> ProtocolId {
         self.id
     }
@@ -138,6 +161,10 @@ impl CKBProtocol {
             .iter()
             .map(ToString::to_string)
             .collect::<Vec<_>>();
+        // Messages are compressed and decompressed by the handler and the context,
+        // where the session is known, to count the traffic of each peer.
+        self.network_state
+            .register_traffic(self.id, protocol_name.clone(), self.message_names);
         MetaBuilder::default()
             .id(self.id)
             .name(move |_| protocol_name.clone())
@@ -154,10 +181,9 @@ impl CKBProtocol {
                     proto_id: self.id,
                     network_state: Arc::clone(&self.network_state),
                     handler: (self.handler)(),
+                    held_messages: HashMap::default(),
                 }))
             })
-            .before_send(compress)
-            .before_receive(|| Some(Box::new(decompress)))
             .build()
     }
 }
@@ -166,6 +192,43 @@ struct CKBHandler {
     proto_id: ProtocolId,
     network_state: Arc<NetworkState>,
     handler: Box<dyn CKBProtocolHandler>,
+    // The messages held until the download limits are met again, in the order
+    // they are received from each peer
+    held_messages: HashMap<PeerIndex, VecDeque<Bytes>>,
+}
+
+impl CKBHandler {
+    fn release_held_messages(&mut self, context: &mut ProtocolContext) {
+        let peers = self.held_messages.keys().cloned().collect::<Vec<_>>();
+        for peer_index in peers {
+            while !self.network_state.download_paused(peer_index) {
+                let data = match self
+                    .held_messages
+                    .get_mut(&peer_index)
+                    .and_then(VecDeque::pop_front)
+                {
+                    Some(data) => data,
+                    None => break,
+                };
+                let nc = DefaultCKBProtocolContext {
+                    proto_id: self.proto_id,
+                    network_state: Arc::clone(&self.network_state),
+                    p2p_control: context.control().to_owned(),
+                    session_id: Some(peer_index),
+                    send_paused: false,
+                };
+                self.handler.received(Arc::new(nc), peer_index, data);
+            }
+            if self
+                .held_messages
+                .get(&peer_index)
+                .map(VecDeque::is_empty)
+                .unwrap_or(false)
+            {
+                self.held_messages.remove(&peer_index);
+            }
+        }
+    }
 }
 
 // Just proxy to inner handler, this struct exists for convenient unit test.
@@ -175,10 +238,13 @@ impl ServiceProtocol for CKBHandler {
             proto_id: self.proto_id,
             network_state: Arc::clone(&self.network_state),
             p2p_control: context.control().to_owned(),
+            session_id: None,
             send_paused: false,
         };
         nc.set_notify(Duration::from_secs(6), std::u64::MAX)
             .expect("set_notify at init should be ok");
+        nc.set_notify(HELD_MESSAGES_INTERVAL, HELD_MESSAGES_TOKEN)
+            .expect("set_notify at init should be ok");
         self.handler.init(Arc::new(nc));
     }
 
@@ -189,6 +255,7 @@ impl ServiceProtocol for CKBHandler {
             proto_id: self.proto_id,
             network_state: Arc::clone(&self.network_state),
             p2p_control: context.control().to_owned(),
+            session_id: Some(context.session.id),
             send_paused,
         };
         let peer_index = context.session.id;
@@ -202,9 +269,11 @@ impl ServiceProtocol for CKBHandler {
             proto_id: self.proto_id,
             network_state: Arc::clone(&self.network_state),
             p2p_control: context.control().to_owned(),
+            session_id: Some(context.session.id),
             send_paused,
         };
         let peer_index = context.session.id;
+        self.held_messages.remove(&peer_index);
         self.handler.disconnected(Arc::new(nc), peer_index);
     }
 
@@ -215,26 +284,76 @@ impl ServiceProtocol for CKBHandler {
             context.session.id,
             data.len()
         );
+        let peer_index = context.session.id;
+        let bytes = data.len();
+        let data = match decompress(data.into()) {
+            Ok(data) => data,
+            Err(err) => {
+                debug!(
+                    "Peer {} sends us a message can not be decompressed: {}",
+                    peer_index, err
+                );
+                self.network_state.report_session(
+                    context.control(),
+                    peer_index,
+                    Behaviour::MalformedMessage,
+                );
+                return;
+            }
+        };
+        let delay = self
+            .network_state
+            .record_received(self.proto_id, peer_index, bytes, &data);
+        let held = self
+            .held_messages
+            .get(&peer_index)
+            .map(VecDeque::len)
+            .unwrap_or(0);
+        if delay.is_some() || held > 0 {
+            if held >= MAX_HELD_MESSAGES {
+                debug!(
+                    "Peer {} exceeds the download limits, disconnect it",
+                    peer_index
+                );
+                self.held_messages.remove(&peer_index);
+                if let Err(err) = disconnect_with_message(
+                    context.control(),
+                    peer_index,
+                    "download rate limit exceeded",
+                ) {
+                    debug!("Disconnect failed {:?}, error: {:?}", peer_index, err);
+                }
+            } else {
+                self.held_messages
+                    .entry(peer_index)
+                    .or_default()
+                    .push_back(data);
+            }
+            return;
+        }
         let pending_data_size = context.session.pending_data_size();
         let send_paused = pending_data_size >= self.network_state.config.max_send_buffer();
         let nc = DefaultCKBProtocolContext {
             proto_id: self.proto_id,
             network_state: Arc::clone(&self.network_state),
             p2p_control: context.control().to_owned(),
+            session_id: Some(context.session.id),
             send_paused,
         };
-        let peer_index = context.session.id;
         self.handler.received(Arc::new(nc), peer_index, data);
     }
 
     fn notify(&mut self, context: &mut ProtocolContext, token: u64) {
         if token == std::u64::MAX {
             trace!("protocol handler heart beat {}", self.proto_id);
+        } else if token == HELD_MESSAGES_TOKEN {
+            self.release_held_messages(context);
         } else {
             let nc = DefaultCKBProtocolContext {
                 proto_id: self.proto_id,
                 network_state: Arc::clone(&self.network_state),
                 p2p_control: context.control().to_owned(),
+                session_id: None,
                 send_paused: false,
             };
             self.handler.notify(Arc::new(nc), token);
@@ -246,6 +365,7 @@ impl ServiceProtocol for CKBHandler {
             proto_id: self.proto_id,
             network_state: Arc::clone(&self.network_state),
             p2p_control: context.control().to_owned(),
+            session_id: None,
             send_paused: false,
         };
         self.handler.poll(Arc::new(nc));
@@ -256,9 +376,35 @@ struct DefaultCKBProtocolContext {
     proto_id: ProtocolId,
     network_state: Arc<NetworkState>,
     p2p_control: ServiceControl,
+    // The session which triggers the callback
+    session_id: Option<SessionId>,
     send_paused: bool,
 }
 
+impl DefaultCKBProtocolContext {
+    // Sends the compressed message at once, or holds it in a timer until the
+    // upload limits are met again
+    fn send_or_hold<F>(&self, compressed: (Bytes, Option<Duration>), send: F) -> Result<(), Error>
+    where
+        F: FnOnce(&ServiceControl, Bytes) -> Result<(), P2pError> + Send + 'static,
+    {
+        let (data, delay) = compressed;
+        match delay {
+            None => send(&self.p2p_control, data)?,
+            Some(delay) => {
+                let p2p_control = self.p2p_control.clone();
+                let task = Delay::new(Instant::now() + delay).then(move |_| {
+                    send(&p2p_control, data).map_err(|err| {
+                        debug!("Failed to send the held message: {:?}", err);
+                    })
+                });
+                self.p2p_control.future_task(task)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl CKBProtocolContext for DefaultCKBProtocolContext {
     fn set_notify(&self, interval: Duration, token: u64) -> Result<(), Error> {
         self.p2p_control
@@ -277,9 +423,12 @@ impl CKBProtocolContext for DefaultCKBProtocolContext {
             peer_index,
             data.len()
         );
-        self.p2p_control
-            .quick_send_message_to(peer_index, proto_id, data)?;
-        Ok(())
+        let compressed =
+            self.network_state
+                .compress_to_send(proto_id, &TargetSession::Single(peer_index), data);
+        self.send_or_hold(compressed, move |p2p_control, data| {
+            p2p_control.quick_send_message_to(peer_index, proto_id, data)
+        })
     }
     fn quick_send_message_to(&self, peer_index: PeerIndex, data: Bytes) -> Result<(), Error> {
         trace!(
@@ -288,14 +437,20 @@ impl CKBProtocolContext for DefaultCKBProtocolContext {
             peer_index,
             data.len()
         );
-        self.p2p_control
-            .quick_send_message_to(peer_index, self.proto_id, data)?;
-        Ok(())
+        let proto_id = self.proto_id;
+        let compressed =
+            self.network_state
+                .compress_to_send(proto_id, &TargetSession::Single(peer_index), data);
+        self.send_or_hold(compressed, move |p2p_control, data| {
+            p2p_control.quick_send_message_to(peer_index, proto_id, data)
+        })
     }
     fn quick_filter_broadcast(&self, target: TargetSession, data: Bytes) -> Result<(), Error> {
-        self.p2p_control
-            .quick_filter_broadcast(target, self.proto_id, data)?;
-        Ok(())
+        let proto_id = self.proto_id;
+        let compressed = self.network_state.compress_to_send(proto_id, &target, data);
+        self.send_or_hold(compressed, move |p2p_control, data| {
+            p2p_control.quick_filter_broadcast(target, proto_id, data)
+        })
     }
     fn future_task(&self, task: BoxedFutureTask, blocking: bool) -> Result<(), Error> {
         let task = if blocking {
@@ -318,9 +473,12 @@ impl CKBProtocolContext for DefaultCKBProtocolContext {
             peer_index,
             data.len()
         );
-        self.p2p_control
-            .send_message_to(peer_index, proto_id, data)?;
-        Ok(())
+        let compressed =
+            self.network_state
+                .compress_to_send(proto_id, &TargetSession::Single(peer_index), data);
+        self.send_or_hold(compressed, move |p2p_control, data| {
+            p2p_control.send_message_to(peer_index, proto_id, data)
+        })
     }
     fn send_message_to(&self, peer_index: PeerIndex, data: Bytes) -> Result<(), Error> {
         trace!(
@@ -329,14 +487,20 @@ impl CKBProtocolContext for DefaultCKBProtocolContext {
             peer_index,
             data.len()
         );
-        self.p2p_control
-            .send_message_to(peer_index, self.proto_id, data)?;
-        Ok(())
+        let proto_id = self.proto_id;
+        let compressed =
+            self.network_state
+                .compress_to_send(proto_id, &TargetSession::Single(peer_index), data);
+        self.send_or_hold(compressed, move |p2p_control, data| {
+            p2p_control.send_message_to(peer_index, proto_id, data)
+        })
     }
     fn filter_broadcast(&self, target: TargetSession, data: Bytes) -> Result<(), Error> {
-        self.p2p_control
-            .filter_broadcast(target, self.proto_id, data)?;
-        Ok(())
+        let proto_id = self.proto_id;
+        let compressed = self.network_state.compress_to_send(proto_id, &target, data);
+        self.send_or_hold(compressed, move |p2p_control, data| {
+            p2p_control.filter_broadcast(target, proto_id, data)
+        })
     }
     fn disconnect(&self, peer_index: PeerIndex, message: &str) -> Result<(), Error> {
         disconnect_with_message(&self.p2p_control, peer_index, message)?;
@@ -365,7 +529,15 @@ impl CKBProtocolContext for DefaultCKBProtocolContext {
     }
 
     fn send_paused(&self) -> bool {
-        self.send_paused
+        self.send_paused || self.network_state.upload_paused(self.session_id)
+    }
+
+    fn download_paused(&self, peer_index: PeerIndex) -> bool {
+        self.network_state.download_paused(peer_index)
+    }
+
+    fn upload_cap_reached(&self) -> bool {
+        self.network_state.upload_cap_reached()
     }
 }
 
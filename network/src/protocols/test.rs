@@ -109,6 +109,7 @@ fn net_service_start(name: String) -> Node {
         whitelist_only: false,
         max_peers: 19,
         max_outbound_peers: 5,
+        block_relay_only_peers: 0,
         path: tempdir()
             .expect("create tempdir failed")
             .path()
@@ -120,6 +121,13 @@ fn net_service_start(name: String) -> Node {
         upnp: false,
         bootnode_mode: true,
         max_send_buffer: None,
+        max_upload_rate: None,
+        max_download_rate: None,
+        max_peer_upload_rate: None,
+        max_peer_download_rate: None,
+        max_upload_per_day: None,
+        proxy: None,
+        capture: None,
     };
 
     let network_state =
@@ -329,6 +337,38 @@ fn test_identify_behavior() {
     );
 }
 
+#[test]
+fn test_block_relay_only_without_discovery() {
+    let node1 = net_service_start("/test/1".to_string());
+    let node2 = net_service_start("/test/1".to_string());
+
+    node1
+        .network_state
+        .with_peer_registry_mut(|reg| reg.add_block_relay_only_dial(&node2.listen_addr));
+    node1.dial(&node2, DialProtocol::Single(IDENTIFY_PROTOCOL_ID.into()));
+
+    wait_connect_state(&node1, 1);
+    wait_connect_state(&node2, 1);
+
+    let sessions = node1.connected_sessions();
+    if !wait_until(10, || node1.connected_protocols(sessions[0]).len() == 2) {
+        panic!("identify can't open other protocols")
+    }
+    // Give the discovery protocol the time to be opened if it ever would be
+    thread::sleep(Duration::from_secs(2));
+
+    let mut protocols = node1.connected_protocols(sessions[0]);
+    protocols.sort();
+
+    assert_eq!(
+        protocols,
+        vec![PING_PROTOCOL_ID.into(), IDENTIFY_PROTOCOL_ID.into()]
+    );
+    assert!(node1
+        .network_state
+        .with_peer_registry(|reg| reg.get_peer(sessions[0]).unwrap().is_block_relay_only));
+}
+
 #[test]
 fn test_feeler_behavior() {
     let node1 = net_service_start("/test/1".to_string());
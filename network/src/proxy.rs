@@ -0,0 +1,192 @@
+//! Route the outbound connections through a SOCKS5 proxy.
+//!
+//! The p2p service only dials TCP addresses directly, so every proxied dial is
+//! bridged by a one-shot local listener: the service dials the listener, the
+//! bridge connects to the target through the proxy and pipes the bytes between
+//! both sides. The bridge is started once the dial is sent, a bridge dropped
+//! before closes its listener.
+use crate::errors::Error;
+use crate::peer_store::types::MultiaddrExt;
+use ckb_logger::debug;
+use p2p::multiaddr::Multiaddr;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SOCKS5_VERSION: u8 = 0x05;
+const NO_AUTHENTICATION: u8 = 0x00;
+const CONNECT_COMMAND: u8 = 0x01;
+const ADDRESS_TYPE_IPV4: u8 = 0x01;
+const ADDRESS_TYPE_DOMAIN: u8 = 0x03;
+const ADDRESS_TYPE_IPV6: u8 = 0x04;
+const REPLY_SUCCEEDED: u8 = 0x00;
+
+const PROXY_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// The p2p service dials the bridge right after it is created
+const BRIDGE_ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+const BRIDGE_ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Debug)]
+pub struct Socks5Proxy {
+    proxy_addr: SocketAddr,
+}
+
+impl Socks5Proxy {
+    pub fn new(proxy_addr: SocketAddr) -> Self {
+        Socks5Proxy { proxy_addr }
+    }
+
+    /// Binds a bridge to the target, its local address is dialed instead
+    pub fn bridge(&self, target: &Multiaddr) -> Result<Bridge, Error> {
+        let target = target.extract_ip_addr()?;
+        let target = SocketAddr::new(target.ip, target.port);
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        Ok(Bridge {
+            listener,
+            local_addr,
+            proxy_addr: self.proxy_addr,
+            target,
+        })
+    }
+}
+
+/// A bound bridge to a target through the proxy
+pub struct Bridge {
+    listener: TcpListener,
+    local_addr: SocketAddr,
+    proxy_addr: SocketAddr,
+    target: SocketAddr,
+}
+
+impl Bridge {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Waits for the dial to the local address and pipes it to the target
+    pub fn start(self) -> Result<(), Error> {
+        let Bridge {
+            listener,
+            proxy_addr,
+            target,
+            ..
+        } = self;
+        thread::Builder::new()
+            .name("Socks5Bridge".to_string())
+            .spawn(move || {
+                if let Err(err) = run_bridge(&listener, proxy_addr, target) {
+                    debug!("socks5 bridge to {} failed: {}", target, err);
+                }
+            })?;
+        Ok(())
+    }
+}
+
+fn run_bridge(
+    listener: &TcpListener,
+    proxy_addr: SocketAddr,
+    target: SocketAddr,
+) -> io::Result<()> {
+    let started_at = Instant::now();
+    let local = loop {
+        match listener.accept() {
+            Ok((stream, _)) => break stream,
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if started_at.elapsed() > BRIDGE_ACCEPT_TIMEOUT {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                thread::sleep(BRIDGE_ACCEPT_INTERVAL);
+            }
+            Err(err) => return Err(err),
+        }
+    };
+    local.set_nonblocking(false)?;
+    let remote = match connect(proxy_addr, target) {
+        Ok(remote) => remote,
+        Err(err) => {
+            let _ = local.shutdown(Shutdown::Both);
+            return Err(err);
+        }
+    };
+    pipe(local, remote)
+}
+
+/// Connects to the target through the SOCKS5 proxy
+pub fn connect(proxy_addr: SocketAddr, target: SocketAddr) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect_timeout(&proxy_addr, PROXY_CONNECT_TIMEOUT)?;
+    stream.set_read_timeout(Some(PROXY_CONNECT_TIMEOUT))?;
+    stream.set_write_timeout(Some(PROXY_CONNECT_TIMEOUT))?;
+
+    stream.write_all(&[SOCKS5_VERSION, 1, NO_AUTHENTICATION])?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    if reply != [SOCKS5_VERSION, NO_AUTHENTICATION] {
+        return Err(invalid_data("the proxy requires an authentication"));
+    }
+
+    let mut request = vec![SOCKS5_VERSION, CONNECT_COMMAND, 0x00];
+    match target.ip() {
+        IpAddr::V4(ip) => {
+            request.push(ADDRESS_TYPE_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            request.push(ADDRESS_TYPE_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != SOCKS5_VERSION {
+        return Err(invalid_data("invalid socks version"));
+    }
+    if reply[1] != REPLY_SUCCEEDED {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("the proxy replies {}", reply[1]),
+        ));
+    }
+    // skip the bound address and port
+    let address_len = match reply[3] {
+        ADDRESS_TYPE_IPV4 => 4,
+        ADDRESS_TYPE_IPV6 => 16,
+        ADDRESS_TYPE_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => return Err(invalid_data("invalid address type")),
+    };
+    let mut bound = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound)?;
+
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    Ok(stream)
+}
+
+fn pipe(local: TcpStream, remote: TcpStream) -> io::Result<()> {
+    let mut local_reader = local.try_clone()?;
+    let mut remote_writer = remote.try_clone()?;
+    let upstream = thread::Builder::new()
+        .name("Socks5BridgeUp".to_string())
+        .spawn(move || {
+            let _ = io::copy(&mut local_reader, &mut remote_writer);
+            let _ = remote_writer.shutdown(Shutdown::Write);
+        })?;
+    let (mut remote_reader, mut local_writer) = (remote, local);
+    let _ = io::copy(&mut remote_reader, &mut local_writer);
+    let _ = local_writer.shutdown(Shutdown::Write);
+    let _ = upstream.join();
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
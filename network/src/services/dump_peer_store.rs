@@ -1,3 +1,4 @@
+use crate::anchors::{dump_anchors, select_anchors};
 use crate::NetworkState;
 use ckb_logger::{debug, warn};
 use futures::{Async, Future, Stream};
@@ -5,7 +6,7 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::timer::Interval;
 
-const DEFAULT_DUMP_INTERVAL: Duration = Duration::from_secs(3600); // 1 hour
+const DEFAULT_DUMP_INTERVAL: Duration = Duration::from_secs(300); // 5 minutes
 
 pub struct DumpPeerStoreService {
     network_state: Arc<NetworkState>,
@@ -21,21 +22,36 @@ impl DumpPeerStoreService {
     }
 
     fn dump_peer_store(&self) {
-        let path = self.network_state.config.peer_store_path().clone();
         self.network_state.with_peer_store_mut(|peer_store| {
-            if let Err(err) = peer_store.dump_to_dir(&path) {
-                warn!("Dump peer store error, path: {:?} error: {}", path, err);
+            if let Err(err) = peer_store.dump() {
+                warn!("Dump peer store error: {}", err);
             } else {
-                debug!("Dump peer store to {:?}", path);
+                debug!("Dump peer store");
             }
         });
     }
+
+    fn dump_anchors(&self) {
+        let anchors = self
+            .network_state
+            .with_peer_registry(|registry| select_anchors(registry.peers().values()));
+        // keep the last dumped anchors when the peers are already disconnected
+        if anchors.is_empty() {
+            return;
+        }
+        let path = self.network_state.config.anchors_path();
+        if let Err(err) = dump_anchors(&path, &anchors) {
+            warn!("Dump anchors error, path: {:?} error: {}", path, err);
+        }
+    }
 }
 
 impl Drop for DumpPeerStoreService {
     fn drop(&mut self) {
         debug!("dump peer store before exit");
         self.dump_peer_store();
+        self.dump_anchors();
+        self.network_state.flush_capture();
     }
 }
 
@@ -48,6 +64,8 @@ impl Future for DumpPeerStoreService {
             match self.interval.poll() {
                 Ok(Async::Ready(Some(_tick))) => {
                     self.dump_peer_store();
+                    self.dump_anchors();
+                    self.network_state.flush_capture();
                 }
                 Ok(Async::Ready(None)) => {
                     warn!("ckb dump peer store service stopped");
@@ -10,6 +10,13 @@ use tokio::timer::Interval;
 
 const FEELER_CONNECTION_COUNT: usize = 5;
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DialKind {
+    FullRelay,
+    BlockRelayOnly,
+    Feeler,
+}
+
 pub struct OutboundPeerService {
     network_state: Arc<NetworkState>,
     p2p_control: ServiceControl,
@@ -33,7 +40,8 @@ impl OutboundPeerService {
         }
     }
 
-    fn dial_peers(&mut self, is_feeler: bool, count: usize) {
+    fn dial_peers(&mut self, kind: DialKind, count: usize) {
+        let is_feeler = kind == DialKind::Feeler;
         let now_ms = unix_time_as_millis();
         let attempt_peers = self.network_state.with_peer_store_mut(|peer_store| {
             // take extra 5 peers
@@ -55,20 +63,27 @@ impl OutboundPeerService {
             paddrs
         });
         trace!(
-            "count={}, attempt_peers: {:?} is_feeler: {}",
+            "count={}, attempt_peers: {:?} kind: {:?}",
             count,
             attempt_peers,
-            is_feeler
+            kind
         );
 
         for paddr in attempt_peers {
             let AddrInfo { peer_id, addr, .. } = paddr;
-            if is_feeler {
-                self.network_state
-                    .dial_feeler(&self.p2p_control, &peer_id, addr);
-            } else {
-                self.network_state
-                    .dial_identify(&self.p2p_control, &peer_id, addr);
+            match kind {
+                DialKind::Feeler => {
+                    self.network_state
+                        .dial_feeler(&self.p2p_control, &peer_id, addr);
+                }
+                DialKind::BlockRelayOnly => {
+                    self.network_state
+                        .dial_block_relay_only(&self.p2p_control, &peer_id, addr);
+                }
+                DialKind::FullRelay => {
+                    self.network_state
+                        .dial_identify(&self.p2p_control, &peer_id, addr);
+                }
             }
         }
     }
@@ -112,13 +127,27 @@ impl Future for OutboundPeerService {
                             .max_outbound
                             .saturating_sub(status.non_whitelist_outbound)
                             as usize;
+                        let pending_block_relay_only = self
+                            .network_state
+                            .with_peer_registry(|reg| reg.block_relay_only_dials_count())
+                            as u32;
+                        let new_block_relay_only = self
+                            .network_state
+                            .config
+                            .block_relay_only_peers
+                            .saturating_sub(
+                                status.block_relay_only_outbound + pending_block_relay_only,
+                            ) as usize;
                         if !self.network_state.config.whitelist_only {
                             if new_outbound > 0 {
                                 // dial peers
-                                self.dial_peers(false, new_outbound);
+                                self.dial_peers(DialKind::FullRelay, new_outbound);
                             } else {
                                 // feeler peers
-                                self.dial_peers(true, FEELER_CONNECTION_COUNT);
+                                self.dial_peers(DialKind::Feeler, FEELER_CONNECTION_COUNT);
+                            }
+                            if new_block_relay_only > 0 {
+                                self.dial_peers(DialKind::BlockRelayOnly, new_block_relay_only);
                             }
                         }
                         // keep whitelist peer on connected
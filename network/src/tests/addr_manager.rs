@@ -1,12 +1,13 @@
 use crate::{
     multiaddr::Multiaddr,
     peer_store::{
-        addr_manager::AddrManager,
-        types::{AddrInfo, MultiaddrExt},
+        addr_manager::{AddrManager, BUCKET_SIZE, NEW_BUCKETS_PER_SOURCE_GROUP},
+        types::{AddrInfo, Bucket, MultiaddrExt},
     },
     PeerId,
 };
 use proptest::prelude::*;
+use std::collections::HashSet;
 use std::net::Ipv4Addr;
 
 const MAX_FETCHED_ADDRS: usize = 1000;
@@ -45,3 +46,78 @@ proptest! {
         assert_eq!(addrs.len(), count);
     }
 }
+
+fn new_global_addr(id: usize) -> AddrInfo {
+    // every addr is in its own network group
+    let ip = Ipv4Addr::new(20 + (id / 256) as u8, (id % 256) as u8, 0, 1);
+    let addr: Multiaddr = format!("/ip4/{}/tcp/42", ip).parse().unwrap();
+    AddrInfo::new(
+        PeerId::random(),
+        addr.extract_ip_addr().unwrap(),
+        addr,
+        0,
+        0,
+    )
+}
+
+#[test]
+fn test_new_buckets_per_source_group() {
+    let max_addrs = NEW_BUCKETS_PER_SOURCE_GROUP as usize * BUCKET_SIZE;
+    let source: Multiaddr = "/ip4/1.1.1.1/tcp/42".parse().unwrap();
+    let mut addr_manager = AddrManager::default();
+    let addrs: Vec<_> = (0..max_addrs * 2).map(new_global_addr).collect();
+    for addr in &addrs {
+        addr_manager.add_with_source(addr.clone(), Some(&source));
+    }
+    // one source network group can't fill the new table
+    assert!(addr_manager.count() > 0);
+    assert!(addr_manager.count() <= max_addrs);
+    let buckets: HashSet<_> = addr_manager
+        .addrs_iter()
+        .map(|addr| addr.bucket.expect("bucket"))
+        .collect();
+    assert!(buckets.len() <= NEW_BUCKETS_PER_SOURCE_GROUP as usize);
+    assert!(buckets.iter().all(|bucket| !bucket.is_tried()));
+
+    // the good addrs are never evicted from the new table
+    let dropped = addrs
+        .iter()
+        .find(|addr| addr_manager.get(&addr.ip_port()).is_none())
+        .expect("dropped addr")
+        .clone();
+    addr_manager.add_with_source(dropped.clone(), Some(&source));
+    assert!(addr_manager.get(&dropped.ip_port()).is_none());
+
+    // but the terrible ones are
+    let tried_ms = faketime::unix_time_as_millis() - 61_000;
+    for addr in &addrs {
+        if let Some(paddr) = addr_manager.get_mut(&addr.ip_port()) {
+            for _ in 0..3 {
+                paddr.mark_tried(tried_ms);
+            }
+        }
+    }
+    addr_manager.add_with_source(dropped.clone(), Some(&source));
+    assert!(addr_manager.get(&dropped.ip_port()).is_some());
+}
+
+#[test]
+fn test_connected_addr_in_tried_bucket() {
+    let mut addr_manager = AddrManager::default();
+    let mut addr = new_global_addr(1);
+    addr_manager.add(addr.clone());
+    match addr_manager.get(&addr.ip_port()).unwrap().bucket {
+        Some(Bucket::New(_)) => (),
+        bucket => panic!("unexpected bucket {:?}", bucket),
+    }
+
+    addr.last_connected_at_ms = faketime::unix_time_as_millis();
+    addr_manager.add(addr.clone());
+    match addr_manager.get(&addr.ip_port()).unwrap().bucket {
+        Some(Bucket::Tried(_)) => (),
+        bucket => panic!("unexpected bucket {:?}", bucket),
+    }
+    assert_eq!(addr_manager.count(), 1);
+    // the tried addrs are fetched too
+    assert_eq!(addr_manager.fetch_random(2, |_| true).len(), 1);
+}
@@ -0,0 +1,86 @@
+use crate::{
+    anchors::{dump_anchors, load_anchors, select_anchors, Anchor, MAX_ANCHORS},
+    multiaddr::Multiaddr,
+    Peer, PeerId, SessionType,
+};
+use std::time::{Duration, Instant};
+
+fn new_peer(session_id: usize, session_type: SessionType, ip: &str) -> Peer {
+    let addr = format!("/ip4/{}/tcp/42", ip).parse::<Multiaddr>().unwrap();
+    Peer::new(
+        session_id.into(),
+        session_type,
+        PeerId::random(),
+        addr,
+        false,
+    )
+}
+
+#[test]
+fn test_select_anchors() {
+    let now = Instant::now();
+    let mut inbound = new_peer(1, SessionType::Inbound, "1.1.1.1");
+    inbound.last_block_time = Some(now);
+    let mut old_outbound = new_peer(2, SessionType::Outbound, "2.2.2.2");
+    old_outbound.connected_time = now - Duration::from_secs(3600);
+    let young_outbound = new_peer(3, SessionType::Outbound, "3.3.3.3");
+    let mut block_outbound = new_peer(4, SessionType::Outbound, "4.4.4.4");
+    block_outbound.last_block_time = Some(now);
+    let mut feeler = new_peer(5, SessionType::Outbound, "5.5.5.5");
+    feeler.is_feeler = true;
+    feeler.last_block_time = Some(now);
+    let mut block_relay_only = new_peer(6, SessionType::Outbound, "6.6.6.6");
+    block_relay_only.is_block_relay_only = true;
+
+    let peers = vec![
+        inbound,
+        old_outbound,
+        young_outbound,
+        block_outbound,
+        feeler,
+        block_relay_only,
+    ];
+    let anchors = select_anchors(peers.iter());
+    assert_eq!(anchors.len(), MAX_ANCHORS);
+    assert_eq!(
+        anchors[0],
+        Anchor {
+            peer_id: peers[5].peer_id.clone(),
+            addr: peers[5].connected_addr.clone(),
+            block_relay_only: true,
+        }
+    );
+    assert_eq!(
+        anchors[1],
+        Anchor {
+            peer_id: peers[3].peer_id.clone(),
+            addr: peers[3].connected_addr.clone(),
+            block_relay_only: false,
+        }
+    );
+}
+
+#[test]
+fn test_dump_and_load_anchors() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("network").join("anchors.json");
+    assert!(load_anchors(&path).unwrap().is_empty());
+
+    let anchors = vec![
+        Anchor {
+            peer_id: PeerId::random(),
+            addr: "/ip4/1.1.1.1/tcp/42".parse::<Multiaddr>().unwrap(),
+            block_relay_only: false,
+        },
+        Anchor {
+            peer_id: PeerId::random(),
+            addr: "/ip4/2.2.2.2/tcp/42".parse::<Multiaddr>().unwrap(),
+            block_relay_only: true,
+        },
+    ];
+    dump_anchors(&path, &anchors).unwrap();
+    assert_eq!(load_anchors(&path).unwrap(), anchors);
+    // the anchors are only used once
+    assert!(!path.exists());
+    assert!(load_anchors(&path).unwrap().is_empty());
+}
@@ -0,0 +1,174 @@
+use crate::{
+    capture::{CaptureReader, CaptureWriter, CapturedMessage, Direction, CAPTURE_FILE_NAME},
+    multiaddr::Multiaddr,
+    CaptureConfig, NetworkConfig, NetworkState, PeerId, SessionType, TargetSession,
+    MAX_FRAME_LENGTH,
+};
+use p2p::bytes::Bytes;
+use std::io;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn message(timestamp: u64, direction: Direction, data: &[u8]) -> CapturedMessage {
+    CapturedMessage {
+        timestamp,
+        direction,
+        protocol_id: 100.into(),
+        session_ids: vec![1.into()],
+        data: Bytes::from(data),
+    }
+}
+
+fn read_capture<P: AsRef<Path>>(path: P) -> Vec<CapturedMessage> {
+    CaptureReader::open(path)
+        .expect("open capture")
+        .collect::<Result<Vec<_>, _>>()
+        .expect("read capture")
+}
+
+#[test]
+fn test_capture_roundtrip() {
+    let dir = tempdir().expect("tempdir");
+    let messages = vec![
+        message(1, Direction::Inbound, &[1, 2, 3]),
+        message(2, Direction::Outbound, &[]),
+        CapturedMessage {
+            session_ids: vec![1.into(), 2.into(), 3.into()],
+            ..message(3, Direction::Outbound, &[4, 5])
+        },
+        CapturedMessage {
+            session_ids: Vec::new(),
+            ..message(4, Direction::Outbound, &[6])
+        },
+    ];
+    {
+        let mut writer = CaptureWriter::open(dir.path(), &CaptureConfig::default()).unwrap();
+        for message in &messages {
+            writer.write(message).unwrap();
+        }
+        writer.flush().unwrap();
+    }
+    assert_eq!(read_capture(dir.path().join(CAPTURE_FILE_NAME)), messages);
+}
+
+#[test]
+fn test_capture_truncated_record() {
+    let mut data = Vec::new();
+    message(1, Direction::Inbound, &[1, 2, 3])
+        .write_to(&mut data)
+        .unwrap();
+    data.pop();
+    let mut reader = CaptureReader::new(&data[..]);
+    assert!(reader.next().expect("record").is_err());
+}
+
+#[test]
+fn test_capture_oversized_record() {
+    let mut data = Vec::new();
+    message(1, Direction::Inbound, &[1, 2, 3])
+        .write_to(&mut data)
+        .unwrap();
+    // the data length is the 4 bytes before the data
+    let offset = data.len() - 3 - 4;
+    data[offset..offset + 4].copy_from_slice(&(MAX_FRAME_LENGTH as u32 + 1).to_le_bytes());
+    let mut reader = CaptureReader::new(&data[..]);
+    assert_eq!(
+        reader.next().expect("record").unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+
+    let dir = tempdir().expect("tempdir");
+    let mut writer = CaptureWriter::open(dir.path(), &CaptureConfig::default()).unwrap();
+    let data = vec![0; MAX_FRAME_LENGTH + 1];
+    assert!(writer
+        .write(&message(1, Direction::Inbound, &data))
+        .is_err());
+}
+
+#[test]
+fn test_capture_rotation() {
+    let dir = tempdir().expect("tempdir");
+    let config = CaptureConfig {
+        // fits one message
+        max_file_size: 100,
+        max_files: 2,
+    };
+    let messages: Vec<_> = (0..5)
+        .map(|i| message(i, Direction::Inbound, &[0; 50]))
+        .collect();
+    {
+        let mut writer = CaptureWriter::open(dir.path(), &config).unwrap();
+        for message in &messages {
+            writer.write(message).unwrap();
+        }
+        writer.flush().unwrap();
+    }
+    assert_eq!(
+        read_capture(dir.path().join(CAPTURE_FILE_NAME)),
+        vec![messages[4].clone()]
+    );
+    assert_eq!(
+        read_capture(dir.path().join(format!("{}.1", CAPTURE_FILE_NAME))),
+        vec![messages[3].clone()]
+    );
+    assert!(!dir.path().join(format!("{}.2", CAPTURE_FILE_NAME)).exists());
+}
+
+#[test]
+fn test_network_state_capture() {
+    let dir = tempdir().expect("tempdir");
+    let config = NetworkConfig {
+        max_peers: 19,
+        max_outbound_peers: 5,
+        path: dir.path().to_path_buf(),
+        capture: Some(CaptureConfig::default()),
+        ..Default::default()
+    };
+    let network_state = NetworkState::from_config(config).expect("Init network state failed");
+    let proto_id = 100.into();
+    let session_ids = vec![1.into(), 2.into()];
+    {
+        let mut peer_store = network_state.peer_store.lock();
+        let mut peer_registry = network_state.peer_registry.write();
+        for (port, session_id) in session_ids.iter().enumerate() {
+            let addr = format!("/ip4/127.0.0.1/tcp/{}", port + 42)
+                .parse::<Multiaddr>()
+                .unwrap();
+            peer_registry
+                .accept_peer(
+                    PeerId::random(),
+                    addr,
+                    *session_id,
+                    SessionType::Inbound,
+                    &mut peer_store,
+                )
+                .expect("accept peer");
+        }
+    }
+    let session_id = session_ids[0];
+
+    // A broadcast message is recorded once
+    let raw_data = Bytes::from(vec![1; 4096]);
+    network_state.compress_to_send(
+        proto_id,
+        &TargetSession::Multi(session_ids.clone()),
+        raw_data.clone(),
+    );
+    network_state.record_received(proto_id, session_id, 10, &[2; 20]);
+    network_state.record_sent_by_handler(0.into(), &[3; 8]);
+    network_state.flush_capture();
+
+    let messages = read_capture(network_state.config.capture_path().join(CAPTURE_FILE_NAME));
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[0].direction, Direction::Outbound);
+    assert_eq!(messages[0].session_ids, session_ids);
+    assert_eq!(messages[0].data, raw_data);
+    assert_eq!(messages[1].direction, Direction::Inbound);
+    assert_eq!(messages[1].session_ids, vec![session_id]);
+    assert_eq!(messages[1].protocol_id, proto_id);
+    assert_eq!(&messages[1].data[..], &[2; 20][..]);
+    // The session of a core protocol message sent by its handler is unknown
+    assert_eq!(messages[2].direction, Direction::Outbound);
+    assert!(messages[2].session_ids.is_empty());
+    assert_eq!(messages[2].protocol_id, 0.into());
+}
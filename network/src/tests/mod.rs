@@ -1,4 +1,10 @@
 mod addr_manager;
+mod anchors;
+mod capture;
+mod network_group;
 mod peer_registry;
 mod peer_store;
 mod peer_store_db;
+mod proxy;
+mod throttle;
+mod traffic;
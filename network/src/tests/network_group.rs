@@ -0,0 +1,60 @@
+use crate::multiaddr::Multiaddr;
+use crate::network_group::{is_global, Group, NetworkGroup};
+use std::net::IpAddr;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn test_is_global() {
+    for addr in &[
+        "0.0.0.0",
+        "10.0.0.1",
+        "100.64.0.1",
+        "127.0.0.1",
+        "169.254.0.1",
+        "172.16.0.1",
+        "192.0.0.1",
+        "192.0.2.1",
+        "192.168.0.1",
+        "198.18.0.1",
+        "224.0.0.1",
+        "240.0.0.1",
+        "255.255.255.255",
+        "::",
+        "::1",
+        "::ffff:192.168.0.1",
+        "fc00::1",
+        "fe80::1",
+        "ff02::1",
+        "2001:db8::1",
+    ] {
+        assert!(!is_global(&ip(addr)), "{} is not global", addr);
+    }
+    for addr in &[
+        "1.1.1.1",
+        "8.8.8.8",
+        "100.128.0.1",
+        "::ffff:8.8.8.8",
+        "2606:4700::1",
+    ] {
+        assert!(is_global(&ip(addr)), "{} is global", addr);
+    }
+}
+
+#[test]
+fn test_network_group() {
+    let group = |addr: &str| addr.parse::<Multiaddr>().unwrap().network_group();
+    assert_eq!(group("/ip4/127.0.0.1/tcp/42"), Group::LocalNetwork);
+    assert_eq!(group("/ip4/192.168.0.1/tcp/42"), Group::LocalNetwork);
+    assert_eq!(group("/ip4/8.8.4.4/tcp/42"), Group::IP4([8, 8]));
+    assert_eq!(
+        group("/ip6/::ffff:8.8.4.4/tcp/42"),
+        group("/ip4/8.8.8.8/tcp/42")
+    );
+    assert_eq!(
+        group("/ip6/2606:4700:10::1/tcp/42"),
+        Group::IP6([0x26, 0x06, 0x47, 0x00])
+    );
+}
@@ -1,9 +1,12 @@
 use crate::{
     errors::{Error, PeerError},
     multiaddr::Multiaddr,
-    peer_registry::{PeerRegistry, EVICTION_PROTECT_PEERS},
+    peer_registry::{
+        PeerRegistry, EVICTION_PROTECT_BLOCK_PEERS, EVICTION_PROTECT_PEERS,
+        EVICTION_PROTECT_TRANSACTION_PEERS,
+    },
     peer_store::PeerStore,
-    PeerId, SessionType,
+    Peer, PeerId, SessionType,
 };
 use std::time::{Duration, Instant};
 
@@ -113,107 +116,180 @@ fn test_accept_inbound_peer_until_full() {
     );
 }
 
+#[test]
+fn test_accept_block_relay_only_peer() {
+    let mut peer_store = PeerStore::default();
+    let addr = "/ip4/127.0.0.1/tcp/42".parse::<Multiaddr>().unwrap();
+    let mut peers = PeerRegistry::new(3, 1, false, vec![]);
+    peers
+        .accept_peer(
+            PeerId::random(),
+            addr.clone(),
+            1.into(),
+            SessionType::Outbound,
+            &mut peer_store,
+        )
+        .expect("accept");
+    let err = peers
+        .accept_peer(
+            PeerId::random(),
+            addr.clone(),
+            2.into(),
+            SessionType::Outbound,
+            &mut peer_store,
+        )
+        .unwrap_err();
+    assert_eq!(
+        format!("{}", err),
+        format!("{}", Error::Peer(PeerError::ReachMaxOutboundLimit)),
+    );
+
+    // the block-relay-only peers are not limited by the max outbound
+    let block_relay_only_peer = PeerId::random();
+    let block_relay_only_addr = "/ip4/127.0.0.2/tcp/42".parse::<Multiaddr>().unwrap();
+    assert!(peers.add_block_relay_only_dial(&block_relay_only_addr));
+    assert_eq!(peers.block_relay_only_dials_count(), 1);
+
+    // the same peer dialed by another address is a full-relay peer
+    let err = peers
+        .accept_peer(
+            block_relay_only_peer.clone(),
+            addr.clone(),
+            3.into(),
+            SessionType::Outbound,
+            &mut peer_store,
+        )
+        .unwrap_err();
+    assert_eq!(
+        format!("{}", err),
+        format!("{}", Error::Peer(PeerError::ReachMaxOutboundLimit)),
+    );
+    assert_eq!(peers.block_relay_only_dials_count(), 1);
+
+    peers
+        .accept_peer(
+            block_relay_only_peer,
+            block_relay_only_addr,
+            4.into(),
+            SessionType::Outbound,
+            &mut peer_store,
+        )
+        .expect("accept");
+    assert_eq!(peers.block_relay_only_dials_count(), 0);
+    assert!(peers.get_peer(4.into()).unwrap().is_block_relay_only);
+    assert!(!peers.get_peer(1.into()).unwrap().is_block_relay_only);
+
+    let status = peers.connection_status();
+    assert_eq!(status.non_whitelist_outbound, 1);
+    assert_eq!(status.block_relay_only_outbound, 1);
+}
+
 #[test]
 fn test_accept_inbound_peer_eviction() {
     // eviction inbound peer
     // We build an unprotected evict targets set
     // PeerRegistry should
-    // 1. evict from largest network groups
-    // 2. never evict whitelist peer
+    // 1. evict the youngest peer from largest network groups
+    // 2. never evict the protected peers or the whitelist peer
     let mut peer_store = PeerStore::default();
     let whitelist_peer = PeerId::random();
-    let evict_target = PeerId::random();
-    let mut evict_targets = vec![evict_target.to_owned()];
-    let addr1 = "/ip4/127.0.0.1/tcp/42".parse::<Multiaddr>().unwrap();
-    let addr2 = "/ip4/192.168.0.1/tcp/42".parse::<Multiaddr>().unwrap();
-    // prepare protected peers
-    let longest_connection_time_peers_count = 5;
-    let protected_peers_count = 2 * (EVICTION_PROTECT_PEERS + longest_connection_time_peers_count);
-    let mut peers_registry = PeerRegistry::new(
-        (protected_peers_count) as u32,
-        3,
-        false,
-        vec![whitelist_peer.clone()],
-    );
+    let large_group_addr = "/ip4/8.8.0.1/tcp/42".parse::<Multiaddr>().unwrap();
+    let new_peer_addr = "/ip4/9.9.0.1/tcp/42".parse::<Multiaddr>().unwrap();
+    let longest_connection_time_peers_count = 10;
+    let evict_targets_count = 6;
+    let other_groups_count = 4;
+    let large_group_count = EVICTION_PROTECT_PEERS
+        + EVICTION_PROTECT_BLOCK_PEERS
+        + EVICTION_PROTECT_TRANSACTION_PEERS
+        + longest_connection_time_peers_count
+        + evict_targets_count;
+    let max_inbound = large_group_count + other_groups_count;
+    let mut peers_registry =
+        PeerRegistry::new(max_inbound as u32, 3, false, vec![whitelist_peer.clone()]);
     // prepare all peers
-    for session_id in 0..protected_peers_count {
-        assert!(peers_registry
+    let mut peers = Vec::new();
+    for session_id in 0..large_group_count {
+        let peer_id = PeerId::random();
+        peers_registry
             .accept_peer(
-                PeerId::random(),
-                addr2.clone(),
+                peer_id.clone(),
+                large_group_addr.clone(),
                 session_id.into(),
                 SessionType::Inbound,
                 &mut peer_store,
             )
-            .is_ok());
+            .expect("accept");
+        peers.push(peer_id);
     }
-    let peers: Vec<_> = {
+    // the peers in other network groups
+    for i in 0..other_groups_count {
+        let addr = format!("/ip4/{}.1.0.1/tcp/42", i + 1)
+            .parse::<Multiaddr>()
+            .unwrap();
         peers_registry
-            .peers()
-            .values()
-            .map(|peer| peer.peer_id.clone())
-            .collect()
-    };
-
-    let mut peers_iter = peers.iter();
-    // lowest ping peers
-    for _ in 0..EVICTION_PROTECT_PEERS {
-        let peer_id = peers_iter.next().unwrap();
-        let session_id = peers_registry
-            .get_key_by_peer_id(&peer_id)
-            .expect("get_key_by_peer_id failed");
-        if let Some(peer) = peers_registry.get_peer_mut(session_id) {
-            peer.ping = Some(Duration::from_secs(0));
-        };
+            .accept_peer(
+                PeerId::random(),
+                addr,
+                (large_group_count + i).into(),
+                SessionType::Inbound,
+                &mut peer_store,
+            )
+            .expect("accept");
     }
 
-    // to prevent time error, we set now to 10ago.
-    let now = Instant::now() - Duration::from_secs(10);
-    // peers which most recently sent messages
-    for _ in 0..EVICTION_PROTECT_PEERS {
-        let peer_id = peers_iter.next().unwrap();
-        let session_id = peers_registry
-            .get_key_by_peer_id(&peer_id)
-            .expect("get_key_by_peer_id failed");
-        if let Some(peer) = peers_registry.get_peer_mut(session_id) {
-            peer.last_message_time = Some(now + Duration::from_secs(10));
-        };
-    }
-    // protect half peers which have the longest connection time
-    for _ in 0..longest_connection_time_peers_count {
-        let peer_id = peers_iter.next().unwrap();
-        let session_id = peers_registry
-            .get_key_by_peer_id(&peer_id)
-            .expect("get_key_by_peer_id failed");
-        if let Some(peer) = peers_registry.get_peer_mut(session_id) {
-            peer.connected_time = now - Duration::from_secs(10);
-        };
-    }
-    // thoses peers will not be protect, we add them to evict_targets
-    for _ in 0..longest_connection_time_peers_count {
-        let peer_id = peers_iter.next().unwrap();
-        evict_targets.push(peer_id.to_owned());
-        let session_id = peers_registry
-            .get_key_by_peer_id(&peer_id)
-            .expect("get_key_by_peer_id failed");
-        if let Some(peer) = peers_registry.get_peer_mut(session_id) {
-            peer.connected_time = now - Duration::from_secs(10);
-        };
-    }
+    // to prevent time error, we set now to 100 seconds ago.
+    let now = Instant::now() - Duration::from_secs(100);
+    let mut protected_peers = Vec::new();
+    let mut peers_iter = peers.into_iter();
+    let mut update_peers = |count: usize, update: &dyn Fn(&mut Peer)| {
+        (0..count)
+            .map(|_| {
+                let peer_id = peers_iter.next().unwrap();
+                let session_id = peers_registry
+                    .get_key_by_peer_id(&peer_id)
+                    .expect("get_key_by_peer_id failed");
+                update(peers_registry.get_peer_mut(session_id).unwrap());
+                peer_id
+            })
+            .collect::<Vec<_>>()
+    };
+    // lowest ping peers
+    protected_peers.extend(update_peers(EVICTION_PROTECT_PEERS, &|peer| {
+        peer.ping = Some(Duration::from_secs(0))
+    }));
+    // peers which most recently delivered new blocks
+    protected_peers.extend(update_peers(EVICTION_PROTECT_BLOCK_PEERS, &|peer| {
+        peer.last_block_time = Some(now + Duration::from_secs(10))
+    }));
+    // peers which most recently delivered new transactions
+    protected_peers.extend(update_peers(EVICTION_PROTECT_TRANSACTION_PEERS, &|peer| {
+        peer.last_transaction_time = Some(now + Duration::from_secs(10))
+    }));
+    // peers which have the longest connection time
+    protected_peers.extend(update_peers(longest_connection_time_peers_count, &|peer| {
+        peer.connected_time = now
+    }));
+    // thoses peers will not be protect, they are younger than the long connected peers
+    let evict_targets = update_peers(evict_targets_count, &|peer| {
+        peer.connected_time = now + Duration::from_secs(50)
+    });
 
     peers_registry
         .accept_peer(
             PeerId::random(),
-            addr1.clone(),
+            new_peer_addr,
             2000.into(),
             SessionType::Inbound,
             &mut peer_store,
         )
         .expect("accept");
-    let len_after_eviction = evict_targets
-        .iter()
-        .filter_map(|peer_id| peers_registry.get_key_by_peer_id(peer_id))
-        .count();
+    let count_peers = |peer_ids: &[PeerId]| {
+        peer_ids
+            .iter()
+            .filter_map(|peer_id| peers_registry.get_key_by_peer_id(peer_id))
+            .count()
+    };
     // should evict from one of evict_targets
-    assert_eq!(len_after_eviction, evict_targets.len() - 1);
+    assert_eq!(count_peers(&protected_peers), protected_peers.len());
+    assert_eq!(count_peers(&evict_targets), evict_targets.len() - 1);
 }
@@ -1,6 +1,8 @@
 use crate::{
     multiaddr::{self, Multiaddr},
-    peer_store::{types::MultiaddrExt, PeerStore, Status, ADDR_COUNT_LIMIT},
+    peer_store::{
+        addr_manager::BUCKET_SIZE, types::MultiaddrExt, PeerScoreConfig, PeerStore, Status,
+    },
     Behaviour, PeerId, SessionType,
 };
 
@@ -36,6 +38,114 @@ fn test_report() {
     assert!(peer_store.report(&peer_id, Behaviour::TestGood).is_ok());
 }
 
+#[test]
+fn test_report_ban() {
+    let mut peer_store: PeerStore = Default::default();
+    let peer_id = PeerId::random();
+    let addr: Multiaddr = "/ip4/127.0.0.1/tcp/42".parse().unwrap();
+    peer_store
+        .add_connected_peer(peer_id.clone(), addr.clone(), SessionType::Inbound)
+        .unwrap();
+    // 100 - 6 * 10 is not below the ban score 40
+    for _ in 0..6 {
+        assert!(peer_store
+            .report(&peer_id, Behaviour::TestBad)
+            .unwrap()
+            .is_ok());
+    }
+    assert!(!peer_store.is_addr_banned(&addr));
+    assert!(peer_store
+        .report(&peer_id, Behaviour::TestBad)
+        .unwrap()
+        .is_banned());
+    assert!(peer_store.is_addr_banned(&addr));
+}
+
+#[test]
+fn test_report_fatal_ban() {
+    let mut peer_store: PeerStore = Default::default();
+    let peer_id = PeerId::random();
+    let addr: Multiaddr = "/ip4/127.0.0.1/tcp/42".parse().unwrap();
+    peer_store
+        .add_connected_peer(peer_id.clone(), addr.clone(), SessionType::Inbound)
+        .unwrap();
+    // A peer with the max score is banned at once
+    for _ in 0..100 {
+        peer_store.report(&peer_id, Behaviour::UsefulBlock).unwrap();
+    }
+    assert!(peer_store
+        .report(&peer_id, Behaviour::InvalidCompactBlock)
+        .unwrap()
+        .is_banned());
+    assert!(peer_store.is_addr_banned(&addr));
+}
+
+#[test]
+fn test_report_outbound_score() {
+    let mut peer_store: PeerStore = Default::default();
+    let peer_id = PeerId::random();
+    let addr: Multiaddr = "/ip4/127.0.0.1/tcp/42".parse().unwrap();
+    peer_store
+        .add_connected_peer(peer_id.clone(), addr.clone(), SessionType::Outbound)
+        .unwrap();
+    peer_store.report(&peer_id, Behaviour::UsefulBlock).unwrap();
+    peer_store.report(&peer_id, Behaviour::Timeout).unwrap();
+    let key = addr.extract_ip_addr().unwrap();
+    assert_eq!(
+        peer_store.mut_addr_manager().get_mut(&key).unwrap().score,
+        82
+    );
+}
+
+#[test]
+fn test_reconnect_keep_score() {
+    let mut peer_store: PeerStore = Default::default();
+    let peer_id = PeerId::random();
+    let addr: Multiaddr = "/ip4/127.0.0.1/tcp/42".parse().unwrap();
+    let key = addr.extract_ip_addr().unwrap();
+    let now = faketime::unix_time_as_millis();
+    peer_store.add_addr(peer_id.clone(), addr.clone()).unwrap();
+    if let Some(paddr) = peer_store.mut_addr_manager().get_mut(&key) {
+        paddr.score = 70;
+        paddr.score_updated_at_ms = now - 1000;
+    }
+
+    // The first connection moves the address to the tried table
+    peer_store
+        .add_connected_peer(peer_id.clone(), addr.clone(), SessionType::Outbound)
+        .unwrap();
+    let paddr = peer_store.mut_addr_manager().get_mut(&key).unwrap();
+    assert!(paddr.bucket.unwrap().is_tried());
+    assert_eq!(paddr.score, 70);
+    assert_eq!(paddr.score_updated_at_ms, now - 1000);
+
+    // A later reconnection
+    paddr.last_connected_at_ms = now - 60_000;
+    peer_store
+        .add_connected_peer(peer_id, addr, SessionType::Outbound)
+        .unwrap();
+    let paddr = peer_store.mut_addr_manager().get_mut(&key).unwrap();
+    assert!(paddr.bucket.unwrap().is_tried());
+    assert!(paddr.last_connected_at_ms >= now);
+    assert_eq!(paddr.score, 70);
+    assert_eq!(paddr.score_updated_at_ms, now - 1000);
+}
+
+#[test]
+fn test_score_decay() {
+    let config = PeerScoreConfig::default();
+    let half_life = config.score_half_life_ms;
+    assert_eq!(config.decay(40, 0), 40);
+    assert_eq!(config.decay(40, half_life), 70);
+    assert_eq!(config.decay(160, 2 * half_life), 115);
+    assert_eq!(config.decay(100, half_life), 100);
+    assert_eq!(
+        config.update(config.max_score, 0, Behaviour::UsefulBlock),
+        config.max_score
+    );
+    assert_eq!(config.update(40, half_life, Behaviour::TestBad), 60);
+}
+
 #[test]
 fn test_update_status() {
     let mut peer_store: PeerStore = Default::default();
@@ -76,6 +186,42 @@ fn test_attempt_ban() {
     assert_eq!(peer_store.fetch_addrs_to_attempt(2).len(), 0);
 }
 
+#[test]
+fn test_eviction() {
+    let mut peer_store = PeerStore::default();
+    // the addrs in one network group go into one bucket of the new table
+    for port in 1..=(BUCKET_SIZE * 2) {
+        let addr: Multiaddr = format!("/ip4/225.0.0.1/tcp/{}", port).parse().unwrap();
+        peer_store.add_addr(PeerId::random(), addr).unwrap();
+    }
+    assert_eq!(peer_store.addr_manager().count(), BUCKET_SIZE);
+
+    // the full bucket only evicts a terrible addr
+    let new_addr: Multiaddr = "/ip4/225.0.0.1/tcp/10000".parse().unwrap();
+    let new_ip_port = new_addr.extract_ip_addr().unwrap();
+    peer_store
+        .add_addr(PeerId::random(), new_addr.clone())
+        .unwrap();
+    assert!(peer_store.addr_manager().get(&new_ip_port).is_none());
+
+    let evict_ip_port = peer_store
+        .addr_manager()
+        .addrs_iter()
+        .next()
+        .expect("addr")
+        .ip_port();
+    let tried_ms = faketime::unix_time_as_millis() - 61_000;
+    if let Some(paddr) = peer_store.mut_addr_manager().get_mut(&evict_ip_port) {
+        for _ in 0..3 {
+            paddr.mark_tried(tried_ms);
+        }
+    }
+    peer_store.add_addr(PeerId::random(), new_addr).unwrap();
+    assert_eq!(peer_store.addr_manager().count(), BUCKET_SIZE);
+    assert!(peer_store.addr_manager().get(&evict_ip_port).is_none());
+    assert!(peer_store.addr_manager().get(&new_ip_port).is_some());
+}
+
 #[test]
 fn test_fetch_addrs_to_attempt() {
     let mut peer_store: PeerStore = Default::default();
@@ -198,63 +344,3 @@ fn test_trim_p2p_phase() {
     });
     assert!(has_p2p_phase.is_none());
 }
-
-#[test]
-fn test_eviction() {
-    let mut peer_store = PeerStore::default();
-    let now = faketime::unix_time_as_millis();
-    let tried_ms = now - 61_000;
-    // add addrs
-    for i in 0..(ADDR_COUNT_LIMIT - 2) {
-        let addr: Multiaddr = format!("/ip4/225.0.0.1/tcp/{}", i).parse().unwrap();
-        peer_store.add_addr(PeerId::random(), addr).unwrap();
-    }
-    // this peer will be evict from peer store
-    let evict_addr: Multiaddr = "/ip4/225.0.0.2/tcp/42".parse().unwrap();
-    peer_store
-        .add_addr(PeerId::random(), evict_addr.clone())
-        .unwrap();
-    // this peer will reserve in peer store
-    let reserved_addr: Multiaddr = "/ip4/192.163.1.1/tcp/42".parse().unwrap();
-    peer_store
-        .add_addr(PeerId::random(), reserved_addr.clone())
-        .unwrap();
-    // mark two peers as terrible peer
-    if let Some(paddr) = peer_store
-        .mut_addr_manager()
-        .get_mut(&evict_addr.extract_ip_addr().unwrap())
-    {
-        paddr.mark_tried(tried_ms);
-        paddr.mark_tried(tried_ms);
-        paddr.mark_tried(tried_ms);
-    }
-    if let Some(paddr) = peer_store
-        .mut_addr_manager()
-        .get_mut(&reserved_addr.extract_ip_addr().unwrap())
-    {
-        paddr.mark_tried(tried_ms);
-        paddr.mark_tried(tried_ms);
-        paddr.mark_tried(tried_ms);
-        assert!(paddr.is_terrible(now));
-    }
-    // should evict evict_addr and accept new_peer
-    let new_peer_addr: Multiaddr = "/ip4/225.0.0.3/tcp/42".parse().unwrap();
-    peer_store
-        .add_addr(PeerId::random(), new_peer_addr.clone())
-        .unwrap();
-    // check addrs
-    // peer store will only evict peers from largest network group
-    // the evict_addr should be evict, other two addrs will remain in peer store
-    assert!(peer_store
-        .mut_addr_manager()
-        .get(&new_peer_addr.extract_ip_addr().unwrap())
-        .is_some());
-    assert!(peer_store
-        .mut_addr_manager()
-        .get(&reserved_addr.extract_ip_addr().unwrap())
-        .is_some());
-    assert!(peer_store
-        .mut_addr_manager()
-        .get(&evict_addr.extract_ip_addr().unwrap())
-        .is_none());
-}
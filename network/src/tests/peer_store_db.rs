@@ -12,7 +12,8 @@ use std::collections::HashSet;
 #[test]
 fn test_peer_store_persistent() {
     let now_ms = faketime::unix_time_as_millis();
-    let mut peer_store = PeerStore::default();
+    let dir = tempfile::tempdir().unwrap();
+    let mut peer_store = PeerStore::load_from_dir(&dir.path()).unwrap();
 
     // add addrs to addr manager
     let addr_manager = peer_store.mut_addr_manager();
@@ -69,9 +70,19 @@ fn test_peer_store_persistent() {
     ban_list.ban(ban3.clone());
 
     // dump and load
-    let dir = tempfile::tempdir().unwrap();
-    peer_store.dump_to_dir(&dir.path()).unwrap();
-    let peer_store2 = PeerStore::load_from_dir(&dir.path()).unwrap();
+    peer_store.dump().unwrap();
+    let expected_addrs = peer_store
+        .addr_manager()
+        .addrs_iter()
+        .cloned()
+        .map(|mut paddr| {
+            paddr.random_id_pos = 0;
+            paddr
+        })
+        .collect::<HashSet<_>>();
+    assert_eq!(expected_addrs.len(), 2);
+    drop(peer_store);
+    let mut peer_store2 = PeerStore::load_from_dir(&dir.path()).unwrap();
 
     // check addr manager
     let addr_manager2 = peer_store2.addr_manager();
@@ -80,18 +91,92 @@ fn test_peer_store_persistent() {
         paddr.random_id_pos = 0;
         paddr
     });
-    assert_eq!(
-        addrs.collect::<HashSet<_>>(),
-        vec![addr1, addr2].into_iter().collect::<HashSet<_>>()
-    );
+    assert_eq!(addrs.collect::<HashSet<_>>(), expected_addrs);
 
     // check ban list
-    let ban_list2 = peer_store2.ban_list();
+    {
+        let ban_list2 = peer_store2.ban_list();
+        assert_eq!(
+            ban_list2
+                .get_banned_addrs()
+                .into_iter()
+                .collect::<HashSet<_>>(),
+            vec![ban1, ban2.clone(), ban3.clone()]
+                .into_iter()
+                .collect::<HashSet<_>>()
+        );
+    }
+
+    // only the changes are written
+    peer_store2.mut_addr_manager().remove(&addr1.ip_port());
+    peer_store2.mut_ban_list().unban_network(&addr3);
+    peer_store2.dump().unwrap();
+    drop(peer_store2);
+    let peer_store3 = PeerStore::load_from_dir(&dir.path()).unwrap();
+    assert!(peer_store3.addr_manager().get(&addr1.ip_port()).is_none());
+    assert!(peer_store3.addr_manager().get(&addr2.ip_port()).is_some());
     assert_eq!(
-        ban_list2
+        peer_store3
+            .ban_list()
             .get_banned_addrs()
             .into_iter()
             .collect::<HashSet<_>>(),
-        vec![ban1, ban2, ban3].into_iter().collect::<HashSet<_>>()
+        vec![ban2, ban3].into_iter().collect::<HashSet<_>>()
+    );
+}
+
+#[test]
+fn test_import_json_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let addr: Multiaddr = "/ip4/1.1.1.1/tcp/42".parse().unwrap();
+    let addr_info = AddrInfo::new(
+        PeerId::random(),
+        addr.extract_ip_addr().unwrap(),
+        addr,
+        0,
+        100,
     );
+    // the addr manager dumped by the old versions has no bucket
+    let mut value = serde_json::to_value(vec![addr_info.clone()]).unwrap();
+    value[0].as_object_mut().unwrap().remove("bucket");
+    let json_path = dir.path().join("addr_manager.db");
+    serde_json::to_writer(std::fs::File::create(&json_path).unwrap(), &value).unwrap();
+
+    let peer_store = PeerStore::load_from_dir(&dir.path()).unwrap();
+    let imported = peer_store
+        .addr_manager()
+        .get(&addr_info.ip_port())
+        .expect("imported");
+    assert_eq!(imported.peer_id, addr_info.peer_id);
+    assert!(imported.bucket.is_some());
+    assert!(!json_path.exists());
+    drop(peer_store);
+
+    // the imported addrs are kept in the database
+    let peer_store = PeerStore::load_from_dir(&dir.path()).unwrap();
+    assert!(peer_store
+        .addr_manager()
+        .get(&addr_info.ip_port())
+        .is_some());
+}
+
+#[test]
+fn test_open_locked_db() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut peer_store = PeerStore::load_from_dir(&dir.path()).unwrap();
+    let now_ms = faketime::unix_time_as_millis();
+    let banned_addr = BannedAddr {
+        address: multiaddr_to_ip_network(&"/ip4/1.1.1.1/tcp/42".parse().unwrap()).unwrap(),
+        ban_until: now_ms + 10_000,
+        ban_reason: "test".into(),
+        created_at: now_ms,
+    };
+    peer_store.mut_ban_list().ban(banned_addr.clone());
+    peer_store.dump().unwrap();
+
+    // the database locked by another instance is not recreated
+    assert!(PeerStore::load_from_dir(&dir.path()).is_err());
+    drop(peer_store);
+    let peer_store = PeerStore::load_from_dir(&dir.path()).unwrap();
+    assert_eq!(peer_store.ban_list().get_banned_addrs(), vec![banned_addr]);
 }
@@ -0,0 +1,91 @@
+use crate::proxy::{connect, Socks5Proxy};
+use p2p::utils::socketaddr_to_multiaddr;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+use std::thread;
+
+fn start_echo_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = stream.try_clone().unwrap();
+            thread::spawn(move || {
+                let _ = io::copy(&mut reader, &mut stream);
+            });
+        }
+    });
+    addr
+}
+
+// A SOCKS5 stand-in which supports the CONNECT command to IPv4 targets only
+fn start_socks5_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut client = stream.unwrap();
+            thread::spawn(move || {
+                let mut greeting = [0u8; 3];
+                client.read_exact(&mut greeting).unwrap();
+                assert_eq!(greeting, [5, 1, 0]);
+                client.write_all(&[5, 0]).unwrap();
+
+                let mut request = [0u8; 10];
+                client.read_exact(&mut request).unwrap();
+                assert_eq!(request[..4], [5, 1, 0, 1]);
+                let ip = Ipv4Addr::new(request[4], request[5], request[6], request[7]);
+                let port = u16::from_be_bytes([request[8], request[9]]);
+                let mut target = TcpStream::connect(SocketAddrV4::new(ip, port)).unwrap();
+                client.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).unwrap();
+
+                let mut client_reader = client.try_clone().unwrap();
+                let mut target_writer = target.try_clone().unwrap();
+                thread::spawn(move || {
+                    let _ = io::copy(&mut client_reader, &mut target_writer);
+                });
+                let _ = io::copy(&mut target, &mut client);
+            });
+        }
+    });
+    addr
+}
+
+fn assert_echo(mut stream: TcpStream) {
+    stream.write_all(b"ping").unwrap();
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[test]
+fn test_socks5_connect() {
+    let echo_addr = start_echo_server();
+    let proxy_addr = start_socks5_server();
+    assert_echo(connect(proxy_addr, echo_addr).unwrap());
+}
+
+#[test]
+fn test_socks5_bridge() {
+    let echo_addr = start_echo_server();
+    let proxy_addr = start_socks5_server();
+    let proxy = Socks5Proxy::new(proxy_addr);
+    let bridge = proxy.bridge(&socketaddr_to_multiaddr(echo_addr)).unwrap();
+    let bridge_addr = bridge.local_addr();
+    assert_ne!(bridge_addr, echo_addr);
+    bridge.start().unwrap();
+    assert_echo(TcpStream::connect(bridge_addr).unwrap());
+}
+
+#[test]
+fn test_socks5_bridge_dropped() {
+    let echo_addr = start_echo_server();
+    let proxy_addr = start_socks5_server();
+    let proxy = Socks5Proxy::new(proxy_addr);
+    let bridge = proxy.bridge(&socketaddr_to_multiaddr(echo_addr)).unwrap();
+    let bridge_addr = bridge.local_addr();
+    // the dial through the bridge failed
+    drop(bridge);
+    assert!(TcpStream::connect(bridge_addr).is_err());
+}
@@ -0,0 +1,60 @@
+use crate::throttle::{Bandwidth, RateLimiter, UploadCap};
+use crate::NetworkConfig;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_rate_limiter() {
+    let now = Instant::now();
+    let mut limiter = RateLimiter::new(1000);
+    assert!(!limiter.is_exhausted(now));
+
+    // the bucket goes into debt
+    limiter.consume(1500, now);
+    assert!(limiter.is_exhausted(now));
+    assert!(limiter.is_exhausted(now + Duration::from_millis(400)));
+    assert!(!limiter.is_exhausted(now + Duration::from_millis(600)));
+
+    // the burst is capped at one second
+    let later = now + Duration::from_secs(10);
+    limiter.consume(1000, later);
+    assert!(limiter.is_exhausted(later));
+}
+
+#[test]
+fn test_upload_cap() {
+    let now = Instant::now();
+    let mut upload_cap = UploadCap::new(1000);
+    upload_cap.consume(999, now);
+    assert!(!upload_cap.is_reached(now));
+    upload_cap.consume(1, now);
+    assert!(upload_cap.is_reached(now));
+
+    // a new window starts after 24 hours
+    assert!(!upload_cap.is_reached(now + Duration::from_secs(25 * 60 * 60)));
+}
+
+#[test]
+fn test_bandwidth_peers() {
+    let config = NetworkConfig {
+        max_peer_upload_rate: Some(1000),
+        ..Default::default()
+    };
+    let mut bandwidth = Bandwidth::from_config(&config);
+    let now = Instant::now();
+    let session_id = 1.into();
+
+    // the unknown or closed sessions are not tracked
+    bandwidth.record_sent(session_id, 1500, now);
+    assert_eq!(bandwidth.upload_delay(&[session_id], now), None);
+
+    bandwidth.add_peer(session_id);
+    bandwidth.record_sent(session_id, 1500, now);
+    assert_eq!(
+        bandwidth.upload_delay(&[session_id], now),
+        Some(Duration::from_millis(501))
+    );
+
+    bandwidth.remove_peer(session_id);
+    bandwidth.record_sent(session_id, 1500, now);
+    assert_eq!(bandwidth.upload_delay(&[session_id], now), None);
+}
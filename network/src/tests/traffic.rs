@@ -0,0 +1,116 @@
+use crate::{
+    multiaddr::Multiaddr, traffic::UNKNOWN_MESSAGE_NAME, MessageCount, MessageNames, NetworkConfig,
+    NetworkState, PeerId, SessionType, TargetSession,
+};
+use p2p::bytes::Bytes;
+use tempfile::tempdir;
+
+const MESSAGE_NAMES: MessageNames = MessageNames {
+    names: &["Ping"],
+    index: |data| data.first().and_then(|id| (*id as usize).checked_sub(1)),
+};
+
+fn network_state() -> NetworkState {
+    let config = NetworkConfig {
+        max_peers: 19,
+        max_outbound_peers: 5,
+        path: tempdir()
+            .expect("create tempdir failed")
+            .path()
+            .to_path_buf(),
+        ..Default::default()
+    };
+    NetworkState::from_config(config).expect("Init network state failed")
+}
+
+#[test]
+fn test_record_traffic() {
+    let network_state = network_state();
+    let proto_id = 100.into();
+    network_state.register_traffic(proto_id, "/ckb/syn/".to_string(), Some(MESSAGE_NAMES));
+
+    let addr = "/ip4/127.0.0.1/tcp/42".parse::<Multiaddr>().unwrap();
+    let session_id = 1.into();
+    {
+        let mut peer_store = network_state.peer_store.lock();
+        network_state
+            .peer_registry
+            .write()
+            .accept_peer(
+                PeerId::random(),
+                addr,
+                session_id,
+                SessionType::Inbound,
+                &mut peer_store,
+            )
+            .expect("accept peer");
+    }
+
+    // large messages are compressed on the wire
+    let raw_data = Bytes::from(vec![1; 4096]);
+    let (data, delay) =
+        network_state.compress_to_send(proto_id, &TargetSession::Single(session_id), raw_data);
+    assert_eq!(delay, None);
+    assert!(data.len() < 4096);
+    assert_eq!(
+        network_state.record_received(proto_id, session_id, 10, &[2; 20]),
+        None
+    );
+
+    let protocols = network_state.protocols_traffic();
+    assert_eq!(protocols.len(), 1);
+    let traffic = &protocols[0].1.traffic;
+    assert_eq!(traffic.bytes_sent, data.len() as u64);
+    assert_eq!(traffic.raw_bytes_sent, 4096);
+    assert_eq!(traffic.bytes_received, 10);
+    assert_eq!(traffic.raw_bytes_received, 20);
+    assert_eq!(traffic.messages_sent, 1);
+    assert_eq!(traffic.messages_received, 1);
+    assert_eq!(
+        traffic.message_types.get("Ping"),
+        Some(&MessageCount {
+            received: 0,
+            sent: 1
+        })
+    );
+    assert_eq!(
+        traffic.message_types.get(UNKNOWN_MESSAGE_NAME),
+        Some(&MessageCount {
+            received: 1,
+            sent: 0
+        })
+    );
+
+    let peer_traffic = network_state.with_peer_registry(|reg| {
+        reg.get_peer(session_id)
+            .map(|peer| peer.traffic.protocols())
+            .expect("peer traffic")
+    });
+    assert_eq!(peer_traffic.len(), 1);
+    assert_eq!(peer_traffic[0].0, proto_id);
+    assert_eq!(&peer_traffic[0].1.traffic, traffic);
+}
+
+#[test]
+fn test_record_core_protocol_traffic() {
+    let network_state = network_state();
+    let proto_id = 0.into();
+    network_state.register_traffic(proto_id, "/p2p/ping".to_string(), None);
+
+    network_state.record_sent_by_handler(proto_id, &[1; 8]);
+    network_state.record_received(proto_id, 1.into(), 8, &[1; 8]);
+
+    let protocols = network_state.protocols_traffic();
+    assert_eq!(protocols.len(), 1);
+    assert_eq!(protocols[0].1.name, "/p2p/ping");
+    let traffic = &protocols[0].1.traffic;
+    assert_eq!(traffic.bytes_sent, 8);
+    assert_eq!(traffic.bytes_received, 8);
+    assert_eq!(
+        traffic.message_types.get(UNKNOWN_MESSAGE_NAME),
+        Some(&MessageCount {
+            received: 1,
+            sent: 1
+        })
+    );
+}
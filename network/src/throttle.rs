@@ -0,0 +1,252 @@
+use crate::NetworkConfig;
+use p2p::SessionId;
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const UPLOAD_CAP_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Token bucket limiting the bytes per second, it allows a burst of one second.
+///
+/// The bucket may go into debt when a message larger than the available
+/// tokens is sent, the peer is throttled until the debt is paid off.
+#[derive(Clone, Debug)]
+pub(crate) struct RateLimiter {
+    rate: u64,
+    tokens: i64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: u64) -> Self {
+        RateLimiter {
+            rate,
+            tokens: rate as i64,
+            updated_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now <= self.updated_at {
+            return;
+        }
+        let elapsed = now - self.updated_at;
+        let refilled = elapsed.as_millis() as u64 * self.rate / 1000;
+        if refilled > 0 {
+            self.tokens = min(
+                self.rate as i64,
+                self.tokens.saturating_add(refilled as i64),
+            );
+            self.updated_at = now;
+        }
+    }
+
+    pub(crate) fn consume(&mut self, bytes: usize, now: Instant) {
+        self.refill(now);
+        self.tokens = self.tokens.saturating_sub(bytes as i64);
+    }
+
+    pub(crate) fn is_exhausted(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens <= 0
+    }
+
+    /// How long until the debt is paid off, `None` if tokens are available
+    pub(crate) fn delay(&mut self, now: Instant) -> Option<Duration> {
+        if !self.is_exhausted(now) {
+            return None;
+        }
+        let debt = (-self.tokens) as u64 + 1;
+        let rate = max(self.rate, 1);
+        Some(Duration::from_millis((debt * 1000 + rate - 1) / rate))
+    }
+}
+
+/// Bytes uploaded in the current window of 24 hours
+#[derive(Clone, Debug)]
+pub(crate) struct UploadCap {
+    max_bytes: u64,
+    uploaded: u64,
+    started_at: Instant,
+}
+
+impl UploadCap {
+    pub(crate) fn new(max_bytes: u64) -> Self {
+        UploadCap {
+            max_bytes,
+            uploaded: 0,
+            started_at: Instant::now(),
+        }
+    }
+
+    fn roll(&mut self, now: Instant) {
+        if now > self.started_at && now - self.started_at >= UPLOAD_CAP_WINDOW {
+            self.uploaded = 0;
+            self.started_at = now;
+        }
+    }
+
+    pub(crate) fn consume(&mut self, bytes: usize, now: Instant) {
+        self.roll(now);
+        self.uploaded = self.uploaded.saturating_add(bytes as u64);
+    }
+
+    pub(crate) fn is_reached(&mut self, now: Instant) -> bool {
+        self.roll(now);
+        self.uploaded >= self.max_bytes
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct PeerLimiters {
+    upload: Option<RateLimiter>,
+    download: Option<RateLimiter>,
+}
+
+/// Upload and download limits of the CKB protocols, configured in `NetworkConfig`
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Bandwidth {
+    upload: Option<RateLimiter>,
+    download: Option<RateLimiter>,
+    peer_upload_rate: Option<u64>,
+    peer_download_rate: Option<u64>,
+    upload_cap: Option<UploadCap>,
+    peers: HashMap<SessionId, PeerLimiters>,
+}
+
+impl Bandwidth {
+    pub(crate) fn from_config(config: &NetworkConfig) -> Self {
+        Bandwidth {
+            upload: config.max_upload_rate.map(RateLimiter::new),
+            download: config.max_download_rate.map(RateLimiter::new),
+            peer_upload_rate: config.max_peer_upload_rate,
+            peer_download_rate: config.max_peer_download_rate,
+            upload_cap: config.max_upload_per_day.map(UploadCap::new),
+            peers: HashMap::default(),
+        }
+    }
+
+    /// Tracks the limits of an opened session, the closed ones are removed
+    /// by `remove_peer`
+    pub(crate) fn add_peer(&mut self, session_id: SessionId) {
+        let peer_upload_rate = self.peer_upload_rate;
+        let peer_download_rate = self.peer_download_rate;
+        self.peers
+            .entry(session_id)
+            .or_insert_with(|| PeerLimiters {
+                upload: peer_upload_rate.map(RateLimiter::new),
+                download: peer_download_rate.map(RateLimiter::new),
+            });
+    }
+
+    pub(crate) fn record_sent(&mut self, session_id: SessionId, bytes: usize, now: Instant) {
+        if let Some(ref mut limiter) = self.upload {
+            limiter.consume(bytes, now);
+        }
+        if let Some(ref mut upload_cap) = self.upload_cap {
+            upload_cap.consume(bytes, now);
+        }
+        if let Some(limiter) = self
+            .peers
+            .get_mut(&session_id)
+            .and_then(|peer| peer.upload.as_mut())
+        {
+            limiter.consume(bytes, now);
+        }
+    }
+
+    pub(crate) fn record_received(&mut self, session_id: SessionId, bytes: usize, now: Instant) {
+        if let Some(ref mut limiter) = self.download {
+            limiter.consume(bytes, now);
+        }
+        if let Some(limiter) = self
+            .peers
+            .get_mut(&session_id)
+            .and_then(|peer| peer.download.as_mut())
+        {
+            limiter.consume(bytes, now);
+        }
+    }
+
+    /// Whether the global or the peer upload limit is exceeded
+    pub(crate) fn upload_paused(&mut self, session_id: Option<SessionId>, now: Instant) -> bool {
+        if self
+            .upload
+            .as_mut()
+            .map(|limiter| limiter.is_exhausted(now))
+            .unwrap_or(false)
+        {
+            return true;
+        }
+        session_id
+            .and_then(|session_id| self.peers.get_mut(&session_id))
+            .and_then(|peer| peer.upload.as_mut())
+            .map(|limiter| limiter.is_exhausted(now))
+            .unwrap_or(false)
+    }
+
+    /// Whether the global or the peer download limit is exceeded
+    pub(crate) fn download_paused(&mut self, session_id: SessionId, now: Instant) -> bool {
+        self.download
+            .as_mut()
+            .map(|limiter| limiter.is_exhausted(now))
+            .unwrap_or(false)
+            || self
+                .peers
+                .get_mut(&session_id)
+                .and_then(|peer| peer.download.as_mut())
+                .map(|limiter| limiter.is_exhausted(now))
+                .unwrap_or(false)
+    }
+
+    /// How long to hold the messages to the sessions until the global and
+    /// the peers upload limits are met again
+    pub(crate) fn upload_delay(
+        &mut self,
+        session_ids: &[SessionId],
+        now: Instant,
+    ) -> Option<Duration> {
+        let global = self.upload.as_mut().and_then(|limiter| limiter.delay(now));
+        let peers = &mut self.peers;
+        session_ids
+            .iter()
+            .filter_map(|session_id| {
+                peers
+                    .get_mut(session_id)
+                    .and_then(|peer| peer.upload.as_mut())
+                    .and_then(|limiter| limiter.delay(now))
+            })
+            .chain(global)
+            .max()
+    }
+
+    /// How long to hold the messages from the session until the global and
+    /// the peer download limits are met again
+    pub(crate) fn download_delay(
+        &mut self,
+        session_id: SessionId,
+        now: Instant,
+    ) -> Option<Duration> {
+        let global = self
+            .download
+            .as_mut()
+            .and_then(|limiter| limiter.delay(now));
+        let peer = self
+            .peers
+            .get_mut(&session_id)
+            .and_then(|peer| peer.download.as_mut())
+            .and_then(|limiter| limiter.delay(now));
+        max(global, peer)
+    }
+
+    pub(crate) fn upload_cap_reached(&mut self, now: Instant) -> bool {
+        self.upload_cap
+            .as_mut()
+            .map(|upload_cap| upload_cap.is_reached(now))
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn remove_peer(&mut self, session_id: SessionId) {
+        self.peers.remove(&session_id);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{NetworkState, ProtocolId};
+use ckb_util::RwLock;
+use p2p::{
+    bytes::{Bytes, BytesMut},
+    context::{ProtocolContext, ProtocolContextMutRef},
+    traits::ServiceProtocol,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::iter;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::codec::{length_delimited::LengthDelimitedCodec, Decoder, Encoder};
+
+/// Names the messages of a protocol in the traffic statistics
+#[derive(Clone, Copy)]
+pub struct MessageNames {
+    pub names: &'static [&'static str],
+    /// The index in `names` of the message carried in the raw (uncompressed) payload
+    pub index: fn(&[u8]) -> Option<usize>,
+}
+
+// The higher-ranked fn pointer does not implement `Debug`
+impl fmt::Debug for MessageNames {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MessageNames")
+            .field("names", &self.names)
+            .finish()
+    }
+}
+
+/// The name used when a protocol does not name its messages or the payload is unknown
+pub const UNKNOWN_MESSAGE_NAME: &str = "Unknown";
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MessageCount {
+    pub received: u64,
+    pub sent: u64,
+}
+
+/// Traffic counters of one protocol
+///
+/// `bytes_*` count the payload on the wire, which is compressed,
+/// `raw_bytes_*` count the payload before compression.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Traffic {
+    pub bytes_received: u64,
+    pub raw_bytes_received: u64,
+    pub bytes_sent: u64,
+    pub raw_bytes_sent: u64,
+    pub messages_received: u64,
+    pub messages_sent: u64,
+    pub message_types: HashMap<&'static str, MessageCount>,
+}
+
+/// Traffic of a protocol, summed over all the peers or exchanged with a peer
+#[derive(Clone, Debug)]
+pub struct ProtocolTraffic {
+    pub name: String,
+    pub traffic: Traffic,
+}
+
+// The atomic counters behind `Traffic`, the messages are counted without locks
+#[derive(Debug)]
+struct TrafficCounters {
+    bytes_received: AtomicU64,
+    raw_bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    raw_bytes_sent: AtomicU64,
+    // The received and sent messages of each name, the last one counts the
+    // unknown messages
+    message_types: Vec<(AtomicU64, AtomicU64)>,
+}
+
+impl TrafficCounters {
+    fn new(names: usize) -> Self {
+        TrafficCounters {
+            bytes_received: AtomicU64::new(0),
+            raw_bytes_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            raw_bytes_sent: AtomicU64::new(0),
+            message_types: (0..=names)
+                .map(|_| (AtomicU64::new(0), AtomicU64::new(0)))
+                .collect(),
+        }
+    }
+
+    fn traffic(&self, names: &'static [&'static str]) -> Traffic {
+        let mut traffic = Traffic {
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            raw_bytes_received: self.raw_bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            raw_bytes_sent: self.raw_bytes_sent.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        let names = names
+            .iter()
+            .cloned()
+            .chain(iter::once(UNKNOWN_MESSAGE_NAME));
+        for (name, (received, sent)) in names.zip(self.message_types.iter()) {
+            let count = MessageCount {
+                received: received.load(Ordering::Relaxed),
+                sent: sent.load(Ordering::Relaxed),
+            };
+            if count != MessageCount::default() {
+                traffic.messages_received += count.received;
+                traffic.messages_sent += count.sent;
+                traffic.message_types.insert(name, count);
+            }
+        }
+        traffic
+    }
+}
+
+/// The live traffic counters of a protocol
+#[derive(Debug)]
+pub(crate) struct ProtocolCounters {
+    name: String,
+    message_names: Option<MessageNames>,
+    counters: TrafficCounters,
+}
+
+impl ProtocolCounters {
+    pub(crate) fn new(name: String, message_names: Option<MessageNames>) -> Self {
+        let counters = TrafficCounters::new(message_names.map(|m| m.names.len()).unwrap_or(0));
+        ProtocolCounters {
+            name,
+            message_names,
+            counters,
+        }
+    }
+
+    fn names(&self) -> &'static [&'static str] {
+        self.message_names.map(|m| m.names).unwrap_or(&[])
+    }
+
+    /// The index of the message counter, the unknown messages share the last one
+    pub(crate) fn message_index(&self, raw_data: &[u8]) -> usize {
+        let names = self.names().len();
+        self.message_names
+            .and_then(|m| (m.index)(raw_data))
+            .filter(|index| *index < names)
+            .unwrap_or(names)
+    }
+
+    /// Counts a received message
+    pub(crate) fn record_received(&self, bytes: usize, raw_bytes: usize, index: usize) {
+        let counters = &self.counters;
+        counters
+            .bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        counters
+            .raw_bytes_received
+            .fetch_add(raw_bytes as u64, Ordering::Relaxed);
+        counters.message_types[index]
+            .0
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts the same payload sent `count` times
+    pub(crate) fn record_sent(&self, bytes: usize, raw_bytes: usize, index: usize, count: usize) {
+        let counters = &self.counters;
+        let count = count as u64;
+        counters
+            .bytes_sent
+            .fetch_add(bytes as u64 * count, Ordering::Relaxed);
+        counters
+            .raw_bytes_sent
+            .fetch_add(raw_bytes as u64 * count, Ordering::Relaxed);
+        counters.message_types[index]
+            .1
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn traffic(&self) -> ProtocolTraffic {
+        ProtocolTraffic {
+            name: self.name.clone(),
+            traffic: self.counters.traffic(self.names()),
+        }
+    }
+}
+
+/// Traffic exchanged with a peer, the counters of a protocol are created on
+/// its first message
+#[derive(Debug, Default)]
+pub struct PeerTraffic {
+    protocols: RwLock<HashMap<ProtocolId, Arc<ProtocolCounters>>>,
+}
+
+impl PeerTraffic {
+    pub(crate) fn counters(
+        &self,
+        proto_id: ProtocolId,
+        protocol: &ProtocolCounters,
+    ) -> Arc<ProtocolCounters> {
+        if let Some(counters) = self.protocols.read().get(&proto_id) {
+            return Arc::clone(counters);
+        }
+        let mut protocols = self.protocols.write();
+        Arc::clone(protocols.entry(proto_id).or_insert_with(|| {
+            Arc::new(ProtocolCounters::new(
+                protocol.name.clone(),
+                protocol.message_names,
+            ))
+        }))
+    }
+
+    /// Traffic of each protocol exchanged with the peer, ordered by protocol id
+    pub fn protocols(&self) -> Vec<(ProtocolId, ProtocolTraffic)> {
+        let mut protocols = self
+            .protocols
+            .read()
+            .iter()
+            .map(|(proto_id, counters)| (*proto_id, counters.traffic()))
+            .collect::<Vec<_>>();
+        protocols.sort_by_key(|(proto_id, _)| *proto_id);
+        protocols
+    }
+}
+
+/// Counts the messages received by the handler of a core protocol, such as
+/// ping, discovery and identify, which do not go through `CKBProtocol`
+pub(crate) struct TrafficHandler {
+    pub(crate) proto_id: ProtocolId,
+    pub(crate) network_state: Arc<NetworkState>,
+    pub(crate) inner: Box<dyn ServiceProtocol + Send + 'static>,
+}
+
+impl ServiceProtocol for TrafficHandler {
+    fn init(&mut self, context: &mut ProtocolContext) {
+        self.inner.init(context)
+    }
+
+    fn connected(&mut self, context: ProtocolContextMutRef, version: &str) {
+        self.inner.connected(context, version)
+    }
+
+    fn disconnected(&mut self, context: ProtocolContextMutRef) {
+        self.inner.disconnected(context)
+    }
+
+    fn received(&mut self, context: ProtocolContextMutRef, data: Bytes) {
+        // The core protocols don't compress the messages, and their messages are
+        // too small to be held by the download limits
+        self.network_state
+            .record_received(self.proto_id, context.session.id, data.len(), &data);
+        self.inner.received(context, data)
+    }
+
+    fn notify(&mut self, context: &mut ProtocolContext, token: u64) {
+        self.inner.notify(context, token)
+    }
+
+    fn poll(&mut self, context: &mut ProtocolContext) {
+        self.inner.poll(context)
+    }
+}
+
+/// Counts the messages sent by the handler of a core protocol.
+///
+/// The handler sends the messages itself, the codec does not know the session,
+/// so the sent messages are only counted in the protocol traffic, not in the
+/// peer traffic.
+pub(crate) struct TrafficCodec {
+    proto_id: ProtocolId,
+    network_state: Arc<NetworkState>,
+    inner: LengthDelimitedCodec,
+}
+
+impl TrafficCodec {
+    pub(crate) fn new(proto_id: ProtocolId, network_state: Arc<NetworkState>) -> Self {
+        TrafficCodec {
+            proto_id,
+            network_state,
+            inner: LengthDelimitedCodec::new(),
+        }
+    }
+}
+
+impl Encoder for TrafficCodec {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), io::Error> {
+        self.network_state
+            .record_sent_by_handler(self.proto_id, &item);
+        self.inner.encode(item, dst)
+    }
+}
+
+impl Decoder for TrafficCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, io::Error> {
+        self.inner.decode(src)
+    }
+}
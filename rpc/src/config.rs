@@ -11,6 +11,7 @@ pub enum Module {
     Indexer,
     IntegrationTest,
     Alert,
+    Admin,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -57,4 +58,8 @@ impl Config {
     pub(crate) fn alert_enable(&self) -> bool {
         self.modules.contains(&Module::Alert)
     }
+
+    pub(crate) fn admin_enable(&self) -> bool {
+        self.modules.contains(&Module::Admin)
+    }
 }
@@ -3,6 +3,7 @@ use jsonrpc_core::{Error, ErrorCode};
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum RPCError {
     Invalid = -3,
+    Pruned = -4,
 }
 
 impl RPCError {
@@ -13,4 +14,13 @@ impl RPCError {
             data: None,
         }
     }
+
+    /// The server fails to serve the request, such as an IO error
+    pub fn internal(message: String) -> Error {
+        Error {
+            code: ErrorCode::InternalError,
+            message,
+            data: None,
+        }
+    }
 }
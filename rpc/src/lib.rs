@@ -8,5 +8,6 @@ pub(crate) mod service_builder;
 mod test;
 
 pub use crate::config::{Config, Module};
+pub use crate::module::{BACKUPS_DIR, BACKUP_DB_DIR, BACKUP_INDEXER_DB_DIR};
 pub use crate::server::RpcServer;
 pub use crate::service_builder::ServiceBuilder;
@@ -0,0 +1,142 @@
+use crate::error::RPCError;
+use ckb_db::Col;
+use ckb_indexer::DefaultIndexerStore;
+use ckb_jsonrpc_types::{CacheStats, ColumnStats, DBStats, StoreStats};
+use ckb_shared::shared::Shared;
+use ckb_store::ChainStore;
+use jsonrpc_core::Result;
+use jsonrpc_derive::rpc;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The sub directory of the data dir holding the backups created by the RPC.
+pub const BACKUPS_DIR: &str = "backups";
+/// The sub directory of a backup holding the chain database.
+pub const BACKUP_DB_DIR: &str = "db";
+/// The sub directory of a backup holding the indexer database.
+pub const BACKUP_INDEXER_DB_DIR: &str = "indexer_db";
+
+#[rpc]
+pub trait AdminRpc {
+    // Backs up to `<data dir>/backups/<name>`
+    // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"create_backup","params": ["ckb-20191018"]}' -H 'content-type:application/json' 'http://localhost:8114'
+    #[rpc(name = "create_backup")]
+    fn create_backup(&self, name: String) -> Result<()>;
+
+    // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"get_db_stats","params": []}' -H 'content-type:application/json' 'http://localhost:8114'
+    #[rpc(name = "get_db_stats")]
+    fn get_db_stats(&self) -> Result<StoreStats>;
+}
+
+pub(crate) struct AdminRpcImpl {
+    pub shared: Shared,
+    pub indexer_store: Option<DefaultIndexerStore>,
+    /// the directory holding the backups
+    pub backup_dir: PathBuf,
+}
+
+impl AdminRpc for AdminRpcImpl {
+    fn create_backup(&self, name: String) -> Result<()> {
+        // Only a plain name is accepted, the backups never leave the backup dir.
+        let mut components = Path::new(&name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => {}
+            _ => {
+                return Err(RPCError::custom(
+                    RPCError::Invalid,
+                    format!("{:?} is not a valid backup name", name),
+                ))
+            }
+        }
+        let path = self.backup_dir.join(name);
+        if path.exists() {
+            return Err(RPCError::custom(
+                RPCError::Invalid,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        let backup_error = |err: String| RPCError::internal(format!("Backup error: {}", err));
+        fs::create_dir_all(&path).map_err(|err| backup_error(err.to_string()))?;
+
+        let chain_checkpoint = || {
+            self.shared
+                .store()
+                .create_checkpoint(&path.join(BACKUP_DB_DIR))
+        };
+        // The indexer is copied first and the chain is copied before the
+        // indexer syncs again, so in the backup the indexer never indexes
+        // blocks which the chain database does not have.
+        let result = match self.indexer_store {
+            Some(ref store) => {
+                store.create_checkpoint(&path.join(BACKUP_INDEXER_DB_DIR), chain_checkpoint)
+            }
+            None => chain_checkpoint(),
+        };
+        result.map_err(|err| backup_error(err.to_string()))
+    }
+
+    fn get_db_stats(&self) -> Result<StoreStats> {
+        let stats_error = |err: String| RPCError::internal(format!("Stats error: {}", err));
+        let store = self.shared.store();
+        let chain_db = store.stats().map_err(|err| stats_error(err.to_string()))?;
+        let indexer_db = match self.indexer_store {
+            Some(ref indexer_store) => Some(
+                indexer_store
+                    .stats()
+                    .map_err(|err| stats_error(err.to_string()))?,
+            ),
+            None => None,
+        };
+        let caches = store
+            .cache()
+            .map(|cache| {
+                cache
+                    .stats()
+                    .into_iter()
+                    .map(|(name, stats)| CacheStats {
+                        name: name.to_owned(),
+                        hits: stats.hits.into(),
+                        misses: stats.misses.into(),
+                        len: stats.len.into(),
+                        capacity: stats.capacity.into(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(StoreStats {
+            chain_db: db_stats(chain_db, &ckb_store::COLUMN_NAMES),
+            indexer_db: indexer_db.map(|stats| db_stats(stats, &ckb_indexer::COLUMN_NAMES)),
+            caches,
+        })
+    }
+}
+
+fn db_stats(stats: ckb_db::DBStats, names: &[(Col, &str)]) -> DBStats {
+    let column_name = |col: Col| {
+        names
+            .iter()
+            .find(|(c, _)| *c == col)
+            .map(|(_, name)| (*name).to_owned())
+            .unwrap_or_else(|| col.to_owned())
+    };
+    DBStats {
+        columns: stats
+            .columns
+            .into_iter()
+            .map(|column| ColumnStats {
+                name: column_name(column.column),
+                estimated_keys: column.estimated_keys.into(),
+                estimated_live_data_size: column.estimated_live_data_size.into(),
+                sst_files: column.sst_files.into(),
+                sst_files_size: column.sst_files_size.into(),
+                mem_tables_size: column.mem_tables_size.into(),
+                block_cache_usage: column.block_cache_usage.into(),
+                compaction_pending: column.compaction_pending,
+                pending_compaction_bytes: column.pending_compaction_bytes.into(),
+            })
+            .collect(),
+        running_compactions: stats.running_compactions.into(),
+        block_cache_hits: stats.block_cache_hits.map(Into::into),
+        block_cache_misses: stats.block_cache_misses.map(Into::into),
+    }
+}
@@ -5,7 +5,7 @@ use ckb_jsonrpc_types::{
 };
 use ckb_logger::error;
 use ckb_reward_calculator::RewardCalculator;
-use ckb_shared::shared::Shared;
+use ckb_shared::{shared::Shared, Snapshot};
 use ckb_store::ChainStore;
 use ckb_types::{core::cell::CellProvider, packed, prelude::*, H256};
 use jsonrpc_core::{Error, Result};
@@ -66,18 +66,21 @@ pub(crate) struct ChainRpcImpl {
 
 impl ChainRpc for ChainRpcImpl {
     fn get_block(&self, hash: H256) -> Result<Option<BlockView>> {
-        Ok(self
-            .shared
-            .snapshot()
-            .get_block(&hash.pack())
-            .map(Into::into))
+        let snapshot = self.shared.snapshot();
+        let hash = hash.pack();
+        check_block_pruned(&snapshot, &hash)?;
+        Ok(snapshot.get_block(&hash).map(Into::into))
     }
 
     fn get_block_by_number(&self, number: BlockNumber) -> Result<Option<BlockView>> {
         let snapshot = self.shared.snapshot();
-        Ok(snapshot
-            .get_block_hash(number.into())
-            .and_then(|hash| snapshot.get_block(&hash).map(Into::into)))
+        match snapshot.get_block_hash(number.into()) {
+            Some(hash) => {
+                check_block_pruned(&snapshot, &hash)?;
+                Ok(snapshot.get_block(&hash).map(Into::into))
+            }
+            None => Ok(None),
+        }
     }
 
     fn get_header(&self, hash: H256) -> Result<Option<HeaderView>> {
@@ -184,10 +187,8 @@ impl ChainRpc for ChainRpcImpl {
             }
 
             let block_hash = block_hash.unwrap();
-            let block = snapshot
-                .get_block(&block_hash)
-                .ok_or_else(Error::internal_error)?;
-            for transaction in block.transactions() {
+            // The pruned block bodies still keep the transactions with live cells
+            for transaction in snapshot.get_block_body(&block_hash) {
                 if let Some(transaction_meta) = snapshot.cell_set().get(&transaction.hash()) {
                     for (i, output) in transaction.outputs().into_iter().enumerate() {
                         if output.calc_lock_hash() == lock_hash
@@ -237,3 +238,14 @@ impl ChainRpc for ChainRpcImpl {
         }))
     }
 }
+
+fn check_block_pruned(snapshot: &Snapshot, hash: &packed::Byte32) -> Result<()> {
+    if snapshot.is_block_pruned(hash) {
+        Err(RPCError::custom(
+            RPCError::Pruned,
+            format!("The body of block {} is pruned", hash),
+        ))
+    } else {
+        Ok(())
+    }
+}
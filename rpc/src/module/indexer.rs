@@ -1,3 +1,4 @@
+use crate::error::RPCError;
 use ckb_indexer::IndexerStore;
 use ckb_jsonrpc_types::{BlockNumber, CellTransaction, LiveCell, LockHashIndexState, Uint64};
 use ckb_types::{prelude::*, H256};
@@ -92,9 +93,21 @@ impl<WS: IndexerStore + 'static> IndexerRpc for IndexerRpcImpl<WS> {
         lock_hash: H256,
         index_from: Option<BlockNumber>,
     ) -> Result<LockHashIndexState> {
-        let state = self
-            .store
-            .insert_lock_hash(&lock_hash.pack(), index_from.map(Into::into));
+        let index_from = index_from.map(Into::into);
+        let min_index_from = self.store.min_index_from();
+        if let Some(index_from) = index_from {
+            if index_from < min_index_from {
+                return Err(RPCError::custom(
+                    RPCError::Pruned,
+                    format!(
+                        "The blocks before {} are pruned, index from {} or later",
+                        min_index_from + 1,
+                        min_index_from
+                    ),
+                ));
+            }
+        }
+        let state = self.store.insert_lock_hash(&lock_hash.pack(), index_from);
         Ok(LockHashIndexState {
             lock_hash,
             block_number: state.block_number.into(),
@@ -1,3 +1,4 @@
+mod admin;
 mod alert;
 mod chain;
 mod experiment;
@@ -8,6 +9,8 @@ mod pool;
 mod stats;
 mod test;
 
+pub(crate) use self::admin::{AdminRpc, AdminRpcImpl};
+pub use self::admin::{BACKUPS_DIR, BACKUP_DB_DIR, BACKUP_INDEXER_DB_DIR};
 pub(crate) use self::alert::{AlertRpc, AlertRpcImpl};
 pub(crate) use self::chain::{ChainRpc, ChainRpcImpl};
 pub(crate) use self::experiment::{ExperimentRpc, ExperimentRpcImpl};
@@ -1,6 +1,8 @@
 use crate::error::RPCError;
-use ckb_jsonrpc_types::{BannedAddr, Node, NodeAddress, Timestamp};
-use ckb_network::{MultiaddrExt, NetworkController};
+use ckb_jsonrpc_types::{
+    BannedAddr, MessageTypeCount, NetworkStats, Node, NodeAddress, ProtocolTraffic, Timestamp,
+};
+use ckb_network::{MultiaddrExt, NetworkController, ProtocolId, Traffic};
 use faketime::unix_time_as_millis;
 use jsonrpc_core::Result;
 use jsonrpc_derive::rpc;
@@ -33,6 +35,10 @@ pub trait NetworkRpc {
         absolute: Option<bool>,
         reason: Option<String>,
     ) -> Result<()>;
+
+    // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"get_network_stats","params": []}' -H 'content-type:application/json' 'http://localhost:8114'
+    #[rpc(name = "get_network_stats")]
+    fn get_network_stats(&self) -> Result<NetworkStats>;
 }
 
 pub(crate) struct NetworkRpcImpl {
@@ -44,6 +50,7 @@ impl NetworkRpc for NetworkRpcImpl {
         Ok(Node {
             version: self.network_controller.node_version().to_string(),
             is_outbound: None,
+            traffic: None,
             node_id: self.network_controller.node_id(),
             addresses: self
                 .network_controller
@@ -93,7 +100,16 @@ impl NetworkRpc for NetworkRpcImpl {
                     }
                 }
                 let addresses = addresses.values().cloned().collect();
+                let traffic = peer
+                    .traffic
+                    .protocols()
+                    .into_iter()
+                    .map(|(proto_id, protocol)| {
+                        protocol_traffic(proto_id, protocol.name, &protocol.traffic)
+                    })
+                    .collect();
                 Node {
+                    traffic: Some(traffic),
                     is_outbound: Some(peer.is_outbound()),
                     version: peer
                         .identify_info
@@ -161,4 +177,40 @@ impl NetworkRpc for NetworkRpcImpl {
         }
         Ok(())
     }
+
+    fn get_network_stats(&self) -> Result<NetworkStats> {
+        let protocols = self
+            .network_controller
+            .protocols_traffic()
+            .into_iter()
+            .map(|(proto_id, protocol)| {
+                protocol_traffic(proto_id, protocol.name, &protocol.traffic)
+            })
+            .collect();
+        Ok(NetworkStats { protocols })
+    }
+}
+
+fn protocol_traffic(proto_id: ProtocolId, name: String, traffic: &Traffic) -> ProtocolTraffic {
+    let mut message_types = traffic
+        .message_types
+        .iter()
+        .map(|(message_type, count)| MessageTypeCount {
+            message_type: (*message_type).to_string(),
+            received: count.received.into(),
+            sent: count.sent.into(),
+        })
+        .collect::<Vec<_>>();
+    message_types.sort_by(|a, b| a.message_type.cmp(&b.message_type));
+    ProtocolTraffic {
+        protocol_id: (proto_id.value() as u64).into(),
+        name,
+        bytes_received: traffic.bytes_received.into(),
+        raw_bytes_received: traffic.raw_bytes_received.into(),
+        bytes_sent: traffic.bytes_sent.into(),
+        raw_bytes_sent: traffic.raw_bytes_sent.into(),
+        messages_received: traffic.messages_received.into(),
+        messages_sent: traffic.messages_sent.into(),
+        message_types,
+    }
 }
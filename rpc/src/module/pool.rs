@@ -1,7 +1,6 @@
 use crate::error::RPCError;
 use ckb_jsonrpc_types::{Transaction, TxPoolInfo};
 use ckb_logger::error;
-use ckb_network::PeerIndex;
 use ckb_shared::shared::Shared;
 use ckb_sync::SyncSharedState;
 use ckb_types::{core, packed, prelude::*, H256};
@@ -49,15 +48,10 @@ impl PoolRpc for PoolRpcImpl {
 
         match submit_txs.unwrap() {
             Ok(_) => {
-                // workaround: we are using `PeerIndex(usize::max)` to indicate that tx hash source is itself.
-                let peer_index = PeerIndex::new(usize::max_value());
                 let hash = tx.hash().to_owned();
                 self.sync_shared_state
                     .state()
-                    .tx_hashes()
-                    .entry(peer_index)
-                    .or_default()
-                    .insert(hash.clone());
+                    .add_local_tx_hash(hash.clone());
                 Ok(hash.unpack())
             }
             Err(e) => Err(RPCError::custom(RPCError::Invalid, e.to_string())),
@@ -1,5 +1,6 @@
 use crate::error::RPCError;
 use ckb_chain::{chain::ChainController, switch::Switch};
+use ckb_indexer::DefaultIndexerStore;
 use ckb_jsonrpc_types::{Block, Transaction};
 use ckb_logger::error;
 use ckb_network::NetworkController;
@@ -25,12 +26,17 @@ pub trait IntegrationTestRpc {
 
     #[rpc(name = "broadcast_transaction")]
     fn broadcast_transaction(&self, transaction: Transaction) -> Result<H256>;
+
+    // curl -d '{"id": 2, "jsonrpc": "2.0", "method":"truncate","params": ["0xa5f5c85987a15de25661e5a214f2c1449cd803f071acc7999820f25246471f40"]}' -H 'content-type:application/json' 'http://localhost:8114'
+    #[rpc(name = "truncate")]
+    fn truncate(&self, target_tip_hash: H256) -> Result<()>;
 }
 
 pub(crate) struct IntegrationTestRpcImpl {
     pub network_controller: NetworkController,
     pub shared: Shared,
     pub chain: ChainController,
+    pub indexer_store: Option<DefaultIndexerStore>,
 }
 
 impl IntegrationTestRpc for IntegrationTestRpcImpl {
@@ -84,4 +90,14 @@ impl IntegrationTestRpc for IntegrationTestRpcImpl {
             Ok(hash.unpack())
         }
     }
+
+    fn truncate(&self, target_tip_hash: H256) -> Result<()> {
+        self.chain
+            .truncate(target_tip_hash.pack())
+            .map_err(|err| RPCError::custom(RPCError::Invalid, err.to_string()))?;
+        if let Some(indexer_store) = &self.indexer_store {
+            indexer_store.detach_forks();
+        }
+        Ok(())
+    }
 }
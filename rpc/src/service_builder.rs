@@ -1,8 +1,9 @@
 use crate::config::Config;
 use crate::module::{
-    AlertRpc, AlertRpcImpl, ChainRpc, ChainRpcImpl, ExperimentRpc, ExperimentRpcImpl, IndexerRpc,
-    IndexerRpcImpl, IntegrationTestRpc, IntegrationTestRpcImpl, MinerRpc, MinerRpcImpl, NetworkRpc,
-    NetworkRpcImpl, PoolRpc, PoolRpcImpl, StatsRpc, StatsRpcImpl,
+    AdminRpc, AdminRpcImpl, AlertRpc, AlertRpcImpl, ChainRpc, ChainRpcImpl, ExperimentRpc,
+    ExperimentRpcImpl, IndexerRpc, IndexerRpcImpl, IntegrationTestRpc, IntegrationTestRpcImpl,
+    MinerRpc, MinerRpcImpl, NetworkRpc, NetworkRpcImpl, PoolRpc, PoolRpcImpl, StatsRpc,
+    StatsRpcImpl,
 };
 use ckb_chain::chain::ChainController;
 use ckb_indexer::{DefaultIndexerStore, IndexerConfig};
@@ -13,11 +14,13 @@ use ckb_sync::SyncSharedState;
 use ckb_sync::Synchronizer;
 use ckb_util::Mutex;
 use jsonrpc_core::IoHandler;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 pub struct ServiceBuilder<'a> {
     config: &'a Config,
     io_handler: IoHandler,
+    indexer_store: Option<DefaultIndexerStore>,
 }
 
 impl<'a> ServiceBuilder<'a> {
@@ -25,6 +28,7 @@ impl<'a> ServiceBuilder<'a> {
         Self {
             config,
             io_handler: IoHandler::new(),
+            indexer_store: None,
         }
     }
     pub fn enable_chain(mut self, shared: Shared) -> Self {
@@ -98,6 +102,8 @@ impl<'a> ServiceBuilder<'a> {
         self
     }
 
+    // The truncation only detaches the blocks from the indexer when the indexer
+    // is enabled before.
     pub fn enable_integration_test(
         mut self,
         shared: Shared,
@@ -105,11 +111,13 @@ impl<'a> ServiceBuilder<'a> {
         chain: ChainController,
     ) -> Self {
         if self.config.integration_test_enable() {
+            let indexer_store = self.indexer_store.clone();
             self.io_handler.extend_with(
                 IntegrationTestRpcImpl {
                     shared,
                     network_controller,
                     chain,
+                    indexer_store,
                 }
                 .to_delegate(),
             );
@@ -136,12 +144,30 @@ impl<'a> ServiceBuilder<'a> {
             let store = DefaultIndexerStore::new(indexer_config, shared);
             store.clone().start(Some("IndexerStore"));
 
+            self.indexer_store = Some(store.clone());
             self.io_handler
                 .extend_with(IndexerRpcImpl { store }.to_delegate())
         }
         self
     }
 
+    // The indexer database is only backed up when the indexer is enabled
+    // before.
+    pub fn enable_admin(mut self, shared: Shared, backup_dir: PathBuf) -> Self {
+        if self.config.admin_enable() {
+            let indexer_store = self.indexer_store.clone();
+            self.io_handler.extend_with(
+                AdminRpcImpl {
+                    shared,
+                    indexer_store,
+                    backup_dir,
+                }
+                .to_delegate(),
+            );
+        }
+        self
+    }
+
     pub fn build(self) -> IoHandler {
         let mut io_handler = self.io_handler;
         io_handler.add_method("ping", |_| futures::future::ok("pong".into()));
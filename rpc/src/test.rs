@@ -365,6 +365,7 @@ fn params_of(shared: &Shared, method: &str) -> Value {
         | "local_node_info"
         | "get_peers"
         | "get_banned_addresses"
+        | "get_network_stats"
         | "get_blockchain_info"
         | "tx_pool_info"
         | "get_peers_state"
@@ -2,6 +2,7 @@ use ckb_vm::{
     instructions::{extract_opcode, insts},
     Instruction,
 };
+use serde_derive::{Deserialize, Serialize};
 
 // 0.25 cycles per byte
 pub const BYTES_PER_CYCLE: u64 = 4;
@@ -65,3 +66,97 @@ pub fn instruction_cycles(i: Instruction) -> u64 {
         _ => 1,
     }
 }
+
+// Instructions grouped by the cost model, so the cycles charged per group
+// can be compared against the time actually spent, see `profile`.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum InstructionClass {
+    Alu,
+    Load,
+    Store,
+    Branch,
+    Jump,
+    Multiply,
+    Divide,
+    Environment,
+}
+
+impl InstructionClass {
+    pub fn all() -> &'static [InstructionClass] {
+        &[
+            InstructionClass::Alu,
+            InstructionClass::Load,
+            InstructionClass::Store,
+            InstructionClass::Branch,
+            InstructionClass::Jump,
+            InstructionClass::Multiply,
+            InstructionClass::Divide,
+            InstructionClass::Environment,
+        ]
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            InstructionClass::Alu => "alu",
+            InstructionClass::Load => "load",
+            InstructionClass::Store => "store",
+            InstructionClass::Branch => "branch",
+            InstructionClass::Jump => "jump",
+            InstructionClass::Multiply => "multiply",
+            InstructionClass::Divide => "divide",
+            InstructionClass::Environment => "environment",
+        }
+    }
+}
+
+pub fn instruction_class(i: Instruction) -> InstructionClass {
+    match extract_opcode(i) {
+        insts::OP_LD
+        | insts::OP_LW
+        | insts::OP_LH
+        | insts::OP_LB
+        | insts::OP_LWU
+        | insts::OP_LHU
+        | insts::OP_LBU
+        | insts::OP_RVC_LW
+        | insts::OP_RVC_LD
+        | insts::OP_RVC_LWSP
+        | insts::OP_RVC_LDSP => InstructionClass::Load,
+        insts::OP_SB
+        | insts::OP_SH
+        | insts::OP_SW
+        | insts::OP_SD
+        | insts::OP_RVC_SW
+        | insts::OP_RVC_SD
+        | insts::OP_RVC_SWSP
+        | insts::OP_RVC_SDSP => InstructionClass::Store,
+        insts::OP_BEQ
+        | insts::OP_BGE
+        | insts::OP_BGEU
+        | insts::OP_BLT
+        | insts::OP_BLTU
+        | insts::OP_BNE
+        | insts::OP_RVC_BEQZ
+        | insts::OP_RVC_BNEZ => InstructionClass::Branch,
+        insts::OP_JAL
+        | insts::OP_JALR
+        | insts::OP_RVC_JAL
+        | insts::OP_RVC_J
+        | insts::OP_RVC_JR
+        | insts::OP_RVC_JALR => InstructionClass::Jump,
+        insts::OP_MUL | insts::OP_MULW | insts::OP_MULH | insts::OP_MULHU | insts::OP_MULHSU => {
+            InstructionClass::Multiply
+        }
+        insts::OP_DIV
+        | insts::OP_DIVW
+        | insts::OP_DIVU
+        | insts::OP_DIVUW
+        | insts::OP_REM
+        | insts::OP_REMW
+        | insts::OP_REMU
+        | insts::OP_REMUW => InstructionClass::Divide,
+        insts::OP_ECALL | insts::OP_EBREAK | insts::OP_RVC_EBREAK => InstructionClass::Environment,
+        _ => InstructionClass::Alu,
+    }
+}
@@ -1,10 +1,13 @@
 pub mod cost_model;
 mod error;
+mod profile;
 mod syscalls;
+mod trace;
 mod type_id;
 mod verify;
 
 pub use crate::error::ScriptError;
+pub use crate::profile::{ProfileEntry, ScriptProfile};
 pub use crate::verify::{ScriptGroup, ScriptGroupType, TransactionScriptsVerifier};
 
 /// re-export DataLoader
@@ -0,0 +1,133 @@
+//! Execution profile of a script group, see `TransactionScriptsVerifier::profile_single`.
+//!
+//! The profile records, per instruction class of the cost model and per syscall number, how
+//! many times it was executed, how many cycles it was charged and how much wall-clock time it
+//! took. Each instruction is timed separately, so the recorded time includes the overhead of
+//! reading the clock, which `ScriptProfile::timer_overhead` estimates.
+use crate::{
+    cost_model::{instruction_class, InstructionClass},
+    verify::internal_error,
+};
+use ckb_error::Error;
+use ckb_types::core::Cycle;
+use ckb_vm::{
+    decoder::build_imac_decoder,
+    instructions::{extract_opcode, insts},
+    machine::DefaultMachine,
+    registers::A7,
+    CoreMachine, Register, SupportMachine,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct ProfileEntry {
+    pub count: u64,
+    pub cycles: Cycle,
+    pub elapsed: Duration,
+}
+
+impl ProfileEntry {
+    fn record(&mut self, cycles: Cycle, elapsed: Duration) {
+        self.count += 1;
+        self.cycles += cycles;
+        self.elapsed += elapsed;
+    }
+
+    pub fn merge(&mut self, other: &ProfileEntry) {
+        self.count += other.count;
+        self.cycles += other.cycles;
+        self.elapsed += other.elapsed;
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct ScriptProfile {
+    // Total cycles consumed by the script group, including loading the program.
+    pub cycles: Cycle,
+    pub instructions: HashMap<InstructionClass, ProfileEntry>,
+    // Breakdown of `InstructionClass::Environment` by syscall number, the
+    // cycles include the transferred bytes charged by the syscall itself.
+    pub syscalls: HashMap<u64, ProfileEntry>,
+}
+
+impl ScriptProfile {
+    pub fn merge(&mut self, other: &ScriptProfile) {
+        self.cycles += other.cycles;
+        for (class, entry) in &other.instructions {
+            self.instructions.entry(*class).or_default().merge(entry);
+        }
+        for (number, entry) in &other.syscalls {
+            self.syscalls.entry(*number).or_default().merge(entry);
+        }
+    }
+
+    // Estimated time spent reading the clock around a single instruction.
+    pub fn timer_overhead() -> Duration {
+        const SAMPLES: u32 = 10_000;
+        let start = Instant::now();
+        for _ in 0..SAMPLES {
+            let _ = Instant::now().elapsed();
+        }
+        start.elapsed() / SAMPLES
+    }
+}
+
+pub(crate) struct Profiler {
+    profile: ScriptProfile,
+}
+
+impl Profiler {
+    pub(crate) fn new() -> Self {
+        Profiler {
+            profile: ScriptProfile::default(),
+        }
+    }
+
+    pub(crate) fn into_profile(self, cycles: Cycle) -> ScriptProfile {
+        let mut profile = self.profile;
+        profile.cycles = cycles;
+        profile
+    }
+
+    // Mirrors `DefaultMachine::run`, stepping one instruction at a time so
+    // each instruction can be timed.
+    pub(crate) fn run<Inner: SupportMachine>(
+        &mut self,
+        machine: &mut DefaultMachine<Inner>,
+    ) -> Result<i8, Error> {
+        let decoder = build_imac_decoder::<Inner::REG>();
+        machine.set_running(true);
+        while machine.running() {
+            let pc = machine.pc().to_u64();
+            let instruction = decoder
+                .decode(machine.memory_mut(), pc)
+                .map_err(internal_error)?;
+            let syscall = if extract_opcode(instruction) == insts::OP_ECALL {
+                Some(machine.registers()[A7].to_u64())
+            } else {
+                None
+            };
+            let cycles = machine.cycles();
+
+            let start = Instant::now();
+            machine.step(&decoder).map_err(internal_error)?;
+            let elapsed = start.elapsed();
+
+            let cycles = machine.cycles() - cycles;
+            self.profile
+                .instructions
+                .entry(instruction_class(instruction))
+                .or_default()
+                .record(cycles, elapsed);
+            if let Some(number) = syscall {
+                self.profile
+                    .syscalls
+                    .entry(number)
+                    .or_default()
+                    .record(cycles, elapsed);
+            }
+        }
+        Ok(machine.exit_code())
+    }
+}
@@ -0,0 +1,145 @@
+//! Execution trace of a script group, see `TransactionScriptsVerifier::trace_single`.
+//!
+//! The trace is written in JSON lines, one event per line, in execution order:
+//!
+//! - `{"type":"step","pc":4096,"instruction":..,"opcode":..,"cycles":..,"writes":[[10,0]]}`
+//!   is written for every executed instruction. `instruction` is the instruction decoded by
+//!   ckb-vm, `opcode` its ckb-vm opcode, `cycles` the total consumed cycles after executing it,
+//!   and `writes` the `[register, value]` pairs it changed.
+//! - `{"type":"syscall","pc":..,"number":2061,"args":[..],"return_code":0,"data_length":32}`
+//!   precedes the step of every `ecall`. `number` is `a7`, `args` holds `a0` to `a5` on entry,
+//!   `return_code` is `a0` on return, and `data_length` is the full length of the data behind a
+//!   successful load syscall, which scripts read back through the length pointer in `a1`, or
+//!   `null` for other syscalls.
+use crate::{
+    syscalls::{
+        LOAD_CELL_DATA_AS_CODE_SYSCALL_NUMBER, LOAD_CELL_DATA_SYSCALL_NUMBER,
+        LOAD_TX_HASH_SYSCALL_NUMBER, SUCCESS,
+    },
+    verify::internal_error,
+};
+use ckb_error::{Error, InternalErrorKind};
+use ckb_vm::{
+    decoder::build_imac_decoder,
+    instructions::{extract_opcode, insts},
+    machine::DefaultMachine,
+    registers::{A0, A1, A2, A3, A4, A5, A7},
+    CoreMachine, Memory, Register, SupportMachine,
+};
+use serde_derive::Serialize;
+use std::io::Write;
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TraceEvent {
+    Step {
+        pc: u64,
+        instruction: u64,
+        opcode: u64,
+        cycles: u64,
+        writes: Vec<(usize, u64)>,
+    },
+    Syscall {
+        pc: u64,
+        number: u64,
+        args: [u64; 6],
+        return_code: u64,
+        data_length: Option<u64>,
+    },
+}
+
+pub(crate) struct Tracer<W> {
+    writer: W,
+}
+
+impl<W: Write> Tracer<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Tracer { writer }
+    }
+
+    fn write(&mut self, event: &TraceEvent) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.writer, event)
+            .map_err(|err| InternalErrorKind::System.cause(err))?;
+        self.writer
+            .write_all(b"\n")
+            .map_err(|err| InternalErrorKind::System.cause(err))?;
+        Ok(())
+    }
+
+    // Mirrors `DefaultMachine::run`, stepping one instruction at a time so
+    // the machine state can be recorded in between.
+    pub(crate) fn run<Inner: SupportMachine>(
+        &mut self,
+        machine: &mut DefaultMachine<Inner>,
+    ) -> Result<i8, Error> {
+        let decoder = build_imac_decoder::<Inner::REG>();
+        let mut registers: Vec<u64> = machine.registers().iter().map(Register::to_u64).collect();
+        machine.set_running(true);
+        while machine.running() {
+            let pc = machine.pc().to_u64();
+            let instruction = decoder
+                .decode(machine.memory_mut(), pc)
+                .map_err(internal_error)?;
+            let syscall = if extract_opcode(instruction) == insts::OP_ECALL {
+                let args = [
+                    registers[A0],
+                    registers[A1],
+                    registers[A2],
+                    registers[A3],
+                    registers[A4],
+                    registers[A5],
+                ];
+                Some((registers[A7], args))
+            } else {
+                None
+            };
+
+            machine.step(&decoder).map_err(internal_error)?;
+
+            let writes: Vec<(usize, u64)> = machine
+                .registers()
+                .iter()
+                .map(Register::to_u64)
+                .enumerate()
+                .filter(|(index, value)| registers[*index] != *value)
+                .collect();
+            for (index, value) in &writes {
+                registers[*index] = *value;
+            }
+            if let Some((number, args)) = syscall {
+                let return_code = registers[A0];
+                let data_length = if number >= LOAD_TX_HASH_SYSCALL_NUMBER
+                    && number <= LOAD_CELL_DATA_SYSCALL_NUMBER
+                    && number != LOAD_CELL_DATA_AS_CODE_SYSCALL_NUMBER
+                    && return_code == u64::from(SUCCESS)
+                {
+                    machine
+                        .memory_mut()
+                        .load64(&Inner::REG::from_u64(args[1]))
+                        .ok()
+                        .map(|length| length.to_u64())
+                } else {
+                    None
+                };
+                self.write(&TraceEvent::Syscall {
+                    pc,
+                    number,
+                    args,
+                    return_code,
+                    data_length,
+                })?;
+            }
+            self.write(&TraceEvent::Step {
+                pc,
+                instruction,
+                opcode: u64::from(extract_opcode(instruction)),
+                cycles: machine.cycles(),
+                writes,
+            })?;
+        }
+        self.writer
+            .flush()
+            .map_err(|err| InternalErrorKind::System.cause(err))?;
+        Ok(machine.exit_code())
+    }
+}
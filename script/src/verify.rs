@@ -1,9 +1,11 @@
 use crate::{
     cost_model::{instruction_cycles, transferred_byte_cycles},
+    profile::{Profiler, ScriptProfile},
     syscalls::{
         Debugger, LoadCell, LoadCellData, LoadHeader, LoadInput, LoadScript, LoadScriptHash,
         LoadTxHash, LoadWitness,
     },
+    trace::Tracer,
     type_id::TypeIdSystemScript,
     DataLoader, ScriptError,
 };
@@ -21,17 +23,17 @@ use ckb_types::{
     prelude::*,
 };
 #[cfg(has_asm)]
-use ckb_vm::{
-    machine::asm::{AsmCoreMachine, AsmMachine},
-    DefaultMachineBuilder, SupportMachine,
-};
+use ckb_vm::machine::asm::{AsmCoreMachine, AsmMachine};
 #[cfg(not(has_asm))]
+use ckb_vm::TraceMachine;
 use ckb_vm::{
-    DefaultCoreMachine, DefaultMachineBuilder, SparseMemory, SupportMachine, TraceMachine,
-    WXorXMemory,
+    machine::DefaultMachine, DefaultCoreMachine, DefaultMachineBuilder, SparseMemory,
+    SupportMachine, WXorXMemory,
 };
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::Write;
 
 // A script group is defined as scripts that share the same hash.
 // A script group will only be executed once per transaction, the
@@ -65,7 +67,7 @@ pub enum ScriptGroupType {
 // future, we might refactor this to share buffer to achive zero-copy
 pub struct TransactionScriptsVerifier<'a, DL> {
     data_loader: &'a DL,
-    debug_printer: Option<Box<dyn Fn(&Byte32, &str)>>,
+    debug_printer: Option<Box<dyn Fn(&Byte32, &str) + Sync>>,
 
     outputs: Vec<CellMeta>,
     rtx: &'a ResolvedTransaction,
@@ -155,7 +157,7 @@ impl<'a, DL: DataLoader> TransactionScriptsVerifier<'a, DL> {
         }
     }
 
-    pub fn set_debug_printer<F: Fn(&Byte32, &str) + 'static>(&mut self, func: F) {
+    pub fn set_debug_printer<F: Fn(&Byte32, &str) + Sync + 'static>(&mut self, func: F) {
         self.debug_printer = Some(Box::new(func));
     }
 
@@ -273,28 +275,44 @@ impl<'a, DL: DataLoader> TransactionScriptsVerifier<'a, DL> {
         }
     }
 
+    fn script_groups(&self) -> Vec<&ScriptGroup> {
+        self.lock_groups
+            .values()
+            .chain(self.type_groups.values())
+            .collect()
+    }
+
+    // The script group results are not cached across transactions. The same
+    // lock code with the same witness still reads the rest of the transaction
+    // through the syscalls, e.g. sighash-all hashes the whole transaction, so
+    // the result of a group can't be reused by another transaction.
     pub fn verify(&self, max_cycles: Cycle) -> Result<Cycle, Error> {
         let mut cycles: Cycle = 0;
 
         // Now run each script group
-        for group in self.lock_groups.values().chain(self.type_groups.values()) {
-            let cycle = self.verify_script_group(group, max_cycles).map_err(|e| {
-                #[cfg(feature = "logging")]
-                info!(
-                    "Error validating script group {} of transaction {}: {:?}",
-                    group.script.calc_script_hash(),
-                    self.hash(),
-                    e
-                );
-                e
-            })?;
-            let current_cycles = cycles
-                .checked_add(cycle)
-                .ok_or(ScriptError::ExceededMaximumCycles)?;
-            if current_cycles > max_cycles {
-                return Err(ScriptError::ExceededMaximumCycles.into());
-            }
-            cycles = current_cycles;
+        for group in self.script_groups() {
+            let cycle = self.verify_logged_script_group(group, max_cycles)?;
+            cycles = add_cycles(cycles, cycle, max_cycles)?;
+        }
+        Ok(cycles)
+    }
+
+    // Runs the script groups on the current rayon thread pool. Results are
+    // merged in the same order `verify` runs the groups, so both the consumed
+    // cycles and the returned error are identical to `verify`.
+    pub fn verify_parallel(&self, max_cycles: Cycle) -> Result<Cycle, Error>
+    where
+        DL: Sync,
+    {
+        let results = self
+            .script_groups()
+            .par_iter()
+            .map(|group| self.verify_logged_script_group(group, max_cycles))
+            .collect::<Vec<_>>();
+
+        let mut cycles: Cycle = 0;
+        for result in results {
+            cycles = add_cycles(cycles, result?, max_cycles)?;
         }
         Ok(cycles)
     }
@@ -317,6 +335,23 @@ impl<'a, DL: DataLoader> TransactionScriptsVerifier<'a, DL> {
         }
     }
 
+    fn verify_logged_script_group(
+        &self,
+        group: &ScriptGroup,
+        max_cycles: Cycle,
+    ) -> Result<Cycle, Error> {
+        self.verify_script_group(group, max_cycles).map_err(|e| {
+            #[cfg(feature = "logging")]
+            info!(
+                "Error validating script group {} of transaction {}: {:?}",
+                group.script.calc_script_hash(),
+                self.hash(),
+                e
+            );
+            e
+        })
+    }
+
     fn verify_script_group(&self, group: &ScriptGroup, max_cycles: Cycle) -> Result<Cycle, Error> {
         if group.script.code_hash() == TYPE_ID_CODE_HASH.pack()
             && group.script.hash_type().unpack() == ScriptHashType::Type
@@ -333,41 +368,25 @@ impl<'a, DL: DataLoader> TransactionScriptsVerifier<'a, DL> {
         }
     }
 
-    fn run(
-        &self,
-        program: &Bytes,
-        script_group: &ScriptGroup,
-        max_cycles: Cycle,
-    ) -> Result<Cycle, Error> {
-        let current_script_hash = script_group.script.calc_script_hash();
-        #[cfg(feature = "logging")]
-        let prefix = format!("script group: {}", current_script_hash);
-        let debug_printer = |message: &str| {
-            if let Some(ref printer) = self.debug_printer {
-                printer(&current_script_hash, message);
-            } else {
-                #[cfg(feature = "logging")]
-                debug!("{} DEBUG OUTPUT: {}", prefix, message);
-            };
-        };
-        #[cfg(has_asm)]
-        let machine_builder = {
-            let core_machine = AsmCoreMachine::new_with_max_cycles(max_cycles);
-            DefaultMachineBuilder::<Box<AsmCoreMachine>>::new(core_machine)
-        };
-        #[cfg(not(has_asm))]
-        let machine_builder = {
-            let core_machine =
-                DefaultCoreMachine::<u64, WXorXMemory<u64, SparseMemory<u64>>>::new_with_max_cycles(
-                    max_cycles,
-                );
-            DefaultMachineBuilder::<DefaultCoreMachine<u64, WXorXMemory<u64, SparseMemory<u64>>>>::new(core_machine)
+    fn debug_print(&self, script_hash: &Byte32, message: &str) {
+        if let Some(ref printer) = self.debug_printer {
+            printer(script_hash, message);
+        } else {
+            #[cfg(feature = "logging")]
+            debug!("script group: {} DEBUG OUTPUT: {}", script_hash, message);
         };
-        let default_machine = machine_builder
+    }
+
+    fn build_machine<'b, Inner: SupportMachine>(
+        &'b self,
+        machine_builder: DefaultMachineBuilder<'b, Inner>,
+        script_group: &'b ScriptGroup,
+        current_script_hash: Byte32,
+        debug_printer: &'b dyn Fn(&str),
+    ) -> DefaultMachine<'b, Inner> {
+        machine_builder
             .instruction_cycle_func(Box::new(instruction_cycles))
-            .syscall(Box::new(
-                self.build_load_script_hash(current_script_hash.clone()),
-            ))
+            .syscall(Box::new(self.build_load_script_hash(current_script_hash)))
             .syscall(Box::new(self.build_load_tx_hash()))
             .syscall(Box::new(self.build_load_cell(
                 &script_group.input_indices,
@@ -387,8 +406,34 @@ impl<'a, DL: DataLoader> TransactionScriptsVerifier<'a, DL> {
                 &script_group.input_indices,
                 &script_group.output_indices,
             )))
-            .syscall(Box::new(Debugger::new(&debug_printer)))
-            .build();
+            .syscall(Box::new(Debugger::new(debug_printer)))
+            .build()
+    }
+
+    fn run(
+        &self,
+        program: &Bytes,
+        script_group: &ScriptGroup,
+        max_cycles: Cycle,
+    ) -> Result<Cycle, Error> {
+        let current_script_hash = script_group.script.calc_script_hash();
+        let debug_printer = |message: &str| self.debug_print(&current_script_hash, message);
+        #[cfg(has_asm)]
+        let machine_builder = {
+            let core_machine = AsmCoreMachine::new_with_max_cycles(max_cycles);
+            DefaultMachineBuilder::<Box<AsmCoreMachine>>::new(core_machine)
+        };
+        #[cfg(not(has_asm))]
+        let machine_builder = {
+            let core_machine = InterpreterCoreMachine::new_with_max_cycles(max_cycles);
+            DefaultMachineBuilder::<InterpreterCoreMachine>::new(core_machine)
+        };
+        let default_machine = self.build_machine(
+            machine_builder,
+            script_group,
+            current_script_hash.clone(),
+            &debug_printer,
+        );
         #[cfg(has_asm)]
         let mut machine = AsmMachine::new(default_machine, None);
         #[cfg(not(has_asm))]
@@ -407,15 +452,118 @@ impl<'a, DL: DataLoader> TransactionScriptsVerifier<'a, DL> {
             Err(ScriptError::ValidationFailure(code).into())
         }
     }
+
+    pub fn contains_script_group(
+        &self,
+        script_group_type: &ScriptGroupType,
+        script_hash: &Byte32,
+    ) -> bool {
+        match script_group_type {
+            ScriptGroupType::Lock => self.lock_groups.contains_key(script_hash),
+            ScriptGroupType::Type => self.type_groups.contains_key(script_hash),
+        }
+    }
+
+    // Same as `verify_single`, but also writes the execution trace of the
+    // script group to `writer`, see the `trace` module for the format. The
+    // traced script always runs on the interpreter, which consumes the same
+    // cycles as the asm machine. The type id script is verified natively and
+    // leaves the trace empty.
+    pub fn trace_single<W: Write>(
+        &self,
+        script_group_type: &ScriptGroupType,
+        script_hash: &Byte32,
+        max_cycles: Cycle,
+        writer: W,
+    ) -> Result<Cycle, Error> {
+        let mut tracer = Tracer::new(writer);
+        self.run_interpreter(script_group_type, script_hash, max_cycles, |machine| {
+            tracer.run(machine)
+        })
+    }
+
+    // Same as `verify_single`, but also profiles the time spent per
+    // instruction class and per syscall, see the `profile` module. Like
+    // `trace_single` the script runs on the interpreter, and the type id
+    // script leaves the profile empty except for the consumed cycles.
+    pub fn profile_single(
+        &self,
+        script_group_type: &ScriptGroupType,
+        script_hash: &Byte32,
+        max_cycles: Cycle,
+    ) -> Result<ScriptProfile, Error> {
+        let mut profiler = Profiler::new();
+        let cycles =
+            self.run_interpreter(script_group_type, script_hash, max_cycles, |machine| {
+                profiler.run(machine)
+            })?;
+        Ok(profiler.into_profile(cycles))
+    }
+
+    fn run_interpreter<F>(
+        &self,
+        script_group_type: &ScriptGroupType,
+        script_hash: &Byte32,
+        max_cycles: Cycle,
+        run: F,
+    ) -> Result<Cycle, Error>
+    where
+        F: FnOnce(&mut DefaultMachine<InterpreterCoreMachine>) -> Result<i8, Error>,
+    {
+        let group = match script_group_type {
+            ScriptGroupType::Lock => self.lock_groups.get(script_hash),
+            ScriptGroupType::Type => self.type_groups.get(script_hash),
+        }
+        .ok_or(ScriptError::InvalidCodeHash)?;
+        if group.script.code_hash() == TYPE_ID_CODE_HASH.pack()
+            && group.script.hash_type().unpack() == ScriptHashType::Type
+        {
+            return self.verify_script_group(group, max_cycles);
+        }
+
+        let program = self.extract_script(&group.script)?;
+        let debug_printer = |message: &str| self.debug_print(script_hash, message);
+        let machine_builder = DefaultMachineBuilder::<InterpreterCoreMachine>::new(
+            InterpreterCoreMachine::new_with_max_cycles(max_cycles),
+        );
+        let mut machine =
+            self.build_machine(machine_builder, group, script_hash.clone(), &debug_printer);
+        let bytes = machine
+            .load_program(&program, &[])
+            .map_err(internal_error)?;
+        machine
+            .add_cycles(transferred_byte_cycles(bytes))
+            .map_err(internal_error)?;
+        let code = run(&mut machine)?;
+        if code == 0 {
+            Ok(machine.cycles())
+        } else {
+            Err(ScriptError::ValidationFailure(code).into())
+        }
+    }
+}
+
+type InterpreterCoreMachine = DefaultCoreMachine<u64, WXorXMemory<u64, SparseMemory<u64>>>;
+
+fn add_cycles(cycles: Cycle, cycle: Cycle, max_cycles: Cycle) -> Result<Cycle, Error> {
+    let current_cycles = cycles
+        .checked_add(cycle)
+        .ok_or(ScriptError::ExceededMaximumCycles)?;
+    if current_cycles > max_cycles {
+        Err(ScriptError::ExceededMaximumCycles.into())
+    } else {
+        Ok(current_cycles)
+    }
 }
 
-fn internal_error(error: ckb_vm::Error) -> Error {
+pub(crate) fn internal_error(error: ckb_vm::Error) -> Error {
     InternalErrorKind::VM.reason(format!("{:?}", error)).into()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cost_model::InstructionClass;
     use byteorder::{ByteOrder, LittleEndian};
     use ckb_crypto::secp::{Generator, Privkey, Pubkey, Signature};
     use ckb_db::RocksDB;
@@ -1120,6 +1268,164 @@ mod tests {
         );
     }
 
+    // A transaction spending a cell locked by always_success, returns the
+    // transaction and the hash of the lock
+    fn always_success_lock_rtx() -> (ResolvedTransaction, Byte32) {
+        let (always_success_cell, always_success_cell_data, always_success_script) =
+            always_success_cell();
+        let output = CellOutputBuilder::default()
+            .capacity(capacity_bytes!(100).pack())
+            .lock(always_success_script.clone())
+            .build();
+        let transaction = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::null(), 0))
+            .build();
+        let dummy_cell = CellMetaBuilder::from_cell_output(output, Bytes::new())
+            .transaction_info(default_transaction_info())
+            .build();
+        let always_success_cell = CellMetaBuilder::from_cell_output(
+            always_success_cell.clone(),
+            always_success_cell_data.to_owned(),
+        )
+        .transaction_info(default_transaction_info())
+        .build();
+
+        let rtx = ResolvedTransaction {
+            transaction,
+            resolved_cell_deps: vec![always_success_cell],
+            resolved_inputs: vec![dummy_cell],
+            resolved_dep_groups: vec![],
+        };
+        (rtx, always_success_script.calc_script_hash())
+    }
+
+    #[test]
+    fn check_trace_single() {
+        let (rtx, script_hash) = always_success_lock_rtx();
+        let store = new_store();
+        let data_loader = DataLoaderWrapper::new(&store);
+        let verifier = TransactionScriptsVerifier::new(&rtx, &data_loader);
+
+        let mut trace = Vec::new();
+        let cycles = verifier
+            .trace_single(&ScriptGroupType::Lock, &script_hash, 600, &mut trace)
+            .unwrap();
+        assert_eq!(
+            verifier
+                .verify_single(&ScriptGroupType::Lock, &script_hash, 600)
+                .unwrap(),
+            cycles
+        );
+
+        let events = String::from_utf8(trace)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let last_step = events.last().unwrap();
+        assert_eq!(last_step["type"], "step");
+        assert_eq!(last_step["cycles"], cycles);
+        // always_success exits through the exit syscall
+        assert!(events
+            .iter()
+            .any(|event| event["type"] == "syscall" && event["number"] == 93));
+    }
+
+    #[test]
+    fn check_profile_single() {
+        let (rtx, script_hash) = always_success_lock_rtx();
+        let store = new_store();
+        let data_loader = DataLoaderWrapper::new(&store);
+        let verifier = TransactionScriptsVerifier::new(&rtx, &data_loader);
+
+        let profile = verifier
+            .profile_single(&ScriptGroupType::Lock, &script_hash, 600)
+            .unwrap();
+        assert_eq!(
+            verifier
+                .verify_single(&ScriptGroupType::Lock, &script_hash, 600)
+                .unwrap(),
+            profile.cycles
+        );
+        let executed_cycles: Cycle = profile
+            .instructions
+            .values()
+            .map(|entry| entry.cycles)
+            .sum();
+        assert!(executed_cycles < profile.cycles);
+        // always_success exits through the exit syscall
+        let exit = profile.syscalls[&93];
+        assert_eq!(exit.count, 1);
+        assert_eq!(
+            profile.instructions[&InstructionClass::Environment].count,
+            exit.count
+        );
+    }
+
+    #[test]
+    fn check_parallel_script_groups() {
+        let mut file = open_cell_always_success();
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).unwrap();
+
+        let script = Script::new_builder()
+            .code_hash(blake2b_256(&buffer).pack())
+            .hash_type(ScriptHashType::Data.pack())
+            .build();
+
+        let dep_out_point = OutPoint::new(h256!("0x123").pack(), 8);
+        let cell_dep = CellDep::new_builder()
+            .out_point(dep_out_point.clone())
+            .build();
+        let data = Bytes::from(buffer);
+        let output = CellOutputBuilder::default()
+            .capacity(Capacity::bytes(data.len()).unwrap().pack())
+            .build();
+        let dep_cell = CellMetaBuilder::from_cell_output(output, data)
+            .transaction_info(default_transaction_info())
+            .out_point(dep_out_point.clone())
+            .build();
+
+        let transaction = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::null(), 0))
+            .cell_dep(cell_dep)
+            .build();
+
+        let output = CellOutputBuilder::default()
+            .capacity(capacity_bytes!(100).pack())
+            .lock(script.clone())
+            .type_(Some(script.clone()).pack())
+            .build();
+        let dummy_cell = CellMetaBuilder::from_cell_output(output, Bytes::new())
+            .transaction_info(default_transaction_info())
+            .build();
+
+        let rtx = ResolvedTransaction {
+            transaction,
+            resolved_cell_deps: vec![dep_cell],
+            resolved_inputs: vec![dummy_cell],
+            resolved_dep_groups: vec![],
+        };
+        let store = new_store();
+        let data_loader = DataLoaderWrapper::new(&store);
+
+        let verifier = TransactionScriptsVerifier::new(&rtx, &data_loader);
+        assert_eq!(
+            verifier.verify_parallel(100_000_000).ok(),
+            Some(ALWAYS_SUCCESS_SCRIPT_CYCLE * 2)
+        );
+        assert_error_eq!(
+            verifier
+                .verify_parallel(ALWAYS_SUCCESS_SCRIPT_CYCLE * 2 - 1)
+                .unwrap_err(),
+            ScriptError::ExceededMaximumCycles,
+        );
+        assert_eq!(
+            verifier.verify(100_000_000).ok(),
+            verifier.verify_parallel(100_000_000).ok()
+        );
+    }
+
     #[test]
     fn check_type_id_one_in_one_out() {
         let (always_success_cell, always_success_cell_data, always_success_script) =
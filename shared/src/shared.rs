@@ -4,10 +4,10 @@ use ckb_chain_spec::consensus::Consensus;
 use ckb_chain_spec::SpecError;
 use ckb_db::{DBConfig, RocksDB};
 use ckb_error::{Error, InternalErrorKind};
-use ckb_logger::info_target;
+use ckb_logger::{error, info_target};
 use ckb_proposal_table::{ProposalTable, ProposalView};
 use ckb_store::ChainDB;
-use ckb_store::{ChainStore, StoreConfig, COLUMNS};
+use ckb_store::{migrations, ChainStore, StoreConfig, COLUMNS};
 use ckb_tx_pool::{
     BlockAssemblerConfig, PollLock, TxPoolConfig, TxPoolController, TxPoolServiceBuilder,
 };
@@ -17,16 +17,21 @@ use ckb_types::{
     prelude::*,
     U256,
 };
+use ckb_verification::VerificationConfig;
 use im::hashmap::HashMap as HamtMap;
 use lru_cache::LruCache;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use std::collections::HashSet;
 use std::sync::Arc;
+use std::thread;
 
 #[derive(Clone)]
 pub struct Shared {
     pub(crate) store: Arc<ChainDB>,
     pub(crate) tx_pool_controller: TxPoolController,
     pub(crate) txs_verify_cache: PollLock<LruCache<Byte32, Cycle>>,
+    pub(crate) verification_config: VerificationConfig,
+    pub(crate) block_verify_pool: Option<Arc<ThreadPool>>,
     pub(crate) consensus: Arc<Consensus>,
     pub(crate) snapshot_mgr: Arc<SnapshotMgr>,
 }
@@ -37,6 +42,7 @@ impl Shared {
         consensus: Consensus,
         tx_pool_config: TxPoolConfig,
         block_assembler_config: Option<BlockAssemblerConfig>,
+        verification_config: VerificationConfig,
     ) -> Result<(Self, ProposalTable), Error> {
         let (tip_header, epoch) = Self::init_store(&store, &consensus)?;
         let total_difficulty = store
@@ -50,6 +56,19 @@ impl Shared {
         let consensus = Arc::new(consensus);
 
         let txs_verify_cache = PollLock::new(LruCache::new(tx_pool_config.max_verify_cache_size));
+        let block_verify_pool = if verification_config.block_verify_threads > 0 {
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(verification_config.block_verify_threads)
+                .thread_name(|index| format!("BlockVerifier-{}", index))
+                .build()
+                .map_err(|err| {
+                    InternalErrorKind::System
+                        .reason(format!("failed to build block verify pool: {}", err))
+                })?;
+            Some(Arc::new(pool))
+        } else {
+            None
+        };
         let snapshot = Arc::new(Snapshot::new(
             tip_header,
             total_difficulty,
@@ -75,6 +94,8 @@ impl Shared {
             store,
             consensus,
             txs_verify_cache,
+            verification_config,
+            block_verify_pool,
             snapshot_mgr,
             tx_pool_controller,
         };
@@ -145,6 +166,15 @@ impl Shared {
         store: &ChainDB,
         consensus: &Consensus,
     ) -> Result<(HeaderView, EpochExt), Error> {
+        // The cell set and the indexes are incomplete until the reindex is done
+        if let Some(number) = store.get_reindex_number() {
+            return Err(InternalErrorKind::Database
+                .reason(format!(
+                    "the reindex was interrupted at block {}, run `ckb db reindex` to resume it",
+                    number
+                ))
+                .into());
+        }
         match store
             .get_tip_header()
             .and_then(|header| store.get_current_epoch_ext().map(|epoch| (header, epoch)))
@@ -184,6 +214,14 @@ impl Shared {
         self.txs_verify_cache.clone()
     }
 
+    pub fn verification_config(&self) -> &VerificationConfig {
+        &self.verification_config
+    }
+
+    pub fn block_verify_pool(&self) -> Option<&ThreadPool> {
+        self.block_verify_pool.as_ref().map(AsRef::as_ref)
+    }
+
     pub fn snapshot(&self) -> Guard<Arc<Snapshot>> {
         self.snapshot_mgr.load()
     }
@@ -222,6 +260,23 @@ impl Shared {
     pub fn store(&self) -> &ChainDB {
         &self.store
     }
+
+    /// Catches up the pruning in a background thread, see
+    /// `ChainDB::catch_up_pruning`.
+    pub fn spawn_pruning(&self) {
+        if !self.store.is_pruning_in_background() {
+            return;
+        }
+        let store = Arc::clone(&self.store);
+        thread::Builder::new()
+            .name("PruneCatchUp".to_string())
+            .spawn(move || {
+                if let Err(err) = store.catch_up_pruning() {
+                    error!("catch up the pruning error: {}", err);
+                }
+            })
+            .expect("start the pruning thread failed");
+    }
 }
 
 pub struct SharedBuilder {
@@ -230,6 +285,7 @@ pub struct SharedBuilder {
     tx_pool_config: Option<TxPoolConfig>,
     store_config: Option<StoreConfig>,
     block_assembler_config: Option<BlockAssemblerConfig>,
+    verification_config: Option<VerificationConfig>,
 }
 
 impl Default for SharedBuilder {
@@ -240,13 +296,15 @@ impl Default for SharedBuilder {
             tx_pool_config: None,
             store_config: None,
             block_assembler_config: None,
+            verification_config: None,
         }
     }
 }
 
 impl SharedBuilder {
     pub fn with_db_config(config: &DBConfig) -> Self {
-        let db = RocksDB::open(config, COLUMNS);
+        let db = RocksDB::open_with_migrations(config, COLUMNS, &migrations())
+            .unwrap_or_else(|err| panic!("{}", err));
         SharedBuilder {
             db,
             ..Default::default()
@@ -275,16 +333,23 @@ impl SharedBuilder {
         self
     }
 
+    pub fn verification_config(mut self, config: VerificationConfig) -> Self {
+        self.verification_config = Some(config);
+        self
+    }
+
     pub fn build(self) -> Result<(Shared, ProposalTable), Error> {
         let consensus = self.consensus.unwrap_or_else(Consensus::default);
         let tx_pool_config = self.tx_pool_config.unwrap_or_else(Default::default);
         let store_config = self.store_config.unwrap_or_else(Default::default);
+        let verification_config = self.verification_config.unwrap_or_else(Default::default);
         let store = ChainDB::new(self.db, store_config);
         Shared::init(
             store,
             consensus,
             tx_pool_config,
             self.block_assembler_config,
+            verification_config,
         )
     }
 }
@@ -1,19 +1,87 @@
 use crate::config::StoreConfig;
 use ckb_types::{
     bytes::Bytes,
-    core::{HeaderView, TransactionView, UncleBlockVecView},
+    core::{BlockNumber, HeaderView, TransactionView, UncleBlockVecView},
     packed::{Byte32, ProposalShortIdVec},
 };
 use ckb_util::Mutex;
 use lru_cache::LruCache;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A LRU cache counting its hits and misses
+pub struct Cache<K: Eq + Hash, V> {
+    inner: Mutex<LruCache<K, V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub len: u64,
+    pub capacity: u64,
+}
+
+impl<K: Eq + Hash, V: Clone> Cache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Cache {
+            inner: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let ret = self.inner.lock().get_refresh(key).cloned();
+        self.count(ret.is_some());
+        ret
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        let ret = self.inner.lock().get_refresh(key).is_some();
+        self.count(ret);
+        ret
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        self.inner.lock().insert(key, value);
+    }
+
+    pub fn remove(&self, key: &K) {
+        self.inner.lock().remove(key);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            len: inner.len() as u64,
+            capacity: inner.capacity() as u64,
+        }
+    }
+
+    fn count(&self, hit: bool) {
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
 
 pub struct StoreCache {
-    pub headers: Mutex<LruCache<Byte32, HeaderView>>,
-    pub cell_data: Mutex<LruCache<(Byte32, u32), (Bytes, Byte32)>>,
-    pub block_proposals: Mutex<LruCache<Byte32, ProposalShortIdVec>>,
-    pub block_tx_hashes: Mutex<LruCache<Byte32, Vec<Byte32>>>,
-    pub block_uncles: Mutex<LruCache<Byte32, UncleBlockVecView>>,
-    pub cellbase: Mutex<LruCache<Byte32, TransactionView>>,
+    pub headers: Cache<Byte32, HeaderView>,
+    pub cell_data: Cache<(Byte32, u32), (Bytes, Byte32)>,
+    pub cell_data_by_hash: Cache<Byte32, Bytes>,
+    pub block_proposals: Cache<Byte32, ProposalShortIdVec>,
+    pub block_tx_hashes: Cache<Byte32, Vec<Byte32>>,
+    pub block_uncles: Cache<Byte32, UncleBlockVecView>,
+    pub cellbase: Cache<Byte32, TransactionView>,
+    // The number of the next block to prune, 0 if the pruning is not started
+    pruned_number: AtomicU64,
 }
 
 impl Default for StoreCache {
@@ -25,12 +93,37 @@ impl Default for StoreCache {
 impl StoreCache {
     pub fn from_config(config: StoreConfig) -> Self {
         StoreCache {
-            headers: Mutex::new(LruCache::new(config.header_cache_size)),
-            cell_data: Mutex::new(LruCache::new(config.cell_data_cache_size)),
-            block_proposals: Mutex::new(LruCache::new(config.block_proposals_cache_size)),
-            block_tx_hashes: Mutex::new(LruCache::new(config.block_tx_hashes_cache_size)),
-            block_uncles: Mutex::new(LruCache::new(config.block_uncles_cache_size)),
-            cellbase: Mutex::new(LruCache::new(config.cellbase_cache_size)),
+            headers: Cache::new(config.header_cache_size),
+            cell_data: Cache::new(config.cell_data_cache_size),
+            cell_data_by_hash: Cache::new(config.cell_data_cache_size),
+            block_proposals: Cache::new(config.block_proposals_cache_size),
+            block_tx_hashes: Cache::new(config.block_tx_hashes_cache_size),
+            block_uncles: Cache::new(config.block_uncles_cache_size),
+            cellbase: Cache::new(config.cellbase_cache_size),
+            pruned_number: AtomicU64::new(0),
         }
     }
+
+    /// The bodies of the blocks below this number, except the genesis block,
+    /// are pruned, 0 if the pruning is not started
+    pub fn pruned_number(&self) -> BlockNumber {
+        self.pruned_number.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_pruned_number(&self, pruned_number: BlockNumber) {
+        self.pruned_number.store(pruned_number, Ordering::Release);
+    }
+
+    /// Returns the stats of the caches by their names
+    pub fn stats(&self) -> Vec<(&'static str, CacheStats)> {
+        vec![
+            ("headers", self.headers.stats()),
+            ("cell_data", self.cell_data.stats()),
+            ("cell_data_by_hash", self.cell_data_by_hash.stats()),
+            ("block_proposals", self.block_proposals.stats()),
+            ("block_tx_hashes", self.block_tx_hashes.stats()),
+            ("block_uncles", self.block_uncles.stats()),
+            ("cellbase", self.cellbase.stats()),
+        ]
+    }
 }
@@ -0,0 +1,109 @@
+use ckb_types::{bytes::Bytes, packed, prelude::*};
+
+// With `StoreConfig::cell_data_dedup` enabled, non-empty cell data is stored
+// once per data hash in `COLUMN_CELL_DATA` and left empty in the block body.
+// `COLUMN_CELL_DATA_INDEX` then maps the transaction key of such a body to
+// one item per output: the data hash followed by the data length in little
+// endian, or zeros if the data is stored inline. `COLUMN_CELL_DATA_REFS`
+// counts the outputs referencing each data hash, so the data can be dropped
+// when the last block referencing it is deleted.
+const INDEX_ITEM_SIZE: usize = 32 + 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CellDataIndex(Vec<Option<(packed::Byte32, u64)>>);
+
+impl CellDataIndex {
+    // Returns the index of the deduplicated outputs data of a transaction,
+    // or None if all the data are stored inline.
+    pub(crate) fn new(outputs_data: &[Bytes]) -> Option<Self> {
+        let items: Vec<_> = outputs_data
+            .iter()
+            .map(|data| {
+                if data.is_empty() {
+                    None
+                } else {
+                    Some((packed::CellOutput::calc_data_hash(data), data.len() as u64))
+                }
+            })
+            .collect();
+        if items.iter().any(Option::is_some) {
+            Some(CellDataIndex(items))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn from_slice(slice: &[u8]) -> Self {
+        let items = slice
+            .chunks(INDEX_ITEM_SIZE)
+            .map(|item| {
+                let (hash, len) = item.split_at(32);
+                if hash.iter().all(|byte| *byte == 0) {
+                    None
+                } else {
+                    let mut len_bytes = [0u8; 8];
+                    len_bytes.copy_from_slice(len);
+                    Some((
+                        packed::Byte32Reader::from_slice_should_be_ok(hash).to_entity(),
+                        u64::from_le_bytes(len_bytes),
+                    ))
+                }
+            })
+            .collect();
+        CellDataIndex(items)
+    }
+
+    pub(crate) fn to_vec(&self) -> Vec<u8> {
+        let mut vec = Vec::with_capacity(self.0.len() * INDEX_ITEM_SIZE);
+        for item in &self.0 {
+            match item {
+                Some((hash, len)) => {
+                    vec.extend_from_slice(hash.as_slice());
+                    vec.extend_from_slice(&len.to_le_bytes());
+                }
+                None => vec.extend_from_slice(&[0u8; INDEX_ITEM_SIZE]),
+            }
+        }
+        vec
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&(packed::Byte32, u64)> {
+        self.0.get(index).and_then(Option::as_ref)
+    }
+
+    pub(crate) fn hashes(&self) -> impl Iterator<Item = &packed::Byte32> {
+        self.0
+            .iter()
+            .filter_map(|item| item.as_ref().map(|(hash, _)| hash))
+    }
+}
+
+pub(crate) fn encode_refs(refs: u64) -> [u8; 8] {
+    refs.to_le_bytes()
+}
+
+pub(crate) fn decode_refs(slice: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(slice);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_data_index_round_trip() {
+        let outputs_data = vec![Bytes::new(), Bytes::from(vec![1u8; 100]), Bytes::new()];
+        let index = CellDataIndex::new(&outputs_data).unwrap();
+        assert_eq!(index, CellDataIndex::from_slice(&index.to_vec()));
+        assert_eq!(index.get(0), None);
+        assert_eq!(
+            index.get(1),
+            Some(&(packed::CellOutput::calc_data_hash(&outputs_data[1]), 100))
+        );
+        assert_eq!(index.hashes().count(), 1);
+
+        assert_eq!(CellDataIndex::new(&[Bytes::new()]), None);
+    }
+}
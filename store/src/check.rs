@@ -0,0 +1,721 @@
+//! Verifies the invariants of the stored chain, see `ChainDB::check`.
+use crate::db::ChainDB;
+use crate::store::ChainStore;
+use crate::{COLUMN_EPOCH, COLUMN_INDEX, COLUMN_TRANSACTION_INFO, COLUMN_UNCLES};
+use ckb_db::Direction;
+use ckb_error::Error;
+use ckb_types::{
+    core::{
+        BlockExt, BlockNumber, BlockView, EpochExt, EpochNumber, HeaderView, TransactionInfo,
+        TransactionMeta,
+    },
+    packed,
+    prelude::*,
+};
+use std::collections::HashMap;
+use std::fmt;
+
+/// An inconsistency found by `ChainDB::check`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inconsistency {
+    /// The tip header is missing
+    MissingTip,
+    /// A header of the main chain is missing, the blocks below it are not checked
+    MissingHeader {
+        number: BlockNumber,
+        hash: packed::Byte32,
+    },
+    /// The uncles, proposals or transactions of a block are missing or do
+    /// not match its header
+    InvalidBody {
+        number: BlockNumber,
+        hash: packed::Byte32,
+        reason: String,
+    },
+    /// The main chain index from the number to the hash, `expected` is None
+    /// above the tip
+    BlockHashIndex {
+        number: BlockNumber,
+        expected: Option<packed::Byte32>,
+        actual: Option<packed::Byte32>,
+    },
+    /// The main chain index from the hash to the number, `expected` is None
+    /// for the blocks not in the main chain
+    BlockNumberIndex {
+        hash: packed::Byte32,
+        expected: Option<BlockNumber>,
+        actual: Option<BlockNumber>,
+    },
+    /// The transaction info, `expected` is None for the transactions not in
+    /// the main chain
+    TransactionInfo {
+        tx_hash: packed::Byte32,
+        expected: Option<TransactionInfo>,
+        actual: Option<TransactionInfo>,
+    },
+    /// An uncle of a main chain block is missing in the uncles index
+    MissingUncle { header: HeaderView },
+    MissingBlockExt {
+        number: BlockNumber,
+        hash: packed::Byte32,
+    },
+    /// The total difficulty or the total uncles count of a block is wrong
+    BlockExt {
+        number: BlockNumber,
+        hash: packed::Byte32,
+        expected: BlockExt,
+        actual: BlockExt,
+    },
+    /// The epoch of a block is missing or does not contain the block
+    BlockEpoch {
+        number: BlockNumber,
+        hash: packed::Byte32,
+    },
+    /// The index from the epoch number to the epoch of the main chain
+    EpochIndex {
+        number: EpochNumber,
+        expected: packed::Byte32,
+        actual: Option<packed::Byte32>,
+    },
+    /// The current epoch is not the epoch of the tip
+    CurrentEpoch {
+        expected: EpochExt,
+        actual: Option<EpochExt>,
+    },
+    /// The cell set differs from a replay of the main chain transactions
+    CellSet {
+        tx_hash: packed::Byte32,
+        expected: Option<TransactionMeta>,
+        actual: Option<TransactionMeta>,
+    },
+}
+
+impl Inconsistency {
+    /// Whether the inconsistency can be fixed from the other data in the store
+    pub fn is_fixable(&self) -> bool {
+        match self {
+            Inconsistency::MissingTip
+            | Inconsistency::MissingHeader { .. }
+            | Inconsistency::InvalidBody { .. }
+            | Inconsistency::MissingBlockExt { .. }
+            | Inconsistency::BlockEpoch { .. } => false,
+            _ => true,
+        }
+    }
+}
+
+fn display_opt<T: fmt::Display>(value: &Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "none".to_owned(),
+    }
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Inconsistency::MissingTip => write!(f, "the tip header is missing"),
+            Inconsistency::MissingHeader { number, hash } => {
+                write!(f, "the header of block {} {} is missing", number, hash)
+            }
+            Inconsistency::InvalidBody {
+                number,
+                hash,
+                reason,
+            } => write!(
+                f,
+                "the body of block {} {} is invalid: {}",
+                number, hash, reason
+            ),
+            Inconsistency::BlockHashIndex {
+                number,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "block {} is indexed to {}, expect {}",
+                number,
+                display_opt(actual),
+                display_opt(expected)
+            ),
+            Inconsistency::BlockNumberIndex {
+                hash,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "block {} is indexed to number {}, expect {}",
+                hash,
+                display_opt(actual),
+                display_opt(expected)
+            ),
+            Inconsistency::TransactionInfo {
+                tx_hash,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "transaction {} is indexed to {:?}, expect {:?}",
+                tx_hash, actual, expected
+            ),
+            Inconsistency::MissingUncle { header } => {
+                write!(f, "uncle {} is missing in the uncles index", header.hash())
+            }
+            Inconsistency::MissingBlockExt { number, hash } => {
+                write!(f, "the ext of block {} {} is missing", number, hash)
+            }
+            Inconsistency::BlockExt {
+                number,
+                hash,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "the ext of block {} {} has total difficulty {:#x} and total uncles {}, \
+                 expect {:#x} and {}",
+                number,
+                hash,
+                actual.total_difficulty,
+                actual.total_uncles_count,
+                expected.total_difficulty,
+                expected.total_uncles_count
+            ),
+            Inconsistency::BlockEpoch { number, hash } => write!(
+                f,
+                "the epoch of block {} {} is missing or does not contain it",
+                number, hash
+            ),
+            Inconsistency::EpochIndex {
+                number,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "epoch {} is indexed to {}, expect {}",
+                number,
+                display_opt(actual),
+                expected
+            ),
+            Inconsistency::CurrentEpoch { expected, actual } => write!(
+                f,
+                "the current epoch is {}, expect {}",
+                display_opt(&actual.as_ref().map(EpochExt::number)),
+                expected.number()
+            ),
+            Inconsistency::CellSet {
+                tx_hash,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "the cell set entry of transaction {} is {:?}, expect {:?}",
+                tx_hash, actual, expected
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CheckReport {
+    /// The number of the checked main chain blocks
+    pub checked_blocks: u64,
+    /// Whether the cell set is checked, it is not for a pruned store, whose
+    /// old transactions can not be replayed, nor when the cell set can not be
+    /// read
+    pub cell_set_checked: bool,
+    pub inconsistencies: Vec<Inconsistency>,
+}
+
+impl CheckReport {
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+}
+
+impl ChainDB {
+    /// Walks the main chain from the tip to the genesis and verifies the
+    /// header and body linkage, the main chain indexes, the block exts, the
+    /// epochs and, unless the store is pruned, the cell set.
+    pub fn check(&self) -> CheckReport {
+        let mut report = CheckReport::default();
+        let tip = match self.get_tip_header() {
+            Some(tip) => tip,
+            None => {
+                report.inconsistencies.push(Inconsistency::MissingTip);
+                return report;
+            }
+        };
+        let main_chain = match self.main_chain(&tip, &mut report) {
+            Some(main_chain) => main_chain,
+            None => return report,
+        };
+        report.checked_blocks = main_chain.len() as u64;
+        report.cell_set_checked = self.get_pruned_number().unwrap_or(0) <= 1;
+
+        let mut parent_ext: Option<BlockExt> = None;
+        let mut last_epoch: Option<EpochNumber> = None;
+        let mut cell_set: HashMap<packed::Byte32, TransactionMeta> = HashMap::new();
+        for header in main_chain.iter() {
+            let hash = header.hash();
+            self.check_main_chain_index(header, &mut report);
+            parent_ext = self.check_block_ext(header, parent_ext.as_ref(), &mut report);
+            if let Some(epoch) = self.check_block_epoch(header, &mut report) {
+                if last_epoch != Some(epoch.number()) {
+                    last_epoch = Some(epoch.number());
+                    self.check_epoch_index(header, &epoch, &mut report);
+                }
+            }
+            if self.is_block_pruned(&hash) {
+                continue;
+            }
+            if let Some(block) = self.check_block_body(header, &mut report) {
+                self.check_block_indexes(&block, &mut report);
+                if report.cell_set_checked {
+                    replay_block_cells(&block, &mut cell_set);
+                }
+            } else {
+                report.cell_set_checked = false;
+            }
+        }
+
+        self.check_current_epoch(&tip, &mut report);
+        self.check_stale_indexes(&main_chain, &mut report);
+        if report.cell_set_checked {
+            self.check_cell_set(cell_set, &mut report);
+        }
+        report
+    }
+
+    /// Fixes the fixable inconsistencies, returns the number of the fixed ones.
+    pub fn fix(&self, inconsistencies: &[Inconsistency]) -> Result<usize, Error> {
+        let txn = self.begin_transaction();
+        let mut fixed = 0;
+        for inconsistency in inconsistencies {
+            match inconsistency {
+                Inconsistency::BlockHashIndex {
+                    number, expected, ..
+                } => {
+                    let key: packed::Uint64 = number.pack();
+                    match expected {
+                        Some(hash) => {
+                            txn.insert_raw(COLUMN_INDEX, key.as_slice(), hash.as_slice())?
+                        }
+                        None => txn.delete(COLUMN_INDEX, key.as_slice())?,
+                    }
+                }
+                Inconsistency::BlockNumberIndex { hash, expected, .. } => match expected {
+                    Some(number) => {
+                        let value: packed::Uint64 = number.pack();
+                        txn.insert_raw(COLUMN_INDEX, hash.as_slice(), value.as_slice())?
+                    }
+                    None => txn.delete(COLUMN_INDEX, hash.as_slice())?,
+                },
+                Inconsistency::TransactionInfo {
+                    tx_hash, expected, ..
+                } => match expected {
+                    Some(info) => txn.insert_raw(
+                        COLUMN_TRANSACTION_INFO,
+                        tx_hash.as_slice(),
+                        info.pack().as_slice(),
+                    )?,
+                    None => txn.delete(COLUMN_TRANSACTION_INFO, tx_hash.as_slice())?,
+                },
+                Inconsistency::MissingUncle { header } => txn.insert_raw(
+                    COLUMN_UNCLES,
+                    header.hash().as_slice(),
+                    header.pack().as_slice(),
+                )?,
+                Inconsistency::BlockExt { hash, expected, .. } => {
+                    txn.insert_block_ext(hash, expected)?
+                }
+                Inconsistency::EpochIndex {
+                    number, expected, ..
+                } => {
+                    let key: packed::Uint64 = number.pack();
+                    txn.insert_raw(COLUMN_EPOCH, key.as_slice(), expected.as_slice())?
+                }
+                Inconsistency::CurrentEpoch { expected, .. } => {
+                    txn.insert_current_epoch_ext(expected)?
+                }
+                Inconsistency::CellSet {
+                    tx_hash, expected, ..
+                } => match expected {
+                    Some(meta) => txn.update_cell_set(tx_hash, &meta.pack())?,
+                    None => txn.delete_cell_set(tx_hash)?,
+                },
+                _ => continue,
+            }
+            fixed += 1;
+        }
+        txn.commit()?;
+        Ok(fixed)
+    }
+
+    // Collects the main chain headers from the genesis to the tip by the
+    // parent hashes.
+    fn main_chain(&self, tip: &HeaderView, report: &mut CheckReport) -> Option<Vec<HeaderView>> {
+        let mut headers = Vec::with_capacity(tip.number() as usize + 1);
+        let mut header = tip.to_owned();
+        loop {
+            let parent_number = header.number().checked_sub(1);
+            let parent_hash = header.parent_hash();
+            headers.push(header);
+            let parent_number = match parent_number {
+                Some(parent_number) => parent_number,
+                None => break,
+            };
+            header = match self.get_block_header(&parent_hash) {
+                Some(parent) if parent.number() == parent_number => parent,
+                _ => {
+                    report.inconsistencies.push(Inconsistency::MissingHeader {
+                        number: parent_number,
+                        hash: parent_hash,
+                    });
+                    return None;
+                }
+            };
+        }
+        headers.reverse();
+        Some(headers)
+    }
+
+    fn check_main_chain_index(&self, header: &HeaderView, report: &mut CheckReport) {
+        let number = header.number();
+        let hash = header.hash();
+        let actual = self.get_block_hash(number);
+        if actual.as_ref() != Some(&hash) {
+            report.inconsistencies.push(Inconsistency::BlockHashIndex {
+                number,
+                expected: Some(hash.clone()),
+                actual,
+            });
+        }
+        let actual = self.get_block_number(&hash);
+        if actual != Some(number) {
+            report
+                .inconsistencies
+                .push(Inconsistency::BlockNumberIndex {
+                    hash,
+                    expected: Some(number),
+                    actual,
+                });
+        }
+    }
+
+    // Returns the ext of the block, with the totals fixed
+    fn check_block_ext(
+        &self,
+        header: &HeaderView,
+        parent_ext: Option<&BlockExt>,
+        report: &mut CheckReport,
+    ) -> Option<BlockExt> {
+        let actual = match self.get_block_ext(&header.hash()) {
+            Some(ext) => ext,
+            None => {
+                report.inconsistencies.push(Inconsistency::MissingBlockExt {
+                    number: header.number(),
+                    hash: header.hash(),
+                });
+                return None;
+            }
+        };
+        let uncles_count = self
+            .get_block_uncles(&header.hash())
+            .map(|uncles| uncles.data().len() as u64)
+            .unwrap_or(0);
+        let (total_difficulty, total_uncles_count) = match parent_ext {
+            Some(parent_ext) => (
+                parent_ext.total_difficulty.to_owned() + header.difficulty(),
+                parent_ext.total_uncles_count + uncles_count,
+            ),
+            // The genesis, or a block whose parent ext is missing
+            None if header.number() == 0 => (header.difficulty(), 0),
+            None => return Some(actual),
+        };
+        if actual.total_difficulty != total_difficulty
+            || actual.total_uncles_count != total_uncles_count
+        {
+            let expected = BlockExt {
+                total_difficulty,
+                total_uncles_count,
+                ..actual.clone()
+            };
+            report.inconsistencies.push(Inconsistency::BlockExt {
+                number: header.number(),
+                hash: header.hash(),
+                expected: expected.clone(),
+                actual,
+            });
+            Some(expected)
+        } else {
+            Some(actual)
+        }
+    }
+
+    fn check_block_epoch(&self, header: &HeaderView, report: &mut CheckReport) -> Option<EpochExt> {
+        let number = header.number();
+        match self.get_block_epoch(&header.hash()) {
+            Some(ref epoch)
+                if epoch.number() == header.epoch().number()
+                    && epoch.start_number() <= number
+                    && number < epoch.start_number() + epoch.length() =>
+            {
+                Some(epoch.to_owned())
+            }
+            _ => {
+                report.inconsistencies.push(Inconsistency::BlockEpoch {
+                    number,
+                    hash: header.hash(),
+                });
+                None
+            }
+        }
+    }
+
+    fn check_epoch_index(&self, header: &HeaderView, epoch: &EpochExt, report: &mut CheckReport) {
+        let expected = self
+            .get_block_epoch_index(&header.hash())
+            .expect("checked in check_block_epoch");
+        let actual = self.get_epoch_index(epoch.number());
+        if actual.as_ref() != Some(&expected) {
+            report.inconsistencies.push(Inconsistency::EpochIndex {
+                number: epoch.number(),
+                expected,
+                actual,
+            });
+        }
+    }
+
+    fn check_current_epoch(&self, tip: &HeaderView, report: &mut CheckReport) {
+        if let Some(expected) = self.get_block_epoch(&tip.hash()) {
+            let actual = self.get_current_epoch_ext();
+            if actual.as_ref() != Some(&expected) {
+                report
+                    .inconsistencies
+                    .push(Inconsistency::CurrentEpoch { expected, actual });
+            }
+        }
+    }
+
+    fn check_block_body(&self, header: &HeaderView, report: &mut CheckReport) -> Option<BlockView> {
+        let hash = header.hash();
+        let invalid_body = |reason: &str| Inconsistency::InvalidBody {
+            number: header.number(),
+            hash: hash.clone(),
+            reason: reason.to_owned(),
+        };
+        let uncles = match self.get_block_uncles(&hash) {
+            Some(uncles) => uncles,
+            None => {
+                report.inconsistencies.push(invalid_body("missing uncles"));
+                return None;
+            }
+        };
+        let proposals = match self.get_block_proposal_txs_ids(&hash) {
+            Some(proposals) => proposals,
+            None => {
+                report
+                    .inconsistencies
+                    .push(invalid_body("missing proposals"));
+                return None;
+            }
+        };
+        let body = self.get_block_body(&hash);
+        if let Some(tx) = body.iter().find(|tx| tx.data().calc_tx_hash() != tx.hash()) {
+            let reason = format!("transaction {} does not match its hash", tx.hash());
+            report.inconsistencies.push(invalid_body(&reason));
+            return None;
+        }
+        let block = BlockView::new_unchecked(header.to_owned(), uncles, body, proposals);
+        let reason = if block.calc_transactions_root() != header.transactions_root() {
+            "the transactions do not match the transactions root"
+        } else if block.calc_uncles_hash() != header.uncles_hash() {
+            "the uncles do not match the uncles hash"
+        } else if block.calc_proposals_hash() != header.proposals_hash() {
+            "the proposals do not match the proposals hash"
+        } else {
+            return Some(block);
+        };
+        report.inconsistencies.push(invalid_body(reason));
+        None
+    }
+
+    fn check_block_indexes(&self, block: &BlockView, report: &mut CheckReport) {
+        for (index, tx_hash) in block.tx_hashes().iter().enumerate() {
+            let expected = TransactionInfo::new(block.number(), block.epoch(), block.hash(), index);
+            let actual = self.get_transaction_info(tx_hash);
+            if actual.as_ref() != Some(&expected) {
+                report.inconsistencies.push(Inconsistency::TransactionInfo {
+                    tx_hash: tx_hash.to_owned(),
+                    expected: Some(expected),
+                    actual,
+                });
+            }
+        }
+        for uncle in block.uncles().into_iter() {
+            if !self.is_uncle(&uncle.hash()) {
+                report.inconsistencies.push(Inconsistency::MissingUncle {
+                    header: uncle.header(),
+                });
+            }
+        }
+    }
+
+    // Finds the index entries of the blocks and transactions not in the main
+    // chain, which are left by an interrupted reorganization.
+    fn check_stale_indexes(&self, main_chain: &[HeaderView], report: &mut CheckReport) {
+        let is_main_chain = |number: BlockNumber, hash: &[u8]| {
+            main_chain
+                .get(number as usize)
+                .map(|header| header.hash().as_slice() == hash)
+                .unwrap_or(false)
+        };
+        for (key, value) in self.get_iter(COLUMN_INDEX, &[], Direction::Forward) {
+            if key.len() == 8 {
+                let number: BlockNumber =
+                    packed::Uint64Reader::from_slice_should_be_ok(&key).unpack();
+                if number as usize >= main_chain.len() {
+                    report.inconsistencies.push(Inconsistency::BlockHashIndex {
+                        number,
+                        expected: None,
+                        actual: Some(
+                            packed::Byte32Reader::from_slice_should_be_ok(&value).to_entity(),
+                        ),
+                    });
+                }
+            } else {
+                let number: BlockNumber =
+                    packed::Uint64Reader::from_slice_should_be_ok(&value).unpack();
+                if !is_main_chain(number, &key) {
+                    report
+                        .inconsistencies
+                        .push(Inconsistency::BlockNumberIndex {
+                            hash: packed::Byte32Reader::from_slice_should_be_ok(&key).to_entity(),
+                            expected: None,
+                            actual: Some(number),
+                        });
+                }
+            }
+        }
+        for (key, value) in self.get_iter(COLUMN_TRANSACTION_INFO, &[], Direction::Forward) {
+            let info: TransactionInfo =
+                packed::TransactionInfoReader::from_slice_should_be_ok(&value).unpack();
+            if !is_main_chain(info.block_number, info.block_hash.as_slice()) {
+                report.inconsistencies.push(Inconsistency::TransactionInfo {
+                    tx_hash: packed::Byte32Reader::from_slice_should_be_ok(&key).to_entity(),
+                    expected: None,
+                    actual: Some(info),
+                });
+            }
+        }
+    }
+
+    fn check_cell_set(
+        &self,
+        mut expected: HashMap<packed::Byte32, TransactionMeta>,
+        report: &mut CheckReport,
+    ) {
+        let mut actual_cell_set = Vec::new();
+        // a partly read cell set would report the unread cells as missing
+        if self
+            .traverse_cell_set(|tx_hash, meta| {
+                actual_cell_set.push((tx_hash, meta.unpack()));
+                Ok(())
+            })
+            .is_err()
+        {
+            report.cell_set_checked = false;
+            return;
+        }
+        for (tx_hash, actual) in actual_cell_set {
+            let expected = expected.remove(&tx_hash);
+            if expected.as_ref() != Some(&actual) {
+                report.inconsistencies.push(Inconsistency::CellSet {
+                    tx_hash,
+                    expected,
+                    actual: Some(actual),
+                });
+            }
+        }
+        for (tx_hash, meta) in expected {
+            report.inconsistencies.push(Inconsistency::CellSet {
+                tx_hash,
+                expected: Some(meta),
+                actual: None,
+            });
+        }
+    }
+}
+
+// Applies the block to the cell set, the same as attaching it in the chain
+// service. The inputs of the genesis are not spent.
+fn replay_block_cells(block: &BlockView, cell_set: &mut HashMap<packed::Byte32, TransactionMeta>) {
+    for tx in block.transactions() {
+        if !block.is_genesis() {
+            for cell in tx.input_pts_iter() {
+                let tx_hash = cell.tx_hash();
+                let all_dead = match cell_set.get_mut(&tx_hash) {
+                    Some(meta) => {
+                        meta.set_dead(cell.index().unpack());
+                        meta.all_dead()
+                    }
+                    None => false,
+                };
+                if all_dead {
+                    cell_set.remove(&tx_hash);
+                }
+            }
+        }
+        let outputs_len = tx.outputs().len();
+        let meta = if tx.is_cellbase() {
+            TransactionMeta::new_cellbase(
+                block.number(),
+                block.epoch().number(),
+                block.hash(),
+                outputs_len,
+                false,
+            )
+        } else {
+            TransactionMeta::new(
+                block.number(),
+                block.epoch().number(),
+                block.hash(),
+                outputs_len,
+                false,
+            )
+        };
+        cell_set.insert(tx.hash(), meta);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::COLUMNS;
+    use ckb_chain_spec::consensus::ConsensusBuilder;
+    use ckb_db::RocksDB;
+
+    #[test]
+    fn check_and_fix() {
+        let store = ChainDB::new(RocksDB::open_tmp(COLUMNS), Default::default());
+        let consensus = ConsensusBuilder::default().build();
+        store.init(&consensus).unwrap();
+        let report = store.check();
+        assert!(report.is_consistent(), "{:?}", report.inconsistencies);
+        assert_eq!(report.checked_blocks, 1);
+        assert!(report.cell_set_checked);
+
+        let genesis = consensus.genesis_block();
+        let txn = store.begin_transaction();
+        txn.delete(COLUMN_INDEX, genesis.hash().as_slice()).unwrap();
+        txn.delete_cell_set(&genesis.transactions()[0].hash())
+            .unwrap();
+        txn.commit().unwrap();
+
+        let report = store.check();
+        assert_eq!(report.inconsistencies.len(), 2);
+        assert!(report.inconsistencies.iter().all(Inconsistency::is_fixable));
+        assert_eq!(store.fix(&report.inconsistencies).unwrap(), 2);
+        assert!(store.check().is_consistent());
+    }
+}
@@ -8,6 +8,14 @@ pub struct StoreConfig {
     pub block_tx_hashes_cache_size: usize,
     pub block_uncles_cache_size: usize,
     pub cellbase_cache_size: usize,
+    // Store each distinct non-empty cell data once, keyed by its data hash
+    #[serde(default)]
+    pub cell_data_dedup: bool,
+    // Delete the bodies of the main chain blocks deeper than this below the
+    // tip, keeping the transactions which still have live cells. Values below
+    // `MIN_PRUNE_DEPTH` are raised to it.
+    #[serde(default)]
+    pub prune_depth: Option<u64>,
 }
 
 impl Default for StoreConfig {
@@ -19,6 +27,8 @@ impl Default for StoreConfig {
             block_tx_hashes_cache_size: 30,
             block_uncles_cache_size: 30,
             cellbase_cache_size: 30,
+            cell_data_dedup: false,
+            prune_depth: None,
         }
     }
 }
@@ -3,23 +3,39 @@ use crate::config::StoreConfig;
 use crate::store::ChainStore;
 use crate::transaction::StoreTransaction;
 use crate::StoreSnapshot;
-use crate::COLUMN_CELL_SET;
+use crate::{
+    COLUMN_CELL_SET, COLUMN_META, COLUMN_NAMES, COLUMN_TRANSACTION_INFO, META_PRUNED_NUMBER_KEY,
+    MIN_PRUNE_DEPTH,
+};
 use ckb_chain_spec::consensus::Consensus;
 use ckb_db::{
     iter::{DBIterator, DBIteratorItem},
-    Col, DBPinnableSlice, Direction, RocksDB,
+    Col, DBPinnableSlice, DBStats, Direction, RocksDB,
 };
 use ckb_error::Error;
 use ckb_types::{
-    core::{BlockExt, TransactionMeta},
+    core::{BlockExt, BlockNumber, TransactionMeta},
     packed,
     prelude::*,
 };
+use ckb_util::{Mutex, MutexGuard};
+use std::cmp;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
+// The max number of blocks pruned in a transaction when catching up
+const PRUNE_CATCH_UP_BATCH_SIZE: BlockNumber = 100;
+// The max number of transactions checked in a transaction when the pruning starts
+const START_PRUNING_BATCH_SIZE: usize = 10_000;
+
 pub struct ChainDB {
     db: RocksDB,
     cache: Arc<StoreCache>,
+    cell_data_dedup: bool,
+    prune_depth: Option<BlockNumber>,
+    pruning_in_background: Arc<AtomicBool>,
+    write_lock: Mutex<()>,
 }
 
 impl<'a> ChainStore<'a> for ChainDB {
@@ -50,10 +66,21 @@ impl<'a> ChainStore<'a> for ChainDB {
 impl ChainDB {
     pub fn new(db: RocksDB, config: StoreConfig) -> Self {
         let cache = StoreCache::from_config(config);
-        ChainDB {
+        let prune_depth = config
+            .prune_depth
+            .map(|prune_depth| cmp::max(prune_depth, MIN_PRUNE_DEPTH));
+        let store = ChainDB {
             db,
             cache: Arc::new(cache),
+            cell_data_dedup: config.cell_data_dedup,
+            prune_depth,
+            pruning_in_background: Arc::new(AtomicBool::new(prune_depth.is_some())),
+            write_lock: Mutex::new(()),
+        };
+        if let Some(pruned_number) = store.get_pruned_number() {
+            store.cache.set_pruned_number(pruned_number);
         }
+        store
     }
 
     pub fn traverse_cell_set<F>(&self, mut callback: F) -> Result<(), Error>
@@ -73,6 +100,9 @@ impl ChainDB {
         StoreTransaction {
             inner: self.db.transaction(),
             cache: Arc::clone(&self.cache),
+            cell_data_dedup: self.cell_data_dedup,
+            prune_depth: self.prune_depth,
+            pruning_in_background: Arc::clone(&self.pruning_in_background),
         }
     }
 
@@ -83,6 +113,127 @@ impl ChainDB {
         }
     }
 
+    /// Serializes the transactions writing the chain data, the block
+    /// insertion and the pruning catch up, hold it from `begin_transaction`
+    /// to `commit`.
+    ///
+    /// The store is an optimistic transaction DB, a transaction doesn't wait
+    /// for another one writing the same keys, such as the refcount of a cell
+    /// data shared by many blocks, its commit fails with Busy instead.
+    pub fn write_lock(&self) -> MutexGuard<'_, ()> {
+        self.write_lock.lock()
+    }
+
+    /// Whether the pruning waits for `catch_up_pruning`
+    pub fn is_pruning_in_background(&self) -> bool {
+        self.pruning_in_background.load(Ordering::Acquire)
+    }
+
+    /// Starts the pruning if needed and prunes the blocks deeper than the
+    /// prune depth in batches of their own transactions, then leaves the
+    /// pruning to the block insertion, which only prunes a few blocks each
+    /// time. It runs beside the block processing, so enabling the pruning on
+    /// a synced node does not stall it, each batch takes the `write_lock`.
+    pub fn catch_up_pruning(&self) -> Result<(), Error> {
+        if self.prune_depth.is_none() {
+            return Ok(());
+        }
+        if self.get_pruned_number().is_none() {
+            self.start_pruning()?;
+        }
+        loop {
+            let _write_guard = self.write_lock();
+            let tip = match self.get_tip_header() {
+                Some(tip) => tip,
+                None => break,
+            };
+            let txn = self.begin_transaction();
+            let more = txn.prune_blocks(&tip, PRUNE_CATCH_UP_BATCH_SIZE)?;
+            txn.commit()?;
+            if !more {
+                break;
+            }
+        }
+        self.pruning_in_background.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    // The transactions spent before the pruning started are recorded as spent
+    // in the tip block, they are deleted once the tip is deep enough. The
+    // transactions spent later are recorded by the block processing.
+    fn start_pruning(&self) -> Result<(), Error> {
+        let tip = match self.get_tip_header() {
+            Some(tip) => tip,
+            None => return Ok(()),
+        };
+        let mut from_key = Vec::new();
+        loop {
+            let keys: Vec<_> = self
+                .get_iter(COLUMN_TRANSACTION_INFO, &from_key, Direction::Forward)
+                .skip_while(|(key, _)| key.as_ref() == from_key.as_slice())
+                .take(START_PRUNING_BATCH_SIZE)
+                .map(|(key, _)| key)
+                .collect();
+            let last_key = match keys.last() {
+                Some(last_key) => last_key.to_vec(),
+                None => break,
+            };
+            let _write_guard = self.write_lock();
+            let txn = self.begin_transaction();
+            for key in keys {
+                let tx_hash = packed::Byte32Reader::from_slice_should_be_ok(&key).to_entity();
+                if self.get_tx_meta(&tx_hash).is_none() {
+                    txn.insert_spent_transaction(tip.number(), &tip.hash(), &tx_hash)?;
+                }
+            }
+            txn.commit()?;
+            from_key = last_key;
+        }
+        // The genesis block is never pruned
+        let pruned_number: packed::Uint64 = 1u64.pack();
+        let _write_guard = self.write_lock();
+        let txn = self.begin_transaction();
+        txn.insert_raw(
+            COLUMN_META,
+            META_PRUNED_NUMBER_KEY,
+            pruned_number.as_slice(),
+        )?;
+        txn.commit()?;
+        self.cache.set_pruned_number(1);
+        Ok(())
+    }
+
+    /// Creates a consistent copy of the database in `path`, see
+    /// `RocksDB::create_checkpoint`.
+    pub fn create_checkpoint(&self, path: &Path) -> Result<(), Error> {
+        self.db.create_checkpoint(path)
+    }
+
+    /// Deletes all the keys of the column, in transactions of `batch_size` keys.
+    pub fn clear_column(&self, col: Col, batch_size: usize) -> Result<(), Error> {
+        loop {
+            let keys: Vec<_> = self
+                .get_iter(col, &[], Direction::Forward)
+                .take(batch_size)
+                .map(|(key, _)| key)
+                .collect();
+            if keys.is_empty() {
+                return Ok(());
+            }
+            let txn = self.begin_transaction();
+            for key in keys {
+                txn.delete(col, &key)?;
+            }
+            txn.commit()?;
+        }
+    }
+
+    /// Returns the statistics of the columns, in the order of `COLUMN_NAMES`.
+    pub fn stats(&self) -> Result<DBStats, Error> {
+        let columns: Vec<_> = COLUMN_NAMES.iter().map(|(col, _)| *col).collect();
+        self.db.stats(&columns)
+    }
+
     pub fn init(&self, consensus: &Consensus) -> Result<(), Error> {
         let genesis = consensus.genesis_block();
         let epoch = consensus.genesis_epoch_ext();
@@ -142,6 +293,12 @@ mod tests {
     use super::*;
     use ckb_chain_spec::consensus::ConsensusBuilder;
     use ckb_db::RocksDB;
+    use ckb_types::{
+        bytes::Bytes,
+        core::{BlockBuilder, BlockView, TransactionBuilder, TransactionView},
+        packed::CellInput,
+    };
+    use std::thread;
 
     fn setup_db(columns: u32) -> RocksDB {
         RocksDB::open_tmp(columns)
@@ -182,6 +339,203 @@ mod tests {
         assert_eq!(block, store.get_block(&hash).unwrap());
     }
 
+    #[test]
+    fn save_and_get_block_with_deduplicated_cell_data() {
+        let db = setup_db(COLUMNS);
+        let config = StoreConfig {
+            cell_data_dedup: true,
+            ..Default::default()
+        };
+        let store = ChainDB::new(db, config);
+        let data = Bytes::from(vec![1u8; 1000]);
+        let data_hash = packed::CellOutput::calc_data_hash(&data);
+        let blocks: Vec<BlockView> = (0..2u64)
+            .map(|number| {
+                let tx = TransactionBuilder::default()
+                    .input(CellInput::new(packed::OutPoint::null(), number))
+                    .output(packed::CellOutput::new_builder().build())
+                    .output_data(data.pack())
+                    .output(packed::CellOutput::new_builder().build())
+                    .output_data(Bytes::new().pack())
+                    .build();
+                BlockBuilder::default()
+                    .number(number.pack())
+                    .transaction(tx)
+                    .build()
+            })
+            .collect();
+
+        let txn = store.begin_transaction();
+        for block in &blocks {
+            txn.insert_block(block).unwrap();
+            txn.attach_block(block).unwrap();
+        }
+        txn.commit().unwrap();
+        assert_eq!(store.get_cell_data_refs(&data_hash), 2);
+        for block in &blocks {
+            assert_eq!(block, &store.get_block(&block.hash()).unwrap());
+            let tx_hash = block.transactions()[0].hash();
+            assert_eq!(
+                store.get_cell_data(&tx_hash, 0),
+                Some((data.clone(), data_hash.clone()))
+            );
+            assert_eq!(
+                store.get_cell_meta(&tx_hash, 0).unwrap().data_bytes,
+                data.len() as u64
+            );
+            assert_eq!(store.get_cell_meta(&tx_hash, 1).unwrap().data_bytes, 0);
+        }
+
+        let txn = store.begin_transaction();
+        txn.delete_block(&blocks[0]).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(store.get_cell_data_refs(&data_hash), 1);
+        assert_eq!(store.get_cell_data_by_hash(&data_hash), Some(data));
+        assert!(store.get_block(&blocks[0].hash()).is_none());
+
+        let txn = store.begin_transaction();
+        txn.delete_block(&blocks[1]).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(store.get_cell_data_refs(&data_hash), 0);
+        assert!(store.get_cell_data_by_hash(&data_hash).is_none());
+    }
+
+    #[test]
+    fn prune_spent_transactions() {
+        let db = setup_db(COLUMNS);
+        let config = StoreConfig {
+            prune_depth: Some(MIN_PRUNE_DEPTH),
+            ..Default::default()
+        };
+        let store = ChainDB::new(db, config);
+        let txs: Vec<TransactionView> = (0..3u64)
+            .map(|since| {
+                TransactionBuilder::default()
+                    .input(CellInput::new(packed::OutPoint::null(), since))
+                    .output(packed::CellOutput::new_builder().build())
+                    .output_data(Bytes::from(vec![1u8; 10]).pack())
+                    .build()
+            })
+            .collect();
+        let blocks: Vec<BlockView> = (0..=MIN_PRUNE_DEPTH + 3)
+            .map(|number| {
+                let builder = BlockBuilder::default().number(number.pack());
+                if number == 1 {
+                    builder.transactions(txs.clone()).build()
+                } else {
+                    builder.build()
+                }
+            })
+            .collect();
+        let (live, spent, spent_in_fork) = (txs[0].hash(), txs[1].hash(), txs[2].hash());
+        let meta = TransactionMeta::new(1, 0, blocks[1].hash(), 1, false);
+
+        let txn = store.begin_transaction();
+        for block in &blocks {
+            txn.insert_block(block).unwrap();
+            txn.attach_block(block).unwrap();
+        }
+        txn.update_cell_set(&live, &meta.pack()).unwrap();
+        txn.insert_spent_transaction(2, &blocks[2].hash(), &spent)
+            .unwrap();
+        txn.insert_spent_transaction(2, &packed::Byte32::zero(), &spent_in_fork)
+            .unwrap();
+        txn.insert_tip_header(&blocks[blocks.len() - 1].header())
+            .unwrap();
+        // Waits for the catch up
+        txn.prune(&blocks[blocks.len() - 1].header()).unwrap();
+        txn.commit().unwrap();
+        assert!(store.is_pruning_in_background());
+        assert_eq!(store.get_pruned_number(), None);
+
+        store.catch_up_pruning().unwrap();
+        assert!(!store.is_pruning_in_background());
+        assert_eq!(store.get_pruned_number(), Some(3));
+        assert!(!store.is_block_pruned(&blocks[0].hash()));
+        assert!(store.get_block(&blocks[0].hash()).is_some());
+        assert!(store.is_block_pruned(&blocks[1].hash()));
+        assert!(store.get_block(&blocks[1].hash()).is_none());
+        assert!(store.get_block(&blocks[3].hash()).is_some());
+        assert!(store.get_transaction(&live).is_some());
+        assert_eq!(
+            store.get_cell_data(&live, 0).map(|(data, _)| data),
+            Some(Bytes::from(vec![1u8; 10]))
+        );
+        assert!(store.get_transaction(&spent).is_none());
+        assert!(store.get_transaction(&spent_in_fork).is_some());
+    }
+
+    #[test]
+    fn catch_up_pruning_beside_block_insertion() {
+        let db = setup_db(COLUMNS);
+        let config = StoreConfig {
+            cell_data_dedup: true,
+            prune_depth: Some(MIN_PRUNE_DEPTH),
+            ..Default::default()
+        };
+        let store = Arc::new(ChainDB::new(db, config));
+        // Every block stores the same data, like the DAO deposits, so the
+        // insertion and the pruning update the same refcount
+        let data = Bytes::from(vec![0u8; 8]);
+        let data_hash = packed::CellOutput::calc_data_hash(&data);
+        let block = |number: BlockNumber| {
+            let tx = TransactionBuilder::default()
+                .input(CellInput::new(packed::OutPoint::null(), number))
+                .output(packed::CellOutput::new_builder().build())
+                .output_data(data.pack())
+                .build();
+            BlockBuilder::default()
+                .number(number.pack())
+                .transaction(tx)
+                .build()
+        };
+        // The transaction is spent right away, it is deleted once its block is pruned
+        let insert = |block: &BlockView| {
+            let _write_guard = store.write_lock();
+            let txn = store.begin_transaction();
+            txn.insert_block(block).unwrap();
+            txn.attach_block(block).unwrap();
+            txn.insert_spent_transaction(
+                block.number(),
+                &block.hash(),
+                &block.transactions()[0].hash(),
+            )
+            .unwrap();
+            txn.insert_tip_header(&block.header()).unwrap();
+            txn.prune(&block.header()).unwrap();
+            txn.commit().unwrap();
+        };
+
+        let synced = MIN_PRUNE_DEPTH + 500;
+        let blocks: Vec<_> = (0..=synced).map(block).collect();
+        for block in &blocks {
+            insert(block);
+        }
+        let catch_up = {
+            let store = Arc::clone(&store);
+            thread::spawn(move || store.catch_up_pruning())
+        };
+        let new_blocks: Vec<_> = (synced + 1..=synced + 500).map(block).collect();
+        for block in &new_blocks {
+            insert(block);
+        }
+        catch_up.join().unwrap().unwrap();
+
+        assert!(!store.is_pruning_in_background());
+        assert!(store.get_pruned_number().unwrap() > 1);
+        let stored = blocks
+            .iter()
+            .chain(new_blocks.iter())
+            .filter(|block| {
+                store
+                    .get_transaction(&block.transactions()[0].hash())
+                    .is_some()
+            })
+            .count();
+        assert!(stored < blocks.len() + new_blocks.len());
+        assert_eq!(store.get_cell_data_refs(&data_hash), stored as u64);
+    }
+
     #[test]
     fn save_and_get_block_ext() {
         let db = setup_db(COLUMNS);
@@ -1,21 +1,27 @@
 mod cache;
+mod cell_data;
+mod check;
 mod config;
 pub mod data_loader_wrapper;
 mod db;
+mod migrations;
 mod snapshot;
 mod store;
 mod transaction;
 
-pub use cache::StoreCache;
+pub use cache::{Cache, CacheStats, StoreCache};
+pub use check::{CheckReport, Inconsistency};
 pub use config::StoreConfig;
 pub use db::ChainDB;
+pub use migrations::migrations;
 pub use snapshot::StoreSnapshot;
 pub use store::ChainStore;
 pub use transaction::StoreTransaction;
 
 use ckb_db::Col;
+use ckb_types::core::BlockNumber;
 
-pub const COLUMNS: u32 = 12;
+pub const COLUMNS: u32 = 16;
 pub const COLUMN_INDEX: Col = "0";
 pub const COLUMN_BLOCK_HEADER: Col = "1";
 pub const COLUMN_BLOCK_BODY: Col = "2";
@@ -28,6 +34,36 @@ pub const COLUMN_BLOCK_EPOCH: Col = "8";
 pub const COLUMN_EPOCH: Col = "9";
 pub const COLUMN_CELL_SET: Col = "10";
 pub const COLUMN_UNCLES: Col = "11";
+pub const COLUMN_CELL_DATA: Col = "12";
+pub const COLUMN_CELL_DATA_REFS: Col = "13";
+pub const COLUMN_CELL_DATA_INDEX: Col = "14";
+pub const COLUMN_SPENT_TRANSACTIONS: Col = "15";
+
+/// The names of the columns, shown in the database statistics
+pub const COLUMN_NAMES: [(Col, &str); COLUMNS as usize] = [
+    (COLUMN_INDEX, "index"),
+    (COLUMN_BLOCK_HEADER, "block_header"),
+    (COLUMN_BLOCK_BODY, "block_body"),
+    (COLUMN_BLOCK_UNCLE, "block_uncle"),
+    (COLUMN_META, "meta"),
+    (COLUMN_TRANSACTION_INFO, "transaction_info"),
+    (COLUMN_BLOCK_EXT, "block_ext"),
+    (COLUMN_BLOCK_PROPOSAL_IDS, "block_proposal_ids"),
+    (COLUMN_BLOCK_EPOCH, "block_epoch"),
+    (COLUMN_EPOCH, "epoch"),
+    (COLUMN_CELL_SET, "cell_set"),
+    (COLUMN_UNCLES, "uncles"),
+    (COLUMN_CELL_DATA, "cell_data"),
+    (COLUMN_CELL_DATA_REFS, "cell_data_refs"),
+    (COLUMN_CELL_DATA_INDEX, "cell_data_index"),
+    (COLUMN_SPENT_TRANSACTIONS, "spent_transactions"),
+];
 
 const META_TIP_HEADER_KEY: &[u8] = b"TIP_HEADER";
 const META_CURRENT_EPOCH_KEY: &[u8] = b"CURRENT_EPOCH";
+const META_PRUNED_NUMBER_KEY: &[u8] = b"PRUNED_NUMBER";
+const META_REINDEX_NUMBER_KEY: &[u8] = b"REINDEX_NUMBER";
+
+/// The minimum prune depth, deep enough for the reorganizations and for the
+/// block rewards, which read the recent block bodies.
+pub const MIN_PRUNE_DEPTH: BlockNumber = 1000;
@@ -0,0 +1,9 @@
+use ckb_db::Migrations;
+
+/// The migrations of the chain database, see `ckb_db::migration`.
+///
+/// Register a migration here along with the bump of the database version
+/// which requires it.
+pub fn migrations() -> Migrations {
+    Migrations::new()
+}
@@ -1,9 +1,11 @@
 use crate::cache::StoreCache;
+use crate::cell_data::{decode_refs, CellDataIndex};
 use crate::{
     COLUMN_BLOCK_BODY, COLUMN_BLOCK_EPOCH, COLUMN_BLOCK_EXT, COLUMN_BLOCK_HEADER,
-    COLUMN_BLOCK_PROPOSAL_IDS, COLUMN_BLOCK_UNCLE, COLUMN_CELL_SET, COLUMN_EPOCH, COLUMN_INDEX,
-    COLUMN_META, COLUMN_TRANSACTION_INFO, COLUMN_UNCLES, META_CURRENT_EPOCH_KEY,
-    META_TIP_HEADER_KEY,
+    COLUMN_BLOCK_PROPOSAL_IDS, COLUMN_BLOCK_UNCLE, COLUMN_CELL_DATA, COLUMN_CELL_DATA_INDEX,
+    COLUMN_CELL_DATA_REFS, COLUMN_CELL_SET, COLUMN_EPOCH, COLUMN_INDEX, COLUMN_META,
+    COLUMN_TRANSACTION_INFO, COLUMN_UNCLES, META_CURRENT_EPOCH_KEY, META_PRUNED_NUMBER_KEY,
+    META_REINDEX_NUMBER_KEY, META_TIP_HEADER_KEY,
 };
 use ckb_chain_spec::consensus::Consensus;
 use ckb_db::{iter::DBIteratorItem, Col, Direction};
@@ -28,8 +30,11 @@ pub trait ChainStore<'a>: Send + Sync {
         direction: Direction,
     ) -> Box<dyn Iterator<Item = DBIteratorItem> + 'i>;
 
-    /// Get block by block header hash
+    /// Get block by block header hash, None if the block body is pruned
     fn get_block(&'a self, h: &packed::Byte32) -> Option<BlockView> {
+        if self.is_block_pruned(h) {
+            return None;
+        }
         self.get_block_header(h).map(|header| {
             let body = self.get_block_body(h);
             let uncles = self
@@ -45,8 +50,8 @@ pub trait ChainStore<'a>: Send + Sync {
     /// Get header by block header hash
     fn get_block_header(&'a self, hash: &packed::Byte32) -> Option<HeaderView> {
         if let Some(cache) = self.cache() {
-            if let Some(header) = cache.headers.lock().get_refresh(hash) {
-                return Some(header.clone());
+            if let Some(header) = cache.headers.get(hash) {
+                return Some(header);
             }
         };
         let ret = self.get(COLUMN_BLOCK_HEADER, hash.as_slice()).map(|slice| {
@@ -56,7 +61,7 @@ pub trait ChainStore<'a>: Send + Sync {
 
         if let Some(cache) = self.cache() {
             ret.map(|header| {
-                cache.headers.lock().insert(hash.clone(), header.clone());
+                cache.headers.insert(hash.clone(), header.clone());
                 header
             })
         } else {
@@ -69,19 +74,15 @@ pub trait ChainStore<'a>: Send + Sync {
         let prefix = hash.as_slice();
         self.get_iter(COLUMN_BLOCK_BODY, prefix, Direction::Forward)
             .take_while(|(key, _)| key.starts_with(prefix))
-            .map(|(_key, value)| {
-                let reader =
-                    packed::TransactionViewReader::from_slice_should_be_ok(&value.as_ref());
-                Unpack::<TransactionView>::unpack(&reader)
-            })
+            .map(|(key, value)| unpack_transaction(self, &key, &value))
             .collect()
     }
 
     /// Get all transaction-hashes in block body by block header hash
     fn get_block_txs_hashes(&'a self, hash: &packed::Byte32) -> Vec<packed::Byte32> {
         if let Some(cache) = self.cache() {
-            if let Some(hashes) = cache.block_tx_hashes.lock().get_refresh(hash) {
-                return hashes.clone();
+            if let Some(hashes) = cache.block_tx_hashes.get(hash) {
+                return hashes;
             }
         };
 
@@ -97,10 +98,7 @@ pub trait ChainStore<'a>: Send + Sync {
             .collect();
 
         if let Some(cache) = self.cache() {
-            cache
-                .block_tx_hashes
-                .lock()
-                .insert(hash.clone(), ret.clone());
+            cache.block_tx_hashes.insert(hash.clone(), ret.clone());
         }
 
         ret
@@ -112,8 +110,8 @@ pub trait ChainStore<'a>: Send + Sync {
         hash: &packed::Byte32,
     ) -> Option<packed::ProposalShortIdVec> {
         if let Some(cache) = self.cache() {
-            if let Some(data) = cache.block_proposals.lock().get_refresh(hash) {
-                return Some(data.clone());
+            if let Some(data) = cache.block_proposals.get(hash) {
+                return Some(data);
             }
         };
 
@@ -126,10 +124,7 @@ pub trait ChainStore<'a>: Send + Sync {
 
         if let Some(cache) = self.cache() {
             ret.map(|data| {
-                cache
-                    .block_proposals
-                    .lock()
-                    .insert(hash.clone(), data.clone());
+                cache.block_proposals.insert(hash.clone(), data.clone());
                 data
             })
         } else {
@@ -140,8 +135,8 @@ pub trait ChainStore<'a>: Send + Sync {
     /// Get block uncles by block header hash
     fn get_block_uncles(&'a self, hash: &packed::Byte32) -> Option<UncleBlockVecView> {
         if let Some(cache) = self.cache() {
-            if let Some(data) = cache.block_uncles.lock().get_refresh(hash) {
-                return Some(data.clone());
+            if let Some(data) = cache.block_uncles.get(hash) {
+                return Some(data);
             }
         };
 
@@ -152,10 +147,7 @@ pub trait ChainStore<'a>: Send + Sync {
 
         if let Some(cache) = self.cache() {
             ret.map(|uncles| {
-                cache
-                    .block_uncles
-                    .lock()
-                    .insert(hash.clone(), uncles.clone());
+                cache.block_uncles.insert(hash.clone(), uncles.clone());
                 uncles
             })
         } else {
@@ -184,6 +176,34 @@ pub trait ChainStore<'a>: Send + Sync {
             .map(|raw| packed::Uint64Reader::from_slice_should_be_ok(&raw.as_ref()[..]).unpack())
     }
 
+    /// Get the number of the pruned main chain blocks, the bodies of the blocks
+    /// below it, except the genesis block, only keep the transactions with
+    /// live cells. None if the pruning is not started.
+    fn get_pruned_number(&'a self) -> Option<BlockNumber> {
+        self.get(COLUMN_META, META_PRUNED_NUMBER_KEY)
+            .map(|raw| packed::Uint64Reader::from_slice_should_be_ok(&raw.as_ref()[..]).unpack())
+    }
+
+    /// Get the number of the next block to reindex, None if no reindex is in
+    /// progress
+    fn get_reindex_number(&'a self) -> Option<BlockNumber> {
+        self.get(COLUMN_META, META_REINDEX_NUMBER_KEY)
+            .map(|raw| packed::Uint64Reader::from_slice_should_be_ok(&raw.as_ref()[..]).unpack())
+    }
+
+    /// Whether the body of the block is pruned, the genesis block is never pruned
+    fn is_block_pruned(&'a self, hash: &packed::Byte32) -> bool {
+        let pruned_number = match self.cache() {
+            Some(cache) => cache.pruned_number(),
+            None => self.get_pruned_number().unwrap_or(0),
+        };
+        pruned_number > 1
+            && self
+                .get_block_number(hash)
+                .map(|number| number > 0 && number < pruned_number)
+                .unwrap_or(false)
+    }
+
     fn get_tip_header(&'a self) -> Option<HeaderView> {
         self.get(COLUMN_META, META_TIP_HEADER_KEY)
             .and_then(|raw| {
@@ -202,10 +222,9 @@ pub trait ChainStore<'a>: Send + Sync {
         self.get_transaction_info_packed(hash).map(|info| {
             self.get(COLUMN_BLOCK_BODY, info.key().as_slice())
                 .map(|slice| {
-                    let reader =
-                        packed::TransactionViewReader::from_slice_should_be_ok(&slice.as_ref());
+                    let tx = unpack_transaction(self, info.key().as_slice(), &slice.as_ref());
                     let hash = info.as_reader().key().block_hash().to_entity();
-                    (reader.unpack(), hash)
+                    (tx, hash)
                 })
                 .expect("since tx info is existed, so tx data should be existed")
         })
@@ -252,7 +271,7 @@ pub trait ChainStore<'a>: Send + Sync {
                             .get(index as usize)
                             .expect("inconsistent index")
                             .to_entity();
-                        let data_bytes = reader
+                        let inline_bytes = reader
                             .data()
                             .raw()
                             .outputs_data()
@@ -260,6 +279,16 @@ pub trait ChainStore<'a>: Send + Sync {
                             .expect("inconsistent index")
                             .raw_data()
                             .len() as u64;
+                        // Only the stripped (empty) outputs data may be deduplicated
+                        let data_bytes = if inline_bytes > 0 {
+                            inline_bytes
+                        } else {
+                            get_cell_data_index(self, &tx_info.key())
+                                .as_ref()
+                                .and_then(|data_index| data_index.get(index as usize))
+                                .map(|(_, len)| *len)
+                                .unwrap_or(0)
+                        };
                         let out_point = packed::OutPoint::new_builder()
                             .tx_hash(tx_hash.to_owned())
                             .index(index.pack())
@@ -282,17 +311,14 @@ pub trait ChainStore<'a>: Send + Sync {
         index: u32,
     ) -> Option<(Bytes, packed::Byte32)> {
         if let Some(cache) = self.cache() {
-            if let Some(cached) = cache
-                .cell_data
-                .lock()
-                .get_refresh(&(tx_hash.clone(), index))
-            {
-                return Some(cached.clone());
+            if let Some(cached) = cache.cell_data.get(&(tx_hash.clone(), index)) {
+                return Some(cached);
             }
         };
 
         let ret = self.get_transaction_info_packed(tx_hash).and_then(|info| {
-            self.get(COLUMN_BLOCK_BODY, info.key().as_slice())
+            let inline = self
+                .get(COLUMN_BLOCK_BODY, info.key().as_slice())
                 .and_then(|slice| {
                     let reader =
                         packed::TransactionViewReader::from_slice_should_be_ok(&slice.as_ref());
@@ -301,20 +327,27 @@ pub trait ChainStore<'a>: Send + Sync {
                         .raw()
                         .outputs_data()
                         .get(index as usize)
-                        .map(|data| {
-                            (
-                                Unpack::<Bytes>::unpack(&data),
-                                packed::CellOutput::calc_data_hash(&data.raw_data()),
-                            )
-                        })
-                })
+                        .map(|data| Unpack::<Bytes>::unpack(&data))
+                })?;
+            // Only the stripped (empty) outputs data may be deduplicated
+            if inline.is_empty() {
+                let deduplicated = get_cell_data_index(self, &info.key())
+                    .as_ref()
+                    .and_then(|data_index| data_index.get(index as usize).cloned());
+                if let Some((data_hash, _)) = deduplicated {
+                    return self
+                        .get_cell_data_by_hash(&data_hash)
+                        .map(|data| (data, data_hash));
+                }
+            }
+            let data_hash = packed::CellOutput::calc_data_hash(&inline);
+            Some((inline, data_hash))
         });
 
         if let Some(cache) = self.cache() {
             ret.map(|cached| {
                 cache
                     .cell_data
-                    .lock()
                     .insert((tx_hash.clone(), index), cached.clone());
                 cached
             })
@@ -323,6 +356,37 @@ pub trait ChainStore<'a>: Send + Sync {
         }
     }
 
+    /// Get cell data stored in the content-addressed cell data column by its data hash
+    fn get_cell_data_by_hash(&'a self, data_hash: &packed::Byte32) -> Option<Bytes> {
+        if let Some(cache) = self.cache() {
+            if let Some(data) = cache.cell_data_by_hash.get(data_hash) {
+                return Some(data);
+            }
+        };
+
+        let ret = self
+            .get(COLUMN_CELL_DATA, data_hash.as_slice())
+            .map(|slice| Bytes::from(slice.as_ref()));
+
+        if let Some(cache) = self.cache() {
+            ret.map(|data| {
+                cache
+                    .cell_data_by_hash
+                    .insert(data_hash.clone(), data.clone());
+                data
+            })
+        } else {
+            ret
+        }
+    }
+
+    /// Get the number of outputs referencing the cell data of the data hash
+    fn get_cell_data_refs(&'a self, data_hash: &packed::Byte32) -> u64 {
+        self.get(COLUMN_CELL_DATA_REFS, data_hash.as_slice())
+            .map(|slice| decode_refs(slice.as_ref()))
+            .unwrap_or(0)
+    }
+
     // Get current epoch ext
     fn get_current_epoch_ext(&'a self) -> Option<EpochExt> {
         self.get(COLUMN_META, META_CURRENT_EPOCH_KEY)
@@ -367,7 +431,7 @@ pub trait ChainStore<'a>: Send + Sync {
 
     fn block_exists(&'a self, hash: &packed::Byte32) -> bool {
         if let Some(cache) = self.cache() {
-            if cache.headers.lock().get_refresh(hash).is_some() {
+            if cache.headers.contains_key(hash) {
                 return true;
             }
         };
@@ -377,20 +441,19 @@ pub trait ChainStore<'a>: Send + Sync {
     // Get cellbase by block hash
     fn get_cellbase(&'a self, hash: &packed::Byte32) -> Option<TransactionView> {
         if let Some(cache) = self.cache() {
-            if let Some(data) = cache.cellbase.lock().get_refresh(hash) {
-                return Some(data.clone());
+            if let Some(data) = cache.cellbase.get(hash) {
+                return Some(data);
             }
         };
         let key = packed::TransactionKey::new_builder()
             .block_hash(hash.to_owned())
             .build();
-        let ret = self.get(COLUMN_BLOCK_BODY, key.as_slice()).map(|slice| {
-            let reader = packed::TransactionViewReader::from_slice_should_be_ok(&slice.as_ref());
-            Unpack::<TransactionView>::unpack(&reader)
-        });
+        let ret = self
+            .get(COLUMN_BLOCK_BODY, key.as_slice())
+            .map(|slice| unpack_transaction(self, key.as_slice(), &slice.as_ref()));
         if let Some(cache) = self.cache() {
             ret.map(|data| {
-                cache.cellbase.lock().insert(hash.clone(), data.clone());
+                cache.cellbase.insert(hash.clone(), data.clone());
                 data
             })
         } else {
@@ -412,3 +475,62 @@ pub trait ChainStore<'a>: Send + Sync {
         )
     }
 }
+
+pub(crate) fn get_cell_data_index<'a, S: ChainStore<'a> + ?Sized>(
+    store: &'a S,
+    tx_key: &packed::TransactionKey,
+) -> Option<CellDataIndex> {
+    store
+        .get(COLUMN_CELL_DATA_INDEX, tx_key.as_slice())
+        .map(|slice| CellDataIndex::from_slice(slice.as_ref()))
+}
+
+// Unpacks a transaction stored in the block body, restoring the outputs data
+// moved to the content-addressed cell data column.
+fn unpack_transaction<'a, S: ChainStore<'a> + ?Sized>(
+    store: &'a S,
+    key: &[u8],
+    value: &[u8],
+) -> TransactionView {
+    let reader = packed::TransactionViewReader::from_slice_should_be_ok(value);
+    // Only the stripped (empty) outputs data may be deduplicated
+    if reader
+        .data()
+        .raw()
+        .outputs_data()
+        .iter()
+        .all(|data| !data.raw_data().is_empty())
+    {
+        return reader.unpack();
+    }
+    let data_index = match store.get(COLUMN_CELL_DATA_INDEX, key) {
+        Some(slice) => CellDataIndex::from_slice(slice.as_ref()),
+        None => return reader.unpack(),
+    };
+    let tx = reader.data().to_entity();
+    let outputs_data: Vec<packed::Bytes> = tx
+        .raw()
+        .outputs_data()
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| match data_index.get(index) {
+            Some((data_hash, _)) => store
+                .get_cell_data_by_hash(data_hash)
+                .expect("deduplicated cell data must be stored")
+                .pack(),
+            None => data,
+        })
+        .collect();
+    let raw = tx
+        .raw()
+        .as_builder()
+        .outputs_data(packed::BytesVec::new_builder().set(outputs_data).build())
+        .build();
+    packed::TransactionView::new_builder()
+        .data(tx.as_builder().raw(raw).build())
+        .hash(reader.hash().to_entity())
+        .witness_hash(reader.witness_hash().to_entity())
+        .build()
+        .as_reader()
+        .unpack()
+}
@@ -1,10 +1,12 @@
 use crate::cache::StoreCache;
-use crate::store::ChainStore;
+use crate::cell_data::{decode_refs, encode_refs, CellDataIndex};
+use crate::store::{get_cell_data_index, ChainStore};
 use crate::{
     COLUMN_BLOCK_BODY, COLUMN_BLOCK_EPOCH, COLUMN_BLOCK_EXT, COLUMN_BLOCK_HEADER,
-    COLUMN_BLOCK_PROPOSAL_IDS, COLUMN_BLOCK_UNCLE, COLUMN_CELL_SET, COLUMN_EPOCH, COLUMN_INDEX,
-    COLUMN_META, COLUMN_TRANSACTION_INFO, COLUMN_UNCLES, META_CURRENT_EPOCH_KEY,
-    META_TIP_HEADER_KEY,
+    COLUMN_BLOCK_PROPOSAL_IDS, COLUMN_BLOCK_UNCLE, COLUMN_CELL_DATA, COLUMN_CELL_DATA_INDEX,
+    COLUMN_CELL_DATA_REFS, COLUMN_CELL_SET, COLUMN_EPOCH, COLUMN_INDEX, COLUMN_META,
+    COLUMN_SPENT_TRANSACTIONS, COLUMN_TRANSACTION_INFO, COLUMN_UNCLES, META_CURRENT_EPOCH_KEY,
+    META_PRUNED_NUMBER_KEY, META_REINDEX_NUMBER_KEY, META_TIP_HEADER_KEY,
 };
 use ckb_db::{
     iter::{DBIterator, DBIteratorItem},
@@ -12,15 +14,26 @@ use ckb_db::{
 };
 use ckb_error::Error;
 use ckb_types::{
-    core::{BlockExt, BlockView, EpochExt, HeaderView},
+    bytes::Bytes,
+    core::{BlockExt, BlockNumber, BlockView, EpochExt, HeaderView, TransactionView},
     packed,
     prelude::*,
 };
+use std::cmp;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
+// The max number of blocks pruned when a block is inserted, a node which has
+// just enabled the pruning catches up in the background, see
+// `ChainDB::catch_up_pruning`.
+const PRUNE_BATCH_SIZE: BlockNumber = 4;
+
 pub struct StoreTransaction {
     pub(crate) inner: RocksDBTransaction,
     pub(crate) cache: Arc<StoreCache>,
+    pub(crate) cell_data_dedup: bool,
+    pub(crate) prune_depth: Option<BlockNumber>,
+    pub(crate) pruning_in_background: Arc<AtomicBool>,
 }
 
 impl<'a> ChainStore<'a> for StoreTransaction {
@@ -106,6 +119,23 @@ impl StoreTransaction {
             })
     }
 
+    // Reads the cell data refcount for an update. The store is an optimistic
+    // transaction DB, nothing is locked, the commit fails with Busy if another
+    // transaction has written the refcount in the meantime, so no increment is
+    // lost. The writers which may share refcounts, such as the block insertion
+    // and the pruning catch up, hold `ChainDB::write_lock` to avoid the failure.
+    fn get_cell_data_refs_for_update(
+        &self,
+        data_hash: &packed::Byte32,
+        snapshot: &StoreTransactionSnapshot<'_>,
+    ) -> u64 {
+        self.inner
+            .get_for_update(COLUMN_CELL_DATA_REFS, data_hash.as_slice(), &snapshot.inner)
+            .expect("db operation should be ok")
+            .map(|slice| decode_refs(slice.as_ref()))
+            .unwrap_or(0)
+    }
+
     pub fn insert_tip_header(&self, h: &HeaderView) -> Result<(), Error> {
         self.insert_raw(COLUMN_META, META_TIP_HEADER_KEY, h.hash().as_slice())
     }
@@ -127,12 +157,128 @@ impl StoreTransaction {
                 .block_hash(hash.clone())
                 .index(index.pack())
                 .build();
-            let tx_data = tx.pack();
-            self.insert_raw(COLUMN_BLOCK_BODY, key.as_slice(), tx_data.as_slice())?;
+            if self.cell_data_dedup {
+                self.insert_deduplicated_transaction(&key, &tx)?;
+            } else {
+                let tx_data = tx.pack();
+                self.insert_raw(COLUMN_BLOCK_BODY, key.as_slice(), tx_data.as_slice())?;
+            }
         }
         Ok(())
     }
 
+    // Stores the non-empty outputs data of the transaction in the
+    // content-addressed cell data column, and the rest in the block body.
+    fn insert_deduplicated_transaction(
+        &self,
+        key: &packed::TransactionKey,
+        tx: &TransactionView,
+    ) -> Result<(), Error> {
+        let outputs_data: Vec<Bytes> = tx.outputs_data().into_iter().map(|d| d.unpack()).collect();
+        let data_index = match CellDataIndex::new(&outputs_data) {
+            Some(data_index) => data_index,
+            None => {
+                let tx_data = tx.pack();
+                return self.insert_raw(COLUMN_BLOCK_BODY, key.as_slice(), tx_data.as_slice());
+            }
+        };
+        let snapshot = self.get_snapshot();
+        for (index, data) in outputs_data.iter().enumerate() {
+            if let Some((data_hash, _)) = data_index.get(index) {
+                let refs = self.get_cell_data_refs_for_update(data_hash, &snapshot);
+                if refs == 0 {
+                    self.insert_raw(COLUMN_CELL_DATA, data_hash.as_slice(), data)?;
+                }
+                self.insert_raw(
+                    COLUMN_CELL_DATA_REFS,
+                    data_hash.as_slice(),
+                    &encode_refs(refs + 1),
+                )?;
+            }
+        }
+        self.insert_raw(COLUMN_CELL_DATA_INDEX, key.as_slice(), &data_index.to_vec())?;
+
+        let stripped_outputs_data: Vec<packed::Bytes> = outputs_data
+            .iter()
+            .enumerate()
+            .map(|(index, data)| match data_index.get(index) {
+                Some(_) => Bytes::new().pack(),
+                None => data.pack(),
+            })
+            .collect();
+        let raw = tx
+            .data()
+            .raw()
+            .as_builder()
+            .outputs_data(
+                packed::BytesVec::new_builder()
+                    .set(stripped_outputs_data)
+                    .build(),
+            )
+            .build();
+        let tx_data = packed::TransactionView::new_builder()
+            .data(tx.data().as_builder().raw(raw).build())
+            .hash(tx.hash())
+            .witness_hash(tx.witness_hash())
+            .build();
+        self.insert_raw(COLUMN_BLOCK_BODY, key.as_slice(), tx_data.as_slice())
+    }
+
+    /// Deletes the block, releasing the deduplicated cell data referenced only by it
+    pub fn delete_block(&self, block: &BlockView) -> Result<(), Error> {
+        let hash = block.hash();
+        self.delete(COLUMN_BLOCK_HEADER, hash.as_slice())?;
+        self.delete(COLUMN_BLOCK_UNCLE, hash.as_slice())?;
+        self.delete(COLUMN_BLOCK_PROPOSAL_IDS, hash.as_slice())?;
+        self.cache.headers.remove(&hash);
+        self.cache.block_uncles.remove(&hash);
+        self.cache.block_proposals.remove(&hash);
+        self.cache.block_tx_hashes.remove(&hash);
+        self.cache.cellbase.remove(&hash);
+        for (index, tx) in block.transactions().iter().enumerate() {
+            let key = packed::TransactionKey::new_builder()
+                .block_hash(hash.clone())
+                .index(index.pack())
+                .build();
+            self.delete_transaction_body(&key, &tx.hash(), tx.outputs().len())?;
+        }
+        Ok(())
+    }
+
+    // Deletes the transaction from the block body, releasing the deduplicated
+    // cell data referenced only by it.
+    fn delete_transaction_body(
+        &self,
+        key: &packed::TransactionKey,
+        tx_hash: &packed::Byte32,
+        outputs_len: usize,
+    ) -> Result<(), Error> {
+        for output_index in 0..outputs_len {
+            self.cache
+                .cell_data
+                .remove(&(tx_hash.clone(), output_index as u32));
+        }
+        if let Some(data_index) = get_cell_data_index(self, key) {
+            let snapshot = self.get_snapshot();
+            for data_hash in data_index.hashes() {
+                let refs = self.get_cell_data_refs_for_update(data_hash, &snapshot);
+                if refs <= 1 {
+                    self.delete(COLUMN_CELL_DATA, data_hash.as_slice())?;
+                    self.delete(COLUMN_CELL_DATA_REFS, data_hash.as_slice())?;
+                    self.cache.cell_data_by_hash.remove(data_hash);
+                } else {
+                    self.insert_raw(
+                        COLUMN_CELL_DATA_REFS,
+                        data_hash.as_slice(),
+                        &encode_refs(refs - 1),
+                    )?;
+                }
+            }
+            self.delete(COLUMN_CELL_DATA_INDEX, key.as_slice())?;
+        }
+        self.delete(COLUMN_BLOCK_BODY, key.as_slice())
+    }
+
     pub fn insert_block_ext(
         &self,
         block_hash: &packed::Byte32,
@@ -217,4 +363,129 @@ impl StoreTransaction {
     pub fn delete_cell_set(&self, tx_hash: &packed::Byte32) -> Result<(), Error> {
         self.delete(COLUMN_CELL_SET, tx_hash.as_slice())
     }
+
+    /// Records the number of the next block to reindex, so an interrupted
+    /// reindex resumes from it
+    pub fn insert_reindex_number(&self, number: BlockNumber) -> Result<(), Error> {
+        let number: packed::Uint64 = number.pack();
+        self.insert_raw(COLUMN_META, META_REINDEX_NUMBER_KEY, number.as_slice())
+    }
+
+    pub fn delete_reindex_number(&self) -> Result<(), Error> {
+        self.delete(COLUMN_META, META_REINDEX_NUMBER_KEY)
+    }
+
+    /// Records that the last live cell of the transaction is spent in the
+    /// block, the transaction is deleted when the block is pruned. Does
+    /// nothing if the pruning is disabled.
+    pub fn insert_spent_transaction(
+        &self,
+        block_number: BlockNumber,
+        block_hash: &packed::Byte32,
+        tx_hash: &packed::Byte32,
+    ) -> Result<(), Error> {
+        if self.prune_depth.is_none() {
+            return Ok(());
+        }
+        let mut key = Vec::with_capacity(8 + 32 + 32);
+        key.extend_from_slice(&block_number.to_be_bytes());
+        key.extend_from_slice(block_hash.as_slice());
+        key.extend_from_slice(tx_hash.as_slice());
+        self.insert_raw(COLUMN_SPENT_TRANSACTIONS, &key, &[])
+    }
+
+    /// Prunes the main chain blocks deeper than the prune depth below the tip,
+    /// at most `PRUNE_BATCH_SIZE` blocks each time. Does nothing until the
+    /// pruning is started and caught up in the background.
+    ///
+    /// The headers, uncles, proposals, exts and epochs of the pruned blocks
+    /// are kept, and so are their transactions with live cells. A spent
+    /// transaction is deleted when the block spending its last live cell is
+    /// pruned, so a reorganization within the prune depth can still restore
+    /// its cells.
+    pub fn prune(&self, tip: &HeaderView) -> Result<(), Error> {
+        if self.pruning_in_background.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        self.prune_blocks(tip, PRUNE_BATCH_SIZE).map(|_| ())
+    }
+
+    // Prunes at most `batch_size` blocks, returns whether there are more
+    // blocks to prune. The genesis block is never pruned.
+    pub(crate) fn prune_blocks(
+        &self,
+        tip: &HeaderView,
+        batch_size: BlockNumber,
+    ) -> Result<bool, Error> {
+        let prune_depth = match self.prune_depth {
+            Some(prune_depth) => prune_depth,
+            None => return Ok(false),
+        };
+        let pruned_number = match self.get_pruned_number() {
+            Some(pruned_number) => cmp::max(pruned_number, 1),
+            None => return Ok(false),
+        };
+        let target = tip.number().saturating_sub(prune_depth);
+        let end = cmp::min(target, pruned_number + batch_size);
+        if end <= pruned_number {
+            return Ok(false);
+        }
+        for number in pruned_number..end {
+            self.prune_block(number)?;
+        }
+        let packed_end: packed::Uint64 = end.pack();
+        self.insert_raw(COLUMN_META, META_PRUNED_NUMBER_KEY, packed_end.as_slice())?;
+        // Updated before the commit, so a snapshot taken in between never
+        // reads a pruned block as a whole one.
+        self.cache.set_pruned_number(end);
+        Ok(end < target)
+    }
+
+    fn prune_block(&self, number: BlockNumber) -> Result<(), Error> {
+        let block_hash = self
+            .get_block_hash(number)
+            .expect("pruned blocks are in the main chain");
+        let prefix = number.to_be_bytes();
+        let spent: Vec<_> = self
+            .get_iter(COLUMN_SPENT_TRANSACTIONS, &prefix, Direction::Forward)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(key, _)| key)
+            .collect();
+        for key in spent {
+            self.delete(COLUMN_SPENT_TRANSACTIONS, &key)?;
+            // The records of the detached blocks are dropped, the transactions
+            // they spent have been restored and may be spent again later.
+            if &key[8..40] != block_hash.as_slice() {
+                continue;
+            }
+            let tx_hash = packed::Byte32Reader::from_slice_should_be_ok(&key[40..]).to_entity();
+            if self.get_tx_meta(&tx_hash).is_none() {
+                self.delete_spent_transaction(&tx_hash)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_spent_transaction(&self, tx_hash: &packed::Byte32) -> Result<(), Error> {
+        let key = match self.get_transaction_info_packed(tx_hash) {
+            Some(info) => info.key(),
+            None => return Ok(()),
+        };
+        let outputs_len = self
+            .get(COLUMN_BLOCK_BODY, key.as_slice())
+            .map(|slice| {
+                packed::TransactionViewReader::from_slice_should_be_ok(&slice.as_ref())
+                    .data()
+                    .raw()
+                    .outputs()
+                    .len()
+            })
+            .unwrap_or(0);
+        self.delete_transaction_body(&key, tx_hash, outputs_len)?;
+        self.delete(COLUMN_TRANSACTION_INFO, tx_hash.as_slice())?;
+        let block_hash = key.block_hash();
+        self.cache.block_tx_hashes.remove(&block_hash);
+        self.cache.cellbase.remove(&block_hash);
+        Ok(())
+    }
 }
@@ -26,12 +26,14 @@ pub const MAX_BLOCKS_TO_ANNOUNCE: usize = 8;
 pub const MAX_UNCONNECTING_HEADERS: usize = 10;
 pub const MAX_BLOCKS_IN_TRANSIT_PER_PEER: usize = 16;
 pub const MAX_TIP_AGE: u64 = 24 * 60 * 60 * 1000;
+// Blocks older than this are not served once the daily upload cap is reached
+pub const HISTORICAL_BLOCK_AGE: u64 = 7 * 24 * 60 * 60 * 1000;
 pub const STALE_RELAY_AGE_LIMIT: u64 = 30 * 24 * 60 * 60 * 1000;
 pub const PER_FETCH_BLOCK_LIMIT: usize = 128;
 
 pub(crate) const LOG_TARGET_RELAY: &str = "ckb-relay";
 
-use ckb_network::ProtocolId;
+use ckb_network::{MessageNames, ProtocolId};
 
 pub enum NetworkProtocol {
     SYNC = 100,
@@ -46,6 +48,51 @@ impl Into<ProtocolId> for NetworkProtocol {
     }
 }
 
+const SYNC_MESSAGES: [&str; 9] = [
+    "GetHeaders",
+    "SendHeaders",
+    "GetBlocks",
+    "SendBlock",
+    "SetFilter",
+    "AddFilter",
+    "ClearFilter",
+    "FilteredBlock",
+    "InIBD",
+];
+
+const RELAY_MESSAGES: [&str; 8] = [
+    "CompactBlock",
+    "RelayTransactions",
+    "RelayTransactionHashes",
+    "GetRelayTransactions",
+    "GetBlockTransactions",
+    "BlockTransactions",
+    "GetBlockProposal",
+    "BlockProposal",
+];
+
+// The molecule union starts with the item id in little endian, 0 is not set
+fn union_item_index(data: &[u8]) -> Option<usize> {
+    if data.len() < 4 {
+        return None;
+    }
+    let mut item_id = [0u8; 4];
+    item_id.copy_from_slice(&data[..4]);
+    (u32::from_le_bytes(item_id) as usize).checked_sub(1)
+}
+
+/// Names the sync messages in the network traffic statistics
+pub const SYNC_MESSAGE_NAMES: MessageNames = MessageNames {
+    names: &SYNC_MESSAGES,
+    index: union_item_index,
+};
+
+/// Names the relay messages in the network traffic statistics
+pub const RELAY_MESSAGE_NAMES: MessageNames = MessageNames {
+    names: &RELAY_MESSAGES,
+    index: union_item_index,
+};
+
 //  Timeout = base + per_header * (expected number of headers)
 pub const HEADERS_DOWNLOAD_TIMEOUT_BASE: u64 = 6 * 60 * 1000; // 6 minutes
 pub const HEADERS_DOWNLOAD_TIMEOUT_PER_HEADER: u64 = 1; // 1ms/header
@@ -64,6 +111,14 @@ pub const MAX_LOCATOR_SIZE: usize = 101;
 
 pub const BLOCK_DOWNLOAD_TIMEOUT: u64 = 30 * 1000; // 30s
 
+// The tx hashes are announced to each peer after a Poisson distributed delay
+// with these averages, so the observers can't tell which node a tx comes from.
+// The inbound peers wait longer since they are easier for an attacker to get.
+pub const OUTBOUND_TX_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+pub const INBOUND_TX_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+// Average delay before the txs submitted locally are queued for announcement
+pub const LOCAL_TX_ANNOUNCE_DELAY: Duration = Duration::from_secs(5);
+
 // ban time
 // 5 minutes
 pub const BAD_MESSAGE_BAN_TIME: Duration = Duration::from_secs(5 * 60);
@@ -14,7 +14,9 @@ mod transaction_hashes_process;
 mod transactions_process;
 
 use self::block_proposal_process::BlockProposalProcess;
-use self::block_transactions_process::BlockTransactionsProcess;
+use self::block_transactions_process::{
+    BlockTransactionsProcess, Status as BlockTransactionsStatus,
+};
 use self::compact_block_process::CompactBlockProcess;
 pub use self::error::{Error, Misbehavior};
 use self::get_block_proposal_process::GetBlockProposalProcess;
@@ -24,10 +26,9 @@ use self::transaction_hashes_process::TransactionHashesProcess;
 use self::transactions_process::TransactionsProcess;
 use crate::block_status::BlockStatus;
 use crate::types::{SyncSharedState, SyncSnapshot};
-use crate::BAD_MESSAGE_BAN_TIME;
 use ckb_chain::chain::ChainController;
 use ckb_logger::{debug_target, info_target, trace_target};
-use ckb_network::{CKBProtocolContext, CKBProtocolHandler, PeerIndex, TargetSession};
+use ckb_network::{Behaviour, CKBProtocolContext, CKBProtocolHandler, PeerIndex, TargetSession};
 use ckb_types::{
     core,
     packed::{self, Byte32, ProposalShortId},
@@ -76,6 +77,23 @@ impl Relayer {
         peer: PeerIndex,
         message: packed::RelayMessageUnionReader<'r>,
     ) -> Result<(), FailureError> {
+        let is_transaction_message = match message {
+            packed::RelayMessageUnionReader::RelayTransactions(_)
+            | packed::RelayMessageUnionReader::RelayTransactionHashes(_)
+            | packed::RelayMessageUnionReader::GetRelayTransactions(_) => true,
+            _ => false,
+        };
+        // The remote end doesn't know the connection is block-relay-only, its
+        // transaction messages are ignored rather than punished
+        if is_transaction_message && self.shared().state().peers().is_block_relay_only(peer) {
+            debug_target!(
+                crate::LOG_TARGET_RELAY,
+                "ignore {} from block-relay-only peer {}",
+                message.item_name(),
+                peer
+            );
+            return Ok(());
+        }
         match message {
             packed::RelayMessageUnionReader::CompactBlock(reader) => {
                 CompactBlockProcess::new(reader, self, nc, peer).execute()?;
@@ -98,7 +116,11 @@ impl Relayer {
             }
             packed::RelayMessageUnionReader::BlockTransactions(reader) => {
                 if reader.check_data() {
-                    BlockTransactionsProcess::new(reader, self, nc, peer).execute()?;
+                    let status = BlockTransactionsProcess::new(reader, self, Arc::clone(&nc), peer)
+                        .execute()?;
+                    if status == BlockTransactionsStatus::UnkownRequest {
+                        nc.report_peer(peer, Behaviour::UnrequestedData);
+                    }
                 } else {
                     return Err(err_msg("BlockTransactions: invalid data"));
                 }
@@ -123,7 +145,13 @@ impl Relayer {
         if let Err(err) = self.try_process(Arc::clone(&nc), peer, message) {
             if let Some(&Error::Misbehavior(ref e)) = err.downcast_ref() {
                 debug_target!(crate::LOG_TARGET_RELAY, "try_process error {}", e);
-                nc.ban_peer(peer, BAD_MESSAGE_BAN_TIME);
+                let behaviour = match e {
+                    Misbehavior::BlockInvalid | Misbehavior::HeaderInvalid => {
+                        Behaviour::InvalidBlock
+                    }
+                    _ => Behaviour::InvalidCompactBlock,
+                };
+                nc.report_peer(peer, behaviour);
                 return;
             }
         }
@@ -192,6 +220,7 @@ impl Relayer {
                 boxed.header().hash(),
                 unix_time_as_millis()
             );
+            nc.report_peer(peer, Behaviour::UsefulBlock);
             let block_hash = boxed.hash();
             snapshot.state().remove_header_view(&block_hash);
             let cb = packed::CompactBlock::build_from_block(&boxed, &HashSet::new());
@@ -472,26 +501,57 @@ impl Relayer {
         }
     }
 
-    // Send bulk of tx hashes to selected peers
+    // Queue the new tx hashes for the selected peers, and announce the queued
+    // hashes to each peer when its trickle timer fires
     pub fn send_bulk_of_tx_hashes(&self, nc: &dyn CKBProtocolContext) {
-        let mut selected: HashMap<PeerIndex, HashSet<Byte32>> = HashMap::default();
+        let now = Instant::now();
+        let state = self.shared.state();
+        let mut peer_tx_hashes: Vec<(Option<PeerIndex>, HashSet<Byte32>)> = state
+            .take_tx_hashes()
+            .into_iter()
+            .map(|(peer_index, tx_hashes)| (Some(peer_index), tx_hashes))
+            .collect();
+        peer_tx_hashes.push((None, state.take_local_tx_hashes(now).into_iter().collect()));
+
+        let mut selected: Vec<(PeerIndex, Vec<Byte32>)> = Vec::new();
         {
-            let peer_tx_hashes = self.shared.state().take_tx_hashes();
-            let mut known_txs = self.shared.state().known_txs();
+            let mut known_txs = state.known_txs();
+            let mut peers = state.peers().state.write();
+            // the transactions are not relayed to the block-relay-only peers
+            let target_peers: Vec<PeerIndex> = nc
+                .connected_peers()
+                .into_iter()
+                .filter(|peer| {
+                    peers
+                        .get(peer)
+                        .map(|peer_state| !peer_state.peer_flags.is_block_relay_only)
+                        .unwrap_or(false)
+                })
+                .collect();
 
             for (peer_index, tx_hashes) in peer_tx_hashes.into_iter() {
                 for tx_hash in tx_hashes {
-                    for peer in nc
-                        .connected_peers()
-                        .into_iter()
+                    for peer in target_peers
+                        .iter()
+                        .cloned()
                         .filter(|target_peer| {
                             known_txs.insert(*target_peer, tx_hash.clone())
-                                && (peer_index != *target_peer)
+                                && (peer_index != Some(*target_peer))
                         })
                         .take(MAX_RELAY_PEERS)
                     {
-                        let hashes = selected.entry(peer).or_insert_with(HashSet::default);
-                        hashes.insert(tx_hash.clone());
+                        if let Some(peer_state) = peers.get_mut(&peer) {
+                            peer_state.add_tx_announcement(tx_hash.clone());
+                        }
+                    }
+                }
+            }
+
+            for peer in target_peers {
+                if let Some(peer_state) = peers.get_mut(&peer) {
+                    let hashes = peer_state.pop_tx_announcements(now);
+                    if !hashes.is_empty() {
+                        selected.push((peer, hashes));
                     }
                 }
             }
@@ -543,7 +603,7 @@ impl CKBProtocolHandler for Relayer {
                     "Peer {} sends us a malformed message",
                     peer_index
                 );
-                nc.ban_peer(peer_index, BAD_MESSAGE_BAN_TIME);
+                nc.report_peer(peer_index, Behaviour::MalformedMessage);
                 return;
             }
         };
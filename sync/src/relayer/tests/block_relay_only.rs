@@ -0,0 +1,91 @@
+use crate::relayer::tests::helper::{build_chain, new_index_transaction, MockProtocalContext};
+use crate::types::PeerFlags;
+use ckb_network::{CKBProtocolContext, PeerIndex};
+use ckb_types::{
+    packed::{self, Byte32},
+    prelude::*,
+};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn tx_hash(index: usize) -> Byte32 {
+    new_index_transaction(index).transaction().calc_tx_hash()
+}
+
+fn block_relay_only_flags() -> PeerFlags {
+    PeerFlags {
+        is_outbound: true,
+        is_block_relay_only: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_ignore_transaction_hashes_from_block_relay_only_peer() {
+    let (relayer, _) = build_chain(5);
+    let full_peer: PeerIndex = 1.into();
+    let block_relay_only_peer: PeerIndex = 2.into();
+    let peers = relayer.shared().state().peers();
+    peers.on_connected(full_peer, PeerFlags::default());
+    peers.on_connected(block_relay_only_peer, block_relay_only_flags());
+
+    let nc: Arc<dyn CKBProtocolContext + Sync> = Arc::new(MockProtocalContext::default());
+    for (peer, hash) in vec![(full_peer, tx_hash(1)), (block_relay_only_peer, tx_hash(2))] {
+        let content = packed::RelayTransactionHashes::new_builder()
+            .tx_hashes(vec![hash].pack())
+            .build();
+        let message = packed::RelayMessage::new_builder().set(content).build();
+        relayer
+            .try_process(Arc::clone(&nc), peer, message.to_enum().as_reader())
+            .unwrap();
+    }
+
+    let inflight_transactions = relayer.shared().state().inflight_transactions();
+    assert!(inflight_transactions.contains_key(&tx_hash(1)));
+    assert!(!inflight_transactions.contains_key(&tx_hash(2)));
+}
+
+#[test]
+fn test_not_announce_transaction_hashes_to_block_relay_only_peer() {
+    let (relayer, _) = build_chain(5);
+    let state = relayer.shared().state();
+    let full_peer: PeerIndex = 1.into();
+    let block_relay_only_peer: PeerIndex = 2.into();
+    state.peers().on_connected(full_peer, PeerFlags::default());
+    state
+        .peers()
+        .on_connected(block_relay_only_peer, block_relay_only_flags());
+
+    // Schedule the trickle timers far enough, so the announcements stay queued
+    let later = Instant::now() + Duration::from_secs(3600);
+    for peer_state in state.peers().state.write().values_mut() {
+        assert!(peer_state.pop_tx_announcements(later).is_empty());
+    }
+
+    // A tx relayed by another peer
+    state
+        .tx_hashes()
+        .entry(3.into())
+        .or_default()
+        .insert(tx_hash(1));
+    let nc = MockProtocalContext {
+        connected_peers: vec![full_peer, block_relay_only_peer],
+        ..Default::default()
+    };
+    relayer.send_bulk_of_tx_hashes(&nc);
+
+    let mut peers = state.peers().state.write();
+    let later = later + Duration::from_secs(3600);
+    assert_eq!(
+        peers
+            .get_mut(&full_peer)
+            .unwrap()
+            .pop_tx_announcements(later),
+        vec![tx_hash(1)]
+    );
+    assert!(peers
+        .get_mut(&block_relay_only_peer)
+        .unwrap()
+        .pop_tx_announcements(later)
+        .is_empty());
+}
@@ -321,7 +321,7 @@ fn test_collision_and_send_missing_indexes() {
     assert!(nc
         .as_ref()
         .sent_messages_to
-        .borrow()
+        .lock()
         .contains(&(peer_index, data)));
 
     // update cached missing_index
@@ -434,6 +434,6 @@ fn test_missing() {
     assert!(nc
         .as_ref()
         .sent_messages_to
-        .borrow()
+        .lock()
         .contains(&(peer_index, data)));
 }
@@ -14,7 +14,6 @@ use ckb_types::{
     packed::{self, CellInput, CellOutputBuilder, CompactBlock, OutPoint, ProposalShortId},
 };
 use faketime::unix_time_as_millis;
-use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
 use std::sync::Arc;
@@ -127,8 +126,8 @@ fn test_unknow_parent() {
 
     // send_getheaders_to_peer
     assert_eq!(
-        nc.as_ref().sent_messages,
-        RefCell::new(vec![(NetworkProtocol::SYNC.into(), peer_index, data)])
+        *nc.as_ref().sent_messages.lock(),
+        vec![(NetworkProtocol::SYNC.into(), peer_index, data)]
     );
 }
 
@@ -437,7 +436,7 @@ fn test_send_missing_indexes() {
     assert!(nc
         .as_ref()
         .sent_messages_to
-        .borrow()
+        .lock()
         .contains(&(peer_index, data)));
 
     // insert inflight proposal
@@ -458,7 +457,7 @@ fn test_send_missing_indexes() {
     assert!(nc
         .as_ref()
         .sent_messages_to
-        .borrow()
+        .lock()
         .contains(&(peer_index, data)));
 }
 
@@ -676,6 +675,6 @@ fn test_collision() {
     assert!(nc
         .as_ref()
         .sent_messages_to
-        .borrow()
+        .lock()
         .contains(&(peer_index, data)));
 }
@@ -21,8 +21,8 @@ use ckb_types::{
     utilities::difficulty_to_compact,
     U256,
 };
+use ckb_util::Mutex;
 use faketime::{self, unix_time_as_millis};
-use std::cell::RefCell;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -160,8 +160,9 @@ pub(crate) fn build_chain(tip: BlockNumber) -> (Relayer, OutPoint) {
 
 #[derive(Default)]
 pub(crate) struct MockProtocalContext {
-    pub sent_messages: RefCell<Vec<(ProtocolId, PeerIndex, Bytes)>>,
-    pub sent_messages_to: RefCell<Vec<(PeerIndex, Bytes)>>,
+    pub sent_messages: Mutex<Vec<(ProtocolId, PeerIndex, Bytes)>>,
+    pub sent_messages_to: Mutex<Vec<(PeerIndex, Bytes)>>,
+    pub connected_peers: Vec<PeerIndex>,
 }
 
 impl CKBProtocolContext for MockProtocalContext {
@@ -197,18 +198,19 @@ impl CKBProtocolContext for MockProtocalContext {
         peer_index: PeerIndex,
         data: Bytes,
     ) -> Result<(), Error> {
-        self.sent_messages
-            .borrow_mut()
-            .push((proto_id, peer_index, data));
+        self.sent_messages.lock().push((proto_id, peer_index, data));
         Ok(())
     }
     fn send_message_to(&self, peer_index: PeerIndex, data: Bytes) -> Result<(), Error> {
-        self.sent_messages_to.borrow_mut().push((peer_index, data));
+        self.sent_messages_to.lock().push((peer_index, data));
         Ok(())
     }
 
-    fn filter_broadcast(&self, _target: TargetSession, _data: Bytes) -> Result<(), Error> {
-        unimplemented!();
+    fn filter_broadcast(&self, target: TargetSession, data: Bytes) -> Result<(), Error> {
+        match target {
+            TargetSession::Single(peer_index) => self.send_message_to(peer_index, data),
+            _ => unimplemented!(),
+        }
     }
     fn disconnect(&self, _peer_index: PeerIndex, _message: &str) -> Result<(), Error> {
         unimplemented!();
@@ -217,11 +219,9 @@ impl CKBProtocolContext for MockProtocalContext {
         unimplemented!();
     }
     fn connected_peers(&self) -> Vec<PeerIndex> {
-        unimplemented!();
-    }
-    fn report_peer(&self, _peer_index: PeerIndex, _behaviour: Behaviour) {
-        unimplemented!();
+        self.connected_peers.clone()
     }
+    fn report_peer(&self, _peer_index: PeerIndex, _behaviour: Behaviour) {}
     fn ban_peer(&self, _peer_index: PeerIndex, _duration: Duration) {
         unimplemented!();
     }
@@ -231,4 +231,12 @@ impl CKBProtocolContext for MockProtocalContext {
     fn send_paused(&self) -> bool {
         false
     }
+
+    fn download_paused(&self, _peer_index: PeerIndex) -> bool {
+        false
+    }
+
+    fn upload_cap_reached(&self) -> bool {
+        false
+    }
 }
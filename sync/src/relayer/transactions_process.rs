@@ -1,7 +1,7 @@
 use crate::relayer::Relayer;
 use ckb_error::{Error, ErrorKind, InternalError, InternalErrorKind};
 use ckb_logger::debug_target;
-use ckb_network::{CKBProtocolContext, PeerIndex};
+use ckb_network::{Behaviour, CKBProtocolContext, PeerIndex};
 use ckb_types::{
     core::{Cycle, TransactionView},
     packed,
@@ -91,6 +91,7 @@ impl<'a> TransactionsProcess<'a> {
 
         let callback = Box::new(move |ret: Result<Vec<Cycle>, Error>| match ret {
             Ok(cycles_vec) => {
+                let mut useful = false;
                 for ((tx_hash, relay_cycles), cycles) in
                     relay_cycles_vec.into_iter().zip(cycles_vec.into_iter())
                 {
@@ -98,6 +99,7 @@ impl<'a> TransactionsProcess<'a> {
                         let mut cache = shared.state().tx_hashes();
                         let entry = cache.entry(peer_index).or_insert_with(HashSet::default);
                         entry.insert(tx_hash);
+                        useful = true;
                     } else {
                         debug_target!(
                             crate::LOG_TARGET_RELAY,
@@ -109,9 +111,13 @@ impl<'a> TransactionsProcess<'a> {
                         );
 
                         nc.ban_peer(peer_index, DEFAULT_BAN_TIME);
+                        useful = false;
                         break;
                     }
                 }
+                if useful {
+                    nc.report_peer(peer_index, Behaviour::UsefulTransaction);
+                }
             }
             Err(err) => {
                 if is_malformed(&err) {
@@ -4,7 +4,7 @@ use crate::types::{HeaderView, SyncSnapshot};
 use crate::{MAX_BLOCKS_IN_TRANSIT_PER_PEER, PER_FETCH_BLOCK_LIMIT};
 use ckb_logger::{debug, trace};
 use ckb_network::PeerIndex;
-use ckb_store::ChainStore;
+use ckb_store::{ChainStore, MIN_PRUNE_DEPTH};
 use ckb_types::{core, packed};
 use std::cmp::min;
 
@@ -34,6 +34,16 @@ impl BlockFetcher {
         header.is_better_than(&self.snapshot.total_difficulty())
     }
 
+    pub fn is_pruned_peer(&self) -> bool {
+        self.synchronizer
+            .peers()
+            .state
+            .read()
+            .get(&self.peer)
+            .map(|state| state.peer_flags.is_pruned)
+            .unwrap_or(false)
+    }
+
     pub fn peer_best_known_header(&self) -> Option<HeaderView> {
         self.synchronizer.peers().get_best_known_header(self.peer)
     }
@@ -112,6 +122,18 @@ impl BlockFetcher {
 
         debug_assert!(best_known_header.number() > fixed_last_common_header.number());
 
+        // A pruned peer keeps at least the last `MIN_PRUNE_DEPTH` block bodies
+        if self.is_pruned_peer()
+            && fixed_last_common_header.number() + MIN_PRUNE_DEPTH < best_known_header.number()
+        {
+            trace!(
+                "[block downloader] pruned peer {} cannot serve block {}",
+                self.peer,
+                fixed_last_common_header.number() + 1
+            );
+            return None;
+        }
+
         let mut index_height = fixed_last_common_header.number();
         // Read up to 128, get_ancestor may be as expensive
         // as iterating over ~100 entries anyway.
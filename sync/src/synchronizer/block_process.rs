@@ -1,9 +1,6 @@
-use crate::{
-    synchronizer::{BlockStatus, Synchronizer},
-    BAD_MESSAGE_BAN_TIME,
-};
+use crate::synchronizer::{BlockStatus, Synchronizer};
 use ckb_logger::{debug, info};
-use ckb_network::{CKBProtocolContext, PeerIndex};
+use ckb_network::{Behaviour, CKBProtocolContext, PeerIndex};
 use ckb_types::{packed, prelude::*};
 use failure::Error as FailureError;
 
@@ -38,26 +35,48 @@ impl<'a> BlockProcess<'a> {
         );
         let snapshot = self.synchronizer.shared().snapshot();
 
-        if self
+        let state = self.synchronizer.shared().state();
+        if !state.new_block_received(&block) {
+            // The duplicated blocks and the blocks arriving after the timeout
+            // were requested from the peer too
+            if state
+                .write_inflight_blocks()
+                .is_requested_from(self.peer, &block.hash())
+            {
+                debug!(
+                    "Peer {} sent us the duplicated or late block {}",
+                    self.peer,
+                    block.hash()
+                );
+            } else {
+                debug!(
+                    "Peer {} sent us the unrequested block {}",
+                    self.peer,
+                    block.hash()
+                );
+                self.nc.report_peer(self.peer, Behaviour::UnrequestedData);
+            }
+            return Ok(());
+        }
+        match self
             .synchronizer
-            .shared()
-            .state()
-            .new_block_received(&block)
-            && self
-                .synchronizer
-                .process_new_block(&snapshot, self.peer, block.clone())
-                .is_err()
+            .process_new_block(&snapshot, self.peer, block.clone())
         {
-            info!(
-                "Ban peer {:?} for {} seconds, reason: it sent us an invalid block",
-                self.peer,
-                BAD_MESSAGE_BAN_TIME.as_secs()
-            );
-            self.synchronizer
-                .shared()
-                .state()
-                .insert_block_status(block.hash(), BlockStatus::BLOCK_INVALID);
-            self.nc.ban_peer(self.peer, BAD_MESSAGE_BAN_TIME);
+            Ok(true) => self.nc.report_peer(self.peer, Behaviour::UsefulBlock),
+            Ok(false) => {}
+            Err(err) => {
+                info!(
+                    "Peer {} sent us the invalid block {}, error: {}",
+                    self.peer,
+                    block.hash(),
+                    err
+                );
+                self.synchronizer
+                    .shared()
+                    .state()
+                    .insert_block_status(block.hash(), BlockStatus::BLOCK_INVALID);
+                self.nc.report_peer(self.peer, Behaviour::InvalidBlock);
+            }
         }
 
         Ok(())
@@ -1,10 +1,12 @@
 use crate::block_status::BlockStatus;
 use crate::synchronizer::Synchronizer;
-use crate::MAX_BLOCKS_IN_TRANSIT_PER_PEER;
+use crate::{HISTORICAL_BLOCK_AGE, MAX_BLOCKS_IN_TRANSIT_PER_PEER};
 use ckb_logger::{debug, warn};
 use ckb_network::{CKBProtocolContext, PeerIndex};
+use ckb_store::ChainStore;
 use ckb_types::{packed, prelude::*};
 use failure::Error as FailureError;
+use faketime::unix_time_as_millis;
 use std::cmp::min;
 
 pub struct GetBlocksProcess<'a> {
@@ -34,6 +36,7 @@ impl<'a> GetBlocksProcess<'a> {
         let snapshot = self.synchronizer.shared.snapshot();
 
         let n_limit = min(MAX_BLOCKS_IN_TRANSIT_PER_PEER as usize, block_hashes.len());
+        let upload_cap_reached = self.nc.upload_cap_reached();
         for block_hash in block_hashes.iter().take(n_limit) {
             debug!("get_blocks {} from peer {:?}", block_hash, self.peer);
             let block_hash = block_hash.to_entity();
@@ -46,6 +49,14 @@ impl<'a> GetBlocksProcess<'a> {
                 continue;
             }
 
+            if snapshot.store().is_block_pruned(&block_hash) {
+                debug!(
+                    "ignoring get_block {} request from peer={} for pruned",
+                    block_hash, self.peer
+                );
+                break;
+            }
+
             if self.nc.send_paused() {
                 debug!(
                     "Session send buffer is full, stop send blocks to peer {:?}",
@@ -55,6 +66,21 @@ impl<'a> GetBlocksProcess<'a> {
             }
 
             if let Some(block) = snapshot.get_block(&block_hash) {
+                if upload_cap_reached
+                    && unix_time_as_millis().saturating_sub(block.timestamp())
+                        > HISTORICAL_BLOCK_AGE
+                {
+                    // The peer would wait for the remaining blocks until timeout,
+                    // disconnect it to fetch them from the others
+                    debug!(
+                        "Daily upload cap is reached, disconnect peer {:?} requesting historical blocks",
+                        self.peer
+                    );
+                    if let Err(err) = self.nc.disconnect(self.peer, "daily upload cap reached") {
+                        debug!("synchronizer disconnect error: {:?}", err);
+                    }
+                    break;
+                }
                 debug!(
                     "respond_block {} {} to peer {:?}",
                     block.number(),
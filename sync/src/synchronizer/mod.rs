@@ -14,13 +14,13 @@ use self::in_ibd_process::InIBDProcess;
 use crate::block_status::BlockStatus;
 use crate::types::{HeaderView, PeerFlags, Peers, SyncSharedState, SyncSnapshot};
 use crate::{
-    BAD_MESSAGE_BAN_TIME, CHAIN_SYNC_TIMEOUT, EVICTION_HEADERS_RESPONSE_TIME,
-    HEADERS_DOWNLOAD_TIMEOUT_BASE, HEADERS_DOWNLOAD_TIMEOUT_PER_HEADER, MAX_HEADERS_LEN,
+    CHAIN_SYNC_TIMEOUT, EVICTION_HEADERS_RESPONSE_TIME, HEADERS_DOWNLOAD_TIMEOUT_BASE,
+    HEADERS_DOWNLOAD_TIMEOUT_PER_HEADER, MAX_HEADERS_LEN,
     MAX_OUTBOUND_PEERS_TO_PROTECT_FROM_DISCONNECT, POW_SPACE,
 };
 use ckb_chain::chain::ChainController;
 use ckb_logger::{debug, info, trace};
-use ckb_network::{CKBProtocolContext, CKBProtocolHandler, PeerIndex};
+use ckb_network::{Behaviour, CKBProtocolContext, CKBProtocolHandler, PeerIndex};
 use ckb_types::{core, packed, prelude::*};
 use failure::err_msg;
 use failure::Error as FailureError;
@@ -94,7 +94,7 @@ impl Synchronizer {
     ) {
         if let Err(err) = self.try_process(nc, peer, message) {
             debug!("try_process error: {}", err);
-            nc.ban_peer(peer, BAD_MESSAGE_BAN_TIME);
+            nc.report_peer(peer, Behaviour::MalformedMessage);
         }
     }
 
@@ -142,10 +142,17 @@ impl Synchronizer {
     }
 
     fn on_connected(&self, nc: &dyn CKBProtocolContext, peer: PeerIndex) {
-        let (is_outbound, is_whitelist) = nc
+        let (is_outbound, is_whitelist, is_pruned, is_block_relay_only) = nc
             .get_peer(peer)
-            .map(|peer| (peer.is_outbound(), peer.is_whitelist))
-            .unwrap_or((false, false));
+            .map(|peer| {
+                (
+                    peer.is_outbound(),
+                    peer.is_whitelist,
+                    peer.identify_info.map(|info| info.pruned).unwrap_or(false),
+                    peer.is_block_relay_only,
+                )
+            })
+            .unwrap_or((false, false, false, false));
 
         let sync_state = self.shared().state();
         let protect_outbound = is_outbound
@@ -166,6 +173,8 @@ impl Synchronizer {
                 is_outbound,
                 is_whitelist,
                 is_protect: protect_outbound,
+                is_pruned,
+                is_block_relay_only,
             },
         );
     }
@@ -260,6 +269,7 @@ impl Synchronizer {
         }
         for peer in eviction {
             info!("timeout eviction peer={}", peer);
+            nc.report_peer(peer, Behaviour::Timeout);
             if let Err(err) = nc.disconnect(peer, "sync timeout eviction") {
                 debug!("synchronizer disconnect error: {:?}", err);
             }
@@ -348,6 +358,13 @@ impl Synchronizer {
             self.shared().state().write_inflight_blocks().prune();
         }
         for peer in peers {
+            if nc.download_paused(peer) {
+                trace!(
+                    "download rate limit is exceeded, skip fetching from peer {}",
+                    peer
+                );
+                continue;
+            }
             if let Some(fetch) = self.get_blocks_to_fetch(peer) {
                 if !fetch.is_empty() {
                     self.send_getblocks(fetch, nc, peer);
@@ -399,7 +416,7 @@ impl CKBProtocolHandler for Synchronizer {
             Ok(msg) => msg.to_enum(),
             _ => {
                 info!("Peer {} sends us a malformed message", peer_index);
-                nc.ban_peer(peer_index, BAD_MESSAGE_BAN_TIME);
+                nc.report_peer(peer_index, Behaviour::MalformedMessage);
                 return;
             }
         };
@@ -1002,6 +1019,14 @@ mod tests {
         fn send_paused(&self) -> bool {
             false
         }
+
+        fn download_paused(&self, _peer_index: PeerIndex) -> bool {
+            false
+        }
+
+        fn upload_cap_reached(&self) -> bool {
+            false
+        }
     }
 
     fn mock_network_context(peer_num: usize) -> DummyNetworkContext {
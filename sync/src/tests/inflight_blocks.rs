@@ -138,3 +138,21 @@ fn inflight_blocks_timeout() {
         Some(HashSet::from_iter(vec![1.into(), 4.into()]))
     );
 }
+
+#[test]
+fn inflight_blocks_requested() {
+    let mut inflight_blocks = InflightBlocks::default();
+
+    assert!(inflight_blocks.insert(1.into(), h256!("0x1").pack()));
+    assert!(inflight_blocks.insert(1.into(), h256!("0x2").pack()));
+    assert!(inflight_blocks.is_requested_from(1.into(), &h256!("0x1").pack()));
+    assert!(!inflight_blocks.is_requested_from(2.into(), &h256!("0x1").pack()));
+
+    // The duplicated blocks are still requested from the peer
+    inflight_blocks.remove_by_block(h256!("0x1").pack());
+    assert!(inflight_blocks.is_requested_from(1.into(), &h256!("0x1").pack()));
+    assert!(!inflight_blocks.is_requested_from(1.into(), &h256!("0x3").pack()));
+
+    inflight_blocks.remove_by_peer(1.into());
+    assert!(!inflight_blocks.is_requested_from(1.into(), &h256!("0x2").pack()));
+}
@@ -1,4 +1,5 @@
 use ckb_network::{
+    capture::{CapturedMessage, Direction},
     Behaviour, CKBProtocolContext, CKBProtocolHandler, Peer, PeerIndex, ProtocolId, TargetSession,
 };
 use ckb_types::bytes::Bytes;
@@ -95,6 +96,30 @@ impl TestNode {
         }
     }
 
+    /// Feeds the inbound messages of a network capture to the protocol handlers,
+    /// the captured session ids are used as the peer indexes
+    pub fn replay<I: IntoIterator<Item = CapturedMessage>>(&self, messages: I) {
+        for message in messages {
+            if message.direction != Direction::Inbound {
+                continue;
+            }
+            if let Some(handler) = self.protocols.get(&message.protocol_id) {
+                // an inbound message is recorded with the only session it comes from
+                for session_id in message.session_ids {
+                    handler.write().received(
+                        Arc::new(TestNetworkContext {
+                            protocol: message.protocol_id,
+                            msg_senders: self.msg_senders.clone(),
+                            timer_senders: self.timer_senders.clone(),
+                        }),
+                        session_id,
+                        message.data.clone(),
+                    )
+                }
+            }
+        }
+    }
+
     pub fn start<F: Fn(&[u8]) -> bool>(&self, signal: &SyncSender<()>, pred: F) {
         loop {
             for ((protocol, peer), receiver) in &self.msg_receivers {
@@ -245,4 +270,12 @@ impl CKBProtocolContext for TestNetworkContext {
     fn send_paused(&self) -> bool {
         false
     }
+
+    fn download_paused(&self, _peer_index: PeerIndex) -> bool {
+        false
+    }
+
+    fn upload_cap_reached(&self) -> bool {
+        false
+    }
 }
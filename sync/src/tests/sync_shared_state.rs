@@ -1,5 +1,6 @@
 use crate::block_status::BlockStatus;
 use crate::tests::util::{build_chain, inherit_block};
+use crate::types::{PeerFlags, PeerState};
 use crate::SyncSharedState;
 use ckb_chain::chain::ChainService;
 use ckb_network::PeerIndex;
@@ -7,8 +8,10 @@ use ckb_shared::shared::SharedBuilder;
 use ckb_store::{self, ChainStore};
 use ckb_test_chain_utils::always_success_cellbase;
 use ckb_types::core::{BlockBuilder, BlockView, Capacity, TransactionBuilder};
+use ckb_types::packed::Byte32;
 use ckb_types::prelude::*;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_insert_new_block() {
@@ -276,3 +279,40 @@ fn test_switch_valid_fork() {
         );
     }
 }
+
+#[test]
+fn test_local_tx_announcement_delay() {
+    let (shared, _chain) = build_chain(0);
+    let now = Instant::now();
+    let tx_hash = Byte32::zero();
+    shared.state().add_local_tx_hash(tx_hash.clone());
+
+    assert!(shared.state().take_local_tx_hashes(now).is_empty());
+    assert_eq!(
+        shared
+            .state()
+            .take_local_tx_hashes(now + Duration::from_secs(3600)),
+        vec![tx_hash]
+    );
+    assert!(shared
+        .state()
+        .take_local_tx_hashes(now + Duration::from_secs(3600))
+        .is_empty());
+}
+
+#[test]
+fn test_tx_announcement_trickle() {
+    let mut peer_state = PeerState::new(PeerFlags {
+        is_outbound: true,
+        ..Default::default()
+    });
+    let now = Instant::now();
+    let tx_hash = Byte32::zero();
+    peer_state.add_tx_announcement(tx_hash.clone());
+
+    // the first call only schedules the trickle timer
+    assert!(peer_state.pop_tx_announcements(now).is_empty());
+    let later = now + Duration::from_secs(3600);
+    assert_eq!(peer_state.pop_tx_announcements(later), vec![tx_hash]);
+    assert!(peer_state.pop_tx_announcements(later).is_empty());
+}
@@ -8,6 +8,10 @@ use ckb_chain::{chain::ChainService, switch::Switch};
 use ckb_chain_spec::consensus::ConsensusBuilder;
 use ckb_dao::DaoCalculator;
 use ckb_dao_utils::genesis_dao_data;
+use ckb_network::{
+    capture::{CaptureReader, CaptureWriter, CapturedMessage, Direction, CAPTURE_FILE_NAME},
+    CaptureConfig,
+};
 use ckb_shared::shared::{Shared, SharedBuilder};
 use ckb_store::ChainStore;
 use ckb_test_chain_utils::always_success_cell;
@@ -25,6 +29,7 @@ use std::collections::HashSet;
 use std::sync::mpsc::sync_channel;
 use std::sync::Arc;
 use std::thread;
+use tempfile::tempdir;
 
 const DEFAULT_CHANNEL: usize = 128;
 
@@ -34,8 +39,8 @@ fn basic_sync() {
     faketime::enable(&faketime_file);
     let thread_name = format!("FAKETIME={}", faketime_file.display());
 
-    let (mut node1, shared1) = setup_node(1);
-    let (mut node2, shared2) = setup_node(3);
+    let (mut node1, shared1, _) = setup_node(1);
+    let (mut node2, shared2, _) = setup_node(3);
 
     node1.connect(&mut node2, NetworkProtocol::SYNC.into());
 
@@ -76,7 +81,56 @@ fn basic_sync() {
     );
 }
 
-fn setup_node(height: u64) -> (TestNode, Shared) {
+#[test]
+fn replay_captured_headers() {
+    let faketime_file = faketime::millis_tempfile(0).expect("create faketime file");
+    faketime::enable(&faketime_file);
+
+    let (mut node1, _, sync_shared_state1) = setup_node(1);
+    let (_, shared2, _) = setup_node(3);
+
+    // capture the headers sent by the peer
+    let snapshot = shared2.snapshot();
+    let content = packed::SendHeaders::new_builder()
+        .headers(
+            (1..=3)
+                .map(|number| {
+                    let hash = snapshot.get_block_hash(number).expect("block hash");
+                    snapshot.get_block_header(&hash).expect("header").data()
+                })
+                .pack(),
+        )
+        .build();
+    let message = packed::SyncMessage::new_builder().set(content).build();
+    let dir = tempdir().expect("create tempdir");
+    {
+        let mut writer =
+            CaptureWriter::open(dir.path(), &CaptureConfig::default()).expect("open capture");
+        writer
+            .write(&CapturedMessage {
+                timestamp: unix_time_as_millis(),
+                direction: Direction::Inbound,
+                protocol_id: NetworkProtocol::SYNC.into(),
+                session_ids: vec![0.into()],
+                data: message.as_bytes(),
+            })
+            .expect("capture message");
+        writer.flush().expect("flush capture");
+    }
+
+    // the captured session is the first peer of the node
+    let mut remote = TestNode::default();
+    node1.connect(&mut remote, NetworkProtocol::SYNC.into());
+    node1.replay(
+        CaptureReader::open(dir.path().join(CAPTURE_FILE_NAME))
+            .expect("open capture")
+            .map(|message| message.expect("captured message")),
+    );
+
+    assert_eq!(sync_shared_state1.shared_best_header().number(), 3);
+}
+
+fn setup_node(height: u64) -> (TestNode, Shared, Arc<SyncSharedState>) {
     let (always_success_cell, always_success_cell_data, always_success_script) =
         always_success_cell();
     let always_success_tx = TransactionBuilder::default()
@@ -160,7 +214,7 @@ fn setup_node(height: u64) -> (TestNode, Shared) {
     }
 
     let sync_shared_state = Arc::new(SyncSharedState::new(shared.clone()));
-    let synchronizer = Synchronizer::new(chain_controller, sync_shared_state);
+    let synchronizer = Synchronizer::new(chain_controller, Arc::clone(&sync_shared_state));
     let mut node = TestNode::default();
     let protocol = Arc::new(RwLock::new(synchronizer)) as Arc<_>;
     node.add_protocol(
@@ -173,5 +227,5 @@ fn setup_node(height: u64) -> (TestNode, Shared) {
             TIMEOUT_EVICTION_TOKEN,
         ],
     );
-    (node, shared)
+    (node, shared, sync_shared_state)
 }
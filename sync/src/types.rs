@@ -1,10 +1,11 @@
 use crate::block_status::BlockStatus;
 use crate::orphan_block_pool::OrphanBlockPool;
 use crate::BLOCK_DOWNLOAD_TIMEOUT;
-use crate::MAX_PEERS_PER_BLOCK;
 use crate::{NetworkProtocol, SUSPEND_SYNC_TIME};
+use crate::{INBOUND_TX_ANNOUNCE_INTERVAL, LOCAL_TX_ANNOUNCE_DELAY, OUTBOUND_TX_ANNOUNCE_INTERVAL};
+use crate::{MAX_BLOCKS_IN_TRANSIT_PER_PEER, MAX_PEERS_PER_BLOCK};
 use crate::{MAX_HEADERS_LEN, MAX_TIP_AGE};
-use ckb_chain::chain::ChainController;
+use ckb_chain::{chain::ChainController, switch::Switch};
 use ckb_chain_spec::consensus::Consensus;
 use ckb_logger::{debug, debug_target, error};
 use ckb_network::{CKBProtocolContext, PeerIndex};
@@ -35,6 +36,7 @@ use std::time::{Duration, Instant};
 const FILTER_SIZE: usize = 20000;
 const MAX_ASK_MAP_SIZE: usize = 50000;
 const MAX_ASK_SET_SIZE: usize = MAX_ASK_MAP_SIZE * 2;
+const MAX_TX_ANNOUNCE_SET_SIZE: usize = MAX_ASK_MAP_SIZE;
 const GET_HEADERS_CACHE_SIZE: usize = 10000;
 // TODO: Need discussed
 const GET_HEADERS_TIMEOUT: Duration = Duration::from_secs(15);
@@ -70,6 +72,10 @@ pub struct PeerFlags {
     pub is_outbound: bool,
     pub is_protect: bool,
     pub is_whitelist: bool,
+    // The peer only serves the recent blocks
+    pub is_pruned: bool,
+    // No transactions are relayed with the peer
+    pub is_block_relay_only: bool,
 }
 
 #[derive(Clone, Default, Debug)]
@@ -83,6 +89,9 @@ pub struct PeerState {
     // The key is a `timeout`, means do not ask the tx before `timeout`.
     tx_ask_for_map: BTreeMap<Instant, Vec<Byte32>>,
     tx_ask_for_set: HashSet<Byte32>,
+    // The tx hashes waiting to be announced, they are sent at `next_tx_announce`
+    tx_announce_set: HashSet<Byte32>,
+    next_tx_announce: Option<Instant>,
 
     pub best_known_header: Option<HeaderView>,
     pub last_common_header: Option<core::HeaderView>,
@@ -99,6 +108,8 @@ impl PeerState {
             chain_sync: ChainSyncState::default(),
             tx_ask_for_map: BTreeMap::default(),
             tx_ask_for_set: HashSet::new(),
+            tx_announce_set: HashSet::new(),
+            next_tx_announce: None,
             best_known_header: None,
             last_common_header: None,
         }
@@ -196,6 +207,47 @@ impl PeerState {
         }
         all_txs
     }
+
+    pub fn add_tx_announcement(&mut self, tx_hash: Byte32) {
+        if self.tx_announce_set.len() >= MAX_TX_ANNOUNCE_SET_SIZE {
+            debug_target!(
+                crate::LOG_TARGET_RELAY,
+                "this peer tx_announce_set is full, ignore {}",
+                tx_hash
+            );
+            return;
+        }
+        self.tx_announce_set.insert(tx_hash);
+    }
+
+    /// Take the tx hashes to announce once the trickle timer of the peer fires,
+    /// the next announcement is scheduled after a Poisson distributed delay
+    pub fn pop_tx_announcements(&mut self, now: Instant) -> Vec<Byte32> {
+        let interval = if self.peer_flags.is_outbound {
+            OUTBOUND_TX_ANNOUNCE_INTERVAL
+        } else {
+            INBOUND_TX_ANNOUNCE_INTERVAL
+        };
+        match self.next_tx_announce {
+            Some(next_tx_announce) if next_tx_announce > now => Vec::new(),
+            Some(_) => {
+                self.next_tx_announce = Some(poisson_next_time(now, interval));
+                self.tx_announce_set.drain().collect()
+            }
+            None => {
+                self.next_tx_announce = Some(poisson_next_time(now, interval));
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// The time after a Poisson distributed delay with the average, the delays
+/// between the events of a Poisson process are exponentially distributed
+pub fn poisson_next_time(now: Instant, average: Duration) -> Instant {
+    // `1 - random()` is in (0, 1], so the logarithm is finite
+    let factor = -(1.0 - rand::random::<f64>()).ln();
+    now + Duration::from_millis((average.as_millis() as f64 * factor) as u64)
 }
 
 #[derive(Default)]
@@ -262,10 +314,16 @@ impl InflightState {
     }
 }
 
+// The number of the latest blocks requested from each peer which are
+// remembered after they are received or timed out
+const REQUESTED_BLOCKS_PER_PEER: usize = MAX_BLOCKS_IN_TRANSIT_PER_PEER * 8;
+
 #[derive(Clone)]
 pub struct InflightBlocks {
     blocks: HashMap<PeerIndex, HashSet<Byte32>>,
     states: HashMap<Byte32, InflightState>,
+    // The duplicated or late blocks from these are not unrequested data
+    requested: HashMap<PeerIndex, LruCache<Byte32, ()>>,
 }
 
 impl Default for InflightBlocks {
@@ -273,6 +331,7 @@ impl Default for InflightBlocks {
         InflightBlocks {
             blocks: HashMap::default(),
             states: HashMap::default(),
+            requested: HashMap::default(),
         }
     }
 }
@@ -342,14 +401,28 @@ impl InflightBlocks {
         }
 
         let blocks = self.blocks.entry(peer).or_insert_with(HashSet::default);
-        let ret = blocks.insert(hash);
+        let ret = blocks.insert(hash.clone());
         if ret {
             state.peers.insert(peer);
+            self.requested
+                .entry(peer)
+                .or_insert_with(|| LruCache::new(REQUESTED_BLOCKS_PER_PEER))
+                .insert(hash, ());
         }
         ret
     }
 
+    /// Whether the block is one of the latest blocks requested from the peer,
+    /// no matter it is received or timed out
+    pub fn is_requested_from(&mut self, peer: PeerIndex, hash: &Byte32) -> bool {
+        self.requested
+            .get_mut(&peer)
+            .map(|requested| requested.contains_key(hash))
+            .unwrap_or(false)
+    }
+
     pub fn remove_by_peer(&mut self, peer: PeerIndex) -> bool {
+        self.requested.remove(&peer);
         self.blocks
             .remove(&peer)
             .map(|blocks| {
@@ -385,6 +458,14 @@ impl Peers {
             .or_insert_with(|| PeerState::new(peer_flags));
     }
 
+    pub fn is_block_relay_only(&self, pi: PeerIndex) -> bool {
+        self.state
+            .read()
+            .get(&pi)
+            .map(|peer_state| peer_state.peer_flags.is_block_relay_only)
+            .unwrap_or(false)
+    }
+
     pub fn get_best_known_header(&self, pi: PeerIndex) -> Option<HeaderView> {
         self.state
             .read()
@@ -601,6 +682,11 @@ pub struct SyncState {
 
     /* cached for sending bulk */
     tx_hashes: Mutex<HashMap<PeerIndex, HashSet<Byte32>>>,
+    // The txs submitted locally, keyed by the time they can be announced
+    local_tx_hashes: Mutex<BTreeMap<Instant, Vec<Byte32>>>,
+
+    // The blocks up to this one are accepted without running the scripts
+    assume_valid_target: Option<Byte32>,
 }
 
 impl SyncSharedState {
@@ -633,6 +719,12 @@ impl SyncSharedState {
             inflight_blocks: RwLock::new(InflightBlocks::default()),
             pending_get_headers: RwLock::new(LruCache::new(GET_HEADERS_CACHE_SIZE)),
             tx_hashes: Mutex::new(HashMap::default()),
+            local_tx_hashes: Mutex::new(BTreeMap::default()),
+            assume_valid_target: shared
+                .verification_config()
+                .assume_valid_target
+                .as_ref()
+                .map(|hash| hash.pack()),
         };
 
         SyncSharedState {
@@ -717,6 +809,27 @@ impl SyncState {
         mem::replace(&mut *map, HashMap::default())
     }
 
+    /// Add a tx submitted locally, it is not announced before a random delay,
+    /// so the peers can't tell it comes from this node by its first announcement
+    pub fn add_local_tx_hash(&self, tx_hash: Byte32) {
+        let announce_at = poisson_next_time(Instant::now(), LOCAL_TX_ANNOUNCE_DELAY);
+        self.local_tx_hashes
+            .lock()
+            .entry(announce_at)
+            .or_default()
+            .push(tx_hash);
+    }
+
+    /// Take the local txs which can be announced now
+    pub fn take_local_tx_hashes(&self, now: Instant) -> Vec<Byte32> {
+        let mut local_tx_hashes = self.local_tx_hashes.lock();
+        let pending = local_tx_hashes.split_off(&now);
+        mem::replace(&mut *local_tx_hashes, pending)
+            .into_iter()
+            .flat_map(|(_, tx_hashes)| tx_hashes)
+            .collect()
+    }
+
     pub fn is_initial_header_sync(&self) -> bool {
         unix_time_as_millis().saturating_sub(self.shared_best_header().timestamp()) > MAX_TIP_AGE
     }
@@ -1209,13 +1322,29 @@ impl SyncSnapshot {
         ret
     }
 
+    // The assume valid target and its ancestors are accepted without running
+    // the scripts, once the header of the target is known.
+    fn switch_for(&self, block: &core::BlockView) -> Switch {
+        match self.state.assume_valid_target {
+            Some(ref target)
+                if self
+                    .get_ancestor(target, block.number())
+                    .map(|header| header.hash())
+                    == Some(block.hash()) =>
+            {
+                Switch::ASSUME_VALID
+            }
+            _ => Switch::NONE,
+        }
+    }
+
     fn accept_block(
         &self,
         chain: &ChainController,
         peer: PeerIndex,
         block: Arc<core::BlockView>,
     ) -> Result<bool, FailureError> {
-        let ret = chain.process_block(Arc::clone(&block));
+        let ret = chain.internal_process_block(Arc::clone(&block), self.switch_for(&block));
         if ret.is_err() {
             error!("accept block {:?} {:?}", block, ret);
             self.state
@@ -91,6 +91,7 @@ impl Net {
             whitelist_only: false,
             max_peers: self.num_nodes(),
             max_outbound_peers: self.num_nodes(),
+            block_relay_only_peers: 0,
             path: self.working_dir().into(),
             ping_interval_secs: 15,
             ping_timeout_secs: 20,
@@ -99,6 +100,13 @@ impl Net {
             upnp: false,
             bootnode_mode: false,
             max_send_buffer: None,
+            max_upload_rate: None,
+            max_download_rate: None,
+            max_peer_upload_rate: None,
+            max_peer_download_rate: None,
+            max_upload_per_day: None,
+            proxy: None,
+            capture: None,
         };
 
         let network_state =
@@ -81,6 +81,14 @@ impl RpcClient {
             .map(|x| x.pack())
     }
 
+    pub fn truncate(&self, target_tip_hash: Byte32) {
+        self.inner
+            .lock()
+            .truncate(target_tip_hash.unpack())
+            .call()
+            .expect("rpc call truncate")
+    }
+
     pub fn get_tip_header(&self) -> HeaderView {
         self.inner
             .lock()
@@ -398,6 +406,7 @@ jsonrpc_client!(pub struct Inner {
     pub fn add_node(&mut self, peer_id: String, address: String) -> RpcRequest<()>;
     pub fn remove_node(&mut self, peer_id: String) -> RpcRequest<()>;
     pub fn process_block_without_verify(&mut self, _data: Block) -> RpcRequest<Option<H256>>;
+    pub fn truncate(&mut self, target_tip_hash: H256) -> RpcRequest<()>;
 
     pub fn get_live_cells_by_lock_hash(&mut self, lock_hash: H256, page: Uint64, per_page: Uint64, reverse_order: Option<bool>) -> RpcRequest<Vec<LiveCell>>;
     pub fn get_transactions_by_lock_hash(&mut self, lock_hash: H256, page: Uint64, per_page: Uint64, reverse_order: Option<bool>) -> RpcRequest<Vec<CellTransaction>>;
@@ -25,9 +25,10 @@ impl Spec for TransactionRelayBasic {
         node1.generate_block();
         let hash = node1.generate_transaction();
 
+        // the transaction announcements are delayed randomly for privacy
         info!("Waiting for relay");
         let rpc_client = node0.rpc_client();
-        let ret = wait_until(10, || {
+        let ret = wait_until(60, || {
             if let Some(transaction) = rpc_client.get_transaction(hash.clone()) {
                 transaction.tx_status.block_hash.is_none()
             } else {
@@ -37,7 +38,7 @@ impl Spec for TransactionRelayBasic {
         assert!(ret, "Transaction should be relayed to node0");
 
         let rpc_client = node2.rpc_client();
-        let ret = wait_until(10, || {
+        let ret = wait_until(60, || {
             if let Some(transaction) = rpc_client.get_transaction(hash.clone()) {
                 transaction.tx_status.block_hash.is_none()
             } else {
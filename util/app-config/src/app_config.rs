@@ -23,6 +23,7 @@ use ckb_resource::Resource;
 use ckb_rpc::Config as RpcConfig;
 use ckb_store::StoreConfig;
 use ckb_tx_pool::{BlockAssemblerConfig, TxPoolConfig};
+use ckb_verification::VerificationConfig;
 
 use super::sentry_config::SentryConfig;
 use super::{cli, ExitCode};
@@ -50,6 +51,8 @@ pub struct CKBAppConfig {
     pub tx_pool: TxPoolConfig,
     #[serde(default)]
     pub store: StoreConfig,
+    #[serde(default)]
+    pub verification: VerificationConfig,
     pub alert_signature: Option<AlertSignatureConfig>,
     pub alert_notifier: Option<AlertNotifierConfig>,
 }
@@ -175,6 +178,7 @@ impl CKBAppConfig {
         self.db.path = mkdir(self.data_dir.join("db"))?;
         self.indexer.db.path = mkdir(self.data_dir.join("indexer_db"))?;
         self.network.path = mkdir(self.data_dir.join("network"))?;
+        self.verification.trace_dir = self.data_dir.join("traces");
         self.chain.spec.absolutize(root_dir);
 
         Ok(self)
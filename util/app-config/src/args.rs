@@ -4,6 +4,7 @@ use ckb_instrument::Format;
 use ckb_jsonrpc_types::ScriptHashType;
 use ckb_miner::MinerConfig;
 use ckb_pow::PowEngine;
+use ckb_types::H256;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -12,6 +13,11 @@ pub struct ExportArgs {
     pub consensus: Consensus,
     pub format: Format,
     pub target: PathBuf,
+    pub compress: bool,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub append: bool,
+    pub chunk_size: Option<u64>,
 }
 
 pub struct ImportArgs {
@@ -19,6 +25,47 @@ pub struct ImportArgs {
     pub consensus: Consensus,
     pub format: Format,
     pub source: PathBuf,
+    pub assume_valid_target: Option<H256>,
+}
+
+pub struct MigrateArgs {
+    pub config: Box<CKBAppConfig>,
+}
+
+pub struct BackupArgs {
+    pub config: Box<CKBAppConfig>,
+    pub target: PathBuf,
+}
+
+pub struct DBStatsArgs {
+    pub config: Box<CKBAppConfig>,
+}
+
+pub struct DBCheckArgs {
+    pub config: Box<CKBAppConfig>,
+    pub fix: bool,
+}
+
+pub struct DBReindexArgs {
+    pub config: Box<CKBAppConfig>,
+    pub consensus: Consensus,
+}
+
+pub enum RollbackTarget {
+    Number(u64),
+    Hash(H256),
+}
+
+pub struct DBRollbackArgs {
+    pub config: Box<CKBAppConfig>,
+    pub consensus: Consensus,
+    pub target: RollbackTarget,
+}
+
+pub struct RestoreArgs {
+    pub config: Box<CKBAppConfig>,
+    pub source: PathBuf,
+    pub force: bool,
 }
 
 pub struct RunArgs {
@@ -14,12 +14,24 @@ pub const CMD_HASHES: &str = "hashes";
 pub const CMD_BLAKE256: &str = "blake256";
 pub const CMD_BLAKE160: &str = "blake160";
 pub const CMD_SECP256K1_LOCK: &str = "secp256k1-lock";
+pub const CMD_DECODE_CAPTURE: &str = "decode-capture";
 pub const CMD_RESET_DATA: &str = "reset-data";
+pub const CMD_MIGRATE: &str = "migrate";
+pub const CMD_BACKUP: &str = "backup";
+pub const CMD_RESTORE: &str = "restore";
+pub const CMD_DB: &str = "db";
+pub const CMD_CHECK: &str = "check";
+pub const CMD_REINDEX: &str = "reindex";
+pub const CMD_ROLLBACK: &str = "rollback";
 
 pub const ARG_CONFIG_DIR: &str = "config-dir";
 pub const ARG_FORMAT: &str = "format";
 pub const ARG_TARGET: &str = "target";
 pub const ARG_SOURCE: &str = "source";
+pub const ARG_COMPRESS: &str = "compress";
+pub const ARG_APPEND: &str = "append";
+pub const ARG_CHUNK_SIZE: &str = "chunk-size";
+pub const ARG_ASSUME_VALID: &str = "assume-valid";
 pub const ARG_DATA: &str = "data";
 pub const ARG_LIST_CHAINS: &str = "list-chains";
 pub const ARG_INTERACTIVE: &str = "interactive";
@@ -42,9 +54,110 @@ pub const ARG_NETWORK: &str = "network";
 pub const ARG_NETWORK_PEER_STORE: &str = "network-peer-store";
 pub const ARG_NETWORK_SECRET_KEY: &str = "network-secret-key";
 pub const ARG_LOGS: &str = "logs";
+pub const ARG_FIX: &str = "fix";
+pub const ARG_FILE: &str = "file";
+pub const ARG_TRACE_SCRIPT: &str = "trace-script";
 
 const GROUP_BA: &str = "ba";
 
+fn migrate() -> App<'static, 'static> {
+    SubCommand::with_name(CMD_MIGRATE)
+        .about("Migrates the data of the database and the indexer database to the current version")
+}
+
+fn backup() -> App<'static, 'static> {
+    SubCommand::with_name(CMD_BACKUP)
+        .about(
+            "Copies the database and the indexer database of a stopped node\n\
+             Use the `create_backup` RPC of the Admin module to back up a running node",
+        )
+        .arg(
+            Arg::with_name(ARG_TARGET)
+                .short("t")
+                .long(ARG_TARGET)
+                .value_name("path")
+                .required(true)
+                .index(1)
+                .help("Specifies the backup directory, which must not exist."),
+        )
+}
+
+fn restore() -> App<'static, 'static> {
+    SubCommand::with_name(CMD_RESTORE)
+        .about("Restores the database and the indexer database from a backup")
+        .arg(
+            Arg::with_name(ARG_SOURCE)
+                .short("s")
+                .long(ARG_SOURCE)
+                .value_name("path")
+                .required(true)
+                .index(1)
+                .help("Specifies the backup directory."),
+        )
+        .arg(
+            Arg::with_name(ARG_FORCE)
+                .short("f")
+                .long(ARG_FORCE)
+                .help("Replaces the existing databases"),
+        )
+}
+
+fn db() -> App<'static, 'static> {
+    SubCommand::with_name(CMD_DB)
+        .about("Database tools")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(db_stats())
+        .subcommand(db_check())
+        .subcommand(db_reindex())
+        .subcommand(db_rollback())
+}
+
+fn db_stats() -> App<'static, 'static> {
+    SubCommand::with_name(CMD_STATS).about(
+        "Prints the statistics of the database and the indexer database of a stopped node\n\
+         Use the `get_db_stats` RPC of the Admin module to inspect a running node, \
+         which also reports the hits of the store caches",
+    )
+}
+
+fn db_check() -> App<'static, 'static> {
+    SubCommand::with_name(CMD_CHECK)
+        .about(
+            "Verifies the database and the indexer database of a stopped node, such as the \
+             block indexes, the block exts, the epochs and the cell set",
+        )
+        .arg(
+            Arg::with_name(ARG_FIX)
+                .long(ARG_FIX)
+                .help("Fixes the inconsistencies which can be derived from the other data"),
+        )
+}
+
+fn db_reindex() -> App<'static, 'static> {
+    SubCommand::with_name(CMD_REINDEX).about(
+        "Rebuilds the cell set, the transaction infos, the main chain index and the epoch \
+         indexes of a stopped node from the stored blocks\n\
+         The progress is saved after each batch, run it again to resume an interrupted reindex",
+    )
+}
+
+fn db_rollback() -> App<'static, 'static> {
+    SubCommand::with_name(CMD_ROLLBACK)
+        .about(
+            "Rolls the main chain of a stopped node back to the block, restoring the cell set, \
+             the epoch and the indexer database.\n\
+             Use the `truncate` RPC of the IntegrationTest module on a running node",
+        )
+        .arg(
+            Arg::with_name(ARG_TARGET)
+                .required(true)
+                .index(1)
+                .value_name("number or hash")
+                .validator(is_number_or_h256)
+                .help("Specifies the block which becomes the tip"),
+        )
+}
+
 fn basic_app<'b>() -> App<'static, 'b> {
     App::new("ckb")
         .author("Nervos Core Dev <dev@nervos.org>")
@@ -69,6 +182,10 @@ fn basic_app<'b>() -> App<'static, 'b> {
         .subcommand(prof())
         .subcommand(stats())
         .subcommand(reset_data())
+        .subcommand(migrate())
+        .subcommand(backup())
+        .subcommand(restore())
+        .subcommand(db())
 }
 
 pub fn get_matches(version: &Version) -> ArgMatches<'static> {
@@ -79,11 +196,37 @@ pub fn get_matches(version: &Version) -> ArgMatches<'static> {
 }
 
 fn run() -> App<'static, 'static> {
-    SubCommand::with_name(CMD_RUN).about("Runs ckb node").arg(
-        Arg::with_name(ARG_BA_ADVANCED)
-            .long(ARG_BA_ADVANCED)
-            .help("Allows any block assembler code hash and args"),
-    )
+    SubCommand::with_name(CMD_RUN)
+        .about("Runs ckb node")
+        .arg(
+            Arg::with_name(ARG_BA_ADVANCED)
+                .long(ARG_BA_ADVANCED)
+                .help("Allows any block assembler code hash and args"),
+        )
+        .arg(
+            Arg::with_name(ARG_TRACE_SCRIPT)
+                .long(ARG_TRACE_SCRIPT)
+                .value_name("script hash")
+                .validator(is_h256)
+                .takes_value(true)
+                .help(
+                    "Writes the execution traces of the script groups with this script hash \
+                     to data/traces when verifying the block transactions. Overrides \
+                     `verification.trace_script_hash` in ckb.toml.",
+                ),
+        )
+        .arg(
+            Arg::with_name(ARG_ASSUME_VALID)
+                .long(ARG_ASSUME_VALID)
+                .value_name("block hash")
+                .validator(is_h256)
+                .takes_value(true)
+                .help(
+                    "Trusts the blocks up to this one when syncing, they are accepted without \
+                     running the scripts. Overrides `verification.assume_valid_target` in \
+                     ckb.toml.",
+                ),
+        )
 }
 
 fn miner() -> App<'static, 'static> {
@@ -192,7 +335,7 @@ fn arg_format() -> Arg<'static, 'static> {
         .help("Specifies the format.")
 }
 
-fn export() -> App<'static, 'static> {
+pub(crate) fn export() -> App<'static, 'static> {
     SubCommand::with_name(CMD_EXPORT)
         .about("Exports ckb data")
         .arg(arg_format())
@@ -205,9 +348,36 @@ fn export() -> App<'static, 'static> {
                 .index(1)
                 .help("Specifies the export target path."),
         )
+        .arg(
+            Arg::with_name(ARG_COMPRESS)
+                .long(ARG_COMPRESS)
+                .help("Compresses the exported blocks, only for the bin format."),
+        )
+        .arg(
+            Arg::with_name(ARG_FROM)
+                .long(ARG_FROM)
+                .takes_value(true)
+                .help("Specifies from block number, defaults to 0."),
+        )
+        .arg(
+            Arg::with_name(ARG_TO)
+                .long(ARG_TO)
+                .takes_value(true)
+                .help("Specifies to block number, defaults to the tip."),
+        )
+        .arg(Arg::with_name(ARG_APPEND).long(ARG_APPEND).help(
+            "Continues the export in the target path from the block after the last \
+             exported one.",
+        ))
+        .arg(
+            Arg::with_name(ARG_CHUNK_SIZE)
+                .long(ARG_CHUNK_SIZE)
+                .takes_value(true)
+                .help("Splits the export into files of at most this many blocks."),
+        )
 }
 
-fn import() -> App<'static, 'static> {
+pub(crate) fn import() -> App<'static, 'static> {
     SubCommand::with_name(CMD_IMPORT)
         .about("Imports ckb data")
         .arg(arg_format())
@@ -220,6 +390,17 @@ fn import() -> App<'static, 'static> {
                 .index(1)
                 .help("Specifies the exported data path."),
         )
+        .arg(
+            Arg::with_name(ARG_ASSUME_VALID)
+                .long(ARG_ASSUME_VALID)
+                .value_name("block hash")
+                .validator(is_h256)
+                .takes_value(true)
+                .help(
+                    "Trusts the blocks up to this one, they are imported without running the \
+                     scripts. The PoW and the links between blocks are still verified.",
+                ),
+        )
 }
 
 fn cli() -> App<'static, 'static> {
@@ -230,6 +411,7 @@ fn cli() -> App<'static, 'static> {
         .subcommand(cli_blake256())
         .subcommand(cli_blake160())
         .subcommand(cli_secp256k1_lock())
+        .subcommand(cli_decode_capture())
 }
 
 fn cli_hashes() -> App<'static, 'static> {
@@ -267,6 +449,17 @@ fn cli_blake160() -> App<'static, 'static> {
         .arg(arg_hex_data())
 }
 
+fn cli_decode_capture() -> App<'static, 'static> {
+    SubCommand::with_name(CMD_DECODE_CAPTURE)
+        .about("Decodes the captured network messages, prints one JSON object per message")
+        .arg(
+            Arg::with_name(ARG_FILE)
+                .required(true)
+                .index(1)
+                .help("The capture file, e.g. data/network/capture/messages.capture"),
+        )
+}
+
 fn cli_secp256k1_lock() -> App<'static, 'static> {
     SubCommand::with_name(CMD_SECP256K1_LOCK)
         .about("Prints lock args from secp256k1 pubkey")
@@ -408,6 +601,25 @@ fn is_hex(hex: String) -> Result<(), String> {
     }
 }
 
+fn is_h256(hex: String) -> Result<(), String> {
+    if hex.len() != 66 {
+        Err("Must be a 0x-prefixed hexadecimal string of 32 bytes".to_string())
+    } else {
+        is_hex(hex)
+    }
+}
+
+fn is_number_or_h256(input: String) -> Result<(), String> {
+    if input.starts_with("0x") {
+        is_h256(input)
+    } else {
+        input
+            .parse::<u64>()
+            .map(|_| ())
+            .map_err(|_| "Must be a block number or a block hash".to_string())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -6,7 +6,9 @@ mod sentry_config;
 
 pub use app_config::{AppConfig, CKBAppConfig, MinerAppConfig};
 pub use args::{
-    ExportArgs, ImportArgs, InitArgs, MinerArgs, ProfArgs, ResetDataArgs, RunArgs, StatsArgs,
+    BackupArgs, DBCheckArgs, DBReindexArgs, DBRollbackArgs, DBStatsArgs, ExportArgs, ImportArgs,
+    InitArgs, MigrateArgs, MinerArgs, ProfArgs, ResetDataArgs, RestoreArgs, RollbackTarget,
+    RunArgs, StatsArgs,
 };
 pub use ckb_tx_pool::BlockAssemblerConfig;
 pub use exit_code::ExitCode;
@@ -16,8 +18,10 @@ use ckb_chain_spec::{consensus::Consensus, ChainSpec};
 use ckb_instrument::Format;
 use ckb_jsonrpc_types::ScriptHashType;
 use ckb_logger::{info_target, LoggerInitGuard};
+use ckb_types::H256;
 use clap::{value_t, ArgMatches, ErrorKind};
 use std::path::PathBuf;
+use std::str::FromStr;
 
 pub(crate) const LOG_TARGET_SENTRY: &str = "sentry";
 
@@ -97,7 +101,21 @@ impl Setup {
 
     pub fn run<'m>(self, matches: &ArgMatches<'m>) -> Result<RunArgs, ExitCode> {
         let consensus = self.consensus()?;
-        let config = self.config.into_ckb()?;
+        let mut config = self.config.into_ckb()?;
+        if let Some(hash) = matches.value_of(cli::ARG_TRACE_SCRIPT) {
+            let hash = H256::from_str(&hash[2..]).map_err(|err| {
+                eprintln!("Invalid trace script hash: {:?}", err);
+                ExitCode::Cli
+            })?;
+            config.verification.trace_script_hash = Some(hash);
+        }
+        if let Some(hash) = matches.value_of(cli::ARG_ASSUME_VALID) {
+            let hash = H256::from_str(&hash[2..]).map_err(|err| {
+                eprintln!("Invalid assume valid block hash: {:?}", err);
+                ExitCode::Cli
+            })?;
+            config.verification.assume_valid_target = Some(hash);
+        }
 
         Ok(RunArgs {
             config,
@@ -164,12 +182,21 @@ impl Setup {
         let config = self.config.into_ckb()?;
         let format = value_t!(matches.value_of(cli::ARG_FORMAT), Format)?;
         let source = value_t!(matches.value_of(cli::ARG_SOURCE), PathBuf)?;
+        let assume_valid_target = matches
+            .value_of(cli::ARG_ASSUME_VALID)
+            .map(|hash| H256::from_str(&hash[2..]))
+            .transpose()
+            .map_err(|err| {
+                eprintln!("Invalid assume valid block hash: {:?}", err);
+                ExitCode::Cli
+            })?;
 
         Ok(ImportArgs {
             config,
             consensus,
             format,
             source,
+            assume_valid_target,
         })
     }
 
@@ -178,12 +205,26 @@ impl Setup {
         let config = self.config.into_ckb()?;
         let format = value_t!(matches.value_of(cli::ARG_FORMAT), Format)?;
         let target = value_t!(matches.value_of(cli::ARG_TARGET), PathBuf)?;
+        let compress = matches.is_present(cli::ARG_COMPRESS);
+        let from = optional_u64(matches, cli::ARG_FROM)?;
+        let to = optional_u64(matches, cli::ARG_TO)?;
+        let append = matches.is_present(cli::ARG_APPEND);
+        let chunk_size = optional_u64(matches, cli::ARG_CHUNK_SIZE)?;
+        if chunk_size == Some(0) {
+            eprintln!("The chunk size must be greater than 0");
+            return Err(ExitCode::Cli);
+        }
 
         Ok(ExportArgs {
             config,
             consensus,
             format,
             target,
+            compress,
+            from,
+            to,
+            append,
+            chunk_size,
         })
     }
 
@@ -246,6 +287,71 @@ impl Setup {
         })
     }
 
+    pub fn migrate(self) -> Result<MigrateArgs, ExitCode> {
+        let config = self.config.into_ckb()?;
+
+        Ok(MigrateArgs { config })
+    }
+
+    pub fn backup<'m>(self, matches: &ArgMatches<'m>) -> Result<BackupArgs, ExitCode> {
+        let config = self.config.into_ckb()?;
+        let target = value_t!(matches.value_of(cli::ARG_TARGET), PathBuf)?;
+
+        Ok(BackupArgs { config, target })
+    }
+
+    pub fn db_stats(self) -> Result<DBStatsArgs, ExitCode> {
+        let config = self.config.into_ckb()?;
+
+        Ok(DBStatsArgs { config })
+    }
+
+    pub fn db_check<'m>(self, matches: &ArgMatches<'m>) -> Result<DBCheckArgs, ExitCode> {
+        let config = self.config.into_ckb()?;
+        let fix = matches.is_present(cli::ARG_FIX);
+
+        Ok(DBCheckArgs { config, fix })
+    }
+
+    pub fn db_reindex(self) -> Result<DBReindexArgs, ExitCode> {
+        let consensus = self.consensus()?;
+        let config = self.config.into_ckb()?;
+
+        Ok(DBReindexArgs { config, consensus })
+    }
+
+    pub fn db_rollback<'m>(self, matches: &ArgMatches<'m>) -> Result<DBRollbackArgs, ExitCode> {
+        let consensus = self.consensus()?;
+        let config = self.config.into_ckb()?;
+        let target = matches.value_of(cli::ARG_TARGET).expect("required arg");
+        let target = if target.starts_with("0x") {
+            RollbackTarget::Hash(H256::from_str(&target[2..]).map_err(|err| {
+                eprintln!("Invalid block hash: {:?}", err);
+                ExitCode::Cli
+            })?)
+        } else {
+            RollbackTarget::Number(value_t!(matches, cli::ARG_TARGET, u64)?)
+        };
+
+        Ok(DBRollbackArgs {
+            config,
+            consensus,
+            target,
+        })
+    }
+
+    pub fn restore<'m>(self, matches: &ArgMatches<'m>) -> Result<RestoreArgs, ExitCode> {
+        let config = self.config.into_ckb()?;
+        let source = value_t!(matches.value_of(cli::ARG_SOURCE), PathBuf)?;
+        let force = matches.is_present(cli::ARG_FORCE);
+
+        Ok(RestoreArgs {
+            config,
+            source,
+            force,
+        })
+    }
+
     pub fn reset_data<'m>(self, matches: &ArgMatches<'m>) -> Result<ResetDataArgs, ExitCode> {
         let config = self.config.into_ckb()?;
         let data_dir = config.data_dir;
@@ -326,6 +432,14 @@ impl Setup {
     }
 }
 
+fn optional_u64<'m>(matches: &ArgMatches<'m>, name: &str) -> Result<Option<u64>, ExitCode> {
+    match value_t!(matches, name, u64) {
+        Ok(value) => Ok(Some(value)),
+        Err(ref e) if e.kind == ErrorKind::ArgumentNotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
 fn is_daemon(subcommand_name: &str) -> bool {
     match subcommand_name {
         cli::CMD_RUN => true,
@@ -344,7 +458,7 @@ fn consensus_from_spec(spec: &ChainSpec) -> Result<Consensus, ExitCode> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cli::CMD_STATS;
+    use crate::cli::{CMD_EXPORT, CMD_IMPORT, CMD_STATS};
     use clap::{App, AppSettings};
 
     #[test]
@@ -371,4 +485,62 @@ mod tests {
             .get_matches_from_safe(vec!["", CMD_STATS, "--from", "10", "--to", "100"]);
         assert!(stats.is_ok());
     }
+
+    #[test]
+    fn export_args() {
+        let app = App::new("export_args_test")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(cli::export());
+
+        let export = app
+            .clone()
+            .get_matches_from_safe(vec!["", CMD_EXPORT, "-f", "bin", "data"]);
+        assert!(export.is_ok());
+
+        let export = app.clone().get_matches_from_safe(vec![
+            "",
+            CMD_EXPORT,
+            "-f",
+            "bin",
+            "--from",
+            "10",
+            "--to",
+            "100",
+            "--append",
+            "--chunk-size",
+            "1000",
+            "--compress",
+            "data",
+        ]);
+        assert!(export.is_ok());
+    }
+
+    #[test]
+    fn import_args() {
+        let app = App::new("import_args_test")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(cli::import());
+
+        let import = app.clone().get_matches_from_safe(vec![
+            "",
+            CMD_IMPORT,
+            "-f",
+            "bin",
+            "--assume-valid",
+            "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "data",
+        ]);
+        assert!(import.is_ok());
+
+        let import = app.clone().get_matches_from_safe(vec![
+            "",
+            CMD_IMPORT,
+            "-f",
+            "bin",
+            "--assume-valid",
+            "0x01",
+            "data",
+        ]);
+        assert!(import.is_err());
+    }
 }
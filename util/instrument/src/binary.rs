@@ -0,0 +1,285 @@
+// The binary export format, all integers are little endian:
+//
+// - header: the magic bytes `ckbblock`, the format version (u8), the flags
+//   (u8, `FLAG_COMPRESSED`), the genesis hash (32 bytes), the number of
+//   blocks (u64) and the chain spec id (u16 length followed by the UTF-8 bytes)
+// - body: for each block, its molecule serialized `packed::Block` prefixed by
+//   its length (u32), then a zero length marking the end of the blocks,
+//   followed by the blake2b-256 checksum of everything before it
+//
+// When compressed, the body is written as a snappy frame stream, and the
+// checksum is calculated over the uncompressed bytes.
+use ckb_hash::{new_blake2b, Blake2b};
+use ckb_types::{packed, prelude::*};
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::mem;
+
+const MAGIC: &[u8; 8] = b"ckbblock";
+const VERSION: u8 = 1;
+const FLAG_COMPRESSED: u8 = 0b1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BinaryHeader {
+    pub compressed: bool,
+    pub spec_id: String,
+    pub genesis_hash: packed::Byte32,
+    pub blocks: u64,
+}
+
+impl BinaryHeader {
+    fn to_vec(&self) -> Vec<u8> {
+        let mut vec = Vec::new();
+        vec.extend_from_slice(MAGIC);
+        vec.push(VERSION);
+        vec.push(if self.compressed { FLAG_COMPRESSED } else { 0 });
+        vec.extend_from_slice(self.genesis_hash.as_slice());
+        vec.extend_from_slice(&self.blocks.to_le_bytes());
+        vec.extend_from_slice(&(self.spec_id.len() as u16).to_le_bytes());
+        vec.extend_from_slice(self.spec_id.as_bytes());
+        vec
+    }
+
+    fn read_from<R: Read>(reader: &mut R, hasher: &mut Blake2b) -> Result<Self, Box<dyn Error>> {
+        let mut fixed = [0u8; 8 + 1 + 1 + 32 + 8 + 2];
+        reader.read_exact(&mut fixed)?;
+        if &fixed[..8] != MAGIC {
+            return Err("not a binary export of ckb blocks".into());
+        }
+        if fixed[8] != VERSION {
+            return Err(format!("unsupported binary export version {}", fixed[8]).into());
+        }
+        let flags = fixed[9];
+        let genesis_hash = packed::Byte32::from_slice(&fixed[10..42])
+            .map_err(|err| format!("malformed genesis hash: {:?}", err))?;
+        let mut blocks = [0u8; 8];
+        blocks.copy_from_slice(&fixed[42..50]);
+        let mut spec_id_len = [0u8; 2];
+        spec_id_len.copy_from_slice(&fixed[50..52]);
+        let mut spec_id = vec![0u8; u16::from_le_bytes(spec_id_len) as usize];
+        reader.read_exact(&mut spec_id)?;
+        hasher.update(&fixed);
+        hasher.update(&spec_id);
+
+        Ok(BinaryHeader {
+            compressed: flags & FLAG_COMPRESSED != 0,
+            spec_id: String::from_utf8(spec_id)?,
+            genesis_hash,
+            blocks: u64::from_le_bytes(blocks),
+        })
+    }
+}
+
+pub struct BinaryWriter<'a> {
+    writer: Box<dyn Write + 'a>,
+    hasher: Blake2b,
+    position: u64,
+}
+
+impl<'a> BinaryWriter<'a> {
+    pub fn new<W: Write + 'a>(mut writer: W, header: &BinaryHeader) -> io::Result<Self> {
+        let mut hasher = new_blake2b();
+        let header_bytes = header.to_vec();
+        writer.write_all(&header_bytes)?;
+        hasher.update(&header_bytes);
+        let writer: Box<dyn Write + 'a> = if header.compressed {
+            Box::new(snap::Writer::new(writer))
+        } else {
+            Box::new(writer)
+        };
+        Ok(BinaryWriter {
+            writer,
+            hasher,
+            position: header_bytes.len() as u64,
+        })
+    }
+
+    fn write_hashed(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.hasher.update(bytes);
+        self.position += bytes.len() as u64;
+        self.writer.write_all(bytes)
+    }
+
+    // The offset of the next block, in the uncompressed content.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn write_block(&mut self, block: &packed::Block) -> io::Result<()> {
+        self.write_hashed(&(block.as_slice().len() as u32).to_le_bytes())?;
+        self.write_hashed(block.as_slice())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.write_hashed(&0u32.to_le_bytes())?;
+        let mut checksum = [0u8; 32];
+        self.hasher.finalize(&mut checksum);
+        self.writer.write_all(&checksum)?;
+        self.writer.flush()
+    }
+}
+
+pub struct BinaryReader<'a> {
+    reader: Box<dyn Read + 'a>,
+    hasher: Blake2b,
+    header: BinaryHeader,
+    max_block_size: usize,
+}
+
+impl<'a> BinaryReader<'a> {
+    /// Blocks larger than `max_block_size` bytes are rejected before being read.
+    pub fn new<R: Read + 'a>(mut reader: R, max_block_size: usize) -> Result<Self, Box<dyn Error>> {
+        let mut hasher = new_blake2b();
+        let header = BinaryHeader::read_from(&mut reader, &mut hasher)?;
+        let reader: Box<dyn Read + 'a> = if header.compressed {
+            Box::new(snap::Reader::new(reader))
+        } else {
+            Box::new(reader)
+        };
+        Ok(BinaryReader {
+            reader,
+            hasher,
+            header,
+            max_block_size,
+        })
+    }
+
+    pub fn header(&self) -> &BinaryHeader {
+        &self.header
+    }
+
+    fn read_hashed(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.reader.read_exact(buf)?;
+        self.hasher.update(buf);
+        Ok(())
+    }
+
+    // Returns the next block, or None after verifying the trailing checksum.
+    pub fn read_block(&mut self) -> Result<Option<packed::Block>, Box<dyn Error>> {
+        let mut len = [0u8; 4];
+        self.read_hashed(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len == 0 {
+            let mut expected = [0u8; 32];
+            self.reader.read_exact(&mut expected)?;
+            let mut checksum = [0u8; 32];
+            mem::replace(&mut self.hasher, new_blake2b()).finalize(&mut checksum);
+            if checksum != expected {
+                return Err("checksum mismatch, the binary export is corrupted".into());
+            }
+            return Ok(None);
+        }
+        if len > self.max_block_size {
+            return Err(format!(
+                "the block size {} exceeds the limit {}, the binary export is corrupted",
+                len, self.max_block_size
+            )
+            .into());
+        }
+        let mut bytes = vec![0u8; len];
+        self.read_hashed(&mut bytes)?;
+        packed::Block::from_slice(&bytes)
+            .map(Some)
+            .map_err(|err| format!("malformed block: {:?}", err).into())
+    }
+
+    /// Reads through all the blocks, then verifies the trailing checksum and
+    /// the number of blocks.
+    pub fn for_each_block<F>(mut self, mut f: F) -> Result<(), Box<dyn Error>>
+    where
+        F: FnMut(packed::Block),
+    {
+        let mut blocks = 0;
+        while let Some(block) = self.read_block()? {
+            f(block);
+            blocks += 1;
+        }
+        if blocks != self.header.blocks {
+            return Err(format!(
+                "the binary export has {} blocks, but the header declares {}",
+                blocks, self.header.blocks
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_types::core::{BlockBuilder, BlockView};
+
+    const MAX_BLOCK_SIZE: usize = 1024;
+
+    fn round_trip(compressed: bool) {
+        let blocks: Vec<BlockView> = (0..3u64)
+            .map(|number| BlockBuilder::default().number(number.pack()).build())
+            .collect();
+        let header = BinaryHeader {
+            compressed,
+            spec_id: "ckb_dev".to_string(),
+            genesis_hash: blocks[0].hash(),
+            blocks: blocks.len() as u64,
+        };
+
+        let mut exported = Vec::new();
+        let mut writer = BinaryWriter::new(&mut exported, &header).unwrap();
+        for block in &blocks {
+            writer.write_block(&block.data()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut reader = BinaryReader::new(&exported[..], MAX_BLOCK_SIZE).unwrap();
+        assert_eq!(reader.header(), &header);
+        for block in &blocks {
+            assert_eq!(
+                reader.read_block().unwrap().unwrap().as_slice(),
+                block.data().as_slice()
+            );
+        }
+        assert!(reader.read_block().unwrap().is_none());
+        assert!(BinaryReader::new(&exported[..], MAX_BLOCK_SIZE)
+            .unwrap()
+            .for_each_block(|_| ())
+            .is_ok());
+
+        // flip a byte of the last block
+        let last = exported.len() - 40;
+        exported[last] ^= 1;
+        let mut reader = BinaryReader::new(&exported[..], MAX_BLOCK_SIZE).unwrap();
+        let corrupted = (0..=blocks.len()).try_for_each(|_| reader.read_block().map(|_| ()));
+        assert!(corrupted.is_err());
+        assert!(BinaryReader::new(&exported[..], MAX_BLOCK_SIZE)
+            .unwrap()
+            .for_each_block(|_| ())
+            .is_err());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        round_trip(false);
+    }
+
+    #[test]
+    fn compressed_binary_round_trip() {
+        round_trip(true);
+    }
+
+    #[test]
+    fn reject_oversized_block() {
+        let header = BinaryHeader {
+            compressed: false,
+            spec_id: "ckb_dev".to_string(),
+            genesis_hash: BlockBuilder::default().build().hash(),
+            blocks: 1,
+        };
+        let mut exported = Vec::new();
+        let writer = BinaryWriter::new(&mut exported, &header).unwrap();
+        drop(writer);
+        exported.extend_from_slice(&u32::max_value().to_le_bytes());
+
+        let mut reader = BinaryReader::new(&exported[..], MAX_BLOCK_SIZE).unwrap();
+        assert!(reader.read_block().is_err());
+    }
+}
@@ -1,10 +1,15 @@
+use crate::binary::{BinaryHeader, BinaryWriter};
 use crate::format::Format;
+use crate::index::{ExportIndex, IndexEntry};
 use crate::iter::ChainIterator;
 use ckb_jsonrpc_types::BlockView as JsonBlock;
 use ckb_shared::shared::Shared;
+use ckb_store::ChainStore;
+use ckb_types::core::BlockNumber;
 #[cfg(feature = "progress_bar")]
 use indicatif::{ProgressBar, ProgressStyle};
 use serde_json;
+use std::cmp;
 use std::error::Error;
 use std::fs;
 use std::io;
@@ -18,6 +23,17 @@ pub struct Export {
     pub shared: Shared,
     /// which format be used to export
     pub format: Format,
+    /// whether to compress the binary format
+    pub compress: bool,
+    /// the first block to export, defaults to the genesis block, or the block
+    /// after the last exported one when appending
+    pub from: Option<BlockNumber>,
+    /// the last block to export, defaults to the tip
+    pub to: Option<BlockNumber>,
+    /// continue the export in the target path
+    pub append: bool,
+    /// the max number of blocks per file
+    pub chunk_size: Option<u64>,
 }
 
 impl Export {
@@ -26,69 +42,268 @@ impl Export {
             shared,
             format,
             target,
+            compress: false,
+            from: None,
+            to: None,
+            append: false,
+            chunk_size: None,
         }
     }
 
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    pub fn from(mut self, from: Option<BlockNumber>) -> Self {
+        self.from = from;
+        self
+    }
+
+    pub fn to(mut self, to: Option<BlockNumber>) -> Self {
+        self.to = to;
+        self
+    }
+
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: Option<u64>) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
     /// Returning ChainIterator dealing with blocks iterate.
     pub fn iter(&self) -> ChainIterator {
-        ChainIterator::new(self.shared.clone())
+        ChainIterator::with_range(
+            self.shared.clone(),
+            self.from.unwrap_or(0),
+            self.to.unwrap_or_else(BlockNumber::max_value),
+        )
+    }
+
+    // Without any range, chunk or append option, the whole chain is exported
+    // to a single file as before.
+    fn is_whole_chain(&self) -> bool {
+        self.from.is_none() && self.to.is_none() && !self.append && self.chunk_size.is_none()
     }
 
     /// export file name
-    fn file_name(&self) -> String {
-        format!("{}.{}", self.shared.consensus().id, self.format)
+    fn file_name(&self, from: BlockNumber, to: BlockNumber) -> String {
+        if self.is_whole_chain() {
+            format!("{}.{}", self.shared.consensus().id, self.format)
+        } else {
+            format!(
+                "{}.{}-{}.{}",
+                self.shared.consensus().id,
+                from,
+                to,
+                self.format
+            )
+        }
+    }
+
+    /// export index file name, see `IndexEntry` for the format
+    fn index_file_name(&self) -> String {
+        format!("{}.{}.index", self.shared.consensus().id, self.format)
+    }
+
+    // Returns the first block to export, checking that an appended export
+    // still follows the current main chain.
+    fn start(&self) -> Result<BlockNumber, Box<dyn Error>> {
+        let last = if self.append {
+            ExportIndex::last_entry(&self.target.join(self.index_file_name()))?
+        } else {
+            None
+        };
+        match last {
+            Some(last) => {
+                let snapshot = self.shared.snapshot();
+                if snapshot.get_block_hash(last.number) != Some(last.hash.clone()) {
+                    return Err(format!(
+                        "the last exported block {} is not in the main chain anymore, \
+                         export to a new target instead",
+                        last.number
+                    )
+                    .into());
+                }
+                match self.from {
+                    Some(from) if from != last.number + 1 => Err(format!(
+                        "the export can only be appended from block {}",
+                        last.number + 1
+                    )
+                    .into()),
+                    _ => Ok(last.number + 1),
+                }
+            }
+            None => Ok(self.from.unwrap_or(0)),
+        }
     }
 
     pub fn execute(self) -> Result<(), Box<dyn Error>> {
         fs::create_dir_all(&self.target)?;
-        match self.format {
-            Format::Json => self.write_to_json(),
-            _ => Ok(()),
+        let from = self.start()?;
+        let to = cmp::min(
+            self.to.unwrap_or_else(BlockNumber::max_value),
+            self.shared.snapshot().tip_number(),
+        );
+        if from > to {
+            if self.append {
+                return Ok(());
+            }
+            return Err(format!("no block to export in range {}-{}", from, to).into());
+        }
+
+        // The genesis block is never pruned
+        if let Some(pruned_number) = self.shared.store().get_pruned_number() {
+            if pruned_number > 1 && to >= 1 && cmp::max(from, 1) < pruned_number {
+                return Err(format!(
+                    "the bodies of the blocks 1-{} are pruned, export from block {} instead",
+                    pruned_number - 1,
+                    pruned_number
+                )
+                .into());
+            }
         }
+
+        let chunk_size = self.chunk_size.unwrap_or_else(u64::max_value);
+        if chunk_size == 0 {
+            return Err("the chunk size must be greater than 0".into());
+        }
+
+        let mut index = ExportIndex::open(&self.target.join(self.index_file_name()), self.append)?;
+        let progress = Progress::new(to - from + 1);
+        let mut chunk_from = from;
+        loop {
+            let chunk_to = cmp::min(to, chunk_from.saturating_add(chunk_size - 1));
+            let blocks = ChainIterator::with_range(self.shared.clone(), chunk_from, chunk_to);
+            match self.format {
+                Format::Json => self.write_to_json(blocks, &mut index, &progress)?,
+                Format::Binary => self.write_to_binary(blocks, &mut index, &progress)?,
+            }
+            if chunk_to == to {
+                break;
+            }
+            chunk_from = chunk_to + 1;
+        }
+        progress.finish();
+        Ok(())
     }
 
-    #[cfg(not(feature = "progress_bar"))]
-    pub fn write_to_json(self) -> Result<(), Box<dyn Error>> {
-        let f = fs::OpenOptions::new()
-            .create_new(true)
+    fn create_file(&self, blocks: &ChainIterator) -> Result<(String, fs::File), Box<dyn Error>> {
+        let file_name = self.file_name(blocks.from(), blocks.to());
+        // An existing file left by an interrupted export is never indexed,
+        // it is overwritten when appending.
+        let mut options = fs::OpenOptions::new();
+        if self.append {
+            options.create(true).truncate(true);
+        } else {
+            options.create_new(true);
+        }
+        let f = options
             .read(true)
             .write(true)
-            .open(&self.target.join(self.file_name()))?;
-        let mut writer = io::BufWriter::new(f);
+            .open(&self.target.join(&file_name))?;
+        Ok((file_name, f))
+    }
 
-        for block in self.iter() {
+    fn write_to_json(
+        &self,
+        blocks: ChainIterator,
+        index: &mut ExportIndex,
+        progress: &Progress,
+    ) -> Result<(), Box<dyn Error>> {
+        let (file_name, f) = self.create_file(&blocks)?;
+        let mut writer = io::BufWriter::new(&f);
+        let mut offset = 0;
+
+        for block in blocks {
+            index.push(IndexEntry {
+                number: block.number(),
+                hash: block.hash(),
+                file: file_name.clone(),
+                offset,
+            });
             let block: JsonBlock = block.into();
             let encoded = serde_json::to_vec(&block)?;
             writer.write_all(&encoded)?;
             writer.write_all(b"\n")?;
+            offset += encoded.len() as u64 + 1;
+            progress.inc();
         }
+        writer.flush()?;
+        // Flush the index after the file is synced, so an interrupted export
+        // only lacks the last file and the index never points past the data.
+        f.sync_all()?;
+        index.flush()?;
         Ok(())
     }
 
+    fn write_to_binary(
+        &self,
+        blocks: ChainIterator,
+        index: &mut ExportIndex,
+        progress: &Progress,
+    ) -> Result<(), Box<dyn Error>> {
+        let (file_name, f) = self.create_file(&blocks)?;
+        let header = BinaryHeader {
+            compressed: self.compress,
+            spec_id: self.shared.consensus().id.clone(),
+            genesis_hash: self.shared.genesis_hash(),
+            blocks: blocks.len(),
+        };
+        let mut writer = BinaryWriter::new(io::BufWriter::new(&f), &header)?;
+
+        for block in blocks {
+            index.push(IndexEntry {
+                number: block.number(),
+                hash: block.hash(),
+                file: file_name.clone(),
+                offset: writer.position(),
+            });
+            writer.write_block(&block.data())?;
+            progress.inc();
+        }
+        writer.finish()?;
+        // See `write_to_json`
+        f.sync_all()?;
+        index.flush()?;
+        Ok(())
+    }
+}
+
+// Reports the exported blocks, does nothing without the `progress_bar` feature.
+struct Progress {
     #[cfg(feature = "progress_bar")]
-    pub fn write_to_json(self) -> Result<(), Box<dyn Error>> {
-        let f = fs::OpenOptions::new()
-            .create_new(true)
-            .read(true)
-            .write(true)
-            .open(&self.target.join(self.file_name()))?;
-        let mut writer = io::BufWriter::new(f);
+    progress_bar: ProgressBar,
+}
+
+impl Progress {
+    #[cfg(not(feature = "progress_bar"))]
+    fn new(_len: u64) -> Self {
+        Progress {}
+    }
 
-        let blocks_iter = self.iter();
-        let progress_bar = ProgressBar::new(blocks_iter.len());
+    #[cfg(feature = "progress_bar")]
+    fn new(len: u64) -> Self {
+        let progress_bar = ProgressBar::new(len);
         progress_bar.set_style(
             ProgressStyle::default_bar()
                 .template("[{elapsed_precise}] {bar:50.cyan/blue} {pos:>6}/{len:6} {msg}")
                 .progress_chars("##-"),
         );
-        for block in blocks_iter {
-            let block: JsonBlock = block.into();
-            let encoded = serde_json::to_vec(&block)?;
-            writer.write_all(&encoded)?;
-            writer.write_all(b"\n")?;
-            progress_bar.inc(1);
-        }
-        progress_bar.finish_with_message("done!");
-        Ok(())
+        Progress { progress_bar }
+    }
+
+    fn inc(&self) {
+        #[cfg(feature = "progress_bar")]
+        self.progress_bar.inc(1);
+    }
+
+    fn finish(&self) {
+        #[cfg(feature = "progress_bar")]
+        self.progress_bar.finish_with_message("done!");
     }
 }
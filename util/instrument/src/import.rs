@@ -1,10 +1,20 @@
+use crate::binary::BinaryReader;
 use crate::format::Format;
-use ckb_chain::chain::ChainController;
+use ckb_chain::{chain::ChainController, switch::Switch};
 use ckb_jsonrpc_types::BlockView as JsonBlock;
-use ckb_types::core;
+use ckb_shared::{shared::Shared, Snapshot};
+use ckb_store::ChainStore;
+use ckb_types::{
+    core,
+    packed::{Byte32, ProposalShortId},
+    prelude::*,
+};
+use ckb_verification::{HeaderResolverWrapper, HeaderVerifier, Verifier};
 #[cfg(feature = "progress_bar")]
 use indicatif::{ProgressBar, ProgressStyle};
 use serde_json;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs;
 use std::io;
@@ -17,24 +27,191 @@ pub struct Import {
     /// source file contains block data
     source: PathBuf,
     chain: ChainController,
+    shared: Shared,
     /// source file format
     format: Format,
+    /// the blocks up to this one are trusted and imported without running
+    /// the scripts
+    assume_valid_target: Option<Byte32>,
+    /// the ancestors of the assume valid target found in the source
+    assume_valid_blocks: HashSet<Byte32>,
 }
 
 impl Import {
-    pub fn new(chain: ChainController, format: Format, source: PathBuf) -> Self {
+    pub fn new(chain: ChainController, shared: Shared, format: Format, source: PathBuf) -> Self {
         Import {
             format,
             chain,
+            shared,
             source,
+            assume_valid_target: None,
+            assume_valid_blocks: HashSet::new(),
         }
     }
 
-    pub fn execute(self) -> Result<(), Box<dyn Error>> {
+    pub fn assume_valid_target(mut self, assume_valid_target: Option<Byte32>) -> Self {
+        self.assume_valid_target = assume_valid_target;
+        self
+    }
+
+    pub fn execute(mut self) -> Result<(), Box<dyn Error>> {
+        self.assume_valid_blocks = self.scan_source()?;
         match self.format {
             Format::Json => self.read_from_json(),
-            _ => Ok(()),
+            Format::Binary => self.read_from_binary(),
+        }
+    }
+
+    // Reads through the source before importing anything, verifying the
+    // checksum of the binary format, and returns the assume valid target
+    // and its ancestors in the source.
+    //
+    // Fails if the target is neither in the store nor in the source, the
+    // scripts of the imported blocks would then never be verified.
+    fn scan_source(&self) -> Result<HashSet<Byte32>, Box<dyn Error>> {
+        let target = match self.assume_valid_target {
+            Some(ref target) if !self.shared.store().block_exists(target) => Some(target),
+            _ => None,
+        };
+        let mut parents = HashMap::new();
+        let mut found = false;
+        let mut scan = |header: core::HeaderView| {
+            if let Some(target) = target {
+                if !found {
+                    found = &header.hash() == target;
+                    parents.insert(header.hash(), header.parent_hash());
+                }
+            }
+        };
+        match self.format {
+            Format::Binary => {
+                self.open_binary()?
+                    .for_each_block(|block| scan(block.header().into_view()))?;
+            }
+            Format::Json if target.is_some() => {
+                let f = fs::File::open(&self.source)?;
+                for line in io::BufReader::new(f).lines() {
+                    let block: JsonBlock = serde_json::from_str(&line?)?;
+                    scan(core::BlockView::from(block).header());
+                }
+            }
+            Format::Json => {}
+        }
+
+        let mut blocks = HashSet::new();
+        if let Some(target) = target {
+            if !found {
+                return Err(format!(
+                    "the assume valid block {} is neither stored nor in the source",
+                    target
+                )
+                .into());
+            }
+            let mut hash = target.clone();
+            while let Some(parent) = parents.remove(&hash) {
+                blocks.insert(hash);
+                hash = parent;
+            }
         }
+        Ok(blocks)
+    }
+
+    // Imports a block which is not the genesis block.
+    //
+    // Blocks already in the store are skipped, so an interrupted import can be
+    // resumed from the same source. The header, including the PoW and the
+    // link to its parent, is always verified; only the assume valid target
+    // and its ancestors are imported without running the scripts.
+    fn import_block(&self, block: core::BlockView) -> Result<(), Box<dyn Error>> {
+        if block.is_genesis() || self.shared.store().block_exists(&block.hash()) {
+            return Ok(());
+        }
+
+        let header = block.header();
+        let snapshot: &Snapshot = &self.shared.snapshot();
+        let resolver = HeaderResolverWrapper::new(&header, snapshot);
+        HeaderVerifier::new(snapshot, Arc::clone(&self.shared.consensus().pow_engine()))
+            .verify(&resolver)
+            .map_err(|err| format!("invalid block {} {}: {}", block.number(), block.hash(), err))?;
+
+        let switch = if self.assume_valid_blocks.contains(&block.hash()) {
+            Switch::ASSUME_VALID
+        } else {
+            Switch::NONE
+        };
+        self.chain
+            .internal_process_block(Arc::new(block.clone()), switch)
+            .map_err(|err| {
+                format!(
+                    "failed to import block {} {}: {}",
+                    block.number(),
+                    block.hash(),
+                    err
+                )
+            })?;
+        Ok(())
+    }
+
+    // The exported blocks also include the proposals of the uncles, which
+    // are not counted in the max block bytes, and the genesis block is not
+    // limited by the max block bytes at all.
+    fn max_block_size(&self) -> usize {
+        let consensus = self.shared.consensus();
+        let uncle_proposals_size = consensus.max_uncles_num() as u64
+            * (consensus.max_block_proposals_limit() * ProposalShortId::TOTAL_SIZE as u64 + 4);
+        cmp::max(
+            (consensus.max_block_bytes() + uncle_proposals_size) as usize,
+            consensus.genesis_block().data().as_slice().len(),
+        )
+    }
+
+    fn open_binary(&self) -> Result<BinaryReader<'static>, Box<dyn Error>> {
+        let f = fs::File::open(&self.source)?;
+        let reader = BinaryReader::new(io::BufReader::new(f), self.max_block_size())?;
+        let header = reader.header();
+        let consensus = self.shared.consensus();
+        if header.spec_id != consensus.id {
+            return Err(format!(
+                "the blocks are exported from chain {}, but the current chain is {}",
+                header.spec_id, consensus.id
+            )
+            .into());
+        }
+        if header.genesis_hash != self.shared.genesis_hash() {
+            return Err(format!(
+                "the genesis hash of the exported blocks {} does not match the current {}",
+                header.genesis_hash,
+                self.shared.genesis_hash()
+            )
+            .into());
+        }
+        Ok(reader)
+    }
+
+    #[cfg(not(feature = "progress_bar"))]
+    pub fn read_from_binary(&self) -> Result<(), Box<dyn Error>> {
+        let mut reader = self.open_binary()?;
+        while let Some(block) = reader.read_block()? {
+            self.import_block(block.into_view_without_reset_header())?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "progress_bar")]
+    pub fn read_from_binary(&self) -> Result<(), Box<dyn Error>> {
+        let mut reader = self.open_binary()?;
+        let progress_bar = ProgressBar::new(reader.header().blocks);
+        progress_bar.set_style(
+            ProgressStyle::default_bar()
+                .template("[{elapsed_precise}] {bar:50.cyan/blue} {pos:>6}/{len:6} {msg}")
+                .progress_chars("##-"),
+        );
+        while let Some(block) = reader.read_block()? {
+            self.import_block(block.into_view_without_reset_header())?;
+            progress_bar.inc(1);
+        }
+        progress_bar.finish_with_message("done!");
+        Ok(())
     }
 
     #[cfg(not(feature = "progress_bar"))]
@@ -45,12 +222,7 @@ impl Import {
         for line in reader.lines() {
             let s = line?;
             let block: JsonBlock = serde_json::from_str(&s)?;
-            let block: Arc<core::BlockView> = Arc::new(block.into());
-            if !block.is_genesis() {
-                self.chain
-                    .process_block(block)
-                    .expect("import occur malformation data");
-            }
+            self.import_block(block.into())?;
         }
         Ok(())
     }
@@ -69,12 +241,7 @@ impl Import {
         for line in reader.lines() {
             let s = line?;
             let block: JsonBlock = serde_json::from_str(&s)?;
-            let block: Arc<core::BlockView> = Arc::new(block.into());
-            if !block.is_genesis() {
-                self.chain
-                    .process_block(block)
-                    .expect("import occur malformation data");
-            }
+            self.import_block(block.into())?;
             progress_bar.inc(s.as_bytes().len() as u64);
         }
         progress_bar.finish_with_message("done!");
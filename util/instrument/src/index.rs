@@ -0,0 +1,123 @@
+use ckb_types::{core::BlockNumber, packed::Byte32, prelude::*, H256};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+const INDEX_TAIL_SIZE: u64 = 1024;
+
+/// An entry of the export index, one line per exported block:
+/// `<block number> <block hash> <file name> <offset>`.
+///
+/// The offset is where the block starts in the file, for the binary format it
+/// is the offset in the uncompressed content.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub number: BlockNumber,
+    pub hash: Byte32,
+    pub file: String,
+    pub offset: u64,
+}
+
+impl IndexEntry {
+    fn to_line(&self) -> String {
+        let hash: H256 = self.hash.unpack();
+        format!("{} {} {} {}\n", self.number, hash, self.file, self.offset)
+    }
+
+    fn from_line(line: &str) -> Result<Self, Box<dyn Error>> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 4 {
+            return Err(format!("malformed export index entry: {}", line).into());
+        }
+        let hash = H256::from_str(fields[1])
+            .map_err(|err| format!("malformed block hash in export index: {:?}", err))?;
+        Ok(IndexEntry {
+            number: fields[0].parse()?,
+            hash: hash.pack(),
+            file: fields[2].to_owned(),
+            offset: fields[3].parse()?,
+        })
+    }
+}
+
+/// The entries are kept in memory until `flush`, which must only be called
+/// after the blocks they point to are synced to the data file, so the index
+/// on disk never points past the exported data.
+pub struct ExportIndex {
+    writer: io::BufWriter<fs::File>,
+    pending: Vec<IndexEntry>,
+}
+
+impl ExportIndex {
+    /// Creates a new index, or appends to the existing one when `append` is set.
+    pub fn open(path: &Path, append: bool) -> io::Result<Self> {
+        let f = if append {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+        } else {
+            fs::OpenOptions::new()
+                .create_new(true)
+                .write(true)
+                .open(path)?
+        };
+        Ok(ExportIndex {
+            writer: io::BufWriter::new(f),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Returns the last exported block, or None if nothing was exported.
+    pub fn last_entry(path: &Path) -> Result<Option<IndexEntry>, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        // Only the tail is read, an entry line is far shorter than the tail.
+        let mut f = fs::File::open(path)?;
+        let len = f.metadata()?.len();
+        f.seek(SeekFrom::Start(len.saturating_sub(INDEX_TAIL_SIZE)))?;
+        let mut tail = String::new();
+        f.read_to_string(&mut tail)?;
+        tail.lines()
+            .filter(|line| !line.is_empty())
+            .last()
+            .map(IndexEntry::from_line)
+            .transpose()
+    }
+
+    pub fn push(&mut self, entry: IndexEntry) {
+        self.pending.push(entry);
+    }
+
+    /// Writes the pending entries and syncs the index file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        for entry in self.pending.drain(..) {
+            self.writer.write_all(entry.to_line().as_bytes())?;
+        }
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_types::h256;
+
+    #[test]
+    fn index_entry_round_trip() {
+        let entry = IndexEntry {
+            number: 42,
+            hash: h256!("0x1").pack(),
+            file: "ckb.0-99.bin".to_owned(),
+            offset: 4096,
+        };
+        let line = entry.to_line();
+        assert!(line.ends_with('\n'));
+        assert_eq!(IndexEntry::from_line(line.trim_end()).unwrap(), entry);
+        assert!(IndexEntry::from_line("42 0x1 ckb.0-99.bin").is_err());
+    }
+}
@@ -1,31 +1,52 @@
 use ckb_shared::{shared::Shared, Snapshot};
 use ckb_store::ChainStore;
 use ckb_types::{core::BlockNumber, core::BlockView};
+use std::cmp;
 use std::sync::Arc;
 
 // An iterator over the entries of a `Chain`.
 pub struct ChainIterator {
     snapshot: Arc<Snapshot>,
     current: Option<BlockView>,
+    from: BlockNumber,
     tip: BlockNumber,
 }
 
 impl ChainIterator {
     pub fn new(shared: Shared) -> Self {
+        Self::with_range(shared, 0, BlockNumber::max_value())
+    }
+
+    // Iterates the main chain blocks from `from` to `to` inclusive, `to` is
+    // capped to the tip.
+    pub fn with_range(shared: Shared, from: BlockNumber, to: BlockNumber) -> Self {
         let snapshot = Arc::clone(&shared.snapshot());
-        let current = snapshot
-            .get_block_hash(0)
-            .and_then(|h| snapshot.get_block(&h));
-        let tip = snapshot.tip_number();
+        let tip = cmp::min(to, snapshot.tip_number());
+        let current = if from <= tip {
+            snapshot
+                .get_block_hash(from)
+                .and_then(|h| snapshot.get_block(&h))
+        } else {
+            None
+        };
         ChainIterator {
             snapshot,
             current,
+            from,
             tip,
         }
     }
 
+    pub fn from(&self) -> BlockNumber {
+        self.from
+    }
+
+    pub fn to(&self) -> BlockNumber {
+        self.tip
+    }
+
     pub fn len(&self) -> u64 {
-        self.tip + 1
+        (self.tip + 1).saturating_sub(self.from)
     }
 }
 
@@ -36,14 +57,14 @@ impl Iterator for ChainIterator {
         let current = self.current.take();
 
         self.current = match current {
-            Some(ref b) => {
+            Some(ref b) if b.header().number() < self.tip => {
                 if let Some(block_hash) = self.snapshot.get_block_hash(b.header().number() + 1) {
                     self.snapshot.get_block(&block_hash)
                 } else {
                     None
                 }
             }
-            None => None,
+            _ => None,
         };
         current
     }
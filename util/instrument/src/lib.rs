@@ -7,11 +7,15 @@
 //! - [Import](instrument::import::Import) import block data which
 //!   export from `Export`.
 
+mod binary;
 mod export;
 mod format;
 mod import;
+mod index;
 mod iter;
 
+pub use crate::binary::{BinaryHeader, BinaryReader, BinaryWriter};
 pub use crate::export::Export;
 pub use crate::format::Format;
 pub use crate::import::Import;
+pub use crate::index::{ExportIndex, IndexEntry};
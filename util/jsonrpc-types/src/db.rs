@@ -0,0 +1,40 @@
+use crate::Uint64;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct StoreStats {
+    pub chain_db: DBStats,
+    pub indexer_db: Option<DBStats>,
+    pub caches: Vec<CacheStats>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct DBStats {
+    pub columns: Vec<ColumnStats>,
+    pub running_compactions: Uint64,
+    // None unless the RocksDB statistics are enabled by `db.statistics`
+    pub block_cache_hits: Option<Uint64>,
+    pub block_cache_misses: Option<Uint64>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct ColumnStats {
+    pub name: String,
+    pub estimated_keys: Uint64,
+    pub estimated_live_data_size: Uint64,
+    pub sst_files: Uint64,
+    pub sst_files_size: Uint64,
+    pub mem_tables_size: Uint64,
+    pub block_cache_usage: Uint64,
+    pub compaction_pending: bool,
+    pub pending_compaction_bytes: Uint64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct CacheStats {
+    pub name: String,
+    pub hits: Uint64,
+    pub misses: Uint64,
+    pub len: Uint64,
+    pub capacity: Uint64,
+}
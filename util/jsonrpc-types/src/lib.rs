@@ -4,6 +4,7 @@ mod blockchain;
 mod bytes;
 mod cell;
 mod chain_info;
+mod db;
 mod experiment;
 mod fixed_bytes;
 mod indexer;
@@ -27,10 +28,13 @@ pub use self::blockchain::{
 pub use self::bytes::JsonBytes;
 pub use self::cell::{CellOutputWithOutPoint, CellWithStatus};
 pub use self::chain_info::ChainInfo;
+pub use self::db::{CacheStats, ColumnStats, DBStats, StoreStats};
 pub use self::experiment::DryRunResult;
 pub use self::fixed_bytes::Byte32;
 pub use self::indexer::{CellTransaction, LiveCell, LockHashIndexState, TransactionPoint};
-pub use self::net::{BannedAddr, Node, NodeAddress};
+pub use self::net::{
+    BannedAddr, MessageTypeCount, NetworkStats, Node, NodeAddress, ProtocolTraffic,
+};
 pub use self::pool::TxPoolInfo;
 pub use self::proposal_short_id::ProposalShortId;
 pub use self::sync::PeerState;
@@ -8,6 +8,8 @@ pub struct Node {
     pub node_id: String,
     pub addresses: Vec<NodeAddress>,
     pub is_outbound: Option<bool>,
+    /// Traffic exchanged with the peer, it is absent for the local node
+    pub traffic: Option<Vec<ProtocolTraffic>>,
 }
 
 #[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
@@ -23,3 +25,32 @@ pub struct BannedAddr {
     pub ban_reason: String,
     pub created_at: Timestamp,
 }
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct NetworkStats {
+    pub protocols: Vec<ProtocolTraffic>,
+}
+
+/// Traffic of a protocol
+///
+/// `bytes_*` count the compressed payload on the wire, `raw_bytes_*` count the
+/// payload before compression.
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct ProtocolTraffic {
+    pub protocol_id: Uint64,
+    pub name: String,
+    pub bytes_received: Uint64,
+    pub raw_bytes_received: Uint64,
+    pub bytes_sent: Uint64,
+    pub raw_bytes_sent: Uint64,
+    pub messages_received: Uint64,
+    pub messages_sent: Uint64,
+    pub message_types: Vec<MessageTypeCount>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+pub struct MessageTypeCount {
+    pub message_type: String,
+    pub received: Uint64,
+    pub sent: Uint64,
+}
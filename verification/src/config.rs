@@ -0,0 +1,25 @@
+use ckb_types::H256;
+use serde_derive::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Clone, Serialize, Deserialize, Eq, PartialEq, Hash, Debug, Default)]
+pub struct VerificationConfig {
+    // run the script groups of a transaction concurrently.
+    // There is no per script group cache: a script can load any cell, header or
+    // witness of its transaction, so a group result is only valid for the exact
+    // transaction, which the transaction verify cache already covers.
+    #[serde(default)]
+    pub parallel_script_groups: bool,
+    // threads used to verify the transactions of a block, 0 uses the global rayon pool
+    #[serde(default)]
+    pub block_verify_threads: usize,
+    // write the execution traces of the script groups with this script hash
+    #[serde(default)]
+    pub trace_script_hash: Option<H256>,
+    // trust the blocks up to this one when syncing, they are accepted without running the scripts
+    #[serde(default)]
+    pub assume_valid_target: Option<H256>,
+    // the directory of the execution traces, derived from the data dir
+    #[serde(default)]
+    pub trace_dir: PathBuf,
+}
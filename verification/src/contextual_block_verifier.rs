@@ -25,12 +25,18 @@ use ckb_types::{
 use futures::future::{self, Future};
 use lru_cache::LruCache;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use rayon::ThreadPool;
 use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tokio::sync::lock::Lock;
 
 pub struct VerifyContext<'a, CS> {
     pub(crate) store: &'a CS,
     pub(crate) consensus: &'a Consensus,
+    pub(crate) parallel_script_groups: bool,
+    pub(crate) thread_pool: Option<&'a ThreadPool>,
+    pub(crate) trace_script: Option<(Byte32, &'a Path)>,
 }
 
 pub trait Switch {
@@ -40,11 +46,36 @@ pub trait Switch {
     fn disable_daoheader(&self) -> bool;
     fn disable_reward(&self) -> bool;
     fn disable_txs(&self) -> bool;
+    /// Skips the script execution of the transactions, the other transaction
+    /// checks are still done unless `disable_txs` is set.
+    fn disable_script(&self) -> bool;
 }
 
 impl<'a, CS: ChainStore<'a>> VerifyContext<'a, CS> {
     pub fn new(store: &'a CS, consensus: &'a Consensus) -> Self {
-        VerifyContext { store, consensus }
+        VerifyContext {
+            store,
+            consensus,
+            parallel_script_groups: false,
+            thread_pool: None,
+            trace_script: None,
+        }
+    }
+
+    pub fn parallel_script_groups(mut self, parallel: bool) -> Self {
+        self.parallel_script_groups = parallel;
+        self
+    }
+
+    pub fn thread_pool(mut self, pool: Option<&'a ThreadPool>) -> Self {
+        self.thread_pool = pool;
+        self
+    }
+
+    // Writes the execution traces of the script groups with the script hash to the dir
+    pub fn trace_script(mut self, trace_script: Option<(Byte32, &'a Path)>) -> Self {
+        self.trace_script = trace_script;
+        self
     }
 
     fn finalize_block_reward(&self, parent: &HeaderView) -> Result<(Script, BlockReward), Error> {
@@ -325,6 +356,7 @@ struct BlockTxsVerifier<'a, CS> {
     epoch_number_with_fraction: EpochNumberWithFraction,
     parent_hash: Byte32,
     resolved: &'a [ResolvedTransaction],
+    skip_script: bool,
 }
 
 impl<'a, CS: ChainStore<'a>> BlockTxsVerifier<'a, CS> {
@@ -335,6 +367,7 @@ impl<'a, CS: ChainStore<'a>> BlockTxsVerifier<'a, CS> {
         epoch_number_with_fraction: EpochNumberWithFraction,
         parent_hash: Byte32,
         resolved: &'a [ResolvedTransaction],
+        skip_script: bool,
     ) -> Self {
         BlockTxsVerifier {
             context,
@@ -342,6 +375,7 @@ impl<'a, CS: ChainStore<'a>> BlockTxsVerifier<'a, CS> {
             epoch_number_with_fraction,
             parent_hash,
             resolved,
+            skip_script,
         }
     }
 
@@ -362,6 +396,67 @@ impl<'a, CS: ChainStore<'a>> BlockTxsVerifier<'a, CS> {
         receiver.recv().expect("fetched cache no exception")
     }
 
+    fn verify_transaction(
+        &self,
+        index: usize,
+        tx: &ResolvedTransaction,
+        fetched_cache: &HashMap<Byte32, Cycle>,
+    ) -> Result<(Byte32, Cycle), Error> {
+        let tx_hash = tx.transaction.hash();
+        // Without scripts, a transaction is checked as if its cycles were cached.
+        let cached_cycles = if self.skip_script {
+            Some(0)
+        } else {
+            fetched_cache.get(&tx_hash).cloned()
+        };
+        let ret = if let Some(cycles) = cached_cycles {
+            ContextualTransactionVerifier::new(
+                &tx,
+                self.context,
+                self.block_number,
+                self.epoch_number_with_fraction,
+                self.parent_hash.clone(),
+                self.context.consensus,
+            )
+            .verify()
+            .map(|_| cycles)
+        } else {
+            let mut verifier = TransactionVerifier::new(
+                &tx,
+                self.context,
+                self.block_number,
+                self.epoch_number_with_fraction,
+                self.parent_hash.clone(),
+                self.context.consensus,
+                self.context.store,
+            );
+            verifier
+                .script
+                .set_parallel(self.context.parallel_script_groups);
+            if let Some((script_hash, dir)) = &self.context.trace_script {
+                verifier.script.set_trace(script_hash.clone(), dir);
+            }
+            verifier.verify(self.context.consensus.max_block_cycles())
+        };
+        ret.map(|cycles| (tx_hash, cycles)).map_err(|error| {
+            BlockTransactionsError {
+                index: index as u32,
+                error,
+            }
+            .into()
+        })
+    }
+
+    // Transactions are verified concurrently, on the configured thread pool if any.
+    //
+    // The results are reduced in block order as if the transactions were verified
+    // one by one: the first invalid transaction by index is reported, unless the
+    // cycles consumed by the transactions before it already exceed
+    // `max_block_cycles`. Once a transaction has failed, the later ones are
+    // skipped since they can't change the result.
+    //
+    // When scripts are skipped, the transactions consume no cycles and the
+    // verify cache is neither read nor updated.
     pub fn verify(
         &self,
         txs_verify_cache: Lock<LruCache<Byte32, Cycle>>,
@@ -372,65 +467,65 @@ impl<'a, CS: ChainStore<'a>> BlockTxsVerifier<'a, CS> {
             .iter()
             .map(|rtx| rtx.transaction.hash())
             .collect();
-        let fetched_cache = self.fetched_cache(txs_verify_cache.clone(), keys, executor);
+        let fetched_cache = if self.skip_script {
+            HashMap::new()
+        } else {
+            self.fetched_cache(txs_verify_cache.clone(), keys, executor)
+        };
+
+        let first_failure = AtomicUsize::new(usize::max_value());
 
         // make verifiers orthogonal
-        let ret = self
-            .resolved
-            .par_iter()
-            .enumerate()
-            .map(|(index, tx)| {
-                let tx_hash = tx.transaction.hash();
-                if let Some(cycles) = fetched_cache.get(&tx_hash) {
-                    ContextualTransactionVerifier::new(
-                        &tx,
-                        self.context,
-                        self.block_number,
-                        self.epoch_number_with_fraction,
-                        self.parent_hash.clone(),
-                        self.context.consensus,
-                    )
-                    .verify()
-                    .map_err(|error| {
-                        BlockTransactionsError {
-                            index: index as u32,
-                            error,
-                        }
-                        .into()
-                    })
-                    .map(|_| (tx_hash, *cycles))
-                } else {
-                    TransactionVerifier::new(
-                        &tx,
-                        self.context,
-                        self.block_number,
-                        self.epoch_number_with_fraction,
-                        self.parent_hash.clone(),
-                        self.context.consensus,
-                        self.context.store,
-                    )
-                    .verify(self.context.consensus.max_block_cycles())
-                    .map_err(|error| {
-                        BlockTransactionsError {
-                            index: index as u32,
-                            error,
+        let verify_all = || {
+            self.resolved
+                .par_iter()
+                .enumerate()
+                .map(|(index, tx)| {
+                    if index > first_failure.load(Ordering::SeqCst) {
+                        return None;
+                    }
+                    let ret = self.verify_transaction(index, tx, &fetched_cache);
+                    if ret.is_err() {
+                        let mut current = first_failure.load(Ordering::SeqCst);
+                        while index < current {
+                            match first_failure.compare_exchange(
+                                current,
+                                index,
+                                Ordering::SeqCst,
+                                Ordering::SeqCst,
+                            ) {
+                                Ok(_) => break,
+                                Err(actual) => current = actual,
+                            }
                         }
-                        .into()
-                    })
-                    .map(|cycles| (tx_hash, cycles))
-                }
-            })
-            .collect::<Result<HashMap<Byte32, Cycle>, Error>>()?;
-
-        let sum: Cycle = ret.iter().map(|(_, cycles)| cycles).sum();
-        let update = UpdateCache::new(txs_verify_cache.clone(), ret);
-        executor.spawn(Box::new(update));
+                    }
+                    Some(ret)
+                })
+                .collect::<Vec<_>>()
+        };
+        let results = match self.context.thread_pool {
+            Some(pool) => pool.install(verify_all),
+            None => verify_all(),
+        };
 
-        if sum > self.context.consensus.max_block_cycles() {
-            Err(BlockErrorKind::ExceededMaximumCycles.into())
-        } else {
-            Ok(sum)
+        // A skipped transaction always follows a failed one, so the loop returns
+        // before reaching it.
+        let max_block_cycles = self.context.consensus.max_block_cycles();
+        let mut sum: Cycle = 0;
+        let mut ret = HashMap::with_capacity(results.len());
+        for result in results.into_iter().flatten() {
+            let (tx_hash, cycles) = result?;
+            sum = sum
+                .checked_add(cycles)
+                .filter(|sum| *sum <= max_block_cycles)
+                .ok_or(BlockErrorKind::ExceededMaximumCycles)?;
+            ret.insert(tx_hash, cycles);
+        }
+        if !self.skip_script {
+            let update = UpdateCache::new(txs_verify_cache.clone(), ret);
+            executor.spawn(Box::new(update));
         }
+        Ok(sum)
     }
 }
 
@@ -545,6 +640,7 @@ impl<'a, CS: ChainStore<'a>> ContextualBlockVerifier<'a, CS> {
                 block.epoch(),
                 parent_hash,
                 resolved,
+                switch.disable_script(),
             )
             .verify(txs_verify_cache, executor)?
         } else {
@@ -2,6 +2,7 @@
 extern crate enum_display_derive;
 
 mod block_verifier;
+mod config;
 mod contextual_block_verifier;
 mod convert;
 mod error;
@@ -14,6 +15,7 @@ mod uncles_verifier;
 mod tests;
 
 pub use crate::block_verifier::{BlockVerifier, HeaderResolverWrapper};
+pub use crate::config::VerificationConfig;
 pub use crate::contextual_block_verifier::{ContextualBlockVerifier, Switch, VerifyContext};
 pub use crate::error::{
     BlockError, BlockErrorKind, BlockTransactionsError, CellbaseError, CommitError, EpochError,
@@ -1,7 +1,8 @@
 use crate::TransactionError;
 use ckb_chain_spec::consensus::Consensus;
 use ckb_error::Error;
-use ckb_script::TransactionScriptsVerifier;
+use ckb_logger::error_target;
+use ckb_script::{DataLoader, ScriptGroupType, TransactionScriptsVerifier};
 use ckb_store::{data_loader_wrapper::DataLoaderWrapper, ChainStore};
 use ckb_traits::BlockMedianTimeContext;
 use ckb_types::{
@@ -16,6 +17,9 @@ use ckb_types::{
 use lru_cache::LruCache;
 use std::cell::RefCell;
 use std::collections::HashSet;
+use std::fs::{create_dir_all, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
 pub struct ContextualTransactionVerifier<'a, M> {
     pub maturity: MaturityVerifier<'a>,
@@ -164,6 +168,8 @@ impl<'a> SizeVerifier<'a> {
 pub struct ScriptVerifier<'a, CS> {
     chain_store: &'a CS,
     resolved_transaction: &'a ResolvedTransaction,
+    parallel: bool,
+    trace: Option<(Byte32, &'a Path)>,
 }
 
 impl<'a, CS: ChainStore<'a>> ScriptVerifier<'a, CS> {
@@ -171,12 +177,77 @@ impl<'a, CS: ChainStore<'a>> ScriptVerifier<'a, CS> {
         ScriptVerifier {
             chain_store,
             resolved_transaction,
+            parallel: false,
+            trace: None,
         }
     }
 
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    // Traces the script groups with the script hash to
+    // `<dir>/<tx hash>-<lock|type>-<script hash>.jsonl` on every verification,
+    // see `TransactionScriptsVerifier::trace_single` for the format.
+    pub fn set_trace(&mut self, script_hash: Byte32, dir: &'a Path) {
+        self.trace = Some((script_hash, dir));
+    }
+
     pub fn verify(&self, max_cycles: Cycle) -> Result<Cycle, Error> {
         let data_loader = DataLoaderWrapper::new(self.chain_store);
-        TransactionScriptsVerifier::new(&self.resolved_transaction, &data_loader).verify(max_cycles)
+        let verifier = TransactionScriptsVerifier::new(&self.resolved_transaction, &data_loader);
+        if let Some((script_hash, dir)) = &self.trace {
+            for script_group_type in &[ScriptGroupType::Lock, ScriptGroupType::Type] {
+                if verifier.contains_script_group(script_group_type, script_hash) {
+                    if let Err(err) = self.trace_script_group(
+                        &verifier,
+                        script_group_type,
+                        script_hash,
+                        max_cycles,
+                        dir,
+                    ) {
+                        error_target!(
+                            crate::LOG_TARGET,
+                            "Failed to trace the script group {} of transaction {}: {}",
+                            script_hash,
+                            self.resolved_transaction.transaction.hash(),
+                            err
+                        );
+                    }
+                }
+            }
+        }
+        if self.parallel {
+            verifier.verify_parallel(max_cycles)
+        } else {
+            verifier.verify(max_cycles)
+        }
+    }
+
+    // The script result is reported by the verification itself, the trace
+    // ends where the script fails.
+    fn trace_script_group<DL: DataLoader>(
+        &self,
+        verifier: &TransactionScriptsVerifier<DL>,
+        script_group_type: &ScriptGroupType,
+        script_hash: &Byte32,
+        max_cycles: Cycle,
+        dir: &Path,
+    ) -> Result<(), io::Error> {
+        create_dir_all(dir)?;
+        let group_type = match script_group_type {
+            ScriptGroupType::Lock => "lock",
+            ScriptGroupType::Type => "type",
+        };
+        let path = dir.join(format!(
+            "{:x}-{}-{:x}.jsonl",
+            self.resolved_transaction.transaction.hash(),
+            group_type,
+            script_hash
+        ));
+        let mut writer = BufWriter::new(File::create(path)?);
+        let _ = verifier.trace_single(script_group_type, script_hash, max_cycles, &mut writer);
+        writer.flush()
     }
 }
 